@@ -5,21 +5,55 @@ use std::env::Args;
 use std::error::Error;
 use std::fs::File;
 use std::fs::OpenOptions;
-use std::io::Seek;
-use std::io::SeekFrom;
 use std::io::Write;
 use std::path::Path;
 
 use crate::parser::Instruction;
 
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let mut file = File::open(
+    let file = File::open(
         Path::new(&config.filename)
     )?;
-    let parser = parser::Parser::new(&file);
+    let instructions: Vec<Instruction> = parser::Parser::new(&file).collect();
+    let dictionary = symbols(&instructions).map_err(|(_, error)| error)?;
 
+    let mut output = OpenOptions::new().write(true).truncate(true).create(true).open(
+        Path::new(&config.destination)
+    )?;
+    
+    for instruction in instructions.iter() {
+        if let Some(address) = instruction.to_decimal(&dictionary) {
+            writeln!(output, "{:016b}", address)?;
+        }
+    }
+    println!("Done!");
+    Ok(())
+}
+
+// The machine code of a whole program in memory, one line of 16 bits for each instruction like
+// `run` writes. An instruction that cannot be assembled fails with its line number.
+pub fn assemble(source: &str) -> Result<String, (usize, String)> {
+    let (numbers, instructions): (Vec<usize>, Vec<Instruction>) = source.lines().enumerate()
+        .filter_map(|(number, line)| Some((number + 1, parser::line_to_instruction(line)?)))
+        .unzip();
+    let dictionary = symbols(&instructions).map_err(|(index, error)| (numbers[index], error))?;
+    let mut binary = String::new();
+    for (number, instruction) in numbers.iter().zip(instructions.iter()) {
+        if let Some(address) = instruction.try_to_decimal(&dictionary).map_err(|error| (*number, error))? {
+            binary.push_str(&format!("{:016b}\n", address));
+        }
+    }
+    Ok(binary)
+}
+
+// The number of instructions the ROM of the Hack computer holds
+pub const ROM: usize = 32768;
+
+// The predefined symbols, the labels, and the variables from address 16 on. A program longer
+// than the ROM, or with more variables than the RAM, fails at the instruction that does not fit.
+fn symbols(instructions: &[Instruction]) -> Result<HashMap<String, i16>, (usize, String)> {
     // Line counter
-    let mut counter = 0i16;
+    let mut counter = 0usize;
     // Memory counter
     let mut m_address = 15i16;
 
@@ -38,44 +72,29 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     dictionary.insert("THIS".to_string(), 3);
     dictionary.insert("THAT".to_string(), 4);
     // Label symbols
-    for instruction in parser {
+    for (index, instruction) in instructions.iter().enumerate() {
         match instruction {
             Instruction::L(symbol) => {
-                dictionary.entry(symbol).or_insert(counter);
+                let address = i16::try_from(counter).map_err(|_| (index, format!("Label past the end of the ROM: {}", symbol)))?;
+                dictionary.entry(symbol.clone()).or_insert(address);
             },
+            _ if counter == ROM => return Err((index, format!("More instructions than the ROM holds: {}", ROM))),
             _ => counter += 1
         }
     }
     // Variable symbols
-    file.seek(SeekFrom::Start(0)).unwrap();
-    let parser = parser::Parser::new(&file);
-    for instruction in parser {
+    for (index, instruction) in instructions.iter().enumerate() {
         match instruction {
             Instruction::A(symbol) => {
-                if symbol.parse::<i16>().is_err() {
-                    dictionary.entry(symbol).or_insert_with(|| {
-                        m_address += 1;
-                        m_address
-                    });
+                if symbol.parse::<i16>().is_err() && !dictionary.contains_key(symbol) {
+                    m_address = m_address.checked_add(1).ok_or((index, format!("No address left for the variable: {}", symbol)))?;
+                    dictionary.insert(symbol.clone(), m_address);
                 }
             },
             _ => {}
         }
     }
-
-    let mut output = OpenOptions::new().write(true).truncate(true).create(true).open(
-        Path::new(&config.destination)
-    )?;
-    
-    file.seek(SeekFrom::Start(0)).unwrap();
-    let parser = parser::Parser::new(&file);
-    for instruction in parser {
-        if let Some(address) = instruction.to_decimal(&dictionary) {
-            writeln!(output, "{:016b}", address)?;
-        }
-    }
-    println!("Done!");
-    Ok(())
+    Ok(dictionary)
 }
 
 pub struct Config {
@@ -102,3 +121,27 @@ impl Config {
         Ok(Config { filename, destination })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assemble_text() {
+        let binary = assemble("// R1 = R0\n@R0\nD=M\n(END)\n@END\n0;JMP\n").unwrap();
+        assert_eq!(binary, "0000000000000000\n1111110000010000\n0000000000000010\n1110101010000111\n");
+        assert_eq!(assemble("@1\n\nD=Q\n").unwrap_err(), (3, "Invalid comp: Q".to_string()));
+    }
+
+    #[test]
+    fn programs_larger_than_the_rom_are_errors() {
+        let full = "D=0\n".repeat(ROM);
+        assert_eq!(assemble(&full).unwrap().lines().count(), ROM);
+        let error = (ROM + 1, format!("More instructions than the ROM holds: {}", ROM));
+        assert_eq!(assemble(&format!("{}D=0\n", full)).unwrap_err(), error);
+        let error = (ROM + 1, "Label past the end of the ROM: END".to_string());
+        assert_eq!(assemble(&format!("{}(END)\n@END\n", full)).unwrap_err(), error);
+        // a label at the last instruction still fits
+        assert!(assemble(&format!("(LAST)\n{}@LAST\n", "D=0\n".repeat(ROM - 1))).is_ok());
+    }
+}
@@ -12,17 +12,22 @@ pub enum Instruction {
 
 impl Instruction {
     pub fn to_decimal(&self, dictionary: &HashMap<String, i16>) -> Option<i16> {
+        self.try_to_decimal(dictionary).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // to_decimal with an error for a C instruction that does not exist
+    pub fn try_to_decimal(&self, dictionary: &HashMap<String, i16>) -> Result<Option<i16>, String> {
         match &self {
             &Instruction::A(symbol) => {
                 if let Ok(address) = symbol.parse::<i16>() {
-                    Some(address)
+                    Ok(Some(address))
                 } else {
-                    let address = dictionary.get(symbol).unwrap();
-                    Some(*address)
+                    let address = dictionary.get(symbol).ok_or(format!("Unknown symbol: {}", symbol))?;
+                    Ok(Some(*address))
                 }
             },
             &Instruction::L(_symbol) => {
-                None
+                Ok(None)
             },
             &Instruction::C { dest, comp, jump } => {
                 let opcode_b: i16 = 0b111 << 13;
@@ -55,7 +60,7 @@ impl Instruction {
                     "D&M" | "M&D" => 0b1000000,
                     "D|A" | "A|D" => 0b0010101,
                     "D|M" | "M|D" => 0b1010101,
-                    _ => panic!("Invalid comp: {}", comp)
+                    _ => return Err(format!("Invalid comp: {}", comp))
                 } << 6;
                 let dest_b: i16 = if let Some(v) = dest {
                     match v.as_ref() {
@@ -66,7 +71,7 @@ impl Instruction {
                         "AM" | "MA" => 0b101,
                         "AD" | "DA" => 0b110,
                         "ADM" | "AMD" | "DAM" | "DMA" | "MAD" | "MDA" => 0b111,
-                        _ => panic!("Invalid dest: {}", v)
+                        _ => return Err(format!("Invalid dest: {}", v))
                     }
                 } else {
                     0b000
@@ -80,13 +85,13 @@ impl Instruction {
                         "JNE" => 0b101,
                         "JLE" => 0b110,
                         "JMP" => 0b111,
-                        _ => panic!("Invalid jump")
+                        _ => return Err(format!("Invalid jump: {}", v))
                     }
                 } else {
                     0b000
                 };
                 let binary = opcode_b | comp_b | dest_b | jump_b;
-                Some(binary)
+                Ok(Some(binary))
             }
         }
     }
@@ -112,7 +117,7 @@ impl<'a> Iterator for Parser<'a> {
     }
 }
 
-fn line_to_instruction(line: &str) -> Option<Instruction> {
+pub(crate) fn line_to_instruction(line: &str) -> Option<Instruction> {
     let line = if let Some((line_without_comment, _comment)) = line.split_once("//") {
         line_without_comment
     } else {
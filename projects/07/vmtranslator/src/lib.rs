@@ -38,6 +38,28 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+// The assembly of a whole program from the text of its .vm files, `("Main.vm", text)`, in the
+// same layout as `run` writes. A line that is not a VM command fails with the index of its file.
+pub fn translate(files: &[(&str, &str)]) -> Result<String, (usize, String)> {
+    let mut output = format!("// Bootstrap\n{}", platform::Hack::bootstrap());
+    for (index, (filename, text)) in files.iter().enumerate() {
+        let mut platform = platform::Hack::new(filename);
+        for (number, line) in text.lines().enumerate() {
+            let code = line.split_once("//").map_or(line, |(code, _)| code);
+            if code.trim().is_empty() {
+                continue;
+            }
+            let command = parser::line_to_command(line)
+                .ok_or_else(|| (index, format!("{}:{}: not a VM command: {}", filename, number + 1, line.trim())))?;
+            if let Some(assembly) = platform.translate(&command) {
+                output.push_str(&format!("// {}\n{}", &command, assembly));
+            }
+        }
+    }
+    output.push_str(&format!("// Program end\n{}", platform::Hack::end()));
+    Ok(output)
+}
+
 fn handle_file(filename: &str, output: &mut File) -> Result<(), Box<dyn Error>> {
     let file = File::open(filename)?;
     let parser = parser::Parser::new(file);
@@ -116,6 +138,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn translate_text() {
+        let assembly = translate(&[("Main.vm", "function Main.main 0\n// done\npush constant 7\nreturn\n")]).unwrap();
+        assert!(assembly.starts_with("// Bootstrap\n@256\n"));
+        assert!(assembly.contains("// function Main.main 0\n(Main.main)\n"));
+        assert!(assembly.contains("// push constant 7\n@7\n"));
+        assert!(assembly.ends_with("// Program end\n(END)\n@END\n0;JMP\n"));
+
+        let error = translate(&[("Main.vm", "return\n"), ("Sys.vm", "\npush nowhere 1\n")]).unwrap_err();
+        assert_eq!(error, (1, "Sys.vm:2: not a VM command: push nowhere 1".to_string()));
+    }
+
     #[test]
     fn directory_source() {
         let args = vec!["app".to_string(), "../myfolder/".to_string()];
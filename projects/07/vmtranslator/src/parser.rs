@@ -95,7 +95,7 @@ impl Iterator for Parser {
     }
 }
 
-fn line_to_command(line: &str) -> Option<Command> {
+pub(crate) fn line_to_command(line: &str) -> Option<Command> {
     // Remove comments
     let line = if let Some((non_comment, _comment)) = line.split_once("//") {
         non_comment
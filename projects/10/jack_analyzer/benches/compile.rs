@@ -0,0 +1,34 @@
+// Compiles a large generated class, `cargo bench` prints how long that takes.
+use std::time::{Duration, Instant};
+
+// a subroutine for every 50 statements, with lets, ifs, whiles, calls and strings mixed
+fn generated_class(statements: usize) -> String {
+    let mut source = String::from("class Big {\n    field int x, y;\n    static Array table;\n");
+    for subroutine in 0..statements / 50 {
+        source.push_str(&format!("    method int f{}(int a, int b) {{\n        var int i, sum;\n", subroutine));
+        for statement in 0..50 {
+            source.push_str(&match statement % 5 {
+                0 => format!("        let sum = sum + (a * {}) - (b / 3);\n", statement),
+                1 => "        if (~(a < b) & (x = y)) { let x = x + 1; } else { let y = y - 1; }\n".to_string(),
+                2 => "        while (i < 10) { let table[i] = i + sum; let i = i + 1; }\n".to_string(),
+                3 => "        do Output.printString(\"generated statement\");\n".to_string(),
+                _ => format!("        let sum = f{}(sum, -a);\n", subroutine)
+            });
+        }
+        source.push_str("        return sum;\n    }\n");
+    }
+    source.push_str("}\n");
+    source
+}
+
+fn main() {
+    let source = generated_class(5000);
+    let runs = 20;
+    let mut times: Vec<Duration> = (0..runs).map(|_| {
+        let start = Instant::now();
+        jack_analyzer::compile_to_vm(&source, Some("Big")).unwrap();
+        start.elapsed()
+    }).collect();
+    times.sort();
+    println!("compile 5000 statements: median {:?}, fastest {:?} over {} runs", times[runs / 2], times[0], runs);
+}
@@ -0,0 +1,584 @@
+use std::collections::HashSet;
+use crate::ast::*;
+use crate::error::SemanticError;
+use crate::diagnostics::{Diagnostics, Limits, Lint};
+use crate::metrics::SubroutineMetrics;
+use crate::signatures::{Signatures, OS_CLASSES};
+use crate::utils::Scopes;
+
+// A non-void subroutine has to return on every path, the VM function would otherwise run into the next one
+pub fn check_returns(class_name: &str, subroutine_dec: &SubroutineDec) -> Option<SemanticError> {
+    if let SubroutineReturnType::Void = subroutine_dec.return_type {
+        return None;
+    }
+    let reason = missing_return(&subroutine_dec.body.statements)?;
+    let message = format!("not every path through {}.{} returns a value", class_name, subroutine_dec.name.0);
+    Some(SemanticError::new(&message, subroutine_dec.body.end).with_note(reason))
+}
+
+// control can reach the end of the statements
+pub fn falls_through(statements: &Statements) -> bool {
+    missing_return(statements).is_some()
+}
+
+// why control can reach the end of the statements, none when every path returns
+fn missing_return(statements: &Statements) -> Option<&'static str> {
+    if statements.0.iter().any(always_returns) {
+        return None;
+    }
+    Some(match statements.0.last() {
+        None => "the body has no return statement",
+        Some(Statement::If(statement)) => match &statement.else_statements {
+            None => "the last if statement has no else branch",
+            Some(_) if missing_return(&statement.if_statements).is_some() => "the if branch can end without returning",
+            Some(_) => "the else branch can end without returning"
+        },
+        Some(Statement::While(_)) => "the last while loop may not run at all",
+        Some(_) => "the last statement is not a return"
+    })
+}
+
+// a while loop may not run, so only an if with both branches returning counts,
+// and `while (true)` without a break, which only a return can leave
+fn always_returns(statement: &Statement) -> bool {
+    match statement {
+        Statement::Return(..) => true,
+        Statement::While(statement) => is_true(&statement.expression) && !breaks(&statement.statements),
+        Statement::If(statement) => match &statement.else_statements {
+            Some(else_statements) => {
+                missing_return(&statement.if_statements).is_none() && missing_return(else_statements).is_none()
+            },
+            None => false
+        },
+        _ => false
+    }
+}
+
+// a break of the loop the statements are the body of, not of a loop inside it
+fn breaks(statements: &Statements) -> bool {
+    statements.iter().any(|statement| match statement {
+        Statement::Break(_) => true,
+        Statement::If(statement) => breaks(&statement.if_statements) || statement.else_statements.as_ref().is_some_and(breaks),
+        _ => false
+    })
+}
+
+// Statements after a return, a break or a continue, or after a statement that always returns, never run.
+// An unreachable statement is reported but not looked into.
+pub fn unreachable_statements(statements: &Statements, diagnostics: &mut Diagnostics) {
+    let mut ended_by: Option<String> = None;
+    for statement in statements.iter() {
+        if let Some(note) = &ended_by {
+            diagnostics.warn(Lint::Unreachable, SemanticError::new("unreachable statement", statement.span()).with_note(note));
+            continue;
+        }
+        match statement {
+            Statement::If(statement) => {
+                unreachable_statements(&statement.if_statements, diagnostics);
+                if let Some(else_statements) = &statement.else_statements {
+                    unreachable_statements(else_statements, diagnostics);
+                }
+            },
+            Statement::While(statement) => unreachable_statements(&statement.statements, diagnostics),
+            _ => {}
+        }
+        if always_returns(statement) {
+            ended_by = Some(match statement {
+                Statement::If(_) => format!("the if statement at {} returns in both branches", statement.span()),
+                Statement::While(_) => format!("the while (true) loop at {} can only be left by a return", statement.span()),
+                _ => format!("it follows the return at {}", statement.span())
+            });
+        }
+        match statement {
+            Statement::Break(span) => ended_by = Some(format!("it follows the break at {}", span)),
+            Statement::Continue(span) => ended_by = Some(format!("it follows the continue at {}", span)),
+            _ => {}
+        }
+    }
+}
+
+// the condition is the constant `true`
+fn is_true(expression: &Expression) -> bool {
+    constant_condition(expression) == Some(true)
+}
+
+// the value of a condition that is `true` or `false` as written
+pub fn constant_condition(expression: &Expression) -> Option<bool> {
+    if !expression.extra_op_terms.is_empty() {
+        return None;
+    }
+    match expression.term {
+        Term::KeywordConstant(KeywordConstant::True, _) => Some(true),
+        Term::KeywordConstant(KeywordConstant::False, _) => Some(false),
+        _ => None
+    }
+}
+
+// Fields and statics are private to their class, so one that none of its subroutines
+// reads or writes is never used. Every field still takes a word in every object.
+pub fn unused_class_vars(class: &Class, diagnostics: &mut Diagnostics) {
+    let mut used = HashSet::new();
+    for subroutine_dec in class.subroutine_decs.iter() {
+        let mut names = HashSet::new();
+        statement_names(&subroutine_dec.body.statements, &mut names);
+        // parameters and locals hide the class variables of the same name
+        let declared: HashSet<&str> = subroutine_dec.parameters.iter().map(|parameter| parameter.1.as_str())
+            .chain(subroutine_dec.body.var_decs.iter().flat_map(|var_dec| var_dec.var_names().map(VarName::as_str)))
+            .collect();
+        used.extend(names.into_iter().filter(|name| !declared.contains(name)));
+    }
+    // what an initializer reads
+    for initializer in class.class_var_decs.iter().filter_map(|class_var_dec| class_var_dec.initializer.as_ref()) {
+        expression_names(initializer, &mut used);
+    }
+    for class_var_dec in class.class_var_decs.iter() {
+        for var_name in class_var_dec.var_names() {
+            if !used.contains(var_name.as_str()) {
+                let kind = match class_var_dec.dec_type {
+                    ClassVarDecType::Field => "field",
+                    ClassVarDecType::Static => "static",
+                    // other classes may use it, which only the whole project shows
+                    ClassVarDecType::Const => continue
+                };
+                let message = format!("{} {} of {} is never used", kind, var_name.0, class.name.0);
+                diagnostics.warn(Lint::Unused, SemanticError::new(&message, var_name.1));
+            }
+        }
+    }
+}
+
+// the names the statements read or write, `-O` leaves out the locals that are not among them
+pub fn referenced_names(statements: &Statements) -> HashSet<&str> {
+    let mut names = HashSet::new();
+    statement_names(statements, &mut names);
+    names
+}
+
+// the names of the variables used by the statements
+fn statement_names<'a>(statements: &'a Statements, names: &mut HashSet<&'a str>) {
+    for statement in statements.iter() {
+        match statement {
+            Statement::Let(statement) => {
+                names.insert(statement.var_name.as_str());
+                if let Some(expression) = &statement.index_expression {
+                    expression_names(expression, names);
+                }
+                expression_names(&statement.expression, names);
+            },
+            Statement::If(statement) => {
+                expression_names(&statement.expression, names);
+                statement_names(&statement.if_statements, names);
+                if let Some(else_statements) = &statement.else_statements {
+                    statement_names(else_statements, names);
+                }
+            },
+            Statement::While(statement) => {
+                expression_names(&statement.expression, names);
+                statement_names(&statement.statements, names);
+            },
+            Statement::Do(subroutine_call, _) => call_names(subroutine_call, names),
+            Statement::Return(expression, _) => {
+                if let Some(expression) = expression {
+                    expression_names(expression, names);
+                }
+            },
+            Statement::Break(_) | Statement::Continue(_) => {}
+        }
+    }
+}
+
+fn expression_names<'a>(expression: &'a Expression, names: &mut HashSet<&'a str>) {
+    for term in expression.terms() {
+        term_names(term, names);
+    }
+}
+
+fn term_names<'a>(term: &'a Term, names: &mut HashSet<&'a str>) {
+    match term {
+        Term::VarName(name, _) => {
+            names.insert(name);
+        },
+        Term::IndexVar(name, expression, _) => {
+            names.insert(name);
+            expression_names(expression, names);
+        },
+        Term::Call(subroutine_call) => call_names(subroutine_call, names),
+        Term::Expression(expression) | Term::Nested(expression) => expression_names(expression, names),
+        Term::WithUnary(_, term) => term_names(term, names),
+        Term::IntegerConstant(_) | Term::StringConstant(..) | Term::KeywordConstant(..) | Term::ClassConstant(..) => {}
+    }
+}
+
+// `ball.move(dx)` uses ball and dx
+fn call_names<'a>(subroutine_call: &'a SubroutineCall, names: &mut HashSet<&'a str>) {
+    if let Some(caller) = &subroutine_call.caller {
+        names.insert(caller);
+    }
+    for expression in subroutine_call.expression_list.iter() {
+        expression_names(expression, names);
+    }
+}
+
+// The subroutines a subroutine calls: `draw()` goes to its own class, `ball.move()` to the class
+// ball is declared as, and `Ball.new()` to Ball
+pub fn called_subroutines(class: &Class, subroutine_dec: &SubroutineDec) -> Vec<(String, String)> {
+    let mut scopes = Scopes::for_class(class);
+    scopes.push_subroutine(&class.name.0, subroutine_dec);
+    let mut calls = Vec::new();
+    statement_calls(&subroutine_dec.body.statements, &mut calls);
+    calls.into_iter().map(|subroutine_call| {
+        let class_name = match &subroutine_call.caller {
+            None => class.name.0.clone(),
+            Some(caller) => match scopes.find_by(caller) {
+                Some(symbol) => symbol.var_type().as_str().to_string(),
+                None => caller.clone()
+            }
+        };
+        (class_name, subroutine_call.subroutine_name.0.clone())
+    }).collect()
+}
+
+fn statement_calls<'a>(statements: &'a Statements, calls: &mut Vec<&'a SubroutineCall>) {
+    for statement in statements.iter() {
+        match statement {
+            Statement::Let(statement) => {
+                if let Some(expression) = &statement.index_expression {
+                    expression_calls(expression, calls);
+                }
+                expression_calls(&statement.expression, calls);
+            },
+            Statement::If(statement) => {
+                expression_calls(&statement.expression, calls);
+                statement_calls(&statement.if_statements, calls);
+                if let Some(else_statements) = &statement.else_statements {
+                    statement_calls(else_statements, calls);
+                }
+            },
+            Statement::While(statement) => {
+                expression_calls(&statement.expression, calls);
+                statement_calls(&statement.statements, calls);
+            },
+            Statement::Do(subroutine_call, _) => call_calls(subroutine_call, calls),
+            Statement::Return(expression, _) => {
+                if let Some(expression) = expression {
+                    expression_calls(expression, calls);
+                }
+            },
+            Statement::Break(_) | Statement::Continue(_) => {}
+        }
+    }
+}
+
+fn expression_calls<'a>(expression: &'a Expression, calls: &mut Vec<&'a SubroutineCall>) {
+    for term in expression.terms() {
+        term_calls(term, calls);
+    }
+}
+
+fn term_calls<'a>(term: &'a Term, calls: &mut Vec<&'a SubroutineCall>) {
+    match term {
+        Term::Call(subroutine_call) => call_calls(subroutine_call, calls),
+        Term::IndexVar(_, expression, _) => expression_calls(expression, calls),
+        Term::Expression(expression) | Term::Nested(expression) => expression_calls(expression, calls),
+        Term::WithUnary(_, term) => term_calls(term, calls),
+        Term::IntegerConstant(_) | Term::StringConstant(..) | Term::KeywordConstant(..) | Term::VarName(..) | Term::ClassConstant(..) => {}
+    }
+}
+
+// the call, and the calls in its arguments
+fn call_calls<'a>(subroutine_call: &'a SubroutineCall, calls: &mut Vec<&'a SubroutineCall>) {
+    calls.push(subroutine_call);
+    for expression in subroutine_call.expression_list.iter() {
+        expression_calls(expression, calls);
+    }
+}
+
+// Subroutines that no chain of calls from Main.main leads to never run; being called only by them
+// does not count. The classes that replace the OS are left out, the OS calls into them itself.
+pub fn uncalled_subroutines(class: &Class, signatures: &Signatures, diagnostics: &mut Diagnostics) {
+    let project_class = |class_name: &str| signatures.subroutines(class_name).iter().any(|signature| signature.declared.is_some());
+    if OS_CLASSES.contains(&class.name.as_str()) || !project_class("Main") || signatures.find("Main", "main").is_none() {
+        return;
+    }
+    let mut reached: HashSet<(&str, &str)> = HashSet::new();
+    let mut pending = vec![("Main", "main")];
+    for class_name in OS_CLASSES.into_iter().filter(|class_name| project_class(class_name)) {
+        pending.extend(signatures.subroutines(class_name).iter().map(|signature| (class_name, signature.name.as_str())));
+    }
+    while let Some(subroutine) = pending.pop() {
+        if !reached.insert(subroutine) {
+            continue;
+        }
+        if let Some(signature) = signatures.find(subroutine.0, subroutine.1) {
+            pending.extend(signature.calls.iter().map(|(class_name, name)| (class_name.as_str(), name.as_str())));
+        }
+    }
+    for subroutine_dec in class.subroutine_decs.iter() {
+        let name = subroutine_dec.name.as_str();
+        if reached.contains(&(class.name.as_str(), name)) {
+            continue;
+        }
+        let message = format!("{} {}.{} is never called", subroutine_dec.subroutine_type.as_str(), class.name.0, name);
+        let mut error = SemanticError::new(&message, subroutine_dec.span);
+        let called = signatures.class_names()
+            .flat_map(|class_name| signatures.subroutines(class_name))
+            .any(|signature| signature.calls.iter().any(|(class_name, called)| *class_name == class.name.0 && called == name));
+        if called {
+            error = error.with_note("it is only called from subroutines that are never called themselves");
+        }
+        diagnostics.warn(Lint::DeadCode, error);
+    }
+}
+
+// A project's own Math.jack or Memory.jack replaces the OS class, and the other classes, the OS
+// among them, still call it as documented. A subroutine it leaves out, or declares with another
+// number of parameters or as a method instead of a function, breaks those calls at run time.
+pub fn os_conformance(class: &Class, diagnostics: &mut Diagnostics) {
+    if !OS_CLASSES.contains(&class.name.as_str()) {
+        return;
+    }
+    let os = Signatures::with_os();
+    for documented in os.subroutines(class.name.as_str()) {
+        let note = format!("the Jack OS declares {}", documented.declaration());
+        let Some(subroutine_dec) = class.subroutine(&documented.name) else {
+            let message = format!("{}.{} of the Jack OS is missing", class.name.0, documented.name);
+            diagnostics.warn(Lint::OsConformance, SemanticError::new(&message, class.span).with_note(&note));
+            continue;
+        };
+        let function_name = format!("{}.{}", class.name.0, documented.name);
+        if subroutine_dec.subroutine_type != documented.kind {
+            let message = format!("{} is a {} where the Jack OS has a {}", function_name, subroutine_dec.subroutine_type.as_str(), documented.kind.as_str());
+            diagnostics.warn(Lint::OsConformance, SemanticError::new(&message, subroutine_dec.span).with_note(&note));
+        } else if subroutine_dec.parameters.len() != documented.parameters.len() {
+            let message = format!(
+                "{} takes {} parameters where the Jack OS takes {}",
+                function_name, subroutine_dec.parameters.len(), documented.parameters.len()
+            );
+            diagnostics.warn(Lint::OsConformance, SemanticError::new(&message, subroutine_dec.span).with_note(&note));
+        }
+    }
+}
+
+// `-W complexity`: a subroutine with more decisions or statements than the limits allow is hard to
+// follow and to test
+pub fn check_complexity(class_name: &str, subroutine_dec: &SubroutineDec, limits: Limits, diagnostics: &mut Diagnostics) {
+    let metrics = SubroutineMetrics::new(subroutine_dec);
+    let function_name = format!("{}.{}", class_name, metrics.name);
+    if metrics.complexity > limits.complexity {
+        let message = format!("{} has a complexity of {}, more than {}", function_name, metrics.complexity, limits.complexity);
+        let note = "that is 1 and one for each if and while, --max-complexity sets the limit";
+        diagnostics.warn(Lint::Complexity, SemanticError::new(&message, subroutine_dec.span).with_note(note));
+    }
+    if metrics.statements > limits.statements {
+        let message = format!("{} has {} statements, more than {}", function_name, metrics.statements, limits.statements);
+        let note = "--max-statements sets the limit";
+        diagnostics.warn(Lint::Complexity, SemanticError::new(&message, subroutine_dec.span).with_note(note));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(body: &str) -> Option<String> {
+        let content = format!("class A {{\n    function int f(int x) {{\n{}\n    }}\n}}", body);
+        let class = crate::parse_str(&content).unwrap();
+        check_returns("A", &class.subroutine_decs[0]).map(|error| error.to_string())
+    }
+
+    #[test]
+    fn if_without_else() {
+        assert_eq!(
+            check("        if (x) { return 1; }").unwrap(),
+            "4:5: not every path through A.f returns a value\n  note: the last if statement has no else branch"
+        );
+    }
+
+    #[test]
+    fn return_only_in_one_branch() {
+        assert_eq!(
+            check("        if (x) { return 1; } else { let x = 2; }").unwrap(),
+            "4:5: not every path through A.f returns a value\n  note: the else branch can end without returning"
+        );
+        assert_eq!(
+            check("        if (x) { if (x) { return 1; } } else { return 2; }").unwrap(),
+            "4:5: not every path through A.f returns a value\n  note: the if branch can end without returning"
+        );
+    }
+
+    #[test]
+    fn while_loops_may_not_run() {
+        assert_eq!(
+            check("        while (x) { return 1; }").unwrap(),
+            "4:5: not every path through A.f returns a value\n  note: the last while loop may not run at all"
+        );
+        assert!(check("").unwrap().ends_with("note: the body has no return statement"));
+    }
+
+    #[test]
+    fn every_path_returns() {
+        assert_eq!(check("        if (x) { return 1; } else { if (x) { return 2; } else { return 3; } }"), None);
+        assert_eq!(check("        while (x) { let x = 1; }\n        return 0;"), None);
+        assert_eq!(check("        if (x) { return 1; }\n        return 0;"), None);
+        assert_eq!(check("        while (true) { if (x) { return 1; } }"), None);
+    }
+
+    fn unreachable(body: &str) -> Vec<String> {
+        let content = format!("class A {{\n    function int f(int x) {{\n{}\n    }}\n}}", body);
+        let class = crate::parse_str(&content).unwrap();
+        let mut diagnostics = Diagnostics::default();
+        unreachable_statements(&class.subroutine_decs[0].body.statements, &mut diagnostics);
+        diagnostics.finish().unwrap().iter().map(|warning| warning.error.to_string()).collect()
+    }
+
+    #[test]
+    fn statements_after_return() {
+        assert_eq!(unreachable("        return 1;\n        let x = 2;\n        do A.f(x);"), vec![
+            "4:9: unreachable statement\n  note: it follows the return at 3:9",
+            "5:9: unreachable statement\n  note: it follows the return at 3:9"
+        ]);
+        assert_eq!(unreachable("        while (x) { return 1; let x = 2; }\n        return 0;"), vec![
+            "3:31: unreachable statement\n  note: it follows the return at 3:21"
+        ]);
+        assert_eq!(unreachable("        if (x) { return 1; } else { return 2; }\n        return 0;"), vec![
+            "4:9: unreachable statement\n  note: the if statement at 3:9 returns in both branches"
+        ]);
+        assert_eq!(unreachable("        while (true) { let x = x + 1; }\n        if (x) { return 1; }"), vec![
+            "4:9: unreachable statement\n  note: the while (true) loop at 3:9 can only be left by a return"
+        ]);
+    }
+
+    #[test]
+    fn return_inside_if_leaves_the_rest_reachable() {
+        assert!(unreachable("        if (x) { return 1; }\n        while (x) { return 2; }\n        return 0;").is_empty());
+        assert!(unreachable("        while (x = true) { let x = 1; }\n        return 0;").is_empty());
+    }
+
+    #[test]
+    fn unused_fields_and_statics() {
+        let class = crate::parse_str("\
+class Ball {
+    field int x, y, size;
+    static int count;
+    static boolean unused;
+    constructor Ball new(int size) {
+        let x = 0;
+        let size = size;
+        let count = count + 1;
+        return this;
+    }
+    method void draw() {
+        do Screen.drawCircle(x, y, 1);
+        return;
+    }
+}").unwrap();
+        let mut diagnostics = Diagnostics::default();
+        unused_class_vars(&class, &mut diagnostics);
+        let warnings: Vec<String> = diagnostics.finish().unwrap().iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(warnings, vec![
+            "2:21: field size of Ball is never used [-W unused]",
+            "4:20: static unused of Ball is never used [-W unused]"
+        ]);
+    }
+
+    #[test]
+    fn subroutines_never_called_from_main() {
+        let classes = [
+            "class Main { function void main() { var Game game; let game = Game.new(); do game.run(); return; } }",
+            "\
+class Game {
+    constructor Game new() { return this; }
+    method void run() { do step(Math.max(1, tick())); return; }
+    method void step(int n) { return; }
+    function int tick() { return 1; }
+    method void unused() { do Game.orphan(); return; }
+    function void orphan() { return; }
+}",
+            // the OS calls into the classes that replace it
+            "class Math { function int max(int a, int b) { return a; } function int unused() { return 0; } }"
+        ].map(|source| crate::parse_str(source).unwrap());
+        let mut signatures = Signatures::with_os();
+        for class in classes.iter() {
+            signatures.add_class(class, &format!("{}.jack", class.name.0));
+        }
+        let mut diagnostics = Diagnostics::default();
+        for class in classes.iter() {
+            uncalled_subroutines(class, &signatures, &mut diagnostics);
+        }
+        let warnings: Vec<String> = diagnostics.finish().unwrap().iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(warnings, vec![
+            "6:17: method Game.unused is never called [-W dead-code]",
+            "7:19: function Game.orphan is never called [-W dead-code]\n  note: it is only called from subroutines that are never called themselves"
+        ]);
+
+        // without a Main.main there is nothing to start from
+        let mut diagnostics = Diagnostics::default();
+        uncalled_subroutines(&classes[1], &Signatures::with_os(), &mut diagnostics);
+        assert!(diagnostics.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn complexity_up_to_the_limits() {
+        let class = crate::parse_str("\
+class A {
+    function void trivial() { return; }
+    function void atLimit(int x) {
+        if (x > 0) { let x = 1; } else { while (x < 0) { let x = x + 1; } }
+        return;
+    }
+    function void over(int x) {
+        if (x > 0) { if (x > 1) { while (x < 0) { let x = 1; } } }
+        return;
+    }
+}").unwrap();
+        let limits = Limits { complexity: 3, statements: 5 };
+        let warnings = |subroutine: &str| {
+            let mut diagnostics = Diagnostics::new(crate::Lints::default().with(Lint::Complexity));
+            check_complexity("A", class.subroutine(subroutine).unwrap(), limits, &mut diagnostics);
+            diagnostics.finish().unwrap().iter().map(|warning| warning.to_string()).collect::<Vec<_>>()
+        };
+        assert!(warnings("trivial").is_empty());
+        assert_eq!(SubroutineMetrics::new(class.subroutine("atLimit").unwrap()), SubroutineMetrics { name: "atLimit".to_string(), complexity: 3, statements: 5 });
+        assert!(warnings("atLimit").is_empty());
+        assert_eq!(warnings("over"), vec![
+            "7:19: A.over has a complexity of 4, more than 3 [-W complexity]\n  note: that is 1 and one for each if and while, --max-complexity sets the limit"
+        ]);
+        let limits = Limits { statements: 4, ..limits };
+        let mut diagnostics = Diagnostics::new(crate::Lints::default().with(Lint::Complexity));
+        check_complexity("A", class.subroutine("atLimit").unwrap(), limits, &mut diagnostics);
+        assert_eq!(diagnostics.finish().unwrap()[0].error.message, "A.atLimit has 5 statements, more than 4");
+        // off by default
+        let mut diagnostics = Diagnostics::default();
+        check_complexity("A", class.subroutine("over").unwrap(), Limits { complexity: 1, statements: 1 }, &mut diagnostics);
+        assert!(diagnostics.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn os_classes_of_the_project_against_the_api() {
+        let math = crate::parse_str("\
+class Math {
+    function void init() { return; }
+    function int abs(int x) { return x; }
+    function int multiply(int x, int y, int z) { return 0; }
+    method int divide(int x, int y) { return 0; }
+    function int min(int x, int y) { return x; }
+    function int max(int x, int y) { return x; }
+    function int extra() { return 0; }
+}").unwrap();
+        let mut diagnostics = Diagnostics::default();
+        os_conformance(&math, &mut diagnostics);
+        let warnings: Vec<String> = diagnostics.finish().unwrap().iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(warnings, vec![
+            "4:18: Math.multiply takes 3 parameters where the Jack OS takes 2 [-W os-conformance]\n  note: the Jack OS declares function int multiply(int, int)",
+            "5:16: Math.divide is a method where the Jack OS has a function [-W os-conformance]\n  note: the Jack OS declares function int divide(int, int)",
+            "1:7: Math.sqrt of the Jack OS is missing [-W os-conformance]\n  note: the Jack OS declares function int sqrt(int)"
+        ]);
+
+        // other classes, and those that keep to the API
+        let mut diagnostics = Diagnostics::default();
+        os_conformance(&crate::parse_str("class Game { function int multiply() { return 0; } }").unwrap(), &mut diagnostics);
+        os_conformance(&crate::parse_str("class Sys { function void init() { return; } function void halt() { return; } \
+            function void error(int code) { return; } function void wait(int ms) { return; } }").unwrap(), &mut diagnostics);
+        assert!(diagnostics.finish().unwrap().is_empty());
+    }
+}
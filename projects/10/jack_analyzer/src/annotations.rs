@@ -0,0 +1,245 @@
+use crate::ast::*;
+use crate::parser::XML;
+use crate::utils::{Scopes, SymbolKind};
+
+// What an identifier of `--xml-annotations` stands for
+struct Annotation {
+    name: String,
+    category: &'static str,
+    // the index in its segment, for variables
+    index: Option<i16>,
+    declared: bool
+}
+
+// The course's XML with every identifier annotated, `<identifier category="field" index="2" usage="used"> x </identifier>`.
+// The identifiers are collected from the tree in the order the XML writer puts them out.
+pub fn annotated_xml(class: &Class) -> String {
+    let mut annotations = Vec::new();
+    Annotator::new(class, &mut annotations).class(class);
+    let mut annotations = annotations.into_iter();
+    let mut xml = String::new();
+    for line in XML::text(class).lines() {
+        let trimmed = line.trim_start();
+        match trimmed.strip_prefix("<identifier> ").and_then(|rest| rest.strip_suffix(" </identifier>")) {
+            Some(name) => {
+                let annotation = annotations.next().expect("an annotation for every identifier");
+                debug_assert_eq!(annotation.name, name);
+                let index = annotation.index.map(|index| format!(" index=\"{}\"", index)).unwrap_or_default();
+                let usage = if annotation.declared { "declared" } else { "used" };
+                xml.push_str(&format!(
+                    "{}<identifier category=\"{}\"{} usage=\"{}\"> {} </identifier>\n",
+                    &line[..line.len() - trimmed.len()], annotation.category, index, usage, name
+                ));
+            },
+            None => {
+                xml.push_str(line);
+                xml.push('\n');
+            }
+        }
+    }
+    xml
+}
+
+struct Annotator<'a> {
+    scopes: Scopes,
+    // the constants of `--ext-const`, which are not in the scopes
+    constants: Vec<String>,
+    annotations: &'a mut Vec<Annotation>
+}
+
+impl<'a> Annotator<'a> {
+    fn new(class: &Class, annotations: &'a mut Vec<Annotation>) -> Self {
+        let constants = class.constants().map(|(name, _)| name.to_string()).collect();
+        Annotator { scopes: Scopes::for_class(class), constants, annotations }
+    }
+
+    fn push(&mut self, name: &str, category: &'static str, index: Option<i16>, declared: bool) {
+        self.annotations.push(Annotation { name: name.to_string(), category, index, declared });
+    }
+
+    // a variable by its symbol, undefined ones are taken for locals
+    fn variable(&mut self, name: &str, declared: bool) {
+        let symbol = self.scopes.find_by(name);
+        if symbol.is_none() && self.constants.iter().any(|constant| constant == name) {
+            return self.push(name, "constant", None, declared);
+        }
+        let category = match symbol.map(|symbol| symbol.kind()) {
+            Some(SymbolKind::Static) => "static",
+            Some(SymbolKind::Field) => "field",
+            Some(SymbolKind::Argument) => "argument",
+            Some(SymbolKind::Local) | None => "var"
+        };
+        let index = symbol.map(|symbol| symbol.index());
+        self.push(name, category, index, declared);
+    }
+
+    fn var_type(&mut self, var_type: &Type) {
+        if let Type::ClassName(class_name) = var_type {
+            self.push(class_name, "class", None, false);
+        }
+    }
+
+    fn class(&mut self, class: &Class) {
+        self.push(&class.name.0, "class", None, true);
+        for class_var_dec in class.class_var_decs.iter() {
+            self.var_type(&class_var_dec.var_type);
+            for var_name in class_var_dec.var_names() {
+                self.variable(&var_name.0, true);
+            }
+            if let Some(initializer) = &class_var_dec.initializer {
+                self.expression(initializer);
+            }
+        }
+        for subroutine_dec in class.subroutine_decs.iter() {
+            self.scopes.push_subroutine(&class.name.0, subroutine_dec);
+            if let SubroutineReturnType::General(return_type) = &subroutine_dec.return_type {
+                self.var_type(return_type);
+            }
+            self.push(&subroutine_dec.name.0, "subroutine", None, true);
+            for Parameter(var_type, var_name) in subroutine_dec.parameters.iter() {
+                self.var_type(var_type);
+                self.variable(&var_name.0, true);
+            }
+            for var_dec in subroutine_dec.body.var_decs.iter() {
+                self.var_type(&var_dec.var_type);
+                for var_name in var_dec.var_names() {
+                    self.variable(&var_name.0, true);
+                }
+            }
+            self.statements(&subroutine_dec.body.statements);
+            self.scopes.pop_scope();
+        }
+    }
+
+    fn statements(&mut self, statements: &Statements) {
+        for statement in statements.iter() {
+            match statement {
+                Statement::Let(statement) => {
+                    self.variable(&statement.var_name.0, false);
+                    if let Some(expression) = &statement.index_expression {
+                        self.expression(expression);
+                    }
+                    self.expression(&statement.expression);
+                },
+                Statement::If(statement) => {
+                    self.expression(&statement.expression);
+                    self.statements(&statement.if_statements);
+                    if let Some(else_statements) = &statement.else_statements {
+                        self.statements(else_statements);
+                    }
+                },
+                Statement::While(statement) => {
+                    self.expression(&statement.expression);
+                    self.statements(&statement.statements);
+                },
+                Statement::Do(subroutine_call, _) => self.call(subroutine_call),
+                Statement::Return(expression, _) => {
+                    if let Some(expression) = expression {
+                        self.expression(expression);
+                    }
+                },
+                Statement::Break(_) | Statement::Continue(_) => {}
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        for term in expression.terms() {
+            self.term(term);
+        }
+    }
+
+    fn term(&mut self, term: &Term) {
+        match term {
+            Term::VarName(name, _) => self.variable(name, false),
+            Term::IndexVar(name, expression, _) => {
+                self.variable(name, false);
+                self.expression(expression);
+            },
+            Term::ClassConstant(class_name, name, _) => {
+                self.push(class_name, "class", None, false);
+                self.push(name, "constant", None, false);
+            },
+            Term::Call(subroutine_call) => self.call(subroutine_call),
+            Term::Expression(expression) | Term::Nested(expression) => self.expression(expression),
+            Term::WithUnary(_, term) => self.term(term),
+            Term::IntegerConstant(_) | Term::StringConstant(..) | Term::KeywordConstant(..) => {}
+        }
+    }
+
+    // `ball.move()` calls on a variable, `Ball.new()` on a class
+    fn call(&mut self, subroutine_call: &SubroutineCall) {
+        if let Some(caller) = &subroutine_call.caller {
+            if self.scopes.find_by(caller).is_some() {
+                self.variable(caller, false);
+            } else {
+                self.push(caller, "class", None, false);
+            }
+        }
+        self.push(&subroutine_call.subroutine_name.0, "subroutine", None, false);
+        for expression in subroutine_call.expression_list.iter() {
+            self.expression(expression);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_are_annotated() {
+        let class = crate::parse_str("\
+class Ball {
+    field int x;
+    static Ball last;
+    method void move(int dx) {
+        var Array steps;
+        let x = x + dx;
+        let steps[0] = Math.abs(dx);
+        do last.move(steps[0]);
+        return;
+    }
+}").unwrap();
+        let identifiers: Vec<String> = annotated_xml(&class).lines()
+            .filter(|line| line.contains("<identifier"))
+            .map(|line| line.trim().to_string())
+            .collect();
+        assert_eq!(identifiers, vec![
+            "<identifier category=\"class\" usage=\"declared\"> Ball </identifier>",
+            "<identifier category=\"field\" index=\"0\" usage=\"declared\"> x </identifier>",
+            "<identifier category=\"class\" usage=\"used\"> Ball </identifier>",
+            "<identifier category=\"static\" index=\"0\" usage=\"declared\"> last </identifier>",
+            "<identifier category=\"subroutine\" usage=\"declared\"> move </identifier>",
+            "<identifier category=\"argument\" index=\"1\" usage=\"declared\"> dx </identifier>",
+            "<identifier category=\"class\" usage=\"used\"> Array </identifier>",
+            "<identifier category=\"var\" index=\"0\" usage=\"declared\"> steps </identifier>",
+            "<identifier category=\"field\" index=\"0\" usage=\"used\"> x </identifier>",
+            "<identifier category=\"field\" index=\"0\" usage=\"used\"> x </identifier>",
+            "<identifier category=\"argument\" index=\"1\" usage=\"used\"> dx </identifier>",
+            "<identifier category=\"var\" index=\"0\" usage=\"used\"> steps </identifier>",
+            "<identifier category=\"class\" usage=\"used\"> Math </identifier>",
+            "<identifier category=\"subroutine\" usage=\"used\"> abs </identifier>",
+            "<identifier category=\"argument\" index=\"1\" usage=\"used\"> dx </identifier>",
+            "<identifier category=\"static\" index=\"0\" usage=\"used\"> last </identifier>",
+            "<identifier category=\"subroutine\" usage=\"used\"> move </identifier>",
+            "<identifier category=\"var\" index=\"0\" usage=\"used\"> steps </identifier>"
+        ]);
+    }
+
+    #[test]
+    fn only_identifiers_change() {
+        for source in [include_str!("../../Square/Square.jack"), include_str!("../../ArrayTest/Main.jack")] {
+            let class = crate::parse_str(source).unwrap();
+            let plain = XML::text(&class);
+            let annotated = annotated_xml(&class);
+            assert_eq!(plain.lines().count(), annotated.lines().count());
+            for (plain, annotated) in plain.lines().zip(annotated.lines()) {
+                if plain != annotated {
+                    assert!(plain.contains("<identifier> "), "{}", plain);
+                    assert!(annotated.ends_with(&plain[plain.find("> ").unwrap()..]), "{}", annotated);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,646 @@
+//! The syntax tree of a Jack class, as produced by [`parse_str`](crate::parse_str)
+//! and [`parse_class`](crate::parse_class).
+//!
+//! Walking the tree to list every subroutine call of a class:
+//!
+//! ```
+//! use jack_analyzer::ast::{Statement, Statements, Term};
+//!
+//! fn calls(statements: &Statements, found: &mut Vec<String>) {
+//!     for statement in statements.iter() {
+//!         match statement {
+//!             Statement::Do(call, _) => found.push(call.subroutine_name.as_str().to_string()),
+//!             Statement::If(statement) => {
+//!                 calls(&statement.if_statements, found);
+//!                 if let Some(else_statements) = &statement.else_statements {
+//!                     calls(else_statements, found);
+//!                 }
+//!             },
+//!             Statement::While(statement) => calls(&statement.statements, found),
+//!             Statement::Let(statement) => {
+//!                 for term in statement.expression.terms() {
+//!                     if let Term::Call(call) = term {
+//!                         found.push(call.subroutine_name.as_str().to_string());
+//!                     }
+//!                 }
+//!             },
+//!             Statement::Return(..) | Statement::Break(_) | Statement::Continue(_) => {}
+//!         }
+//!     }
+//! }
+//!
+//! let class = jack_analyzer::parse_str("
+//!     class Main {
+//!         function void main() {
+//!             var int x;
+//!             let x = Math.max(1, 2);
+//!             while (x > 0) {
+//!                 do Output.printInt(x);
+//!                 let x = x - 1;
+//!             }
+//!             return;
+//!         }
+//!     }
+//! ").unwrap();
+//!
+//! let mut found = Vec::new();
+//! calls(&class.subroutine("main").unwrap().body.statements, &mut found);
+//! assert_eq!(found, vec!["max", "printInt"]);
+//! ```
+
+use crate::tokenizer::Span;
+
+// Program structure
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub name: ClassName,
+    pub class_var_decs: Vec<ClassVarDec>,
+    pub subroutine_decs: Vec<SubroutineDec>,
+    // the text of the `/** */` comment right before the declaration, for `doc`
+    pub doc: Option<String>,
+    // position of the name
+    pub span: Span
+}
+
+// the function `--ext-field-init` makes of the static initializers of a class
+pub const STATIC_INITIALIZER: &str = "init_statics";
+
+impl Class {
+    pub fn subroutine(&self, name: &str) -> Option<&SubroutineDec> {
+        self.subroutine_decs.iter().find(|s| s.name.as_str() == name)
+    }
+
+    // `let count = 0;` for each `field int count = 0;`, or for the statics, at the name of the field
+    pub fn initializers(&self, dec_type: ClassVarDecType) -> Vec<Statement> {
+        self.class_var_decs.iter()
+            .filter(|class_var_dec| class_var_dec.dec_type == dec_type)
+            .filter_map(|class_var_dec| {
+                let expression = class_var_dec.initializer.clone()?;
+                let var_name = class_var_dec.var_name.clone();
+                Some(Statement::Let(LetStatement { span: var_name.1, var_name, index_expression: None, expression }))
+            })
+            .collect()
+    }
+
+    // the constants of `--ext-const` and their values
+    pub fn constants(&self) -> impl Iterator<Item = (&str, &Term)> {
+        self.class_var_decs.iter()
+            .filter(|class_var_dec| class_var_dec.dec_type == ClassVarDecType::Const)
+            .filter_map(|class_var_dec| Some((class_var_dec.var_name.as_str(), &class_var_dec.initializer.as_ref()?.term)))
+    }
+
+    // `function void init_statics()` setting the statics that have an initializer, if any do
+    pub fn static_initializer(&self) -> Option<SubroutineDec> {
+        let mut statements = self.initializers(ClassVarDecType::Static);
+        let span = statements.first()?.span();
+        statements.push(Statement::Return(None, span));
+        Some(SubroutineDec {
+            subroutine_type: SubroutineType::Function,
+            return_type: SubroutineReturnType::Void,
+            name: SubroutineName(STATIC_INITIALIZER.to_string()),
+            parameters: Vec::new(),
+            body: SubroutineBody { var_decs: Vec::new(), statements: Statements(statements), end: span },
+            span,
+            doc: None
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClassVarDecType {
+    Static,
+    Field,
+    // `--ext-const`
+    Const
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassVarDec {
+    pub dec_type: ClassVarDecType,
+    pub var_type: Type,
+    pub var_name: VarName,
+    pub extra_var_names: Vec<VarName>,
+    // `--ext-field-init`: `field int count = 0;`, only for a declaration of one name, and the value
+    // of a constant
+    pub initializer: Option<Expression>,
+    pub doc: Option<String>
+}
+
+impl ClassVarDec {
+    // `field int x, y;` declares x and y
+    pub fn var_names(&self) -> impl Iterator<Item = &VarName> {
+        std::iter::once(&self.var_name).chain(self.extra_var_names.iter())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Char,
+    Boolean,
+    ClassName(String)
+}
+
+impl Type {
+    // as written in the source
+    pub fn as_str(&self) -> &str {
+        match self {
+            Type::Int => "int",
+            Type::Char => "char",
+            Type::Boolean => "boolean",
+            Type::ClassName(v) => v
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubroutineType {
+    Constructor,
+    Function,
+    Method
+}
+
+impl SubroutineType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            SubroutineType::Constructor => "constructor",
+            SubroutineType::Function => "function",
+            SubroutineType::Method => "method"
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubroutineReturnType {
+    Void,
+    General(Type)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubroutineDec {
+    pub subroutine_type: SubroutineType,
+    pub return_type: SubroutineReturnType,
+    pub name: SubroutineName,
+    pub parameters: Vec<Parameter>,
+    pub body: SubroutineBody,
+    // position of the name
+    pub span: Span,
+    pub doc: Option<String>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter(pub Type, pub VarName);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubroutineBody {
+    pub var_decs: Vec<VarDec>,
+    pub statements: Statements,
+    // position of the closing brace
+    pub end: Span
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarDec {
+    pub var_type: Type,
+    pub var_name: VarName,
+    pub extra_var_names: Vec<VarName>
+}
+
+impl VarDec {
+    pub fn var_names(&self) -> impl Iterator<Item = &VarName> {
+        std::iter::once(&self.var_name).chain(self.extra_var_names.iter())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassName(pub String);
+
+impl ClassName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubroutineName(pub String);
+
+impl SubroutineName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+// the span is the position of the name
+pub struct VarName(pub String, pub Span);
+
+impl VarName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+// Statements
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statements(pub Vec<Statement>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Let(LetStatement),
+    If(Box<IfStatement>),
+    While(Box<WhileStatement>),
+    // the span is the position of `do`
+    Do(SubroutineCall, Span),
+    // the span is the position of `return`
+    Return(Option<Expression>, Span),
+    // `--ext-break`, the span is the position of the keyword
+    Break(Span),
+    Continue(Span)
+}
+
+impl Statement {
+    // where the statement starts, at its keyword
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Let(statement) => statement.span,
+            Statement::If(statement) => statement.span,
+            Statement::While(statement) => statement.span,
+            Statement::Do(_, span) | Statement::Return(_, span) | Statement::Break(span) | Statement::Continue(span) => *span
+        }
+    }
+}
+
+impl Statements {
+    pub fn iter(&self) -> std::slice::Iter<'_, Statement> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LetStatement {
+    pub var_name: VarName,
+    // position of `let`, the variable name has its own
+    pub span: Span,
+    pub index_expression: Option<Expression>,
+    pub expression: Expression
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfStatement {
+    pub expression: Expression,
+    pub if_statements: Statements,
+    pub else_statements: Option<Statements>,
+    // position of `if`
+    pub span: Span
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WhileStatement {
+    pub expression: Expression,
+    pub statements: Statements,
+    // position of `while`, or of `for`
+    pub span: Span,
+    // the last statement is the increment of a for loop, where `continue` goes
+    pub increment: bool
+}
+
+// Expressions
+
+#[derive(Debug, Clone, PartialEq)]
+// the span is the position of the operator
+pub struct OpTerm(pub Op, pub Term, pub Span);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub term: Term,
+    pub extra_op_terms: Vec<OpTerm>
+}
+
+impl Expression {
+    // all terms in source order, `a + b * c` gives a, b and c
+    pub fn terms(&self) -> impl Iterator<Item = &Term> {
+        std::iter::once(&self.term).chain(self.extra_op_terms.iter().map(|op_term| &op_term.1))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    // 32768 is i16::MIN, it only appears as `-32768` or `-(32768)`
+    IntegerConstant(i16),
+    StringConstant(String, Span),
+    KeywordConstant(KeywordConstant, Span),
+    // the span is the position of the name
+    VarName(String, Span),
+    IndexVar(String, Box<Expression>, Span),
+    // `Ball.MAX_BALLS` of `--ext-const`, the class and the name, the span is the position of the class
+    ClassConstant(String, String, Span),
+    Call(SubroutineCall),
+    Expression(Box<Expression>),
+    // an operand grouped by `--precedence`, like parentheses that are not in the source
+    Nested(Box<Expression>),
+    WithUnary(UnaryOp, Box<Term>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubroutineCall {
+    pub caller: Option<String>,
+    pub subroutine_name: SubroutineName,
+    pub expression_list: Vec<Expression>,
+    // position of the first name
+    pub span: Span
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeywordConstant {
+    True,
+    False,
+    Null,
+    This
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnaryOp {
+    Negative,
+    Not
+}
+
+impl UnaryOp {
+    pub const ALL: [UnaryOp; 2] = [UnaryOp::Negative, UnaryOp::Not];
+
+    pub fn from_symbol(symbol: char) -> Option<UnaryOp> {
+        UnaryOp::ALL.into_iter().find(|op| op.symbol() == symbol)
+    }
+
+    pub fn symbol(&self) -> char {
+        match self {
+            UnaryOp::Negative => '-',
+            UnaryOp::Not => '~'
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    And,
+    Or,
+    Lt,
+    Gt,
+    Eq
+}
+
+// the parser, the back ends and the formatter all get the operator symbols from here
+impl Op {
+    pub const ALL: [Op; 9] = [Op::Plus, Op::Minus, Op::Multiply, Op::Divide, Op::And, Op::Or, Op::Lt, Op::Gt, Op::Eq];
+
+    pub fn from_symbol(symbol: char) -> Option<Op> {
+        Op::ALL.into_iter().find(|op| op.symbol() == symbol)
+    }
+
+    pub fn symbol(&self) -> char {
+        match self {
+            Op::Plus => '+',
+            Op::Minus => '-',
+            Op::Multiply => '*',
+            Op::Divide => '/',
+            Op::And => '&',
+            Op::Or => '|',
+            Op::Lt => '<',
+            Op::Gt => '>',
+            Op::Eq => '='
+        }
+    }
+}
+
+// Walking the tree
+
+/// A node of the tree for [`walk`]. Parentheses are left out, the tree already groups what they do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Node<'a> {
+    Class(&'a Class),
+    ClassVarDec(&'a ClassVarDec),
+    SubroutineDec(&'a SubroutineDec),
+    VarDec(&'a VarDec),
+    Statement(&'a Statement),
+    // the statements after `else`
+    Else(&'a Statements),
+    // the expression up to its nth operator, which is applied last: `a + b * c` is `(a + b) * c`
+    BinaryOp(&'a Expression, usize),
+    Term(&'a Term)
+}
+
+impl<'a> Node<'a> {
+    // the node of the whole expression, its last operator or its only term
+    pub fn expression(expression: &'a Expression) -> Self {
+        Node::operand(expression, expression.extra_op_terms.len())
+    }
+
+    // `(x)` is the node of x
+    pub fn term(term: &'a Term) -> Self {
+        match term {
+            Term::Expression(expression) | Term::Nested(expression) => Node::expression(expression),
+            term => Node::Term(term)
+        }
+    }
+
+    // the expression up to its nth operator, a loop through the parentheses of `((x))`
+    fn operand(mut expression: &'a Expression, mut operators: usize) -> Self {
+        loop {
+            if operators > 0 {
+                return Node::BinaryOp(expression, operators);
+            }
+            match &expression.term {
+                Term::Expression(inner) | Term::Nested(inner) => {
+                    expression = inner;
+                    operators = inner.extra_op_terms.len();
+                },
+                term => return Node::Term(term)
+            }
+        }
+    }
+
+    // in source order
+    pub fn children(&self) -> Vec<Node<'a>> {
+        let expressions = |expressions: &'a [Expression]| expressions.iter().map(Node::expression).collect();
+        let statements = |statements: &'a Statements| statements.iter().map(Node::Statement);
+        match *self {
+            Node::Class(class) => class.class_var_decs.iter().map(Node::ClassVarDec)
+                .chain(class.subroutine_decs.iter().map(Node::SubroutineDec))
+                .collect(),
+            Node::ClassVarDec(class_var_dec) => class_var_dec.initializer.iter().map(Node::expression).collect(),
+            Node::SubroutineDec(subroutine_dec) => subroutine_dec.body.var_decs.iter().map(Node::VarDec)
+                .chain(statements(&subroutine_dec.body.statements))
+                .collect(),
+            Node::VarDec(_) => Vec::new(),
+            Node::Statement(statement) => match statement {
+                Statement::Let(statement) => statement.index_expression.iter()
+                    .chain(std::iter::once(&statement.expression))
+                    .map(Node::expression)
+                    .collect(),
+                Statement::If(statement) => std::iter::once(Node::expression(&statement.expression))
+                    .chain(statements(&statement.if_statements))
+                    .chain(statement.else_statements.iter().map(Node::Else))
+                    .collect(),
+                Statement::While(statement) => std::iter::once(Node::expression(&statement.expression))
+                    .chain(statements(&statement.statements))
+                    .collect(),
+                Statement::Do(subroutine_call, _) => expressions(&subroutine_call.expression_list),
+                Statement::Return(expression, _) => expression.iter().map(Node::expression).collect(),
+                Statement::Break(_) | Statement::Continue(_) => Vec::new()
+            },
+            Node::Else(else_statements) => statements(else_statements).collect(),
+            Node::BinaryOp(expression, operators) => {
+                let left = Node::operand(expression, operators - 1);
+                vec![left, Node::term(&expression.extra_op_terms[operators - 1].1)]
+            },
+            Node::Term(term) => match term {
+                Term::IndexVar(_, expression, _) | Term::Expression(expression) | Term::Nested(expression) => vec![Node::expression(expression)],
+                Term::Call(subroutine_call) => expressions(&subroutine_call.expression_list),
+                Term::WithUnary(_, term) => vec![Node::term(term)],
+                Term::IntegerConstant(_) | Term::StringConstant(..) | Term::KeywordConstant(..) | Term::VarName(..) | Term::ClassConstant(..) => Vec::new()
+            }
+        }
+    }
+
+    // where the node is in the source, an else and integer constants and unary operators keep
+    // no position
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            Node::Class(class) => Some(class.span),
+            Node::Else(_) => None,
+            Node::ClassVarDec(class_var_dec) => Some(class_var_dec.var_name.1),
+            Node::SubroutineDec(subroutine_dec) => Some(subroutine_dec.span),
+            Node::VarDec(var_dec) => Some(var_dec.var_name.1),
+            Node::Statement(statement) => Some(statement.span()),
+            Node::BinaryOp(expression, operators) => Some(expression.extra_op_terms[operators - 1].2),
+            Node::Term(term) => match term {
+                Term::StringConstant(_, span) | Term::KeywordConstant(_, span) | Term::VarName(_, span)
+                    | Term::IndexVar(_, _, span) | Term::ClassConstant(_, _, span) => Some(*span),
+                Term::Call(subroutine_call) => Some(subroutine_call.span),
+                Term::IntegerConstant(_) | Term::Expression(_) | Term::Nested(_) | Term::WithUnary(..) => None
+            }
+        }
+    }
+}
+
+/// What [`walk`] calls for each node, with its depth below the root.
+pub trait Visitor<'a> {
+    /// Before the children of the node, which are skipped when it returns false.
+    fn enter(&mut self, _node: Node<'a>, _depth: usize) -> bool {
+        true
+    }
+
+    /// After the children of the node.
+    fn leave(&mut self, _node: Node<'a>, _depth: usize) {}
+}
+
+/// Visits the node and everything below it in source order. It keeps a worklist rather than
+/// recursing, so a chain of thousands of operators is no deeper on the stack than a single one.
+///
+/// ```
+/// use jack_analyzer::ast::{walk, Node, Visitor};
+///
+/// #[derive(Default)]
+/// struct Deepest(usize);
+///
+/// impl<'a> Visitor<'a> for Deepest {
+///     fn enter(&mut self, _node: Node<'a>, depth: usize) -> bool {
+///         self.0 = self.0.max(depth);
+///         true
+///     }
+/// }
+///
+/// let class = jack_analyzer::parse_str("class Main { function int f() { return 1 + 2 + 3; } }").unwrap();
+/// let mut deepest = Deepest::default();
+/// walk(Node::Class(&class), &mut deepest);
+/// // the class, f, return, the second +, the first + and 1
+/// assert_eq!(deepest.0, 5);
+/// ```
+pub fn walk<'a>(root: Node<'a>, visitor: &mut impl Visitor<'a>) {
+    // each node is on the list twice, to be entered and then, after its children, left
+    let mut work = vec![(root, 0, false)];
+    while let Some((node, depth, entered)) = work.pop() {
+        if entered {
+            visitor.leave(node, depth);
+            continue;
+        }
+        work.push((node, depth, true));
+        if visitor.enter(node, depth) {
+            work.extend(node.children().into_iter().rev().map(|child| (child, depth + 1, false)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Counter {
+        entered: usize,
+        left: usize,
+        deepest: usize
+    }
+
+    impl<'a> Visitor<'a> for Counter {
+        fn enter(&mut self, node: Node<'a>, depth: usize) -> bool {
+            self.entered += 1;
+            self.deepest = self.deepest.max(depth);
+            // nothing below the calls
+            !matches!(node, Node::Term(Term::Call(_)))
+        }
+
+        fn leave(&mut self, _node: Node<'a>, _depth: usize) {
+            self.left += 1;
+        }
+    }
+
+    #[test]
+    fn walks_long_chains_without_recursing() {
+        // the parser takes the chain in a loop, the walk must not recurse through it either
+        let source = format!("class A {{ function int f() {{ return 1{}; }} }}", " + 1".repeat(100000));
+        let class = crate::parse_str(&source).unwrap();
+        let mut counter = Counter::default();
+        walk(Node::Class(&class), &mut counter);
+        assert_eq!((counter.entered, counter.left, counter.deepest), (3 + 2 * 100000 + 1, 3 + 2 * 100000 + 1, 100000 + 3));
+
+        let class = crate::parse_str("class A { function int f() { return ((g(1 + 2))); } }").unwrap();
+        let mut counter = Counter::default();
+        walk(Node::Class(&class), &mut counter);
+        assert_eq!((counter.entered, counter.deepest), (4, 3));
+    }
+
+    #[test]
+    fn op_symbols() {
+        let symbols: String = Op::ALL.iter().map(Op::symbol).collect();
+        assert_eq!(symbols, "+-*/&|<>=");
+        for op in Op::ALL {
+            assert_eq!(Op::from_symbol(op.symbol()), Some(op));
+        }
+        assert_eq!(Op::from_symbol('~'), None);
+        assert_eq!(Op::from_symbol('('), None);
+    }
+
+    #[test]
+    fn unary_op_symbols() {
+        let symbols: String = UnaryOp::ALL.iter().map(UnaryOp::symbol).collect();
+        assert_eq!(symbols, "-~");
+        for op in UnaryOp::ALL {
+            assert_eq!(UnaryOp::from_symbol(op.symbol()), Some(op));
+        }
+        assert_eq!(UnaryOp::from_symbol('+'), None);
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeSet;
+use crate::signatures::{Signature, Signatures};
+
+// The `--call-graph` of a project in Graphviz: a cluster for each class with a node for each of
+// its subroutines, and an edge for each subroutine it calls. Calls into the OS are dashed and
+// go to nodes outside the clusters, calls to subroutines that do not exist are left out.
+pub fn dot(signatures: &Signatures) -> String {
+    let class_names: Vec<&str> = signatures.class_names().filter(|class_name| in_project(signatures, class_name)).collect();
+    let mut dot = String::from("digraph calls {\n    node [shape = box];\n");
+    let mut edges = BTreeSet::new();
+    let mut os_nodes = BTreeSet::new();
+    for class_name in class_names.iter() {
+        dot.push_str(&format!("    subgraph \"cluster_{}\" {{\n        label = \"{}\";\n", class_name, class_name));
+        for signature in signatures.subroutines(class_name) {
+            let locals = if signature.locals == 1 { "local" } else { "locals" };
+            dot.push_str(&format!(
+                "        \"{}\" [label = \"{} {}\\n{} {}\"];\n",
+                node(signature), signature.kind.as_str(), node(signature), signature.locals, locals
+            ));
+            for (called_class, name) in signature.calls.iter() {
+                let Some(called) = signatures.find(called_class, name) else { continue };
+                let os = called.declared.is_none();
+                if os {
+                    os_nodes.insert(node(called));
+                }
+                edges.insert((node(signature), node(called), os));
+            }
+        }
+        dot.push_str("    }\n");
+    }
+    for os_node in os_nodes {
+        dot.push_str(&format!("    \"{}\" [style = dashed];\n", os_node));
+    }
+    for (from, to, os) in edges {
+        let style = if os { " [style = dashed]" } else { "" };
+        dot.push_str(&format!("    \"{}\" -> \"{}\"{};\n", from, to, style));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn in_project(signatures: &Signatures, class_name: &str) -> bool {
+    signatures.subroutines(class_name).iter().any(|signature| signature.declared.is_some())
+}
+
+fn node(signature: &Signature) -> String {
+    format!("{}.{}", signature.class_name, signature.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calls_between_two_classes() {
+        let classes = [
+            "\
+class Main {
+    function void main() {
+        var Ball ball;
+        let ball = Ball.new();
+        do ball.move(2);
+        do Output.printInt(ball.x());
+        do Main.missing();
+        return;
+    }
+}",
+            "\
+class Ball {
+    field int x;
+    constructor Ball new() { return this; }
+    method void move(int dx) { var int a, b; let x = x + dx; do draw(); return; }
+    method void draw() { do Screen.drawCircle(x, 0, 5); do Screen.drawCircle(x, 0, 3); return; }
+    method int x() { return x; }
+}"
+        ].map(|source| crate::parse_str(source).unwrap());
+        let mut signatures = Signatures::with_os();
+        for class in classes.iter() {
+            signatures.add_class(class, &format!("{}.jack", class.name.0));
+        }
+        let dot = dot(&signatures);
+        let lines: Vec<&str> = dot.lines().map(str::trim).collect();
+        for line in [
+            "subgraph \"cluster_Ball\" {",
+            "\"Ball.move\" [label = \"method Ball.move\\n2 locals\"];",
+            "\"Main.main\" [label = \"function Main.main\\n1 local\"];",
+            "\"Main.main\" -> \"Ball.new\";",
+            // through the declared type of ball
+            "\"Main.main\" -> \"Ball.move\";",
+            "\"Ball.move\" -> \"Ball.draw\";",
+            "\"Output.printInt\" [style = dashed];",
+            "\"Main.main\" -> \"Output.printInt\" [style = dashed];"
+        ] {
+            assert!(lines.contains(&line), "{}\n{}", line, dot);
+        }
+        // once for both calls, and nothing for what does not exist or is not called
+        assert_eq!(lines.iter().filter(|line| line.starts_with("\"Ball.draw\" -> \"Screen.drawCircle\"")).count(), 1);
+        assert!(!dot.contains("Main.missing"));
+        assert!(!dot.contains("\"Ball.x\" ->") && !dot.contains("\"Ball.move\" -> \"Ball.new\""));
+        assert!(!dot.contains("cluster_Output"));
+    }
+}
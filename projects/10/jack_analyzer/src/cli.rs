@@ -0,0 +1,2243 @@
+// The command line: the files and directories to compile, what to write next to them, and how to
+// report on it. Everything that touches the file system, stdin and stdout is here, behind the `fs`
+// feature, so the rest of the crate builds for targets without them.
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::io::{self, Cursor, Read, Write};
+use std::sync::OnceLock;
+use std::time::Instant;
+use crate::ast::{Class, SubroutineType};
+use crate::manifest::{self, Manifest};
+use crate::render::{Color, MessageFormat, Renderer, Verbosity};
+use crate::signatures::Signatures;
+use crate::timings::{Phase, Timings};
+use crate::{call_graph, compare, doc, dump, emit, format_source, interface, parser, repl, testing, text, vm, vm_eval, CompileError, LanguageOptions, Lint, Stage, Tokens, Warning};
+use crate::parser::tokenize;
+
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    if config.selftest {
+        return selftest(&config);
+    }
+    if let Some(snippet) = &config.eval {
+        return eval(snippet, &config);
+    }
+    let result = compile_sources(&config);
+    config.report_timings();
+    result
+}
+
+// `--selftest`: the fixtures compiled into the binary against their goldens
+fn selftest(config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut failed = Vec::new();
+    for fixture in testing::FIXTURES.iter() {
+        match testing::check(fixture) {
+            Ok(()) => config.progress(&format!("Passed: {}", fixture.name)),
+            Err(error) => failed.push(error)
+        }
+    }
+    let summary = format!("{} passed, {} failed", testing::FIXTURES.len() - failed.len(), failed.len());
+    if failed.is_empty() {
+        config.status(&summary);
+        return Ok(());
+    }
+    failed.push(summary);
+    Err(failed.join("\n").into())
+}
+
+// `eval "2 + 3"` and `eval --statements "..."`: the value the snippet returns, after what it printed,
+// or with `--emit-vm` its VM code
+fn eval(snippet: &Snippet, config: &Config) -> Result<(), Box<dyn Error>> {
+    let (snippet, statements) = match snippet {
+        Snippet::Expression(expression) => (expression, false),
+        Snippet::Statements(statements) => (statements, true)
+    };
+    let source = repl::source(snippet, statements);
+    let renderer = Renderer::new("<eval>", &source, config.color, config.message_format);
+    let (vm, commands) = repl::compile(&source, config.options).map_err(|e| renderer.compile_error(&e))?;
+    if config.emit_vm {
+        return Ok(io::stdout().write_all(vm.as_bytes())?);
+    }
+    let outcome = vm_eval::call(&commands, repl::FUNCTION, &[])?;
+    let mut output = outcome.output;
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    Ok(io::stdout().write_all(format!("{}{}\n", output, outcome.value).as_bytes())?)
+}
+
+fn compile_sources(config: &Config) -> Result<(), Box<dyn Error>> {
+    let files = config.files()?;
+    check_destinations(&files, config)?;
+    if config.program {
+        check_program(&project_signatures(&files, config)?)?;
+    }
+    if let Some(filename) = &config.call_graph {
+        write_output(filename, &call_graph::dot(&project_signatures(&files, config)?))?;
+    }
+    if let Target::Asm | Target::Hack = config.target {
+        return compile_program(&files, config);
+    }
+    if let Target::Doc = config.target {
+        return write_docs(&files, config);
+    }
+    if let Some(merged) = &config.merge_output {
+        return merge_files(&files, config, merged);
+    }
+    // calls between the classes are checked when compiling to VM code, those of a single file only
+    // against the classes of `--with-interface`
+    let signatures = match (config.stop_after, &config.target) {
+        _ if files.len() == 1 && config.interfaces.is_empty() => None,
+        (None | Some(Stage::Codegen), Target::VM | Target::Both) => Some(project_signatures(&files, config)?),
+        _ => None
+    };
+    if let [filename] = &files[..] {
+        compile_file(filename, config, signatures.as_ref())?;
+        config.status("1 succeeded, 0 failed");
+        return Ok(());
+    }
+    compile_each(&files, config, |filename| compile_file(filename, config, signatures.as_ref()))?;
+    Ok(())
+}
+
+// Where the files of the flags go, checked before anything is written: no output may replace a
+// source, and no two sources may write the same file, which `-o` makes of Foo.jack in two
+// directories. The `-o` directory is made here.
+fn check_destinations(files: &[String], config: &Config) -> Result<(), Box<dyn Error>> {
+    if let Some(directory) = &config.output {
+        fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory, e))?;
+    }
+    let sources: HashMap<PathBuf, &str> = files.iter().map(|file| (resolved(file), file.as_str())).collect();
+    let mut destinations: Vec<(String, Option<&str>)> = Vec::new();
+    for file in files.iter() {
+        let extensions = [
+            (config.emit_tokens, ".tokens.xml"),
+            (config.emit_ast, ".ast.xml"),
+            (config.emit_interface, interface::EXTENSION),
+            (config.dump_ast && config.output.is_some(), ".ast.txt"),
+            (config.emit_symbols, Stage::Analyze.extension())
+        ];
+        for (_, extension) in extensions.into_iter().filter(|(emitted, _)| *emitted) {
+            destinations.push((artifact_path(file, extension, config), Some(file)));
+        }
+    }
+    // written once for all sources
+    destinations.extend([&config.merge_output, &config.call_graph].into_iter().flatten().map(|file| (file.clone(), None)));
+    let mut written: HashMap<PathBuf, &str> = HashMap::new();
+    for (destination, source) in destinations.iter() {
+        let path = resolved(destination);
+        if let Some(input) = sources.get(&path) {
+            return Err(format!("{} would overwrite the source {}", destination, input).into());
+        }
+        let Some(source) = source else { continue };
+        if let Some(first) = written.insert(path, source) {
+            return Err(format!("{} and {} would both write {}", first, source, destination).into());
+        }
+    }
+    Ok(())
+}
+
+// the path a file is reached by, whether or not it exists yet
+fn resolved(file: &str) -> PathBuf {
+    let path = Path::new(file);
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new(".")
+    };
+    match (fs::canonicalize(parent), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf()
+    }
+}
+
+// Compiles every file even after one failed, to report the errors of all of them
+fn compile_each<T>(
+    files: &[String], config: &Config, compile: impl Fn(&str) -> Result<T, Box<dyn Error>>
+) -> Result<Vec<T>, Box<dyn Error>> {
+    let mut outputs = Vec::new();
+    let mut errors = Vec::new();
+    let mut failed = Vec::new();
+    for filename in files.iter() {
+        match catching_panics(filename, || compile(filename)) {
+            Ok(output) => outputs.push(output),
+            Err(e) => {
+                let error = e.to_string();
+                failed.push(error.lines().next().unwrap_or_default().to_string());
+                errors.push(error);
+            }
+        }
+    }
+    let summary = format!("{} succeeded, {} failed", files.len() - failed.len(), failed.len());
+    if failed.is_empty() {
+        config.status(&summary);
+        return Ok(outputs);
+    }
+    errors.push(format!("{}:", summary));
+    errors.extend(failed.iter().map(|error| format!("  {}", error)));
+    Err(errors.join("\n").into())
+}
+
+// `--merge-output program.vm`: the VM code of all classes in one file, in the order of the files
+fn merge_files(files: &[String], config: &Config, merged: &str) -> Result<(), Box<dyn Error>> {
+    let signatures = project_signatures(files, config)?;
+    let classes = compile_each(files, config, |filename| {
+        let file = Path::new(filename).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        Ok((file, run_stages(filename, Stage::Codegen, config, Some(&signatures))?))
+    })?;
+    let classes: Vec<(&str, &str)> = classes.iter().map(|(file, vm)| (file.as_str(), vm.as_str())).collect();
+    let vm = vm::merge(&classes).map_err(|error| format!("{}: {}", merged, error))?;
+    write_output(merged, &vm)
+}
+
+// The `asm` and `hack` targets: the VM code of the classes, and the other .vm files of the directory
+// like the OS, as one Hack program with the bootstrap. Foo.vm is only written with `--keep-vm`, and
+// for `hack` MyGame.asm and Foo.vm with `--keep-intermediates`.
+fn compile_program(files: &[String], config: &Config) -> Result<(), Box<dyn Error>> {
+    let signatures = project_signatures(files, config)?;
+    let mut program = compile_each(files, config, |filename| {
+        let vm = run_stages(filename, Stage::Codegen, config, Some(&signatures))?;
+        let vm_filename = filename.replace(".jack", Stage::Codegen.extension());
+        if config.keep_vm || config.keep_intermediates {
+            write_output(&vm_filename, &vm)?;
+        }
+        Ok((filename.to_string(), vm_filename, vm))
+    })?;
+    let classes: HashSet<&str> = files.iter().filter_map(|filename| Path::new(filename).file_stem()?.to_str()).collect();
+    for source in config.sources.iter() {
+        if let Source::Directory(directory) = source {
+            for filename in files_with_extension(Path::new(directory), "vm")? {
+                let class = Path::new(&filename).file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+                if !classes.contains(class) {
+                    let vm = fs::read_to_string(&filename)?;
+                    program.push((filename.clone(), filename, vm));
+                }
+            }
+        }
+    }
+    let inputs: Vec<(&str, &str)> = program.iter().map(|(_, vm_filename, vm)| (vm_filename.as_str(), vm.as_str())).collect();
+    // an error in the VM code of a class is the compiler's, and is reported for its .jack file,
+    // the translator already names a .vm file of the directory
+    let asm_filename = config.program_filename(".asm")?;
+    let assembly = config.time(&asm_filename, Phase::Codegen, || vmtranslator::translate(&inputs))
+        .map_err(|(index, error)| match &program[index] {
+            (source, vm_filename, _) if source != vm_filename => format!("{}: {}", source, error),
+            _ => error
+        })?;
+    if let Target::Asm = config.target {
+        config.progress(&format!("Translating: {}", asm_filename));
+        return write_output(&asm_filename, &assembly);
+    }
+    if config.keep_intermediates {
+        write_output(&asm_filename, &assembly)?;
+    }
+    // the whole OS is already more code than the ROM holds
+    let instructions = assembly.lines().filter(|line| is_instruction(line)).count();
+    if instructions > assembler::ROM {
+        let error = format!("{}: the program has {} instructions, the ROM of the Hack computer holds {}", asm_filename, instructions, assembler::ROM);
+        return Err(error.into());
+    }
+    let binary = config.time(&asm_filename, Phase::Codegen, || assembler::assemble(&assembly))
+        .map_err(|(line, error)| assembly_error(&program, &asm_filename, &assembly, line, &error))?;
+    let destination = config.program_filename(".hack")?;
+    config.progress(&format!("Assembling: {}", destination));
+    write_output(&destination, &binary)
+}
+
+// not a label, a comment or an empty line
+fn is_instruction(line: &str) -> bool {
+    let code = line.split_once("//").map_or(line, |(code, _)| code).trim();
+    !code.is_empty() && !code.starts_with('(')
+}
+
+// An error of the assembler, for the file whose VM function the line belongs to. The translator
+// writes each VM command as a comment above its code, `// function Ball.move 0` and the like.
+fn assembly_error(program: &[(String, String, String)], asm_filename: &str, assembly: &str, line: usize, error: &str) -> String {
+    let comments: Vec<&str> = assembly.lines().take(line).filter_map(|line| line.strip_prefix("// ")).collect();
+    let function = comments.iter().rev().find_map(|comment| comment.strip_prefix("function "))
+        .and_then(|function| function.split_whitespace().next());
+    let error = format!("{}:{}: {}", asm_filename, line, error);
+    let Some(function) = function else { return error };
+    let class = function.split('.').next().unwrap_or_default();
+    let file = program.iter()
+        .find(|(_, vm_filename, _)| Path::new(vm_filename).file_stem().and_then(|stem| stem.to_str()) == Some(class))
+        .map_or(asm_filename, |(file, _, _)| file.as_str());
+    let command = comments.last().unwrap_or(&"");
+    format!("{}: {}\n  note: in VM function {}, the code of `{}`", file, error, function, command)
+}
+
+// the .vm or .jacki files of a directory sorted by path
+fn files_with_extension(directory: &Path, extension: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut paths = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    Ok(paths.into_iter()
+        .filter(|path| path.extension() == Some(extension.as_ref()))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
+}
+
+// The first pass over a project, the subroutines of every class.
+// Files that do not parse are left out, the second pass reports their errors.
+// Two files with the same class would both write Ball.new and the like, so that fails the project.
+// The classes of the project replace those of `--with-interface`, as they do the OS.
+fn project_signatures(files: &[String], config: &Config) -> Result<Signatures, Box<dyn Error>> {
+    let mut signatures = if config.builtin_os { Signatures::with_os() } else { Signatures::new() };
+    read_interfaces(config, &mut signatures)?;
+    let mut declared: HashMap<String, &str> = HashMap::new();
+    let options = config.options;
+    for filename in files.iter() {
+        let class = config.time(filename, Phase::Analysis, || panic::catch_unwind(|| {
+            let mut tokens = tokenize(Cursor::new(read_source(filename).ok()?.0), options).ok()?;
+            parser::parse_single_class(&mut tokens).ok()
+        }));
+        if let Ok(Some(class)) = class {
+            if let Some(first) = declared.insert(class.name.0.clone(), filename) {
+                return Err(format!("class {} is declared in both {} and {}", class.name.0, first, filename).into());
+            }
+            signatures.add_class(&class, filename);
+        }
+    }
+    Ok(signatures)
+}
+
+// `--with-interface lib/`: the classes of the .jacki files, those of a directory sorted by path.
+// Errors name the file and line, `lib/Ball.jacki:3: unknown entry field`.
+fn read_interfaces(config: &Config, signatures: &mut Signatures) -> Result<(), Box<dyn Error>> {
+    for path in config.interfaces.iter() {
+        let files = if Path::new(path).is_dir() {
+            files_with_extension(Path::new(path), &interface::EXTENSION[1..])?
+        } else {
+            vec![path.clone()]
+        };
+        for file in files {
+            let content = fs::read_to_string(&file).map_err(|e| format!("{}: {}", file, e))?;
+            let interface = interface::read(&content, &file).map_err(|(line, error)| format!("{}:{}: {}", file, line, error))?;
+            config.progress(&format!("Reading interface: {} with {} fields", interface.class_name, interface.fields));
+            signatures.add_interface(interface);
+        }
+    }
+    Ok(())
+}
+
+// The `doc` target: a Markdown page for each class and index.md in the `-o` directory, `docs` by
+// default. Nothing is written when a file has errors.
+fn write_docs(files: &[String], config: &Config) -> Result<(), Box<dyn Error>> {
+    let classes = compile_each(files, config, |filename| {
+        let (bytes, source) = config.time(filename, Phase::Io, || read_source(filename))?;
+        let renderer = Renderer::new(source_name(filename), &source, config.color, config.message_format);
+        let mut tokens = config.time(filename, Phase::Tokenize, || read_tokens(bytes, &renderer, config))?;
+        let class = parse_and_emit(filename, &mut tokens, &renderer, config)?;
+        config.progress(&format!("Documenting: {}", class.name.0));
+        Ok((filename.to_string(), class))
+    })?;
+    let mut declared: HashMap<&str, &str> = HashMap::new();
+    for (filename, class) in classes.iter() {
+        if let Some(first) = declared.insert(class.name.as_str(), filename) {
+            return Err(format!("class {} is declared in both {} and {}", class.name.0, first, filename).into());
+        }
+    }
+    let directory = Path::new(config.output.as_deref().unwrap_or("docs"));
+    fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+    for (filename, class) in classes.iter() {
+        let page = directory.join(format!("{}.md", class.name.as_str()));
+        config.time(filename, Phase::Io, || write_output(&page.to_string_lossy(), &doc::class_page(class)))?;
+    }
+    let classes: Vec<&Class> = classes.iter().map(|(_, class)| class).collect();
+    write_output(&directory.join("index.md").to_string_lossy(), &doc::index(&classes))?;
+    let undocumented = classes.iter().flat_map(|class| doc::undocumented(class)).count();
+    if undocumented > 0 {
+        let subroutines = if undocumented == 1 { "subroutine has" } else { "subroutines have" };
+        config.status(&format!("{} {} no doc comment, see {}", undocumented, subroutines, directory.join("index.md").display()));
+    }
+    Ok(())
+}
+
+// Sys.init starts a program with `do Main.main()`, without it the VM code has no way in
+fn check_program(signatures: &Signatures) -> Result<(), Box<dyn Error>> {
+    let mut class_names: Vec<&str> = signatures.class_names().collect();
+    class_names.sort();
+    if !signatures.has_class("Main") {
+        let mut error = String::from("the program has no class Main");
+        if let Some(class_name) = class_names.iter().find(|class_name| class_name.eq_ignore_ascii_case("Main")) {
+            error.push_str(&format!("\n  note: there is a class {}, class names are case-sensitive", class_name));
+        }
+        return Err(error.into());
+    }
+    let Some(main) = signatures.find("Main", "main") else {
+        let mut error = String::from("class Main has no function main");
+        if let Some(similar) = signatures.subroutines("Main").iter().find(|signature| signature.name.eq_ignore_ascii_case("main")) {
+            error.push_str(&format!("\n  note: {}, subroutine names are case-sensitive", similar.declared_at()));
+        }
+        return Err(error.into());
+    };
+    let problem = if main.kind != SubroutineType::Function {
+        format!("Main.main is a {}, the OS calls it as a function without an instance", main.kind.as_str())
+    } else if !main.parameters.is_empty() {
+        let parameters = main.parameters.len();
+        format!("Main.main takes {} argument{}, the OS calls it without any", parameters, if parameters == 1 { "" } else { "s" })
+    } else {
+        return Ok(());
+    };
+    Err(format!("{}\n  note: {}", problem, main.declared_at()).into())
+}
+
+// a bug in the compiler fails the file, the others are still compiled
+fn catching_panics<T>(filename: &str, compile: impl FnOnce() -> Result<T, Box<dyn Error>>) -> Result<T, Box<dyn Error>> {
+    match panic::catch_unwind(AssertUnwindSafe(compile)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(format!("{}: internal compiler error: {}", filename, message).into())
+        }
+    }
+}
+
+fn compile_file(filename: &str, config: &Config, signatures: Option<&Signatures>) -> Result<(), Box<dyn Error>> {
+    let stage = match (config.stop_after, &config.target) {
+        (Some(stage), _) => stage,
+        (None, Target::XML) => Stage::Parse,
+        // asm, hack and doc take all files at once and never get here
+        (None, Target::VM | Target::Asm | Target::Hack | Target::Doc) => Stage::Codegen,
+        (None, Target::Both) => return write_xml_and_vm(filename, config, signatures),
+        (None, Target::Format) => return format_file(filename, config)
+    };
+    let output = run_stages(filename, stage, config, signatures)?;
+    match &config.compare {
+        Some(expected_filename) => compare_output(&output, expected_filename, config),
+        None => {
+            config.time(filename, Phase::Io, || write_output(&filename.replace(".jack", stage.extension()), &output))
+        }
+    }
+}
+
+impl Stage {
+    fn new(name: &str) -> Option<Self> {
+        match name {
+            "tokens" => Some(Stage::Tokens),
+            "parse" => Some(Stage::Parse),
+            "analyze" => Some(Stage::Analyze),
+            "codegen" => Some(Stage::Codegen),
+            _ => None
+        }
+    }
+
+    // what Foo.jack's output is called
+    fn extension(&self) -> &'static str {
+        match self {
+            Stage::Tokens => "T.xml",
+            Stage::Parse => ".xml",
+            Stage::Analyze => ".sym",
+            Stage::Codegen => ".vm"
+        }
+    }
+}
+
+// Runs the stages up to `stage`: the tokens as XML, the class as XML, its symbol tables, or its VM code.
+// Nothing is written, files with errors get no output.
+fn run_stages(
+    filename: &str, stage: Stage, config: &Config, signatures: Option<&Signatures>
+) -> Result<String, Box<dyn Error>> {
+    let (bytes, source) = config.time(filename, Phase::Io, || read_source(filename))?;
+    let renderer = Renderer::new(source_name(filename), &source, config.color, config.message_format);
+    let mut tokens = config.time(filename, Phase::Tokenize, || read_tokens(bytes, &renderer, config))?;
+    let class = match stage {
+        Stage::Tokens => {
+            let xml = config.time(filename, Phase::Codegen, || tokens.to_xml());
+            config.time(filename, Phase::Io, || write_artifacts(filename, config.emit_tokens.then(|| xml.clone()), None, config))?;
+            return Ok(xml);
+        },
+        _ => parse_and_emit(filename, &mut tokens, &renderer, config)?
+    };
+    match stage {
+        Stage::Parse => config.progress(&format!("Parsing: {}", class.name.0)),
+        Stage::Codegen => config.progress(&format!("Compiling: {}", class.name.0)),
+        _ => {}
+    }
+    if stage == Stage::Codegen {
+        return codegen(&class, &renderer, config, signatures);
+    }
+    let phase = if stage == Stage::Analyze { Phase::Analysis } else { Phase::Codegen };
+    let mut output = Vec::new();
+    let warnings = config.time(filename, phase, || emit(&class, stage, config.options, signatures, &mut output))
+        .map_err(|e| renderer.compile_error(&e))?;
+    report_warnings(&renderer, warnings, config)?;
+    Ok(text(output))
+}
+
+// the VM code, with `--emit-symbols` Foo.sym is written from the symbol tables it was made with
+fn codegen(
+    class: &Class, renderer: &Renderer, config: &Config, signatures: Option<&Signatures>
+) -> Result<String, Box<dyn Error>> {
+    let file = Path::new(renderer.filename()).file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let listing = config.annotate.then(|| parser::Listing { file, lines: renderer.lines() });
+    let source_map = (config.source_map && config.compare.is_none()).then_some(file);
+    let start = Instant::now();
+    let compiled = parser::VM::compile_with_symbols(class, config.options, signatures, listing, source_map)
+        .map_err(|e| renderer.compile_error(&e))?;
+    if let Some(timings) = &config.timings {
+        timings.add(renderer.filename(), Phase::Analysis, compiled.analysis_time);
+        timings.add(renderer.filename(), Phase::Codegen, start.elapsed().saturating_sub(compiled.analysis_time));
+    }
+    report_warnings(renderer, compiled.warnings, config)?;
+    if config.emit_symbols && config.compare.is_none() {
+        write_artifact(renderer.filename(), Stage::Analyze.extension(), &compiled.symbols, config)?;
+    }
+    if config.metrics {
+        config.status(&compiled.metrics.to_string());
+    }
+    if let Some(source_map) = compiled.source_map {
+        write_output(&renderer.filename().replace(".jack", ".vm.map"), &source_map)?;
+    }
+    Ok(compiled.vm)
+}
+
+// parses once for both outputs, neither is written unless both compile
+fn write_xml_and_vm(filename: &str, config: &Config, signatures: Option<&Signatures>) -> Result<(), Box<dyn Error>> {
+    let (bytes, source) = config.time(filename, Phase::Io, || read_source(filename))?;
+    let renderer = Renderer::new(source_name(filename), &source, config.color, config.message_format);
+    let mut tokens = config.time(filename, Phase::Tokenize, || read_tokens(bytes, &renderer, config))?;
+    let class = parse_and_emit(filename, &mut tokens, &renderer, config)?;
+    config.progress(&format!("Compiling: {}", class.name.0));
+    let mut xml = Vec::new();
+    config.time(filename, Phase::Codegen, || emit(&class, Stage::Parse, config.options, None, &mut xml))?;
+    let xml = text(xml);
+    let vm = codegen(&class, &renderer, config, signatures)?;
+    config.time(filename, Phase::Io, || {
+        write_output(&filename.replace(".jack", Stage::Parse.extension()), &xml)?;
+        write_output(&filename.replace(".jack", Stage::Codegen.extension()), &vm)
+    })
+}
+
+// warnings are printed, or fail the file under `--deny-warnings` and `--strict`
+fn report_warnings(renderer: &Renderer, warnings: Vec<Warning>, config: &Config) -> Result<(), Box<dyn Error>> {
+    if config.hides_warnings() {
+        return Ok(());
+    }
+    Ok(renderer.warnings(&warnings, config.denies_warnings())?)
+}
+
+// checks the output against a compare file instead of writing it
+fn compare_output(output: &str, expected_filename: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    let expected = fs::read_to_string(expected_filename)?;
+    match compare::first_difference(&expected, output) {
+        Some(difference) => Err(format!("{}: {}", expected_filename, difference).into()),
+        None => {
+            config.progress("Comparison ended successfully");
+            Ok(())
+        }
+    }
+}
+
+// rewrites the file formatted, with `--check` only tells whether it already is
+fn format_file(filename: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    let content = config.time(filename, Phase::Io, || match filename {
+        STDIN => Ok::<_, Box<dyn Error>>(read_source(filename)?.1),
+        _ => Ok(fs::read_to_string(filename)?)
+    })?;
+    let renderer = Renderer::new(source_name(filename), &content, config.color, config.message_format);
+    if config.emit_tokens || config.emit_ast || config.emit_interface || config.emit_symbols || config.dump_ast {
+        let mut tokens = config.time(filename, Phase::Tokenize, || read_tokens(content.as_bytes().to_vec(), &renderer, config))?;
+        parse_and_emit(filename, &mut tokens, &renderer, config)?;
+    }
+    let formatted = config.time(filename, Phase::Codegen, || format_source(&content, config.options))
+        .map_err(|e| renderer.compile_error(&e))?;
+    // stdin gets its formatted source back even when nothing changed
+    if formatted == content && (filename != STDIN || config.check) {
+        return Ok(());
+    }
+    if config.check {
+        return Err(format!("{}: not formatted", source_name(filename)).into());
+    }
+    config.progress(&format!("Formatting: {}", filename));
+    config.time(filename, Phase::Io, || write_output(filename, &formatted))
+}
+
+// Replaces the file in one step: a file left half written would be read by the next tool.
+// Errors name the file.
+fn write_output(filename: &str, content: &str) -> Result<(), Box<dyn Error>> {
+    if filename == STDIN {
+        return Ok(io::stdout().write_all(content.as_bytes())?);
+    }
+    let path = Path::new(filename);
+    let Some(name) = path.file_name() else {
+        return Err(format!("{}: not the name of a file", filename).into());
+    };
+    let temporary = path.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+    let result = fs::File::create(&temporary)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .and_then(|_| fs::rename(&temporary, path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temporary);
+        return Err(format!("{}: {}", filename, e).into());
+    }
+    Ok(())
+}
+
+// `-` reads the source from stdin and writes its output to stdout
+const STDIN: &str = "-";
+
+// how errors name a source
+fn source_name(filename: &str) -> &str {
+    if filename == STDIN { "<stdin>" } else { filename }
+}
+
+// the bytes of a file for the tokenizer, and its text for showing the lines errors point at.
+// Stdin is read once, checking the project reads the source again.
+fn read_source(filename: &str) -> Result<(Vec<u8>, String), Box<dyn Error>> {
+    static STDIN_BYTES: OnceLock<Vec<u8>> = OnceLock::new();
+    let bytes = match filename {
+        STDIN => match STDIN_BYTES.get() {
+            Some(bytes) => bytes.clone(),
+            None => {
+                let mut bytes = Vec::new();
+                io::stdin().read_to_end(&mut bytes)?;
+                STDIN_BYTES.get_or_init(|| bytes).clone()
+            }
+        },
+        _ => fs::read(filename)?
+    };
+    let source = String::from_utf8_lossy(&bytes).into_owned();
+    Ok((bytes, source))
+}
+
+fn read_tokens(bytes: Vec<u8>, renderer: &Renderer, config: &Config) -> Result<Tokens, Box<dyn Error>> {
+    Ok(tokenize(Cursor::new(bytes), config.options).map_err(|e| renderer.compile_error(&e))?)
+}
+
+// Parses the tokens, and writes the `--emit-*` files of a class that parses. They come from the
+// writers of `--stop-after tokens`, the xml target and `--stop-after analyze`, Foo.sym only when
+// no VM code is made.
+fn parse_and_emit(filename: &str, tokens: &mut Tokens, renderer: &Renderer, config: &Config) -> Result<Class, Box<dyn Error>> {
+    // the parse uses the tokens up
+    let token_xml = config.emit_tokens.then(|| config.time(filename, Phase::Codegen, || tokens.to_xml()));
+    let class = config.time(filename, Phase::Parse, || parse_tokens(filename, tokens, renderer, config))?;
+    config.time(filename, Phase::Io, || write_artifacts(filename, token_xml, Some(&class), config))?;
+    Ok(class)
+}
+
+fn write_artifacts(filename: &str, token_xml: Option<String>, class: Option<&Class>, config: &Config) -> Result<(), Box<dyn Error>> {
+    // `--compare` writes nothing
+    if config.compare.is_some() {
+        return Ok(());
+    }
+    if let Some(xml) = token_xml {
+        write_artifact(filename, ".tokens.xml", &xml, config)?;
+    }
+    let Some(class) = class else { return Ok(()) };
+    if config.emit_ast {
+        write_artifact(filename, ".ast.xml", &parser::XML::text(class), config)?;
+    }
+    if config.emit_interface {
+        write_artifact(filename, interface::EXTENSION, &interface::write(class), config)?;
+    }
+    // on stdout, Foo.ast.txt in the `-o` directory
+    if config.dump_ast {
+        let tree = dump::dump(class);
+        match config.output {
+            Some(_) => write_artifact(filename, ".ast.txt", &tree, config)?,
+            None => io::stdout().write_all(tree.as_bytes())?
+        }
+    }
+    if config.emit_symbols && !config.generates_code() {
+        write_artifact(filename, Stage::Analyze.extension(), &parser::analyze(class), config)?;
+    }
+    Ok(())
+}
+
+fn write_artifact(filename: &str, extension: &str, content: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    write_output(&artifact_path(filename, extension, config), content)
+}
+
+// Foo.jack's `--emit-*` file with the extension, next to it or in the `-o` directory
+fn artifact_path(filename: &str, extension: &str, config: &Config) -> String {
+    let artifact = filename.replace(".jack", extension);
+    match &config.output {
+        Some(directory) => {
+            let name = Path::new(&artifact).file_name().unwrap_or_default();
+            Path::new(directory).join(name).to_string_lossy().into_owned()
+        },
+        None => artifact
+    }
+}
+
+fn parse_tokens(filename: &str, tokens: &mut Tokens, renderer: &Renderer, config: &Config) -> Result<Class, Box<dyn Error>> {
+    let class = parser::parse_single_class(tokens).map_err(|e| renderer.compile_error(&e))?;
+    if filename == STDIN {
+        return match &config.class_name {
+            Some(expected) if *expected != class.name.0 => {
+                let error = CompileError::ClassName { expected: expected.clone(), found: class.name.0.clone() };
+                Err(renderer.compile_error(&error).into())
+            },
+            _ => Ok(class)
+        };
+    }
+    if let Some(message) = class_name_mismatch(filename, class.name.as_str()) {
+        if !config.options.warnings.enabled(Lint::ClassFileName) || config.hides_warnings() {
+            return Ok(class);
+        }
+        renderer.file_warning(Lint::ClassFileName, &message, config.denies_warnings())?;
+    }
+    Ok(class)
+}
+
+// the VM function names and the OS depend on class Foo living in Foo.jack
+fn class_name_mismatch(filename: &str, class_name: &str) -> Option<String> {
+    let stem = Path::new(filename).file_stem()?.to_str()?;
+    if stem == class_name {
+        None
+    } else {
+        Some(format!("class {} should be declared in {}.jack", class_name, class_name))
+    }
+}
+
+// what `eval` compiles
+enum Snippet {
+    Expression(String),
+    // `--statements`
+    Statements(String)
+}
+
+enum Source {
+    File(String),
+    Directory(String),
+    // `-`
+    Stdin
+}
+
+#[allow(clippy::upper_case_acronyms)]
+enum Target {
+    XML,
+    VM,
+    // `both`: Foo.xml and Foo.vm
+    Both,
+    Format,
+    // `asm`: MyGame/MyGame.asm for MyGame/, or Foo.asm for Foo.jack
+    Asm,
+    // `hack`: MyGame/MyGame.hack, the machine code for the CPU emulator
+    Hack,
+    // `doc`: a Markdown page for each class and an index
+    Doc
+}
+
+pub struct Config {
+    sources: Vec<Source>,
+    target: Target,
+    options: LanguageOptions,
+    // `--check`: report unformatted files instead of rewriting them
+    check: bool,
+    // `--compare expected.xml`: compare the output of a single file instead of writing it
+    compare: Option<String>,
+    // `--stop-after tokens|parse|analyze|codegen`
+    stop_after: Option<Stage>,
+    // `--recursive`: also compile the classes in subdirectories of a directory
+    recursive: bool,
+    // `--no-builtin-os`: calls to the OS are only checked against its classes in the project
+    builtin_os: bool,
+    // `--program`: the classes have to make a program the OS can start, with `function void Main.main()`
+    program: bool,
+    // `--deny-warnings`: a warning fails the file, like under `--strict`
+    deny_warnings: bool,
+    // `--emit-tokens`: Foo.tokens.xml next to the output of any target, the tokens as `--stop-after tokens` writes them
+    emit_tokens: bool,
+    // `--emit-ast`: Foo.ast.xml, the parse tree as the xml target writes it
+    emit_ast: bool,
+    // `--dump-ast`: the tree of each class, indented, for reading
+    dump_ast: bool,
+    // `--emit-interface`: Foo.jacki, the subroutines other classes can call, for `--with-interface`
+    emit_interface: bool,
+    // `--with-interface lib/`: the .jacki files of classes whose sources are compiled apart, to check calls against
+    interfaces: Vec<String>,
+    // `--emit-symbols`: Foo.sym, the symbol tables of the class and its subroutines
+    emit_symbols: bool,
+    // `--annotate`: each statement's source line as a comment before its VM code, and each subroutine's signature
+    annotate: bool,
+    // `--metrics`: the sizes and counts of each class after compiling it
+    metrics: bool,
+    // `--call-graph calls.dot`: the subroutines of the project and their calls in Graphviz
+    call_graph: Option<String>,
+    // `--keep-vm`: the asm target also writes Foo.vm
+    keep_vm: bool,
+    // `--merge-output program.vm`: one VM file for all classes instead of Foo.vm for each
+    merge_output: Option<String>,
+    // `--keep-intermediates`: the hack target also writes Foo.vm and MyGame.asm
+    keep_intermediates: bool,
+    // `--source-map`: Foo.vm.map next to Foo.vm, the position in Foo.jack each line of the code was made for
+    source_map: bool,
+    // `--color=always|never|auto`: errors and warnings in color, by default when stderr is a terminal
+    color: Color,
+    // `--message-format=human|json`: JSON diagnostics on stdout for editors, without the progress lines
+    message_format: MessageFormat,
+    // `--class-name Foo`: the class the source from stdin has to declare
+    class_name: Option<String>,
+    // `--quiet` and `--verbose`
+    verbosity: Verbosity,
+    // `--timings`: how long each file took in each phase, printed at the end
+    timings: Option<Timings>,
+    // `eval "2 + 3"`: run a snippet on the VM evaluator instead of compiling files
+    eval: Option<Snippet>,
+    // `--emit-vm`: eval prints the VM code of the snippet instead of running it
+    emit_vm: bool,
+    // `--selftest`: check the compiler against the fixtures it was built with, instead of compiling files
+    selftest: bool,
+    // `-o docs/`: the directory the doc target writes its pages to, and the `--emit-*` files and the trees of `--dump-ast` go to
+    output: Option<String>
+}
+
+impl Config {
+    // The command line after the flags of jack.toml, the one `--manifest-path` names or else the
+    // closest one above the current directory. `--selftest` reads none.
+    pub fn load(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut args: Vec<String> = args.collect();
+        let path = match args.iter().position(|arg| arg == "--manifest-path") {
+            Some(index) if index + 1 < args.len() => {
+                let path = args.remove(index + 1);
+                args.remove(index);
+                Some(PathBuf::from(path))
+            },
+            Some(_) => return Err("Missing manifest path.".to_string()),
+            None if args.iter().any(|arg| arg == "--selftest") => None,
+            None => {
+                let directory = env::current_dir().map_err(|e| e.to_string())?;
+                // `jack.toml` rather than the whole path when it is in the current directory
+                manifest::find(&directory).map(|path| path.strip_prefix(&directory).map(Path::to_path_buf).unwrap_or(path))
+            }
+        };
+        let manifest = match path {
+            Some(path) => manifest::read(&path)?,
+            None => Manifest::default()
+        };
+        Config::with_manifest(args.into_iter(), manifest).map_err(str::to_string)
+    }
+
+    pub fn new(args: impl Iterator<Item = String>) -> Result<Self, &'static str> {
+        Config::with_manifest(args, Manifest::default())
+    }
+
+    fn with_manifest(mut args: impl Iterator<Item = String>, manifest: Manifest) -> Result<Self, &'static str> {
+        args.next();
+        // the flags of jack.toml first, those of the command line then override them
+        let mut args = manifest.flags.into_iter().chain(args);
+
+        let mut options = LanguageOptions::default();
+        let mut check = false;
+        let mut compare = None;
+        let mut stop_after = None;
+        let mut recursive = false;
+        let mut builtin_os = true;
+        let mut program = false;
+        let mut deny_warnings = false;
+        let mut emit_tokens = false;
+        let mut emit_ast = false;
+        let mut dump_ast = false;
+        let mut emit_interface = false;
+        let mut interfaces = Vec::new();
+        let mut emit_symbols = false;
+        let mut annotate = false;
+        let mut source_map = false;
+        let mut call_graph = None;
+        let mut metrics = false;
+        let mut keep_vm = false;
+        let mut keep_intermediates = false;
+        let mut merge_output = None;
+        let mut color = Color::default();
+        let mut message_format = MessageFormat::default();
+        let mut class_name = None;
+        let mut quiet = false;
+        let mut verbose = false;
+        let mut timings = None;
+        let mut output = None;
+        let mut selftest = false;
+        let mut statements = None;
+        let mut emit_vm = false;
+        let mut positional = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--compare" => match args.next() {
+                    Some(file) => compare = Some(file),
+                    None => return Err("Missing compare file.")
+                },
+                "--stop-after" => match args.next().as_deref().map(Stage::new) {
+                    Some(Some(stage)) => stop_after = Some(stage),
+                    _ => return Err("--stop-after needs tokens, parse, analyze or codegen.")
+                },
+                "--ext-string-escapes" => options.string_escapes = true,
+                "--ext-short-circuit" => options.short_circuit = true,
+                "--ext-for" => options.for_loops = true,
+                "--ext-break" => options.break_continue = true,
+                "--ext-field-init" => options.field_initializers = true,
+                "--ext-const" => options.constants = true,
+                "--precedence" => options.precedence = true,
+                // what jack.toml turns on, the command line can turn off again
+                "--no-ext-string-escapes" => options.string_escapes = false,
+                "--no-ext-short-circuit" => options.short_circuit = false,
+                "--no-ext-for" => options.for_loops = false,
+                "--no-ext-break" => options.break_continue = false,
+                "--no-ext-field-init" => options.field_initializers = false,
+                "--no-ext-const" => options.constants = false,
+                "--no-precedence" => options.precedence = false,
+                "--check" => check = true,
+                "--strict" => options.strict = true,
+                "--lossy-strings" => options.lossy_strings = true,
+                "--type-check" => options.warnings = options.warnings.with(Lint::TypeCheck),
+                "-W" => match args.next().and_then(|name| options.warnings.set(&name)) {
+                    Some(warnings) => options.warnings = warnings,
+                    None => return Err("-W needs the name of a warning, all, or either with no- in front.")
+                },
+                "--deny-warnings" => deny_warnings = true,
+                "--no-deny-warnings" => deny_warnings = false,
+                "--max-complexity" => match args.next().and_then(|limit| limit.parse().ok()) {
+                    Some(limit) => options.limits.complexity = limit,
+                    None => return Err("--max-complexity needs a number.")
+                },
+                "--max-statements" => match args.next().and_then(|limit| limit.parse().ok()) {
+                    Some(limit) => options.limits.statements = limit,
+                    None => return Err("--max-statements needs a number.")
+                },
+                "--emit-tokens" => emit_tokens = true,
+                "--emit-ast" => emit_ast = true,
+                "--dump-ast" => dump_ast = true,
+                "--emit-interface" => emit_interface = true,
+                "--with-interface" => match args.next() {
+                    Some(path) if Path::new(&path).is_dir() || (path.ends_with(interface::EXTENSION) && Path::new(&path).is_file()) => {
+                        interfaces.push(path);
+                    },
+                    _ => return Err("--with-interface needs a .jacki file or a directory of them.")
+                },
+                "--emit-symbols" => emit_symbols = true,
+                "--annotate" => annotate = true,
+                "--source-map" => source_map = true,
+                "--metrics" => metrics = true,
+                "--keep-vm" => keep_vm = true,
+                "--keep-intermediates" => keep_intermediates = true,
+                "--merge-output" => match args.next() {
+                    Some(file) => merge_output = Some(file),
+                    None => return Err("Missing merged output file.")
+                },
+                "--call-graph" => match args.next() {
+                    Some(file) => call_graph = Some(file),
+                    None => return Err("Missing call graph file.")
+                },
+                "--class-name" => match args.next() {
+                    Some(name) => class_name = Some(name),
+                    None => return Err("Missing class name.")
+                },
+                "-O" => options.optimize = true,
+                "--no-optimize" => options.optimize = false,
+                "--inline-accessors" => options.inline_accessors = true,
+                "--bounds-check" => options.bounds_check = true,
+                "--null-check" => options.null_check = true,
+                "--trace-calls" => options.trace_calls = true,
+                "--xml-annotations" => options.xml_annotations = true,
+                "--recursive" => recursive = true,
+                "--no-builtin-os" => builtin_os = false,
+                "--no-os-conformance" => options.warnings = options.warnings.without(Lint::OsConformance),
+                "--program" => program = true,
+                flag if flag.starts_with("--color=") => match Color::new(&flag["--color=".len()..]) {
+                    Some(choice) => color = choice,
+                    None => return Err("--color needs always, never or auto.")
+                },
+                "--quiet" | "-q" => quiet = true,
+                "--verbose" | "-v" => verbose = true,
+                "--timings" => timings = Some(Timings::default()),
+                "--selftest" => selftest = true,
+                "--statements" => match args.next() {
+                    Some(snippet) => statements = Some(snippet),
+                    None => return Err("Missing statements.")
+                },
+                "--emit-vm" => emit_vm = true,
+                "-o" | "--output" => match args.next() {
+                    Some(directory) => output = Some(directory),
+                    None => return Err("Missing output directory.")
+                },
+                flag if flag.starts_with("--message-format=") => match MessageFormat::new(&flag["--message-format=".len()..]) {
+                    Some(format) => message_format = format,
+                    None => return Err("--message-format needs human or json.")
+                },
+                flag if flag.starts_with("--") => return Err("Unknown flag."),
+                _ => positional.push(arg)
+            }
+        }
+        // `eval` and the snippet
+        let eval = match (positional.first().map(String::as_str), &positional[..], statements) {
+            (Some("eval"), [_, expression], None) => Some(Snippet::Expression(expression.clone())),
+            (Some("eval"), [_], Some(statements)) => Some(Snippet::Statements(statements)),
+            (Some("eval"), _, _) => return Err("eval needs an expression, or --statements and the statements."),
+            (_, _, Some(_)) => return Err("--statements only applies to eval."),
+            _ => None
+        };
+        if eval.is_some() {
+            positional.clear();
+        } else if emit_vm {
+            return Err("--emit-vm only applies to eval.");
+        }
+        // jack.toml's target and sources stand in for those the command line leaves out
+        let is_target = |arg: &String| manifest::TARGETS.contains(&arg.as_str());
+        if !positional.iter().any(is_target) && eval.is_none() {
+            positional.extend(manifest.target);
+        }
+        if positional.iter().all(is_target) && eval.is_none() {
+            positional.extend(manifest.sources);
+        }
+        // any number of files and directories, and the target
+        let mut sources = Vec::new();
+        let mut target = None;
+        for arg in positional {
+            match arg.as_str() {
+                "xml" if target.is_none() => target = Some(Target::XML),
+                "vm" if target.is_none() => target = Some(Target::VM),
+                "both" if target.is_none() => target = Some(Target::Both),
+                "fmt" if target.is_none() => target = Some(Target::Format),
+                "asm" if target.is_none() => target = Some(Target::Asm),
+                "hack" if target.is_none() => target = Some(Target::Hack),
+                "doc" if target.is_none() => target = Some(Target::Doc),
+                STDIN => sources.push(Source::Stdin),
+                file if file.ends_with(".jack") && Path::new(file).exists() => sources.push(Source::File(arg)),
+                directory if Path::new(directory).is_dir() => sources.push(Source::Directory(arg)),
+                _ => return Err("Invalid filename or directory.")
+            }
+        }
+        if selftest && !(sources.is_empty() && target.is_none()) {
+            return Err("--selftest takes no files, directories or target.");
+        }
+        if sources.is_empty() && !selftest && eval.is_none() {
+            return Err("Missing filename or directory.");
+        }
+        let stdin = sources.iter().any(|source| matches!(source, Source::Stdin));
+        if stdin && sources.len() > 1 {
+            return Err("- cannot be compiled together with files or directories.");
+        }
+        if stdin && !matches!(target, Some(Target::XML | Target::VM | Target::Format)) {
+            return Err("- needs the xml, vm or fmt target.");
+        }
+        if stdin && (emit_tokens || emit_ast || emit_interface || emit_symbols || source_map || call_graph.is_some()) {
+            return Err("--emit-tokens, --emit-ast, --emit-interface, --emit-symbols, --source-map and --call-graph write files, which - does not.");
+        }
+        if stdin && message_format == MessageFormat::Json {
+            return Err("--message-format=json prints on stdout, where - writes its output.");
+        }
+        if stdin && dump_ast {
+            return Err("--dump-ast prints on stdout, where - writes its output.");
+        }
+        let verbosity = match (quiet, verbose) {
+            (true, true) => return Err("--quiet and --verbose cannot be combined."),
+            (true, false) => Verbosity::Quiet,
+            (false, true) => Verbosity::Verbose,
+            (false, false) => Verbosity::Normal
+        };
+        if class_name.is_some() && !stdin {
+            return Err("--class-name only applies to -.");
+        }
+        let target = target.unwrap_or(Target::VM);
+        if check && !matches!(target, Target::Format) {
+            return Err("--check only applies to fmt.");
+        }
+        if stop_after.is_some() && matches!(target, Target::Format | Target::Both | Target::Asm | Target::Hack | Target::Doc) {
+            return Err("--stop-after does not apply to fmt, both, asm, hack or doc.");
+        }
+        if output.is_none() && (matches!(target, Target::Doc) || emit_tokens || emit_ast || emit_interface || emit_symbols) {
+            output = manifest.output;
+        }
+        if output.is_some() && !matches!(target, Target::Doc) && !(emit_tokens || emit_ast || emit_interface || emit_symbols || dump_ast) {
+            return Err("-o only applies to doc, the --emit files and --dump-ast.");
+        }
+        if dump_ast && output.is_none() && message_format == MessageFormat::Json {
+            return Err("--dump-ast prints on stdout, where --message-format=json writes the diagnostics.");
+        }
+        if (emit_ast || emit_interface || emit_symbols || dump_ast) && stop_after == Some(Stage::Tokens) {
+            return Err("--emit-ast, --emit-interface, --emit-symbols and --dump-ast need the parse, which --stop-after tokens leaves out.");
+        }
+        if annotate && (matches!(target, Target::XML | Target::Format | Target::Asm | Target::Hack | Target::Doc) || stop_after.is_some()) {
+            return Err("--annotate only applies to vm and both, without --stop-after.");
+        }
+        if metrics && (matches!(target, Target::XML | Target::Format | Target::Doc) || stop_after.is_some()) {
+            return Err("--metrics only applies to vm, both, asm and hack, without --stop-after.");
+        }
+        if call_graph.is_some() && matches!(target, Target::Format | Target::Doc) {
+            return Err("--call-graph does not apply to fmt or doc.");
+        }
+        if source_map && (matches!(target, Target::XML | Target::Format | Target::Asm | Target::Hack | Target::Doc) || stop_after.is_some()) {
+            return Err("--source-map only applies to vm and both, without --stop-after.");
+        }
+        if keep_vm && !matches!(target, Target::Asm) {
+            return Err("--keep-vm only applies to asm.");
+        }
+        if merge_output.is_some() && (!matches!(target, Target::VM) || stop_after.is_some() || compare.is_some()) {
+            return Err("--merge-output only applies to vm, without --stop-after and --compare.");
+        }
+        if keep_intermediates && !matches!(target, Target::Hack) {
+            return Err("--keep-intermediates only applies to hack.");
+        }
+        if matches!(target, Target::Asm | Target::Hack) && sources.len() > 1 {
+            return Err("asm and hack need a single file or directory.");
+        }
+        // the formatter writes the class back from its tree, where a for loop is a while loop
+        if options.for_loops && matches!(target, Target::Format) {
+            return Err("--ext-for does not apply to fmt.");
+        }
+        if options.xml_annotations && !matches!(target, Target::XML | Target::Both) {
+            return Err("--xml-annotations only applies to xml and both.");
+        }
+        if options.strict && (options.string_escapes || options.precedence || options.short_circuit || options.for_loops || options.break_continue || options.field_initializers || options.constants) {
+            return Err("--strict does not allow language extensions.");
+        }
+        if compare.is_some() && (matches!(target, Target::Format | Target::Both | Target::Asm | Target::Hack | Target::Doc) || !matches!(sources[..], [Source::File(_)])) {
+            return Err("--compare needs a single file and the xml or vm target.");
+        }
+
+        Ok(Config { sources, target, options, check, compare, stop_after, recursive, builtin_os, program, deny_warnings, emit_tokens, emit_ast, dump_ast, emit_interface, interfaces, emit_symbols, annotate, metrics, call_graph, keep_vm, keep_intermediates, merge_output, source_map, color, message_format, class_name, verbosity, timings, eval, emit_vm, selftest, output })
+    }
+}
+
+impl Config {
+    // whether the VM code is made, `codegen` then writes Foo.sym from the tables it was made with
+    fn generates_code(&self) -> bool {
+        matches!(self.stop_after, None | Some(Stage::Codegen)) && matches!(self.target, Target::VM | Target::Both | Target::Asm | Target::Hack)
+    }
+
+    fn denies_warnings(&self) -> bool {
+        self.deny_warnings || self.options.strict
+    }
+
+    // runs a phase for a file, timed with `--timings`
+    fn time<T>(&self, filename: &str, phase: Phase, run: impl FnOnce() -> T) -> T {
+        match &self.timings {
+            Some(timings) => timings.time(source_name(filename), phase, run),
+            None => run()
+        }
+    }
+
+    // the table on stderr, or a JSON object on stdout with `--message-format=json`
+    fn report_timings(&self) {
+        let Some(timings) = &self.timings else { return };
+        match self.message_format {
+            MessageFormat::Human => eprint!("{}", timings),
+            MessageFormat::Json => println!("{}", timings.json())
+        }
+    }
+
+    // under `--quiet` only the warnings that fail a file are shown
+    fn hides_warnings(&self) -> bool {
+        self.verbosity == Verbosity::Quiet && !self.denies_warnings()
+    }
+
+    // `Compiling: Main` and the like, under `--verbose`. It all goes to stderr, stdout is for the output
+    // of `-` and the JSON diagnostics of `--message-format=json`, which has none of it.
+    fn progress(&self, line: &str) {
+        if self.verbosity == Verbosity::Verbose && self.message_format == MessageFormat::Human {
+            eprintln!("{}", line);
+        }
+    }
+
+    // `3 succeeded, 0 failed` and what was asked for like `--metrics`, unless `--quiet`
+    fn status(&self, line: &str) {
+        if self.verbosity != Verbosity::Quiet && self.message_format == MessageFormat::Human {
+            eprintln!("{}", line);
+        }
+    }
+
+    // where the asm and hack targets write the program, named after its directory like MyGame/MyGame.asm
+    fn program_filename(&self, extension: &str) -> Result<String, Box<dyn Error>> {
+        match &self.sources[..] {
+            [Source::File(filename)] => Ok(filename.replace(".jack", extension)),
+            [Source::Directory(directory)] => {
+                let name = fs::canonicalize(directory)?.file_name().map(|name| name.to_string_lossy().into_owned());
+                let name = name.ok_or_else(|| format!("{}: the directory has no name to give the program", directory))?;
+                Ok(Path::new(directory).join(format!("{}{}", name, extension)).to_string_lossy().into_owned())
+            },
+            _ => Err("asm and hack need a single file or directory.".into())
+        }
+    }
+
+    // The .jack files of all sources in order, each once even when several sources lead to it
+    fn files(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut files = Vec::new();
+        for source in self.sources.iter() {
+            match source {
+                Source::File(filename) => files.push(filename.clone()),
+                Source::Stdin => files.push(STDIN.to_string()),
+                Source::Directory(directory) => jack_files(Path::new(directory), self.recursive, &mut files)?
+            }
+        }
+        let mut seen = HashSet::new();
+        files.retain(|file| seen.insert(fs::canonicalize(file).unwrap_or_else(|_| file.into())));
+        Ok(files)
+    }
+}
+
+// The .jack files of a directory sorted by path, with those of its subdirectories when recursive
+fn jack_files(directory: &Path, recursive: bool, files: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
+    let mut paths = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            if recursive {
+                jack_files(&path, recursive, files)?;
+            }
+        } else if path.extension() == Some("jack".as_ref()) {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn class_name_must_match_file_name() {
+        assert_eq!(class_name_mismatch("projects/11/Square/Square.jack", "Square"), None);
+        assert_eq!(
+            class_name_mismatch("projects/11/Square/Main.jack", "Square"),
+            Some("class Square should be declared in Square.jack".to_string())
+        );
+    }
+
+    #[test]
+    fn strict_mismatched_class_name_fails_without_output() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("Main.jack");
+        fs::write(&source, "class Square { function void f() { return; } }").unwrap();
+        let filename = source.to_str().unwrap().to_string();
+        let args = ["jack_analyzer".to_string(), filename.clone()];
+
+        let config = Config::new(args.clone().into_iter()).unwrap();
+        assert!(run(config).is_ok());
+        assert!(directory.path().join("Main.vm").exists());
+        fs::remove_file(directory.path().join("Main.vm")).unwrap();
+
+        let config = Config::new(args.into_iter().chain(std::iter::once("--strict".to_string()))).unwrap();
+        let error = run(config).unwrap_err();
+        assert!(error.to_string().contains("class Square should be declared in Square.jack"));
+        assert!(!directory.path().join("Main.vm").exists());
+    }
+
+    #[test]
+    fn empty_file_fails_in_directory_mode() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("Empty.jack"), "// to do\n").unwrap();
+        fs::write(directory.path().join("Main.jack"), "class Main { function void main() { return; } }").unwrap();
+        let args = ["jack_analyzer".to_string(), directory.path().to_str().unwrap().to_string()];
+
+        let error = run(Config::new(args.into_iter()).unwrap()).unwrap_err();
+        let empty = directory.path().join("Empty.jack");
+        let message = format!("no class declaration found in {}", empty.to_str().unwrap());
+        assert_eq!(error.to_string(), format!("{}\n1 succeeded, 1 failed:\n  {}", message, message));
+        assert!(!directory.path().join("Empty.vm").exists());
+        assert!(directory.path().join("Main.vm").exists());
+    }
+
+    #[test]
+    fn compare_writes_nothing() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("Main.jack");
+        fs::write(&source, "class Main { function void main() { return; } }").unwrap();
+        let expected = directory.path().join("Main.cmp");
+        fs::write(&expected, "function Main.main 0\n  push constant 0\nreturn\n").unwrap();
+        let args = |expected: &Path| vec![
+            "jack_analyzer".to_string(), source.to_str().unwrap().to_string(),
+            "--compare".to_string(), expected.to_str().unwrap().to_string()
+        ];
+
+        assert!(run(Config::new(args(&expected).into_iter()).unwrap()).is_ok());
+
+        fs::write(&expected, "function Main.main 1\n").unwrap();
+        let error = run(Config::new(args(&expected).into_iter()).unwrap()).unwrap_err();
+        assert!(error.to_string().ends_with("line 1\n  expected: function Main.main 1\n  found:    function Main.main 0"));
+        assert!(!directory.path().join("Main.vm").exists());
+
+        let mut directory_args = args(&expected);
+        directory_args[1] = directory.path().to_str().unwrap().to_string();
+        assert!(matches!(Config::new(directory_args.into_iter()), Err("--compare needs a single file and the xml or vm target.")));
+    }
+
+    #[test]
+    fn strict_rejects_extensions() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("Main.jack");
+        fs::write(&source, "class Main { function void main() { return; } }").unwrap();
+        for extension in ["--ext-string-escapes", "--precedence", "--ext-short-circuit"] {
+            let args = ["jack_analyzer", source.to_str().unwrap(), "--strict", extension].map(String::from);
+            assert!(matches!(Config::new(args.into_iter()), Err("--strict does not allow language extensions.")));
+        }
+        let args = ["jack_analyzer", source.to_str().unwrap(), "--strict"].map(String::from);
+        assert!(Config::new(args.into_iter()).unwrap().options.strict);
+    }
+
+    // compiles Main.jack in a directory of its own, returning the files written
+    fn stop_after(content: &str, stage: &str) -> Vec<(String, String)> {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("Main.jack");
+        fs::write(&source, content).unwrap();
+        let args = ["jack_analyzer", source.to_str().unwrap(), "--stop-after", stage].map(String::from);
+        run(Config::new(args.into_iter()).unwrap()).unwrap();
+        let mut outputs: Vec<(String, String)> = fs::read_dir(directory.path()).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path != &source)
+            .map(|path| (path.file_name().unwrap().to_str().unwrap().to_string(), fs::read_to_string(path).unwrap()))
+            .collect();
+        outputs.sort();
+        outputs
+    }
+
+    #[test]
+    fn stop_after_tokens() {
+        let outputs = stop_after(include_str!("../../ArrayTest/Main.jack"), "tokens");
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].0, "MainT.xml");
+        assert_eq!(compare::first_difference(include_str!("../../ArrayTest/MainT.xml"), &outputs[0].1), None);
+    }
+
+    #[test]
+    fn stop_after_parse() {
+        let outputs = stop_after(include_str!("../../ArrayTest/Main.jack"), "parse");
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].0, "Main.xml");
+        assert_eq!(compare::first_difference(include_str!("../../ArrayTest/Main.xml"), &outputs[0].1), None);
+    }
+
+    #[test]
+    fn stop_after_analyze() {
+        let outputs = stop_after("\
+class Main {
+    static int count;
+    field Array items, more;
+    method void add(int item) {
+        var int i;
+        var boolean found;
+        return;
+    }
+    function void main() { return; }
+}", "analyze");
+        assert_eq!(outputs, vec![("Main.sym".to_string(), "\
+class Main
+count int static 0
+items Array field 0
+more Array field 1
+
+subroutine Main.add
+this Main argument 0
+item int argument 1
+i int local 0
+found boolean local 1
+
+subroutine Main.main
+".to_string())]);
+    }
+
+    #[test]
+    fn stop_after_codegen() {
+        let content = "class Main { function void main() { do Output.printInt(1); return; } }";
+        assert_eq!(stop_after(content, "codegen"), vec![("Main.vm".to_string(), "\
+function Main.main 0
+push constant 1
+call Output.printInt 1
+pop temp 0
+push constant 0
+return
+".to_string())]);
+    }
+
+    #[test]
+    fn unknown_stage() {
+        let args = ["jack_analyzer", "src", "--stop-after", "link"].map(String::from);
+        assert!(matches!(Config::new(args.into_iter()), Err("--stop-after needs tokens, parse, analyze or codegen.")));
+    }
+
+    #[test]
+    fn output_replaces_the_file_in_one_step() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = directory.path().join("Main.vm");
+        fs::write(&output, "old").unwrap();
+        write_output(output.to_str().unwrap(), "new").unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "new");
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 1);
+
+        let missing = directory.path().join("missing").join("Main.vm");
+        let error = write_output(missing.to_str().unwrap(), "new").unwrap_err();
+        assert!(error.to_string().starts_with(&format!("{}: ", missing.to_str().unwrap())));
+        assert_eq!(write_output("..", "new").unwrap_err().to_string(), "..: not the name of a file");
+    }
+
+    #[test]
+    fn command_line_overrides_the_manifest() {
+        let directory = tempfile::tempdir().unwrap();
+        let project = directory.path().to_str().unwrap().to_string();
+        fs::create_dir(directory.path().join("src")).unwrap();
+        fs::write(directory.path().join("Other.jack"), "class Other {}").unwrap();
+        let manifest = directory.path().join(manifest::MANIFEST);
+        fs::write(&manifest, "\
+sources = [\"src\"]
+output = \"build\"
+target = \"xml\"
+optimize = true
+
+[extensions]
+for = true
+
+[warnings]
+unused = false
+deny = true
+").unwrap();
+        let load = |args: &[&str]| {
+            let args: Vec<String> = ["jack_analyzer", "--manifest-path", manifest.to_str().unwrap()].iter().chain(args)
+                .map(|arg| arg.to_string())
+                .collect();
+            Config::load(args.into_iter())
+        };
+        let sources = |config: &Config| -> Vec<String> {
+            config.sources.iter().map(|source| match source {
+                Source::File(file) | Source::Directory(file) => file.clone(),
+                Source::Stdin => STDIN.to_string()
+            }).collect()
+        };
+
+        let config = load(&[]).unwrap();
+        assert!(matches!(config.target, Target::XML));
+        assert_eq!(sources(&config), vec![format!("{}/src", project)]);
+        // the output directory is only for doc and the --emit files
+        assert_eq!(config.output, None);
+        assert!(config.options.optimize && config.options.for_loops && config.deny_warnings);
+        assert!(!config.options.warnings.enabled(Lint::Unused));
+
+        // a target, sources, -o and -W of the command line win, the rest stays
+        let other = format!("{}/Other.jack", project);
+        let config = load(&["vm", &other, "-o", "elsewhere", "-W", "unused", "--emit-ast"]).unwrap();
+        assert!(matches!(config.target, Target::VM));
+        assert_eq!(sources(&config), vec![other]);
+        assert_eq!(config.output.as_deref(), Some("elsewhere"));
+        assert!(config.options.warnings.enabled(Lint::Unused) && config.options.optimize);
+        // and what jack.toml turns on, the command line turns off
+        let config = load(&["--no-optimize", "--no-ext-for", "--no-deny-warnings"]).unwrap();
+        assert!(!config.options.optimize && !config.options.for_loops && !config.deny_warnings);
+        let config = load(&["--no-optimize", "-O"]).unwrap();
+        assert!(config.options.optimize);
+        let config = load(&["doc"]).unwrap();
+        assert!(matches!(config.target, Target::Doc));
+        assert_eq!(sources(&config), vec![format!("{}/src", project)]);
+        assert_eq!(config.output, Some(format!("{}/build", project)));
+
+        fs::write(&manifest, "target = \"vm\"\n[warnings]\nunused = \"off\"\n").unwrap();
+        assert_eq!(load(&[]).err(), Some(format!("{}:3: unused has to be true or false", manifest.display())));
+        fs::write(&manifest, "sources = [\"missing\"]\n").unwrap();
+        assert_eq!(load(&[]).err(), Some(format!("{}: no file or directory {}/missing", manifest.display(), project)));
+        assert_eq!(Config::load(["jack_analyzer", "--manifest-path"].iter().map(|arg| arg.to_string())).err().as_deref(), Some("Missing manifest path."));
+    }
+
+    #[test]
+    fn outputs_never_replace_the_sources() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("Main.jack");
+        let content = "class Main { function void main() { return; } }";
+        fs::write(&source, content).unwrap();
+        let source = source.to_str().unwrap();
+        let run_with = |args: &[&str]| {
+            let args: Vec<String> = ["jack_analyzer", source].iter().chain(args).map(|arg| arg.to_string()).collect();
+            run(Config::new(args.into_iter()).unwrap()).unwrap_err().to_string()
+        };
+        // by another name of the same file
+        let merged = directory.path().join(".").join("Main.jack");
+        assert_eq!(
+            run_with(&["--merge-output", merged.to_str().unwrap()]),
+            format!("{} would overwrite the source {}", merged.display(), source)
+        );
+        assert_eq!(run_with(&["--call-graph", source]), format!("{} would overwrite the source {}", source, source));
+        assert_eq!(fs::read_to_string(source).unwrap(), content);
+        assert!(!directory.path().join("Main.vm").exists());
+    }
+
+    #[test]
+    fn sources_writing_the_same_output_fail_before_writing() {
+        let directory = tempfile::tempdir().unwrap();
+        for game in ["a", "b"] {
+            fs::create_dir(directory.path().join(game)).unwrap();
+            fs::write(directory.path().join(game).join("Main.jack"), "class Main { function void main() { return; } }").unwrap();
+        }
+        let out = directory.path().join("out");
+        let path = |path: PathBuf| path.to_str().unwrap().to_string();
+        let (a, b) = (path(directory.path().join("a")), path(directory.path().join("b")));
+        let args = ["jack_analyzer", &a, &b, "--emit-ast", "-o", &path(out.clone())];
+        let error = run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("{}/Main.jack and {}/Main.jack would both write {}", a, b, path(out.join("Main.ast.xml")))
+        );
+        assert!(!directory.path().join("a").join("Main.vm").exists());
+        assert_eq!(fs::read_dir(&out).unwrap().count(), 0);
+
+        // a file where the directory has to be
+        fs::write(directory.path().join("taken"), "").unwrap();
+        let taken = path(directory.path().join("taken").join("out"));
+        let args = ["jack_analyzer", &a, "--emit-ast", "-o", &taken];
+        let error = run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap_err();
+        assert!(error.to_string().starts_with(&format!("{}: ", taken)), "{}", error);
+    }
+
+    #[test]
+    fn failed_compile_keeps_previous_output() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("Main.jack");
+        fs::write(&source, "class Main { function void main() { return } }").unwrap();
+        fs::write(directory.path().join("Main.vm"), "previous").unwrap();
+        let args = ["jack_analyzer", source.to_str().unwrap()].map(String::from);
+        assert!(run(Config::new(args.into_iter()).unwrap()).is_err());
+        assert_eq!(fs::read_to_string(directory.path().join("Main.vm")).unwrap(), "previous");
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn files_and_directories() {
+        let directory = tempfile::tempdir().unwrap();
+        let src = directory.path().join("src");
+        let extra = directory.path().join("extra");
+        fs::create_dir(&src).unwrap();
+        fs::create_dir(&extra).unwrap();
+        for file in [src.join("Main.jack"), src.join("Game.jack"), extra.join("Helper.jack")] {
+            let class = file.file_stem().unwrap().to_str().unwrap().to_string();
+            fs::write(&file, format!("class {} {{ function void f() {{ return; }} }}", class)).unwrap();
+        }
+        let path = |path: std::path::PathBuf| path.to_str().unwrap().to_string();
+        let args = vec![
+            "jack_analyzer".to_string(), path(src.clone()), path(extra.join("Helper.jack")), "xml".to_string()
+        ];
+        let config = Config::new(args.into_iter()).unwrap();
+        assert_eq!(config.files().unwrap(), vec![
+            path(src.join("Game.jack")), path(src.join("Main.jack")), path(extra.join("Helper.jack"))
+        ]);
+
+        run(config).unwrap();
+        assert!(src.join("Game.xml").exists());
+        assert!(src.join("Main.xml").exists());
+        assert!(extra.join("Helper.xml").exists());
+    }
+
+    #[test]
+    fn file_reached_twice_compiles_once() {
+        let directory = tempfile::tempdir().unwrap();
+        let main = directory.path().join("Main.jack");
+        fs::write(&main, "class Main { function void main() { return; } }").unwrap();
+        let main = main.to_str().unwrap().to_string();
+        let directory = directory.path().to_str().unwrap().to_string();
+        let args = vec!["jack_analyzer".to_string(), main.clone(), directory.clone(), format!("{}/.", directory)];
+        let config = Config::new(args.into_iter()).unwrap();
+        assert_eq!(config.files().unwrap(), vec![main]);
+    }
+
+    #[test]
+    fn directory_summary() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("Broken.jack"), "\
+class Broken {
+    function void f() {
+        let x = ;
+        do g(;
+        return;
+    }
+}").unwrap();
+        fs::write(directory.path().join("Good.jack"), "class Good { function void f() { return; } }").unwrap();
+        let args = ["jack_analyzer".to_string(), directory.path().to_str().unwrap().to_string()];
+
+        let error = run(Config::new(args.into_iter()).unwrap()).unwrap_err().to_string();
+        let broken = directory.path().join("Broken.jack");
+        let broken = broken.to_str().unwrap();
+        assert_eq!(error, format!("\
+{broken}:3:17: expected an expression, found ';'
+  |
+3 |         let x = ;
+  |                 ^
+{broken}:4:14: expected an expression, found ';'
+  |
+4 |         do g(;
+  |              ^
+1 succeeded, 1 failed:
+  {broken}:3:17: expected an expression, found ';'"));
+        assert!(directory.path().join("Good.vm").exists());
+        assert!(!directory.path().join("Broken.vm").exists());
+    }
+
+    #[test]
+    fn doc_writes_a_page_for_each_class_and_the_index() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("Main.jack"), "\
+/** Starts the game. */
+class Main {
+    /** The entry point. */
+    function void main() { return; }
+}").unwrap();
+        fs::write(directory.path().join("Ball.jack"), "class Ball { method void move() { return; } }").unwrap();
+        let docs = directory.path().join("out/docs");
+        let args = ["jack_analyzer", directory.path().to_str().unwrap(), "doc", "-o", docs.to_str().unwrap()];
+
+        run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(docs.join("index.md")).unwrap(), "\
+# Classes
+
+- [Ball](Ball.md)
+- [Main](Main.md): Starts the game.
+
+## Undocumented
+
+- `Ball.move`
+");
+        assert!(fs::read_to_string(docs.join("Main.md")).unwrap().contains("`function void main()`\n\nThe entry point.\n"));
+        assert!(docs.join("Ball.md").exists());
+
+        let args = ["jack_analyzer", directory.path().to_str().unwrap(), "vm", "-o", "docs"];
+        assert_eq!(Config::new(args.iter().map(|arg| arg.to_string())).err(), Some("-o only applies to doc, the --emit files and --dump-ast."));
+    }
+
+    #[test]
+    fn both_targets_match_the_single_ones() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("Main.jack");
+        fs::write(&source, include_str!("../../Square/Square.jack").replace("class Square", "class Main")).unwrap();
+        let compile = |target: &str| {
+            let args = ["jack_analyzer", source.to_str().unwrap(), target].map(String::from);
+            run(Config::new(args.into_iter()).unwrap()).unwrap();
+        };
+        let read = |extension: &str| fs::read_to_string(directory.path().join(format!("Main.{}", extension))).unwrap();
+
+        compile("xml");
+        compile("vm");
+        let (xml, vm) = (read("xml"), read("vm"));
+        fs::remove_file(directory.path().join("Main.xml")).unwrap();
+        fs::remove_file(directory.path().join("Main.vm")).unwrap();
+
+        compile("both");
+        assert_eq!(read("xml"), xml);
+        assert_eq!(read("vm"), vm);
+    }
+
+    #[test]
+    fn directory_skips_other_entries() {
+        let directory = tempfile::tempdir().unwrap();
+        let nested = directory.path().join("nested");
+        fs::create_dir(&nested).unwrap();
+        fs::write(directory.path().join("README"), "not jack").unwrap();
+        fs::write(directory.path().join(".gitignore"), "*.vm").unwrap();
+        for file in [directory.path().join("Main.jack"), directory.path().join("Game.jack"), nested.join("Helper.jack")] {
+            let class = file.file_stem().unwrap().to_str().unwrap().to_string();
+            fs::write(&file, format!("class {} {{ function void f() {{ return; }} }}", class)).unwrap();
+        }
+        let path = |path: std::path::PathBuf| path.to_str().unwrap().to_string();
+        let config = |flags: &[&str]| {
+            let mut args = vec!["jack_analyzer".to_string(), path(directory.path().to_path_buf())];
+            args.extend(flags.iter().map(|flag| flag.to_string()));
+            Config::new(args.into_iter()).unwrap()
+        };
+
+        assert_eq!(config(&[]).files().unwrap(), vec![
+            path(directory.path().join("Game.jack")), path(directory.path().join("Main.jack"))
+        ]);
+        assert_eq!(config(&["--recursive"]).files().unwrap(), vec![
+            path(directory.path().join("Game.jack")), path(directory.path().join("Main.jack")), path(nested.join("Helper.jack"))
+        ]);
+
+        run(config(&["--recursive"])).unwrap();
+        assert!(directory.path().join("Main.vm").exists());
+        assert!(nested.join("Helper.vm").exists());
+    }
+
+    #[test]
+    fn undefined_variable_does_not_stop_other_files() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("A.jack"), "class A { function int f() { return x; } }").unwrap();
+        fs::write(directory.path().join("B.jack"), "class B { function int f() { return 1; } }").unwrap();
+        let args = ["jack_analyzer".to_string(), directory.path().to_str().unwrap().to_string()];
+
+        let error = run(Config::new(args.into_iter()).unwrap()).unwrap_err().to_string();
+        let a = directory.path().join("A.jack");
+        assert!(error.starts_with(&format!("{}:1:37: undefined variable x in A.f", a.to_str().unwrap())));
+        assert!(directory.path().join("B.vm").exists());
+        assert!(!directory.path().join("A.vm").exists());
+    }
+
+    #[test]
+    fn calls_to_the_os_are_checked() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("Main.jack"), "\
+class Main {
+    function void main() {
+        do Output.printInt(\"hi\");
+        do Output.printInt(Math.multiply(2));
+        return;
+    }
+}").unwrap();
+        fs::write(directory.path().join("Game.jack"), "class Game { function void run() { return; } }").unwrap();
+        let run_with = |flags: &[&str]| {
+            let mut args = vec!["jack_analyzer".to_string(), directory.path().to_str().unwrap().to_string()];
+            args.extend(flags.iter().map(|flag| flag.to_string()));
+            run(Config::new(args.into_iter()).unwrap())
+        };
+
+        let error = run_with(&[]).unwrap_err().to_string();
+        let main = directory.path().join("Main.jack");
+        let main = main.to_str().unwrap();
+        assert!(error.starts_with(&format!("\
+{main}:3:12: Output.printInt takes int as argument 1, found a string constant
+  |
+3 |         do Output.printInt(\"hi\");
+  |            ^^^^^^
+  note: Output.printInt is part of the Jack OS
+{main}:4:28: Math.multiply takes 2 arguments, found 1
+  |
+4 |         do Output.printInt(Math.multiply(2));
+  |                            ^^^^
+  note: Math.multiply is part of the Jack OS\n")));
+
+        run_with(&["--no-builtin-os"]).unwrap();
+        assert!(directory.path().join("Main.vm").exists());
+    }
+
+    #[test]
+    fn the_projects_os_classes_are_checked_against_the_api() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("Main.jack"), "\
+class Main {
+    function void main() {
+        do Output.printInt(Math.multiply(2, 3, 4));
+        return;
+    }
+}").unwrap();
+        // a deliberately wrong multiply, which the other classes are checked against all the same
+        let math = "class Math { function int multiply(int x, int y, int z) { return x; } }";
+        fs::write(directory.path().join("Math.jack"), math).unwrap();
+        let run_with = |flags: &[&str]| {
+            let mut args = vec!["jack_analyzer".to_string(), directory.path().to_str().unwrap().to_string(), "--deny-warnings".to_string()];
+            args.extend(flags.iter().map(|flag| flag.to_string()));
+            run(Config::new(args.into_iter()).unwrap())
+        };
+        let error = run_with(&[]).unwrap_err().to_string();
+        assert!(error.contains(":1:27: Math.multiply takes 3 parameters where the Jack OS takes 2 [-W os-conformance]\n"), "{}", error);
+        assert!(error.contains("note: the Jack OS declares function int multiply(int, int)"));
+        assert!(error.contains(":1:7: Math.abs of the Jack OS is missing [-W os-conformance]\n"));
+        assert!(!error.contains("Main.jack"));
+
+        run_with(&["--no-os-conformance"]).unwrap();
+        assert!(directory.path().join("Math.vm").exists() && directory.path().join("Main.vm").exists());
+    }
+
+    #[test]
+    fn duplicate_classes_fail_the_project() {
+        let directory = tempfile::tempdir().unwrap();
+        let old = directory.path().join("old");
+        fs::create_dir(&old).unwrap();
+        for file in [directory.path().join("Ball.jack"), old.join("Ball.jack"), directory.path().join("Main.jack")] {
+            let class = file.file_stem().unwrap().to_str().unwrap().to_string();
+            fs::write(&file, format!("class {} {{ function void f() {{ return; }} }}", class)).unwrap();
+        }
+        let path = |path: std::path::PathBuf| path.to_str().unwrap().to_string();
+        let run_with = |flags: &[&str]| {
+            let mut args = vec!["jack_analyzer".to_string(), path(directory.path().to_path_buf())];
+            args.extend(flags.iter().map(|flag| flag.to_string()));
+            run(Config::new(args.into_iter()).unwrap())
+        };
+
+        let error = run_with(&["--recursive"]).unwrap_err().to_string();
+        assert_eq!(error, format!(
+            "class Ball is declared in both {} and {}", path(directory.path().join("Ball.jack")), path(old.join("Ball.jack"))
+        ));
+        assert!(!directory.path().join("Main.vm").exists());
+        // a single file is compiled on its own
+        run(Config::new([String::from("jack_analyzer"), path(old.join("Ball.jack"))].into_iter()).unwrap()).unwrap();
+        run_with(&[]).unwrap();
+    }
+
+    #[test]
+    fn program_needs_main() {
+        let directory = tempfile::tempdir().unwrap();
+        let main = directory.path().join("Main.jack");
+        let run_with = |main_class: &str| {
+            fs::write(&main, main_class).unwrap();
+            let args = ["jack_analyzer", directory.path().to_str().unwrap(), "--program"];
+            run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).map_err(|e| e.to_string())
+        };
+        fs::write(directory.path().join("Game.jack"), "class Game { function void run() { return; } }").unwrap();
+
+        run_with("class Main { function void main() { do Game.run(); return; } }").unwrap();
+        assert_eq!(
+            run_with("class main { function void main() { return; } }").unwrap_err(),
+            "the program has no class Main\n  note: there is a class main, class names are case-sensitive"
+        );
+        assert_eq!(
+            run_with("class Main { function void Main() { return; } }").unwrap_err(),
+            format!("class Main has no function main\n  note: Main.Main is declared at {}:1:28, subroutine names are case-sensitive", main.display())
+        );
+        assert_eq!(
+            run_with("class Main { method void main() { return; } }").unwrap_err(),
+            format!("Main.main is a method, the OS calls it as a function without an instance\n  note: Main.main is declared at {}:1:26", main.display())
+        );
+        assert_eq!(
+            run_with("class Main { function void main(int n) { return; } }").unwrap_err(),
+            format!("Main.main takes 1 argument, the OS calls it without any\n  note: Main.main is declared at {}:1:28", main.display())
+        );
+    }
+
+    #[test]
+    fn warning_flags() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("A.jack");
+        fs::write(&source, "\
+class A {
+    field int x;
+    function void f(int n) {
+        return;
+        let n[1] = 1;
+        let n = true;
+    }
+}").unwrap();
+        let source = source.to_str().unwrap();
+        let run_with = |flags: &[&str]| {
+            let mut args = vec!["jack_analyzer", source];
+            args.extend(flags);
+            run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).map_err(|e| e.to_string())
+        };
+
+        run_with(&[]).unwrap();
+        assert_eq!(run_with(&["--deny-warnings"]).unwrap_err(), format!("\
+{source}:2:15: field x of A is never used [-W unused]
+  |
+2 |     field int x;
+  |               ^
+{source}:5:9: unreachable statement [-W unreachable]
+  |
+5 |         let n[1] = 1;
+  |         ^^^
+  note: it follows the return at 4:9
+{source}:6:9: unreachable statement [-W unreachable]
+  |
+6 |         let n = true;
+  |         ^^^
+  note: it follows the return at 4:9
+{source}:5:13: indexing n, which is declared int, not Array [-W index-non-array]
+  |
+5 |         let n[1] = 1;
+  |             ^
+  note: n is declared at 3:25
+  |
+3 |     function void f(int n) {{
+  |                         - n is declared here"));
+        run_with(&["-W", "no-unused", "-W", "no-unreachable", "-W", "no-index-non-array", "--deny-warnings"]).unwrap();
+        assert!(run_with(&["-W", "no-all", "-W", "unused", "--strict"]).unwrap_err().contains("never used [-W unused]\n"));
+        // off by default
+        assert!(run_with(&["-W", "no-all", "-W", "type-check", "--deny-warnings"]).unwrap_err().contains("[-W type-check]"));
+        assert!(run_with(&["-W", "all", "--deny-warnings"]).unwrap_err().contains("[-W type-check]"));
+        assert!(run_with(&["-W", "no-all", "-W", "naming", "--deny-warnings"]).unwrap_err().contains("is a single letter [-W naming-short]"));
+        run_with(&["-W", "no-all", "-W", "naming", "-W", "no-naming-short", "--deny-warnings"]).unwrap();
+        let error = run_with(&["-W", "no-all", "-W", "complexity", "--max-statements", "1", "--deny-warnings"]).unwrap_err();
+        assert!(error.contains("statements, more than 1 [-W complexity]"));
+        run_with(&["-W", "no-all", "-W", "complexity", "--deny-warnings"]).unwrap();
+
+        for flags in [&["-W"][..], &["-W", "shadowing"], &["-W", "no-"]] {
+            let mut args = vec!["jack_analyzer", source];
+            args.extend(flags);
+            assert!(Config::new(args.iter().map(|arg| arg.to_string())).is_err(), "{:?}", flags);
+        }
+    }
+
+    #[test]
+    fn emitted_symbols_match_the_vm_code() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("Ball.jack");
+        fs::write(&source, "\
+class Ball {
+    field int y, x;
+    static int count;
+    static Ball last;
+    method void move(int dx, int dy) {
+        var int speed, steps;
+        let speed = dx + dy;
+        let steps = speed;
+        let x = x + steps;
+        let y = y + dy;
+        let count = count + 1;
+        let last = this;
+        return;
+    }
+}").unwrap();
+        let args = ["jack_analyzer", source.to_str().unwrap(), "--emit-symbols"];
+        run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        let symbols = fs::read_to_string(directory.path().join("Ball.sym")).unwrap();
+        assert_eq!(symbols, "\
+class Ball
+count int static 0
+last Ball static 1
+y int field 0
+x int field 1
+
+subroutine Ball.move
+this Ball argument 0
+dx int argument 1
+dy int argument 2
+speed int local 0
+steps int local 1
+");
+
+        // every variable is used, at the segment and index of its symbol
+        let vm = fs::read_to_string(directory.path().join("Ball.vm")).unwrap();
+        for line in symbols.lines().filter(|line| line.split(' ').count() == 4) {
+            let [_, _, kind, index] = line.split(' ').collect::<Vec<_>>()[..] else { unreachable!() };
+            let segment = if kind == "field" { "this" } else { kind };
+            assert!(vm.contains(&format!(" {} {}\n", segment, index)), "{}", line);
+        }
+        assert!(vm.contains("pop this 1\n") && vm.contains("pop static 1\n") && vm.contains("pop local 1\n"));
+
+        let args = ["jack_analyzer", source.to_str().unwrap(), "--stop-after", "tokens", "--emit-symbols"];
+        assert!(Config::new(args.iter().map(|arg| arg.to_string())).is_err());
+    }
+
+    #[test]
+    fn emit_flags_write_the_intermediates() {
+        let directory = tempfile::tempdir().unwrap();
+        let game = directory.path().join("Game");
+        fs::create_dir(&game).unwrap();
+        fs::write(game.join("Main.jack"), "class Main { static int n; function void main() { let n = 1; return; } }").unwrap();
+        let out = directory.path().join("out");
+        let args = ["jack_analyzer", game.to_str().unwrap(), "hack", "-o", out.to_str().unwrap(), "--emit-tokens", "--emit-ast", "--emit-symbols"];
+        run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        assert!(game.join("Game.hack").exists());
+        let tokens = fs::read_to_string(out.join("Main.tokens.xml")).unwrap();
+        assert!(tokens.starts_with("<tokens>\n<keyword> class </keyword>\n<identifier> Main </identifier>\n"));
+        assert!(tokens.ends_with("<symbol> } </symbol>\n</tokens>\n"));
+        let ast = fs::read_to_string(out.join("Main.ast.xml")).unwrap();
+        assert!(ast.starts_with("<class>\n  <keyword> class </keyword>\n") && ast.contains("<letStatement>"));
+        assert_eq!(fs::read_to_string(out.join("Main.sym")).unwrap(), "class Main\nn int static 0\n\nsubroutine Main.main\n");
+        // the sources are left alone
+        assert!(!game.join("Main.sym").exists());
+
+        // without code, Foo.sym comes from the analysis, next to the source
+        let args = ["jack_analyzer", game.to_str().unwrap(), "xml", "--emit-symbols"];
+        run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(game.join("Main.sym")).unwrap(), fs::read_to_string(out.join("Main.sym")).unwrap());
+        assert!(game.join("Main.xml").exists());
+    }
+
+    #[test]
+    fn dump_ast_writes_the_tree_to_the_output_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        let main = directory.path().join("Main.jack");
+        fs::write(&main, "class Main { function void main() { return; } }").unwrap();
+        let out = directory.path().join("out");
+        // also for fmt, which parses only for the `--emit` files otherwise
+        let args = ["jack_analyzer", main.to_str().unwrap(), "fmt", "--dump-ast", "-o", out.to_str().unwrap()];
+        run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        assert_eq!(
+            fs::read_to_string(out.join("Main.ast.txt")).unwrap(),
+            "Class Main @1:7\n  SubroutineDec function void main() @1:28\n    ReturnStatement @1:37\n"
+        );
+        let args = ["jack_analyzer", main.to_str().unwrap(), "--dump-ast", "--stop-after", "tokens"];
+        assert!(Config::new(args.iter().map(|arg| arg.to_string())).is_err());
+    }
+
+    #[test]
+    fn output_does_not_depend_on_the_order_of_the_files() {
+        let pong = Path::new("../../11/Pong");
+        let mut names: Vec<String> = fs::read_dir(pong).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".jack"))
+            .collect();
+        names.sort();
+        let mut outputs = Vec::new();
+        for reversed in [false, false, true] {
+            let directory = tempfile::tempdir().unwrap();
+            if reversed {
+                names.reverse();
+            }
+            // the files are created in a different order on disk
+            for name in names.iter() {
+                fs::copy(pong.join(name), directory.path().join(name)).unwrap();
+            }
+            let call_graph = directory.path().join("calls.dot");
+            let args = ["jack_analyzer", directory.path().to_str().unwrap(), "both", "--call-graph", call_graph.to_str().unwrap()];
+            run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+            let mut files: Vec<(String, Vec<u8>)> = fs::read_dir(directory.path()).unwrap()
+                .map(|entry| entry.unwrap().path())
+                .map(|path| (path.file_name().unwrap().to_string_lossy().into_owned(), fs::read(&path).unwrap()))
+                .collect();
+            files.sort();
+            assert_eq!(files.len(), names.len() * 3 + 1);
+            outputs.push(files);
+        }
+        assert_eq!(outputs[0], outputs[1]);
+        assert_eq!(outputs[0], outputs[2]);
+    }
+
+    #[test]
+    fn annotate_puts_source_lines_before_their_code() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("Sum.jack");
+        fs::write(&source, "\
+class Sum {
+    function int of(Array a, int n) {
+        var int i, sum;
+        while (i < n) {
+            let sum = sum + a[i];
+            let i = i + 1;
+        }
+        return sum;
+    }
+}").unwrap();
+        let args = ["jack_analyzer", source.to_str().unwrap()];
+        run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        let plain = fs::read_to_string(directory.path().join("Sum.vm")).unwrap();
+        assert!(!plain.contains("//"));
+
+        let args = ["jack_analyzer", source.to_str().unwrap(), "--annotate"];
+        run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        let annotated = fs::read_to_string(directory.path().join("Sum.vm")).unwrap();
+        assert!(annotated.starts_with("\
+// function int Sum.of(Array a, int n)
+function Sum.of 2
+// Sum.jack:4: while (i < n) {
+label SUM_0
+"));
+        assert!(annotated.contains("\
+// Sum.jack:5: let sum = sum + a[i];
+push local 1
+"));
+        assert!(annotated.contains("\
+// Sum.jack:6: let i = i + 1;
+push local 0
+push constant 1
+add
+pop local 0
+"));
+        assert!(annotated.ends_with("\
+// Sum.jack:8: return sum;
+push local 1
+return
+"));
+        // without the comments the code is the same
+        let stripped: String = annotated.lines().filter(|line| !line.starts_with("//")).map(|line| format!("{}\n", line)).collect();
+        assert_eq!(stripped, plain);
+
+        let args = ["jack_analyzer", source.to_str().unwrap(), "xml", "--annotate"];
+        assert!(Config::new(args.iter().map(|arg| arg.to_string())).is_err());
+    }
+
+    #[test]
+    fn source_map_leads_back_to_the_statements() {
+        let directory = tempfile::tempdir().unwrap();
+        let source = directory.path().join("Rect.jack");
+        fs::write(&source, "\
+class Rect {
+    field int width, height;
+    method int area() {
+        var int area;
+        if (width < 0) {
+            return 0;
+        }
+        let area = width * height;
+        return area;
+    }
+}").unwrap();
+        let args = ["jack_analyzer", source.to_str().unwrap(), "--source-map"];
+        run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        let vm = fs::read_to_string(directory.path().join("Rect.vm")).unwrap();
+        let map = fs::read_to_string(directory.path().join("Rect.vm.map")).unwrap();
+        let position = |vm_line: usize| map.lines()
+            .find_map(|record| record.strip_prefix(&format!("{} Rect.jack:", vm_line)))
+            .unwrap()
+            .to_string();
+
+        // every line has a record, the multiplication comes from the let on line 8
+        assert_eq!(map.lines().filter(|record| !record.starts_with("function ")).count(), vm.lines().count());
+        let multiply = vm.lines().position(|line| line == "call Math.multiply 2").unwrap() + 1;
+        assert_eq!(position(multiply), "8:9");
+        assert!(map.starts_with("function Rect.area 1 Rect.jack:3:16\n1 Rect.jack:3:16\n"));
+        // the jumps around the branch belong to the if
+        let goto = vm.lines().position(|line| line.starts_with("goto ")).unwrap() + 1;
+        assert_eq!(position(goto), "5:9");
+        assert_eq!(position(goto - 1), "6:13");
+
+        let args = ["jack_analyzer", source.to_str().unwrap(), "xml", "--source-map"];
+        assert!(Config::new(args.iter().map(|arg| arg.to_string())).is_err());
+    }
+
+    #[test]
+    fn asm_translates_the_whole_program() {
+        let root = tempfile::tempdir().unwrap();
+        let directory = root.path().join("MyGame");
+        fs::create_dir(&directory).unwrap();
+        fs::write(directory.join("Main.jack"), "class Main { function void main() { do Math.abs(1); return; } }").unwrap();
+        // stand in for the OS, the .vm files without a .jack file are part of the program
+        fs::write(directory.join("Math.vm"), "function Math.abs 0\npush argument 0\nreturn\n").unwrap();
+        fs::write(directory.join("Sys.vm"), "function Sys.init 0\ncall Main.main 0\npop temp 0\nlabel HALT\ngoto HALT\n").unwrap();
+        let asm = directory.join("MyGame.asm");
+        let path = directory.to_str().unwrap();
+        let compile = |args: &[&str]| {
+            let args: Vec<String> = ["jack_analyzer", path, "asm"].iter().chain(args).map(|arg| arg.to_string()).collect();
+            run(Config::new(args.into_iter()).unwrap())
+        };
+
+        compile(&[]).unwrap();
+        let assembly = fs::read_to_string(&asm).unwrap();
+        assert!(assembly.starts_with("// Bootstrap\n"));
+        for function in ["(Main.main)", "(Math.abs)", "(Sys.init)"] {
+            assert!(assembly.contains(function), "{}", function);
+        }
+        assert!(!directory.join("Main.vm").exists());
+        compile(&["--keep-vm"]).unwrap();
+        assert!(directory.join("Main.vm").exists());
+        assert_eq!(fs::read_to_string(&asm).unwrap(), assembly);
+
+        // an error of the translator names its file
+        fs::write(directory.join("Math.vm"), "function Math.abs 0\npush nowhere 0\nreturn\n").unwrap();
+        let error = compile(&[]).unwrap_err().to_string();
+        let math = directory.join("Math.vm");
+        assert_eq!(error, format!("{}:2: not a VM command: push nowhere 0", math.to_str().unwrap()));
+
+        let args = ["jack_analyzer", path, "vm", "--keep-vm"];
+        assert!(Config::new(args.iter().map(|arg| arg.to_string())).is_err());
+    }
+
+    // the RAM after running the machine code for a number of cycles on a Hack CPU
+    fn run_hack(binary: &str, cycles: usize) -> Vec<i16> {
+        let rom: Vec<u16> = binary.lines().map(|line| u16::from_str_radix(line, 2).unwrap()).collect();
+        let mut ram = vec![0i16; 32768];
+        let (mut a, mut d, mut pc) = (0i16, 0i16, 0usize);
+        for _ in 0..cycles {
+            let instruction = rom[pc];
+            pc += 1;
+            if instruction & 0x8000 == 0 {
+                a = instruction as i16;
+                continue;
+            }
+            let bit = |n: u16| instruction >> n & 1 == 1;
+            let mut x = d;
+            let mut y = if bit(12) { ram[a as u16 as usize & 0x7fff] } else { a };
+            if bit(11) { x = 0; }
+            if bit(10) { x = !x; }
+            if bit(9) { y = 0; }
+            if bit(8) { y = !y; }
+            let mut out = if bit(7) { x.wrapping_add(y) } else { x & y };
+            if bit(6) { out = !out; }
+            if bit(3) {
+                ram[a as u16 as usize & 0x7fff] = out;
+            }
+            let jump = (bit(2) && out < 0) || (bit(1) && out == 0) || (bit(0) && out > 0);
+            if jump {
+                pc = a as u16 as usize;
+            }
+            if bit(5) { a = out; }
+            if bit(4) { d = out; }
+        }
+        ram
+    }
+
+    #[test]
+    fn hack_runs_on_the_cpu() {
+        let root = tempfile::tempdir().unwrap();
+        let directory = root.path().join("Tiny");
+        fs::create_dir(&directory).unwrap();
+        fs::write(directory.join("Main.jack"), "\
+class Main {
+    function void main() {
+        var Array a;
+        let a = 8000;
+        let a[0] = 7 + 5;
+        let a[1] = Main.twice(a[0]);
+        return;
+    }
+    function int twice(int x) {
+        return x + x;
+    }
+}").unwrap();
+        fs::write(directory.join("Sys.vm"), "function Sys.init 0\ncall Main.main 0\npop temp 0\nlabel HALT\ngoto HALT\n").unwrap();
+        let path = directory.to_str().unwrap();
+        let compile = |args: &[&str]| {
+            let args: Vec<String> = ["jack_analyzer", path, "hack"].iter().chain(args).map(|arg| arg.to_string()).collect();
+            run(Config::new(args.into_iter()).unwrap())
+        };
+
+        compile(&[]).unwrap();
+        let binary = fs::read_to_string(directory.join("Tiny.hack")).unwrap();
+        let ram = run_hack(&binary, 10000);
+        assert_eq!(&ram[8000..8002], &[12, 24]);
+        assert!(!directory.join("Tiny.asm").exists());
+        assert!(!directory.join("Main.vm").exists());
+
+        compile(&["--keep-intermediates"]).unwrap();
+        assert!(directory.join("Tiny.asm").exists());
+        assert!(directory.join("Main.vm").exists());
+        assert_eq!(fs::read_to_string(directory.join("Tiny.hack")).unwrap(), binary);
+    }
+
+    #[test]
+    fn hack_with_the_whole_os_is_larger_than_the_rom() {
+        let root = tempfile::tempdir().unwrap();
+        let directory = root.path().join("Hello");
+        fs::create_dir(&directory).unwrap();
+        for os in fs::read_dir("../../../tools/OS").unwrap() {
+            let os = os.unwrap().path();
+            fs::copy(&os, directory.join(os.file_name().unwrap())).unwrap();
+        }
+        fs::write(directory.join("Main.jack"), "class Main { function void main() { do Output.printInt(1); return; } }").unwrap();
+        let args = ["jack_analyzer", directory.to_str().unwrap(), "hack"];
+        let error = run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap_err().to_string();
+        let asm = directory.join("Hello.asm");
+        assert!(error.starts_with(&format!("{}: the program has ", asm.to_str().unwrap())), "{}", error);
+        assert!(error.ends_with(" instructions, the ROM of the Hack computer holds 32768"), "{}", error);
+        assert!(!directory.join("Hello.hack").exists());
+    }
+
+    #[test]
+    fn merged_output_keeps_the_statics_of_each_class() {
+        let directory = tempfile::tempdir().unwrap();
+        for class in ["A", "B"] {
+            let source = format!(
+                "class {0} {{ static int x; function void set(int v) {{ let x = v; return; }} function int get() {{ return x; }} }}", class
+            );
+            fs::write(directory.path().join(format!("{}.jack", class)), source).unwrap();
+        }
+        fs::write(directory.path().join("Main.jack"), "\
+class Main {
+    function void main() {
+        var Array a;
+        let a = 8000;
+        do A.set(3);
+        do B.set(4);
+        let a[0] = A.get();
+        let a[1] = B.get();
+        return;
+    }
+}").unwrap();
+        let merged = directory.path().join("program.vm");
+        let args = ["jack_analyzer", directory.path().to_str().unwrap(), "--merge-output", merged.to_str().unwrap()];
+        run(Config::new(args.iter().map(|arg| arg.to_string())).unwrap()).unwrap();
+        let vm = fs::read_to_string(&merged).unwrap();
+        assert!(!directory.path().join("A.vm").exists());
+        assert!(vm.starts_with("// A.jack, statics from 0\nfunction A.set 0\n"));
+        assert!(vm.contains("// B.jack, statics from 1\nfunction B.set 0\npush argument 0\npop static 1\n"));
+
+        // run on the CPU the classes don't share their static
+        let sys = "function Sys.init 0\ncall Main.main 0\npop temp 0\nlabel HALT\ngoto HALT\n";
+        let assembly = vmtranslator::translate(&[("program.vm", &vm), ("Sys.vm", sys)]).unwrap();
+        let ram = run_hack(&assembler::assemble(&assembly).unwrap(), 10000);
+        assert_eq!(&ram[8000..8002], &[3, 4]);
+
+        let args = ["jack_analyzer", directory.path().to_str().unwrap(), "xml", "--merge-output", "program.vm"];
+        assert!(Config::new(args.iter().map(|arg| arg.to_string())).is_err());
+    }
+
+    #[test]
+    fn assembler_errors_point_at_the_vm_function() {
+        let program = vec![
+            ("src/Main.jack".to_string(), "src/Main.vm".to_string(), String::new()),
+            ("src/Sys.vm".to_string(), "src/Sys.vm".to_string(), String::new())
+        ];
+        let assembly = "// Bootstrap\n@256\n// function Main.main 0\n(Main.main)\n// push constant 1\n@1\nD=Q\n";
+        assert_eq!(assembly_error(&program, "src/src.asm", assembly, 7, "Invalid comp: Q"), "\
+src/Main.jack: src/src.asm:7: Invalid comp: Q
+  note: in VM function Main.main, the code of `push constant 1`");
+        assert_eq!(assembly_error(&program, "src/src.asm", assembly, 2, "Invalid comp: Q"), "src/src.asm:2: Invalid comp: Q");
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+
+// The first line where two outputs differ, 1-based. A missing line means one output ended.
+#[derive(Debug, PartialEq)]
+pub struct Difference {
+    pub line: usize,
+    pub expected: Option<String>,
+    pub found: Option<String>
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |line: &Option<String>| match line {
+            Some(line) => line.clone(),
+            None => "end of file".to_string()
+        };
+        write!(
+            f, "comparison failure at line {}\n  expected: {}\n  found:    {}",
+            self.line, show(&self.expected), show(&self.found)
+        )
+    }
+}
+
+// Compares like the course's TextComparer, ignoring whitespace at the start and end of each line
+pub fn first_difference(expected: &str, found: &str) -> Option<Difference> {
+    let mut expected_lines = expected.lines().map(str::trim);
+    let mut found_lines = found.lines().map(str::trim);
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), found_lines.next()) {
+            (None, None) => return None,
+            (e, f) if e == f => line += 1,
+            (e, f) => return Some(Difference {
+                line,
+                expected: e.map(str::to_string),
+                found: f.map(str::to_string)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_indentation_and_line_endings() {
+        let expected = "<class>\r\n  <keyword> class </keyword>\r\n</class>\r\n";
+        let found = "<class>\n<keyword> class </keyword>   \n</class>\n";
+        assert_eq!(first_difference(expected, found), None);
+    }
+
+    #[test]
+    fn reports_the_first_differing_line() {
+        let difference = first_difference("a\nb\nc\nd\n", "a\n  b\nx\ny\n").unwrap();
+        assert_eq!(difference, Difference { line: 3, expected: Some("c".to_string()), found: Some("x".to_string()) });
+        assert_eq!(difference.to_string(), "comparison failure at line 3\n  expected: c\n  found:    x");
+    }
+
+    #[test]
+    fn reports_missing_lines() {
+        let difference = first_difference("a\nb\n", "a\n").unwrap();
+        assert_eq!(difference, Difference { line: 2, expected: Some("b".to_string()), found: None });
+        assert!(difference.to_string().ends_with("found:    end of file"));
+    }
+
+    #[test]
+    fn inner_whitespace_matters() {
+        assert!(first_difference("<symbol> ( </symbol>", "<symbol>(</symbol>").is_some());
+    }
+}
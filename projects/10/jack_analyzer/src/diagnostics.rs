@@ -0,0 +1,218 @@
+use std::fmt;
+use crate::error::{CompileError, SemanticError};
+
+// The kinds of warnings, by the name `-W` takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lint {
+    ClassFileName,
+    IndexNonArray,
+    LossyString,
+    Unused,
+    Unreachable,
+    ConstantCondition,
+    DeadCode,
+    TypeCheck,
+    OsConformance,
+    Complexity,
+    // `-W naming`, the spelling of names
+    NamingClasses,
+    NamingSubroutines,
+    NamingVariables,
+    NamingStatics,
+    NamingShort
+}
+
+impl Lint {
+    pub const ALL: [Lint; 15] = [
+        Lint::ClassFileName, Lint::IndexNonArray, Lint::LossyString, Lint::Unused, Lint::Unreachable,
+        Lint::ConstantCondition, Lint::DeadCode, Lint::TypeCheck, Lint::OsConformance, Lint::Complexity,
+        Lint::NamingClasses, Lint::NamingSubroutines, Lint::NamingVariables, Lint::NamingStatics, Lint::NamingShort
+    ];
+
+    pub const NAMING: [Lint; 5] = [
+        Lint::NamingClasses, Lint::NamingSubroutines, Lint::NamingVariables, Lint::NamingStatics, Lint::NamingShort
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::ClassFileName => "class-file-name",
+            Lint::IndexNonArray => "index-non-array",
+            Lint::LossyString => "lossy-string",
+            Lint::Unused => "unused",
+            Lint::Unreachable => "unreachable",
+            Lint::ConstantCondition => "constant-condition",
+            Lint::DeadCode => "dead-code",
+            Lint::TypeCheck => "type-check",
+            Lint::OsConformance => "os-conformance",
+            Lint::Complexity => "complexity",
+            Lint::NamingClasses => "naming-classes",
+            Lint::NamingSubroutines => "naming-subroutines",
+            Lint::NamingVariables => "naming-variables",
+            Lint::NamingStatics => "naming-statics",
+            Lint::NamingShort => "naming-short"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+
+    fn bit(&self) -> u32 {
+        1 << *self as u32
+    }
+}
+
+// The warnings that are reported. All but the type checks, the complexity and the naming conventions
+// are on unless turned off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lints(u32);
+
+impl Default for Lints {
+    fn default() -> Self {
+        let off = Lint::NAMING.iter().map(Lint::bit).sum::<u32>() | Lint::TypeCheck.bit() | Lint::Complexity.bit();
+        Lints(Lint::ALL.iter().map(Lint::bit).sum::<u32>() & !off)
+    }
+}
+
+impl Lints {
+    pub fn enabled(&self, lint: Lint) -> bool {
+        self.0 & lint.bit() != 0
+    }
+
+    pub fn with(self, lint: Lint) -> Self {
+        Lints(self.0 | lint.bit())
+    }
+
+    pub fn without(self, lint: Lint) -> Self {
+        Lints(self.0 & !lint.bit())
+    }
+
+    // `-W unused`, `-W no-unused`, `-W all` or `-W no-all`, and `-W naming` for the naming lints,
+    // none for an unknown name
+    pub fn set(self, flag: &str) -> Option<Self> {
+        let (on, name) = match flag.strip_prefix("no-") {
+            Some(name) => (false, name),
+            None => (true, flag)
+        };
+        let lints = match name {
+            "all" => Lint::ALL.to_vec(),
+            "naming" => Lint::NAMING.to_vec(),
+            name => vec![Lint::from_name(name)?]
+        };
+        Some(lints.into_iter().fold(self, |lints, lint| if on { lints.with(lint) } else { lints.without(lint) }))
+    }
+}
+
+// What `-W complexity` allows a subroutine, `--max-complexity` and `--max-statements`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    // 1 and one for each if and while
+    pub complexity: usize,
+    // nested ones included
+    pub statements: usize
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { complexity: 10, statements: 50 }
+    }
+}
+
+// A warning and its kind, `3:9: unreachable statement [-W unreachable]`
+#[derive(Debug)]
+pub struct Warning {
+    pub lint: Lint,
+    pub error: SemanticError
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} [-W {}]", self.error.span, self.error.message, self.lint.name())?;
+        if let Some(note) = &self.error.note {
+            write!(f, "\n  note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+// Where the analysis and code generation of a class report to, warnings that are off are dropped
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    lints: Lints,
+    errors: Vec<SemanticError>,
+    warnings: Vec<Warning>
+}
+
+impl Diagnostics {
+    pub fn new(lints: Lints) -> Self {
+        Diagnostics { lints, errors: Vec::new(), warnings: Vec::new() }
+    }
+
+    pub fn error(&mut self, error: SemanticError) {
+        self.errors.push(error);
+    }
+
+    pub fn warn(&mut self, lint: Lint, error: SemanticError) {
+        if self.lints.enabled(lint) {
+            self.warnings.push(Warning { lint, error });
+        }
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    // the warnings, or the errors when there are any
+    pub fn finish(self) -> Result<Vec<Warning>, CompileError> {
+        if self.errors.is_empty() {
+            Ok(self.warnings)
+        } else {
+            Err(CompileError::Semantic(self.errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::Span;
+
+    #[test]
+    fn lint_names() {
+        for lint in Lint::ALL {
+            assert_eq!(Lint::from_name(lint.name()), Some(lint));
+        }
+        assert_eq!(Lint::from_name("shadowing"), None);
+    }
+
+    #[test]
+    fn turning_warnings_on_and_off() {
+        let lints = Lints::default();
+        assert!(lints.enabled(Lint::Unused));
+        assert!(!lints.enabled(Lint::TypeCheck));
+        let lints = lints.set("no-unused").unwrap().set("type-check").unwrap();
+        assert!(!lints.enabled(Lint::Unused));
+        assert!(lints.enabled(Lint::TypeCheck));
+        assert!(Lint::ALL.iter().all(|lint| lints.set("all").unwrap().enabled(*lint)));
+        assert!(Lint::ALL.iter().all(|lint| !lints.set("no-all").unwrap().enabled(*lint)));
+        assert_eq!(lints.set("no-such-warning"), None);
+        // a group, and one of it
+        let lints = Lints::default().set("naming").unwrap().set("no-naming-short").unwrap();
+        assert!(lints.enabled(Lint::NamingClasses) && !lints.enabled(Lint::NamingShort));
+        assert!(!Lints::default().enabled(Lint::NamingVariables));
+    }
+
+    #[test]
+    fn warnings_that_are_off_are_dropped() {
+        let mut diagnostics = Diagnostics::new(Lints::default().without(Lint::Unreachable));
+        diagnostics.warn(Lint::Unreachable, SemanticError::new("unreachable statement", Span::new(3, 9)));
+        diagnostics.warn(Lint::Unused, SemanticError::new("field x of A is never used", Span::new(2, 15)).with_note("a note"));
+        let warnings: Vec<String> = diagnostics.finish().unwrap().iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(warnings, vec!["2:15: field x of A is never used [-W unused]\n  note: a note"]);
+
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.warn(Lint::Unused, SemanticError::new("field x of A is never used", Span::new(2, 15)));
+        diagnostics.error(SemanticError::new("division by zero", Span::new(4, 19)));
+        assert!(matches!(diagnostics.finish(), Err(CompileError::Semantic(errors)) if errors.len() == 1));
+    }
+}
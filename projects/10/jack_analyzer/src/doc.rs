@@ -0,0 +1,201 @@
+use crate::ast::*;
+
+// `doc`: a Markdown page for each class with its constants, statics, fields and subroutines and the text of
+// their `/** */` comments, and an index linking the pages. Everything is in source order, and the
+// index in the order of the class names, so the same sources always give the same pages.
+pub fn class_page(class: &Class) -> String {
+    let mut page = format!("# {}\n\n", class.name.as_str());
+    if let Some(doc) = &class.doc {
+        page.push_str(&format!("{}\n\n", doc));
+    }
+    let sections = [(ClassVarDecType::Const, "Constants"), (ClassVarDecType::Static, "Statics"), (ClassVarDecType::Field, "Fields")];
+    for (dec_type, heading) in sections {
+        let decs: Vec<&ClassVarDec> = class.class_var_decs.iter().filter(|dec| dec.dec_type == dec_type).collect();
+        if decs.is_empty() {
+            continue;
+        }
+        page.push_str(&format!("## {}\n\n", heading));
+        for dec in decs {
+            let names: Vec<&str> = dec.var_names().map(|name| name.as_str()).collect();
+            let value = match (&dec.dec_type, &dec.initializer) {
+                (ClassVarDecType::Const, Some(initializer)) => format!(" = {}", literal(&initializer.term)),
+                _ => String::new()
+            };
+            page.push_str(&format!("- `{} {}{}`", dec.var_type.as_str(), names.join(", "), value));
+            // a list item is one line
+            if let Some(doc) = &dec.doc {
+                page.push_str(&format!(": {}", doc.split_whitespace().collect::<Vec<_>>().join(" ")));
+            }
+            page.push('\n');
+        }
+        page.push('\n');
+    }
+    if !class.subroutine_decs.is_empty() {
+        page.push_str("## Subroutines\n\n");
+    }
+    for subroutine_dec in class.subroutine_decs.iter() {
+        page.push_str(&format!("### {}\n\n`{}`\n\n", subroutine_dec.name.as_str(), signature(subroutine_dec)));
+        match &subroutine_dec.doc {
+            Some(doc) => page.push_str(&format!("{}\n\n", doc)),
+            None => page.push_str("*Undocumented.*\n\n")
+        }
+    }
+    // no blank line at the end of the file
+    page.truncate(page.trim_end().len() + 1);
+    page
+}
+
+// The classes linked to their pages, and the subroutines without a doc comment. Every subroutine
+// can be called from other classes, so all of them count.
+pub fn index(classes: &[&Class]) -> String {
+    let mut classes = classes.to_vec();
+    classes.sort_by(|a, b| a.name.as_str().cmp(b.name.as_str()));
+    let mut index = String::from("# Classes\n\n");
+    for class in classes.iter() {
+        let name = class.name.as_str();
+        index.push_str(&format!("- [{}]({}.md)", name, name));
+        if let Some(summary) = class.doc.as_deref().and_then(|doc| doc.lines().next()) {
+            index.push_str(&format!(": {}", summary));
+        }
+        index.push('\n');
+    }
+    let undocumented: Vec<String> = classes.iter().flat_map(|class| undocumented(class)).collect();
+    if !undocumented.is_empty() {
+        index.push_str("\n## Undocumented\n\n");
+        for name in undocumented {
+            index.push_str(&format!("- `{}`\n", name));
+        }
+    }
+    index
+}
+
+// `Main.main` for each subroutine of the class without a doc comment
+pub fn undocumented(class: &Class) -> impl Iterator<Item = String> + '_ {
+    class.subroutine_decs.iter()
+        .filter(|subroutine_dec| subroutine_dec.doc.is_none())
+        .map(|subroutine_dec| format!("{}.{}", class.name.as_str(), subroutine_dec.name.as_str()))
+}
+
+// the value of a constant as written, `-1`
+fn literal(term: &Term) -> String {
+    match term {
+        Term::IntegerConstant(v) => (*v as u16).to_string(),
+        Term::KeywordConstant(KeywordConstant::True, _) => "true".to_string(),
+        Term::KeywordConstant(KeywordConstant::False, _) => "false".to_string(),
+        Term::WithUnary(UnaryOp::Negative, term) => format!("-{}", literal(term)),
+        _ => "null".to_string()
+    }
+}
+
+// as declared, `method int distance(Point other)`
+fn signature(subroutine_dec: &SubroutineDec) -> String {
+    let return_type = match &subroutine_dec.return_type {
+        SubroutineReturnType::Void => "void",
+        SubroutineReturnType::General(return_type) => return_type.as_str()
+    };
+    let parameters: Vec<String> = subroutine_dec.parameters.iter()
+        .map(|Parameter(parameter_type, name)| format!("{} {}", parameter_type.as_str(), name.as_str()))
+        .collect();
+    format!(
+        "{} {} {}({})",
+        subroutine_dec.subroutine_type.as_str(), return_type, subroutine_dec.name.as_str(), parameters.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_str;
+
+    const POINT: &str = "\
+/**
+ * A point on the screen.
+ *
+ * Points are immutable.
+ */
+class Point {
+    /** how many points were made */
+    static int count;
+    /**
+     * the position,
+     * in pixels
+     */
+    field int x, y;
+    field Point next; // not a doc comment
+
+    /** Makes a point at (ax, ay). */
+    constructor Point new(int ax, int ay) {
+        let x = ax;
+        let y = ay;
+        let count = count + 1;
+        return this;
+    }
+
+    // only a line comment
+    method int getX() { return x; }
+
+    /** The squared distance to other. */
+    method int distance(Point other) {
+        return 0;
+    }
+}
+";
+
+    #[test]
+    fn page_of_a_documented_class() {
+        let class = parse_str(POINT).unwrap();
+        assert_eq!(class_page(&class), "\
+# Point
+
+A point on the screen.
+
+Points are immutable.
+
+## Statics
+
+- `int count`: how many points were made
+
+## Fields
+
+- `int x, y`: the position, in pixels
+- `Point next`
+
+## Subroutines
+
+### new
+
+`constructor Point new(int ax, int ay)`
+
+Makes a point at (ax, ay).
+
+### getX
+
+`method int getX()`
+
+*Undocumented.*
+
+### distance
+
+`method int distance(Point other)`
+
+The squared distance to other.
+");
+    }
+
+    #[test]
+    fn index_links_the_classes_in_order() {
+        let point = parse_str(POINT).unwrap();
+        let main = parse_str("class Main { function void main() { return; } }").unwrap();
+        assert_eq!(index(&[&point, &main]), "\
+# Classes
+
+- [Main](Main.md)
+- [Point](Point.md): A point on the screen.
+
+## Undocumented
+
+- `Main.main`
+- `Point.getX`
+");
+    }
+}
@@ -0,0 +1,172 @@
+use crate::ast::*;
+
+// `--dump-ast`: the tree of a class for reading, a node on each line under its parent, with the
+// position of the node where it has one. Tests compare it whole, so changes to it show up there.
+// `class Main { function int f() { return 1 + x; } }` gives
+//
+// ```text
+// Class Main @1:7
+//   SubroutineDec function int f() @1:27
+//     ReturnStatement @1:33
+//       BinaryOp + @1:42
+//         IntegerConstant 1
+//         VarName x @1:44
+// ```
+pub fn dump(class: &Class) -> String {
+    let mut printer = Printer(String::new());
+    walk(Node::Class(class), &mut printer);
+    printer.0
+}
+
+struct Printer(String);
+
+impl<'a> Visitor<'a> for Printer {
+    fn enter(&mut self, node: Node<'a>, depth: usize) -> bool {
+        self.0.push_str(&"  ".repeat(depth));
+        self.0.push_str(&label(node));
+        if let Some(span) = node.span() {
+            self.0.push_str(&format!(" @{}", span));
+        }
+        self.0.push('\n');
+        true
+    }
+}
+
+fn label(node: Node) -> String {
+    match node {
+        Node::Class(class) => format!("Class {}", class.name.as_str()),
+        Node::ClassVarDec(class_var_dec) => {
+            let dec_type = match class_var_dec.dec_type {
+                ClassVarDecType::Static => "static",
+                ClassVarDecType::Field => "field",
+                ClassVarDecType::Const => "const"
+            };
+            format!("ClassVarDec {} {} {}", dec_type, class_var_dec.var_type.as_str(), names(class_var_dec.var_names()))
+        },
+        Node::SubroutineDec(subroutine_dec) => {
+            let return_type = match &subroutine_dec.return_type {
+                SubroutineReturnType::Void => "void",
+                SubroutineReturnType::General(return_type) => return_type.as_str()
+            };
+            let parameters: Vec<String> = subroutine_dec.parameters.iter()
+                .map(|Parameter(var_type, var_name)| format!("{} {}", var_type.as_str(), var_name.as_str()))
+                .collect();
+            format!(
+                "SubroutineDec {} {} {}({})",
+                subroutine_dec.subroutine_type.as_str(), return_type, subroutine_dec.name.as_str(), parameters.join(", ")
+            )
+        },
+        Node::VarDec(var_dec) => format!("VarDec {} {}", var_dec.var_type.as_str(), names(var_dec.var_names())),
+        Node::Statement(statement) => match statement {
+            // `a[]` when the first child is the index
+            Statement::Let(statement) => {
+                let index = if statement.index_expression.is_some() { "[]" } else { "" };
+                format!("LetStatement {}{}", statement.var_name.as_str(), index)
+            },
+            Statement::If(_) => "IfStatement".to_string(),
+            Statement::While(_) => "WhileStatement".to_string(),
+            Statement::Do(subroutine_call, _) => format!("DoStatement {}", callee(subroutine_call)),
+            Statement::Return(..) => "ReturnStatement".to_string(),
+            Statement::Break(_) => "BreakStatement".to_string(),
+            Statement::Continue(_) => "ContinueStatement".to_string()
+        },
+        Node::Else(_) => "Else".to_string(),
+        Node::BinaryOp(expression, operators) => format!("BinaryOp {}", expression.extra_op_terms[operators - 1].0.symbol()),
+        Node::Term(term) => match term {
+            Term::IntegerConstant(value) => format!("IntegerConstant {}", value),
+            Term::StringConstant(string, _) => format!("StringConstant \"{}\"", string),
+            Term::KeywordConstant(keyword, _) => {
+                let keyword = match keyword {
+                    KeywordConstant::True => "true",
+                    KeywordConstant::False => "false",
+                    KeywordConstant::Null => "null",
+                    KeywordConstant::This => "this"
+                };
+                format!("KeywordConstant {}", keyword)
+            },
+            Term::VarName(name, _) => format!("VarName {}", name),
+            Term::IndexVar(name, ..) => format!("IndexVar {}", name),
+            Term::ClassConstant(class, name, _) => format!("ClassConstant {}.{}", class, name),
+            Term::Call(subroutine_call) => format!("Call {}", callee(subroutine_call)),
+            Term::Expression(_) | Term::Nested(_) => "Expression".to_string(),
+            Term::WithUnary(op, _) => format!("UnaryOp {}", op.symbol())
+        }
+    }
+}
+
+fn names<'a>(var_names: impl Iterator<Item = &'a VarName>) -> String {
+    var_names.map(VarName::as_str).collect::<Vec<_>>().join(", ")
+}
+
+// `Output.printInt`, or `draw` for a method of the class
+fn callee(subroutine_call: &SubroutineCall) -> String {
+    match &subroutine_call.caller {
+        Some(caller) => format!("{}.{}", caller, subroutine_call.subroutine_name.as_str()),
+        None => subroutine_call.subroutine_name.as_str().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_str;
+
+    #[test]
+    fn one_node_on_each_line() {
+        let class = parse_str("\
+class Square {
+    field int x, size;
+    method void draw(int color) {
+        var Array a;
+        let a[0] = -x;
+        if (~(size < 2)) { do Screen.drawRectangle(x, x, x + size, 8); } else { return; }
+        while (true) { let x = draw(\"a\"); }
+        return;
+    }
+}").unwrap();
+        assert_eq!(dump(&class), "\
+Class Square @1:7
+  ClassVarDec field int x, size @2:15
+  SubroutineDec method void draw(int color) @3:17
+    VarDec Array a @4:19
+    LetStatement a[] @5:9
+      IntegerConstant 0
+      UnaryOp -
+        VarName x @5:21
+    IfStatement @6:9
+      UnaryOp ~
+        BinaryOp < @6:20
+          VarName size @6:15
+          IntegerConstant 2
+      DoStatement Screen.drawRectangle @6:28
+        VarName x @6:52
+        VarName x @6:55
+        BinaryOp + @6:60
+          VarName x @6:58
+          VarName size @6:62
+        IntegerConstant 8
+      Else
+        ReturnStatement @6:81
+    WhileStatement @7:9
+      KeywordConstant true @7:16
+      LetStatement x @7:24
+        Call draw @7:32
+          StringConstant \"a\" @7:37
+    ReturnStatement @8:9
+");
+    }
+
+    #[test]
+    fn the_last_operator_is_the_root() {
+        // left to right
+        let class = parse_str("class A { function int f() { return 1 - 2 * 3; } }").unwrap();
+        assert!(dump(&class).ends_with("\
+    ReturnStatement @1:30
+      BinaryOp * @1:43
+        BinaryOp - @1:39
+          IntegerConstant 1
+          IntegerConstant 2
+        IntegerConstant 3
+"));
+    }
+}
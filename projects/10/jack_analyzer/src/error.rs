@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use crate::tokenizer::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub expected: String,
+    pub found: String,
+    pub span: Span,
+    // replaces "expected ..., found ..." for errors that are not about a missing token
+    pub message: Option<String>,
+    // boxed, a syntax error is passed up through every level of a deeply nested expression
+    pub related: Option<Box<Related>>
+}
+
+// Another place in the file an error points at, like the first declaration of a name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Related {
+    pub span: Span,
+    pub label: String
+}
+
+impl SyntaxError {
+    pub fn new(expected: &str, found: &str, span: Span) -> Self {
+        SyntaxError {
+            expected: expected.to_string(),
+            found: found.to_string(),
+            span,
+            message: None,
+            related: None
+        }
+    }
+
+    pub fn with_message(message: &str, found: &str, span: Span) -> Self {
+        SyntaxError {
+            message: Some(message.to_string()),
+            ..SyntaxError::new("", found, span)
+        }
+    }
+
+    pub fn with_related(self, span: Span, label: &str) -> Self {
+        Self { related: Some(Box::new(Related { span, label: label.to_string() })), ..self }
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{}: {}", self.span, message),
+            None => write!(f, "{}: expected {}, found {}", self.span, self.expected, self.found)
+        }
+    }
+}
+
+impl Error for SyntaxError {}
+
+// A well-formed program the compiler cannot make sense of, like a variable that is not declared
+#[derive(Debug, Clone, PartialEq)]
+pub struct SemanticError {
+    pub message: String,
+    pub span: Span,
+    // more about the cause, printed on its own line
+    pub note: Option<String>,
+    pub related: Option<Box<Related>>
+}
+
+impl SemanticError {
+    pub fn new(message: &str, span: Span) -> Self {
+        SemanticError { message: message.to_string(), span, note: None, related: None }
+    }
+
+    pub fn with_note(self, note: &str) -> Self {
+        SemanticError { note: Some(note.to_string()), ..self }
+    }
+
+    pub fn with_related(self, span: Span, label: &str) -> Self {
+        Self { related: Some(Box::new(Related { span, label: label.to_string() })), ..self }
+    }
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)?;
+        if let Some(note) = &self.note {
+            write!(f, "\n  note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for SemanticError {}
+
+// Why a file could not be compiled, syntax errors are in source order
+#[derive(Debug)]
+pub enum CompileError {
+    Io(io::Error),
+    Syntax(Vec<SyntaxError>),
+    Semantic(Vec<SemanticError>),
+    // nothing but whitespace and comments
+    NoClass,
+    // the class is not the one the source was expected to declare
+    ClassName { expected: String, found: String }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Io(e) => write!(f, "{}", e),
+            CompileError::Syntax(errors) => {
+                let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            },
+            CompileError::Semantic(errors) => {
+                let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            },
+            CompileError::NoClass => write!(f, "no class declaration found"),
+            CompileError::ClassName { expected, found } => {
+                write!(f, "expected class {}, found class {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for CompileError {}
+
+impl From<io::Error> for CompileError {
+    fn from(error: io::Error) -> Self {
+        CompileError::Io(error)
+    }
+}
+
+impl From<SyntaxError> for CompileError {
+    fn from(error: SyntaxError) -> Self {
+        CompileError::Syntax(vec![error])
+    }
+}
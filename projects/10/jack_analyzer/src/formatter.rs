@@ -0,0 +1,621 @@
+use crate::LanguageOptions;
+use crate::ast::*;
+use crate::tokenizer::{Comment, Trivia};
+
+const INDENT: &str = "    ";
+
+// Re-emits a class as canonical Jack source: 4 space indentation, one statement per line,
+// single spaces around binary operators. Comments and single blank lines are kept.
+pub fn format(class: &Class, trivia: &Trivia, options: LanguageOptions) -> String {
+    let mut formatter = Formatter::new(trivia, options);
+    formatter.class(class);
+    formatter.finish()
+}
+
+// Comments are placed by counting the emitted tokens: the output has exactly the tokens of
+// the source, so a comment goes right before the first token that followed it in the source.
+struct Formatter<'a> {
+    trivia: &'a Trivia,
+    options: LanguageOptions,
+    output: String,
+    line: String,
+    indent: usize,
+    // tokens emitted so far
+    token_index: usize,
+    // comments emitted so far
+    comment_index: usize,
+    // source line of the last token or comment emitted
+    source_line: usize,
+    // a blank line is due before the next line
+    separate: bool
+}
+
+impl<'a> Formatter<'a> {
+    fn new(trivia: &'a Trivia, options: LanguageOptions) -> Self {
+        Formatter {
+            trivia,
+            options,
+            output: String::new(),
+            line: String::new(),
+            indent: 0,
+            token_index: 0,
+            comment_index: 0,
+            source_line: 0,
+            separate: false
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.newline();
+        while self.comment_index < self.trivia.comments.len() {
+            self.comment();
+        }
+        self.output
+    }
+
+    // Basic output
+
+    fn token(&mut self, text: &str) {
+        while self.comment_before_token() {
+            if self.comment_is_trailing() && !self.line.is_empty() {
+                // `let x = 1 + // note` followed by more of the statement
+                self.trailing_comment();
+                self.newline();
+            } else {
+                self.comment();
+            }
+        }
+        let source_line = match self.trivia.token_spans.get(self.token_index) {
+            Some(span) => span.line,
+            None => self.source_line
+        };
+        if self.line.is_empty() && text != "}" {
+            self.blank_line(source_line);
+        }
+        self.line.push_str(text);
+        self.source_line = source_line;
+        self.token_index += 1;
+    }
+
+    fn space(&mut self) {
+        self.line.push(' ');
+    }
+
+    // ends the current line, together with the comment after its last token
+    fn newline(&mut self) {
+        while self.comment_index < self.trivia.comments.len() && self.comment_is_trailing() {
+            self.trailing_comment();
+        }
+        if !self.line.is_empty() {
+            self.output.push_str(&INDENT.repeat(self.indent));
+            self.output.push_str(&self.line);
+            self.output.push('\n');
+            self.line.clear();
+        }
+    }
+
+    fn open_block(&mut self) {
+        self.token("{");
+        self.newline();
+        self.indent += 1;
+    }
+
+    // comments at the end of a block stay indented with the block
+    fn close_block(&mut self) {
+        while self.comment_before_token() {
+            self.comment();
+        }
+        self.indent -= 1;
+        self.token("}");
+    }
+
+    // keeps one blank line where the source had some, never right after an opening brace
+    fn blank_line(&mut self, source_line: usize) {
+        let gap = source_line > self.source_line + 1;
+        if (gap || self.separate) && !self.output.is_empty() && !self.output.ends_with("{\n") && !self.output.ends_with("\n\n") {
+            self.output.push('\n');
+        }
+        self.separate = false;
+    }
+
+    // Comments
+
+    fn comment_before_token(&self) -> bool {
+        match (self.trivia.comments.get(self.comment_index), self.trivia.token_spans.get(self.token_index)) {
+            (Some(comment), Some(span)) => comment.span < *span,
+            (Some(_), None) => true,
+            (None, _) => false
+        }
+    }
+
+    // on the line of the token emitted last
+    fn comment_is_trailing(&self) -> bool {
+        match (self.trivia.comments.get(self.comment_index), self.token_index.checked_sub(1)) {
+            (Some(comment), Some(last)) => {
+                self.trivia.token_spans.get(last).map(|span| span.line) == Some(comment.span.line)
+                    && self.comment_before_token()
+            },
+            _ => false
+        }
+    }
+
+    fn trailing_comment(&mut self) {
+        let comment = &self.trivia.comments[self.comment_index];
+        self.line.truncate(self.line.trim_end().len());
+        self.line.push(' ');
+        self.line.push_str(&comment.lines.join(" "));
+        self.source_line = comment.span.line;
+        self.comment_index += 1;
+    }
+
+    // a comment on lines of its own
+    fn comment(&mut self) {
+        self.newline();
+        let comment: &Comment = &self.trivia.comments[self.comment_index];
+        self.blank_line(comment.span.line);
+        let indent = INDENT.repeat(self.indent);
+        for (i, line) in comment.lines.iter().enumerate() {
+            self.output.push_str(&indent);
+            // the ` * ` of a block comment lines up with the first `*` of `/**`
+            if i > 0 && line.starts_with('*') {
+                self.output.push(' ');
+            }
+            self.output.push_str(line);
+            self.output.push('\n');
+        }
+        self.source_line = comment.span.line + comment.lines.len() - 1;
+        self.comment_index += 1;
+    }
+
+    // Program structure
+
+    fn class(&mut self, class: &Class) {
+        self.token("class");
+        self.space();
+        self.token(class.name.as_str());
+        self.space();
+        self.open_block();
+        for class_var_dec in class.class_var_decs.iter() {
+            self.class_var_dec(class_var_dec);
+        }
+        for subroutine_dec in class.subroutine_decs.iter() {
+            self.separate = true;
+            self.subroutine_dec(subroutine_dec);
+        }
+        self.close_block();
+        self.newline();
+    }
+
+    fn class_var_dec(&mut self, class_var_dec: &ClassVarDec) {
+        match class_var_dec.dec_type {
+            ClassVarDecType::Static => self.token("static"),
+            ClassVarDecType::Field => self.token("field"),
+            ClassVarDecType::Const => self.token("const")
+        }
+        self.space();
+        self.var_type(&class_var_dec.var_type);
+        self.space();
+        self.var_names(&class_var_dec.var_name, &class_var_dec.extra_var_names);
+        if let Some(initializer) = &class_var_dec.initializer {
+            self.space();
+            self.token("=");
+            self.space();
+            self.expression(initializer);
+        }
+        self.token(";");
+        self.newline();
+    }
+
+    fn var_type(&mut self, var_type: &Type) {
+        match var_type {
+            Type::Int => self.token("int"),
+            Type::Char => self.token("char"),
+            Type::Boolean => self.token("boolean"),
+            Type::ClassName(v) => self.token(v)
+        }
+    }
+
+    fn var_names(&mut self, var_name: &VarName, extra_var_names: &[VarName]) {
+        self.token(var_name.as_str());
+        for var_name in extra_var_names {
+            self.token(",");
+            self.space();
+            self.token(var_name.as_str());
+        }
+    }
+
+    fn subroutine_dec(&mut self, subroutine_dec: &SubroutineDec) {
+        match subroutine_dec.subroutine_type {
+            SubroutineType::Constructor => self.token("constructor"),
+            SubroutineType::Function => self.token("function"),
+            SubroutineType::Method => self.token("method")
+        }
+        self.space();
+        match &subroutine_dec.return_type {
+            SubroutineReturnType::Void => self.token("void"),
+            SubroutineReturnType::General(v) => self.var_type(v)
+        }
+        self.space();
+        self.token(subroutine_dec.name.as_str());
+        self.token("(");
+        for (i, Parameter(var_type, var_name)) in subroutine_dec.parameters.iter().enumerate() {
+            if i > 0 {
+                self.token(",");
+                self.space();
+            }
+            self.var_type(var_type);
+            self.space();
+            self.token(var_name.as_str());
+        }
+        self.token(")");
+        self.space();
+        self.open_block();
+        for var_dec in subroutine_dec.body.var_decs.iter() {
+            self.token("var");
+            self.space();
+            self.var_type(&var_dec.var_type);
+            self.space();
+            self.var_names(&var_dec.var_name, &var_dec.extra_var_names);
+            self.token(";");
+            self.newline();
+        }
+        self.statements(&subroutine_dec.body.statements);
+        self.close_block();
+        self.newline();
+    }
+
+    // Statements
+
+    fn statements(&mut self, statements: &Statements) {
+        for statement in statements.iter() {
+            self.statement(statement);
+            self.newline();
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let(statement) => {
+                self.token("let");
+                self.space();
+                self.token(statement.var_name.as_str());
+                if let Some(index_expression) = &statement.index_expression {
+                    self.token("[");
+                    self.expression(index_expression);
+                    self.token("]");
+                }
+                self.space();
+                self.token("=");
+                self.space();
+                self.expression(&statement.expression);
+                self.token(";");
+            },
+            Statement::If(statement) => {
+                self.token("if");
+                self.space();
+                self.condition(&statement.expression);
+                self.block(&statement.if_statements);
+                if let Some(else_statements) = &statement.else_statements {
+                    self.space();
+                    self.token("else");
+                    self.space();
+                    self.block(else_statements);
+                }
+            },
+            Statement::While(statement) => {
+                self.token("while");
+                self.space();
+                self.condition(&statement.expression);
+                self.block(&statement.statements);
+            },
+            Statement::Do(subroutine_call, _) => {
+                self.token("do");
+                self.space();
+                self.subroutine_call(subroutine_call);
+                self.token(";");
+            },
+            Statement::Return(expression, _) => {
+                self.token("return");
+                if let Some(expression) = expression {
+                    self.space();
+                    self.expression(expression);
+                }
+                self.token(";");
+            },
+            Statement::Break(_) => {
+                self.token("break");
+                self.token(";");
+            },
+            Statement::Continue(_) => {
+                self.token("continue");
+                self.token(";");
+            }
+        }
+    }
+
+    fn condition(&mut self, expression: &Expression) {
+        self.token("(");
+        self.expression(expression);
+        self.token(")");
+        self.space();
+    }
+
+    fn block(&mut self, statements: &Statements) {
+        self.open_block();
+        self.statements(statements);
+        self.close_block();
+    }
+
+    // Expressions
+
+    fn expression(&mut self, expression: &Expression) {
+        self.term(&expression.term);
+        for OpTerm(op, term, _) in expression.extra_op_terms.iter() {
+            self.space();
+            self.token(&op.symbol().to_string());
+            self.space();
+            self.term(term);
+        }
+    }
+
+    fn term(&mut self, term: &Term) {
+        match term {
+            Term::IntegerConstant(v) => self.token(&(*v as u16).to_string()),
+            Term::StringConstant(v, _) => {
+                let string = self.string_constant(v);
+                self.token(&string);
+            },
+            Term::KeywordConstant(v, _) => self.token(match v {
+                KeywordConstant::True => "true",
+                KeywordConstant::False => "false",
+                KeywordConstant::Null => "null",
+                KeywordConstant::This => "this"
+            }),
+            Term::VarName(v, _) => self.token(v),
+            Term::ClassConstant(class_name, name, _) => {
+                self.token(class_name);
+                self.token(".");
+                self.token(name);
+            },
+            Term::IndexVar(v, expression, _) => {
+                self.token(v);
+                self.token("[");
+                self.expression(expression);
+                self.token("]");
+            },
+            Term::Call(subroutine_call) => self.subroutine_call(subroutine_call),
+            Term::Expression(expression) => {
+                self.token("(");
+                self.expression(expression);
+                self.token(")");
+            },
+            Term::Nested(expression) => self.expression(expression),
+            Term::WithUnary(unary_op, term) => {
+                self.token(&unary_op.symbol().to_string());
+                self.term(term);
+            }
+        }
+    }
+
+    fn string_constant(&self, content: &str) -> String {
+        if !self.options.string_escapes {
+            return format!("\"{}\"", content);
+        }
+        let mut string = String::from("\"");
+        for char in content.chars() {
+            match char {
+                '"' => string.push_str("\\\""),
+                '\\' => string.push_str("\\\\"),
+                '\n' => string.push_str("\\n"),
+                '\t' => string.push_str("\\t"),
+                _ => string.push(char)
+            }
+        }
+        string.push('"');
+        string
+    }
+
+    fn subroutine_call(&mut self, subroutine_call: &SubroutineCall) {
+        if let Some(caller) = &subroutine_call.caller {
+            self.token(caller);
+            self.token(".");
+        }
+        self.token(subroutine_call.subroutine_name.as_str());
+        self.token("(");
+        for (i, expression) in subroutine_call.expression_list.iter().enumerate() {
+            if i > 0 {
+                self.token(",");
+                self.space();
+            }
+            self.expression(expression);
+        }
+        self.token(")");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::format_source;
+    use crate::parser::{tokenize, parse_single_class, XML};
+
+    fn format_jack(content: &str) -> String {
+        format_source(content, LanguageOptions::default()).unwrap()
+    }
+
+    // the class as XML, which unlike the tree has no positions
+    fn parse_with_comments(content: &str, options: LanguageOptions) -> (String, Vec<Vec<String>>) {
+        let mut tokens = tokenize(Cursor::new(content.to_string()), options).unwrap();
+        let comments = tokens.trivia().comments.iter().map(|c| c.lines.clone()).collect();
+        (XML::text(&parse_single_class(&mut tokens).unwrap()), comments)
+    }
+
+    // formatting twice changes nothing, and the class and its comments stay the same
+    fn assert_formats_faithfully(content: &str, options: LanguageOptions) {
+        let formatted = format_source(content, options).unwrap();
+        assert_eq!(format_source(&formatted, options).unwrap(), formatted);
+        assert_eq!(parse_with_comments(&formatted, options), parse_with_comments(content, options));
+    }
+
+    #[test]
+    fn course_programs() {
+        let programs = [
+            include_str!("../../ArrayTest/Main.jack"),
+            include_str!("../../ExpressionLessSquare/Main.jack"),
+            include_str!("../../ExpressionLessSquare/Square.jack"),
+            include_str!("../../ExpressionLessSquare/SquareGame.jack"),
+            include_str!("../../Square/Main.jack"),
+            include_str!("../../Square/Square.jack"),
+            include_str!("../../Square/SquareGame.jack"),
+            include_str!("../../../11/Average/Main.jack"),
+            include_str!("../../../11/ComplexArrays/Main.jack"),
+            include_str!("../../../11/ConvertToBin/Main.jack"),
+            include_str!("../../../11/Pong/Ball.jack"),
+            include_str!("../../../11/Pong/Bat.jack"),
+            include_str!("../../../11/Pong/Main.jack"),
+            include_str!("../../../11/Pong/PongGame.jack"),
+            include_str!("../../../11/Seven/Main.jack")
+        ];
+        for program in programs {
+            assert_formats_faithfully(program, LanguageOptions::default());
+        }
+    }
+
+    #[test]
+    fn normalizes_layout() {
+        let formatted = format_jack("\
+class   Main{
+  field int x ,y;
+function void main( int a,int b ){var Array arr;
+let arr[a+1]=-x*(y- 2);if(~(a<b)){do Output.printInt( Math.max(a,b) );}else{}
+while(a>0){let a=a-1;}return;}
+}");
+        assert_eq!(formatted, "\
+class Main {
+    field int x, y;
+
+    function void main(int a, int b) {
+        var Array arr;
+        let arr[a + 1] = -x * (y - 2);
+        if (~(a < b)) {
+            do Output.printInt(Math.max(a, b));
+        } else {
+        }
+        while (a > 0) {
+            let a = a - 1;
+        }
+        return;
+    }
+}
+");
+    }
+
+    #[test]
+    fn keeps_comments() {
+        let content = "\
+// header
+/** A class. */
+class Main {
+    field int x;    // a field
+
+    /**
+     * Does things.
+     */
+    function void main() {
+        // leading
+        let x = 1 + // inside
+            2;
+        if (x) {
+            return;
+            // end of block
+        }
+        return;
+    } // after subroutine
+    // end of class
+}
+// trailer
+";
+        let formatted = format_jack(content);
+        assert_eq!(formatted, "\
+// header
+/** A class. */
+class Main {
+    field int x; // a field
+
+    /**
+     * Does things.
+     */
+    function void main() {
+        // leading
+        let x = 1 + // inside
+        2;
+        if (x) {
+            return;
+            // end of block
+        }
+        return;
+    } // after subroutine
+    // end of class
+}
+// trailer
+");
+        assert_formats_faithfully(content, LanguageOptions::default());
+    }
+
+    #[test]
+    fn keeps_single_blank_lines() {
+        let formatted = format_jack("\
+class Main {
+
+    function void main() {
+
+        do a();
+
+
+        do b();
+
+    }
+    function void f() {
+        return;
+    }
+}");
+        assert_eq!(formatted, "\
+class Main {
+    function void main() {
+        do a();
+
+        do b();
+    }
+
+    function void f() {
+        return;
+    }
+}
+");
+    }
+
+    #[test]
+    fn reescapes_strings() {
+        let options = LanguageOptions { string_escapes: true, ..Default::default() };
+        let content = "class A { function void f() { do Output.printString(\"say \\\"hi\\\"\\n\\\\\"); return; } }";
+        let formatted = format_source(content, options).unwrap();
+        assert!(formatted.contains("do Output.printString(\"say \\\"hi\\\"\\n\\\\\");"));
+        assert_formats_faithfully(content, options);
+    }
+
+    #[test]
+    fn keeps_windows_line_endings() {
+        let formatted = format_jack("class A {\r\nfunction void f() { return; }\r\n}\r\n");
+        assert_eq!(formatted, "class A {\r\n    function void f() {\r\n        return;\r\n    }\r\n}\r\n");
+    }
+
+    #[test]
+    fn precedence_groups_print_without_parentheses() {
+        let options = LanguageOptions { precedence: true, ..Default::default() };
+        let content = "class A { function int f() { return 2+3*4 - (1+1); } }";
+        let formatted = format_source(content, options).unwrap();
+        assert!(formatted.contains("return 2 + 3 * 4 - (1 + 1);"));
+        assert_formats_faithfully(content, options);
+    }
+}
@@ -0,0 +1,222 @@
+use crate::ast::*;
+use crate::signatures::{type_named, Signature};
+use crate::tokenizer::Span;
+
+// The format Foo.jacki is written in, raised when a change to it would be misread by the compilers
+// that read the one before
+pub const VERSION: u32 = 1;
+
+pub const EXTENSION: &str = ".jacki";
+
+// `--emit-interface`: what other classes see of a class, for compiling them with `--with-interface`
+// where its source is not at hand. A line for each part, the subroutines as `Signature::declaration`
+// writes them:
+//
+// ```text
+// jack-interface 1
+// class Ball
+// fields 4
+// constructor Ball new(int, int)
+// method void move(int, int)
+// function int count()
+// const MAX_BALLS 10
+// init_statics
+// ```
+//
+// `fields` is the number of fields of an instance, `const` the value of a constant of `--ext-const`,
+// and `init_statics` that Main.main has to call the initializer of its statics.
+#[derive(Debug)]
+pub struct Interface {
+    pub class_name: String,
+    pub fields: usize,
+    // declared at their line of the file
+    pub subroutines: Vec<Signature>,
+    pub constants: Vec<(String, Term)>
+}
+
+pub fn write(class: &Class) -> String {
+    let mut lines = vec![format!("jack-interface {}", VERSION), format!("class {}", class.name.0)];
+    let fields = class.class_var_decs.iter()
+        .filter(|class_var_dec| class_var_dec.dec_type == ClassVarDecType::Field)
+        .map(|class_var_dec| class_var_dec.var_names().count())
+        .sum::<usize>();
+    lines.push(format!("fields {}", fields));
+    for subroutine_dec in class.subroutine_decs.iter() {
+        lines.push(Signature::new(&class.name.0, subroutine_dec, "").declaration());
+    }
+    for (name, value) in class.constants() {
+        lines.push(format!("const {} {}", name, literal(value)));
+    }
+    if class.static_initializer().is_some() {
+        lines.push(STATIC_INITIALIZER.to_string());
+    }
+    lines.join("\n") + "\n"
+}
+
+// Errors come with their line, `3: unknown entry field`
+pub fn read(content: &str, file: &str) -> Result<Interface, (usize, String)> {
+    let mut lines = content.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+    let header = format!("jack-interface {}", VERSION);
+    match lines.next() {
+        Some((_, line)) if line == header => {},
+        Some((line_number, line)) => match line.strip_prefix("jack-interface ") {
+            Some(version) => {
+                let message = format!("interface format {}, this compiler reads format {}", version, VERSION);
+                return Err((line_number, message + "\n  note: write it again with --emit-interface"));
+            },
+            None => return Err((line_number, format!("not a Jack interface, which starts with `{}`", header)))
+        },
+        None => return Err((1, format!("not a Jack interface, which starts with `{}`", header)))
+    }
+    let class_name = match lines.next() {
+        Some((_, line)) if line.starts_with("class ") => line["class ".len()..].trim().to_string(),
+        Some((line_number, _)) => return Err((line_number, "expected `class` and the name of the class".to_string())),
+        None => return Err((2, "expected `class` and the name of the class".to_string()))
+    };
+    let mut interface = Interface { class_name, fields: 0, subroutines: Vec::new(), constants: Vec::new() };
+    for (line_number, line) in lines.filter(|(_, line)| !line.is_empty()) {
+        let error = |message: String| (line_number, message);
+        let (entry, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match entry {
+            "fields" => interface.fields = rest.trim().parse().map_err(|_| error(format!("expected the number of fields, found {}", rest)))?,
+            "constructor" | "function" | "method" => {
+                let mut signature = subroutine(entry, rest, &interface.class_name).ok_or_else(|| {
+                    error(format!("expected a subroutine like `method void move(int, int)`, found {}", line))
+                })?;
+                signature.declared = Some((file.to_string(), Span::new(line_number, 1)));
+                interface.subroutines.push(signature);
+            },
+            "const" => {
+                let constant = rest.split_once(' ').and_then(|(name, value)| Some((name.to_string(), parse_literal(value.trim())?)));
+                interface.constants.push(constant.ok_or_else(|| error(format!("expected a constant like `const MAX 10`, found {}", line)))?);
+            },
+            STATIC_INITIALIZER => {
+                let subroutine = subroutine("function", "void init_statics()", &interface.class_name).unwrap();
+                interface.subroutines.push(Signature {
+                    declared: Some((file.to_string(), Span::new(line_number, 1))),
+                    static_initializer: true,
+                    ..subroutine
+                });
+            },
+            entry => return Err(error(format!("unknown entry {}", entry)))
+        }
+    }
+    Ok(interface)
+}
+
+// `void move(int, int)` of a method
+fn subroutine(kind: &str, declaration: &str, class_name: &str) -> Option<Signature> {
+    let (return_type, rest) = declaration.split_once(' ')?;
+    let (name, parameters) = rest.strip_suffix(')')?.split_once('(')?;
+    let parameters = match parameters.trim() {
+        "" => Vec::new(),
+        parameters => parameters.split(',').map(|parameter| type_named(parameter.trim())).collect()
+    };
+    let return_type = match return_type {
+        "void" => SubroutineReturnType::Void,
+        return_type => SubroutineReturnType::General(type_named(return_type))
+    };
+    let kind = match kind {
+        "constructor" => SubroutineType::Constructor,
+        "method" => SubroutineType::Method,
+        _ => SubroutineType::Function
+    };
+    Some(Signature {
+        class_name: class_name.to_string(),
+        name: name.trim().to_string(),
+        kind,
+        parameters,
+        return_type,
+        declared: None,
+        accessor: None,
+        calls: Vec::new(),
+        locals: 0,
+        static_initializer: false
+    })
+}
+
+// a constant's value is a number, true, false or null
+fn literal(term: &Term) -> String {
+    match term {
+        Term::IntegerConstant(value) => value.to_string(),
+        Term::WithUnary(UnaryOp::Negative, term) => format!("-{}", literal(term)),
+        Term::KeywordConstant(KeywordConstant::True, _) => "true".to_string(),
+        Term::KeywordConstant(KeywordConstant::False, _) => "false".to_string(),
+        _ => "null".to_string()
+    }
+}
+
+fn parse_literal(value: &str) -> Option<Term> {
+    let span = Span::new(0, 0);
+    match value {
+        "true" => Some(Term::KeywordConstant(KeywordConstant::True, span)),
+        "false" => Some(Term::KeywordConstant(KeywordConstant::False, span)),
+        "null" => Some(Term::KeywordConstant(KeywordConstant::Null, span)),
+        value => match value.strip_prefix('-') {
+            Some(value) => Some(Term::WithUnary(UnaryOp::Negative, Box::new(Term::IntegerConstant(value.parse().ok()?)))),
+            None => Some(Term::IntegerConstant(value.parse().ok()?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tokenize;
+    use crate::LanguageOptions;
+
+    fn parse(source: &str) -> Class {
+        let options = LanguageOptions { constants: true, field_initializers: true, ..LanguageOptions::default() };
+        let mut tokens = tokenize(std::io::Cursor::new(source.to_string()), options).unwrap();
+        crate::parser::parse_single_class(&mut tokens).unwrap()
+    }
+
+    #[test]
+    fn written_and_read_back() {
+        let class = parse("\
+class Ball {
+    field int x, y;
+    field Array trail;
+    static int count = 0;
+    const int MAX_BALLS = 10;
+    const int BOTTOM = -1;
+    constructor Ball new(int ax, int ay) { return this; }
+    method void move(int dx, Ball other) { return; }
+    function boolean full() { return false; }
+}");
+        let written = write(&class);
+        assert_eq!(written, "\
+jack-interface 1
+class Ball
+fields 3
+constructor Ball new(int, int)
+method void move(int, Ball)
+function boolean full()
+const MAX_BALLS 10
+const BOTTOM -1
+init_statics
+");
+        let interface = read(&written, "lib/Ball.jacki").unwrap();
+        assert_eq!(interface.class_name, "Ball");
+        assert_eq!(interface.fields, 3);
+        let declarations: Vec<String> = interface.subroutines.iter().map(Signature::declaration).collect();
+        assert_eq!(declarations, ["constructor Ball new(int, int)", "method void move(int, Ball)", "function boolean full()", "function void init_statics()"]);
+        assert_eq!(interface.subroutines[1].declared_at(), "Ball.move is declared at lib/Ball.jacki:5:1");
+        assert!(interface.subroutines[3].static_initializer);
+        let constants: Vec<String> = interface.constants.iter().map(|(name, value)| format!("{} {}", name, literal(value))).collect();
+        assert_eq!(constants, ["MAX_BALLS 10", "BOTTOM -1"]);
+    }
+
+    #[test]
+    fn other_versions_and_malformed_lines_are_errors() {
+        let error = |content: &str| read(content, "Ball.jacki").unwrap_err();
+        assert_eq!(error("jack-interface 2\nclass Ball\n"), (1, "interface format 2, this compiler reads format 1\n  note: write it again with --emit-interface".to_string()));
+        assert_eq!(error("class Ball\n"), (1, "not a Jack interface, which starts with `jack-interface 1`".to_string()));
+        assert_eq!(error(""), (1, "not a Jack interface, which starts with `jack-interface 1`".to_string()));
+        assert_eq!(error("jack-interface 1\nfields 2\n"), (2, "expected `class` and the name of the class".to_string()));
+        assert_eq!(error("jack-interface 1\nclass Ball\nfields two\n"), (3, "expected the number of fields, found two".to_string()));
+        assert_eq!(error("jack-interface 1\nclass Ball\nmethod move\n"), (3, "expected a subroutine like `method void move(int, int)`, found method move".to_string()));
+        assert_eq!(error("jack-interface 1\nclass Ball\n\nconst MAX\n"), (4, "expected a constant like `const MAX 10`, found const MAX".to_string()));
+        assert_eq!(error("jack-interface 1\nclass Ball\nfield int x\n"), (3, "unknown entry field".to_string()));
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::Lints;
+
+// The file of a project that gives its sources and flags, found in the current directory or one above it
+pub const MANIFEST: &str = "jack.toml";
+
+// What jack.toml asks for, as arguments of the command line. The command line's own come after the
+// flags, so that a later `-W` wins, and its target, sources and `-o` replace those of jack.toml.
+// `--no-optimize`, `--no-ext-for` and the like turn off again what jack.toml turns on. The output
+// directory only applies where `-o` does.
+//
+// ```toml
+// sources = ["src", "lib/Util.jack"]   # relative to jack.toml
+// output = "build"                     # -o, for doc and the --emit files
+// target = "vm"
+// optimize = true                      # -O
+//
+// [extensions]
+// for = true                           # --ext-for, also string-escapes, short-circuit, break,
+//                                      # field-init, const and precedence
+// [warnings]
+// all = false                          # -W no-all, in the order they are written
+// unused = true                        # -W unused
+// deny = true                          # --deny-warnings
+// ```
+//
+// It is a subset of TOML: a `key = value` on each line, with a string, true, false or an array of
+// strings on one line, `[extensions]` and `[warnings]` sections and `#` comments.
+#[derive(Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub sources: Vec<String>,
+    pub target: Option<String>,
+    pub output: Option<String>,
+    // flags like `-O` and `-W no-unused`
+    pub flags: Vec<String>
+}
+
+// jack.toml in the directory or the closest one above it
+pub fn find(directory: &Path) -> Option<PathBuf> {
+    directory.ancestors().map(|directory| directory.join(MANIFEST)).find(|path| path.is_file())
+}
+
+// Errors name the file and line, `jack.toml:3: unknown key optimise`
+pub fn read(path: &Path) -> Result<Manifest, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let manifest = parse(&content, directory).map_err(|(line, error)| format!("{}:{}: {}", path.display(), line, error))?;
+    match manifest.sources.iter().find(|source| !Path::new(source).exists()) {
+        Some(source) => Err(format!("{}: no file or directory {}", path.display(), source)),
+        None => Ok(manifest)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Bool(bool),
+    Array(Vec<String>)
+}
+
+const EXTENSIONS: [(&str, &str); 7] = [
+    ("string-escapes", "--ext-string-escapes"),
+    ("short-circuit", "--ext-short-circuit"),
+    ("for", "--ext-for"),
+    ("break", "--ext-break"),
+    ("field-init", "--ext-field-init"),
+    ("const", "--ext-const"),
+    ("precedence", "--precedence")
+];
+
+pub const TARGETS: [&str; 7] = ["xml", "vm", "both", "fmt", "asm", "hack", "doc"];
+
+// the paths are relative to the directory of the manifest, errors come with their line
+fn parse(content: &str, directory: &Path) -> Result<Manifest, (usize, String)> {
+    let mut manifest = Manifest::default();
+    let mut section = None;
+    for (index, line) in content.lines().enumerate() {
+        let error = |message: String| (index + 1, message);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            section = match name.strip_suffix(']').map(str::trim) {
+                Some(name @ ("extensions" | "warnings")) => Some(name),
+                Some(name) => return Err(error(format!("unknown section [{}], there are [extensions] and [warnings]", name))),
+                None => return Err(error("expected ']' after the section name".to_string()))
+            };
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(error(format!("expected key = value, found {}", line)));
+        };
+        let key = key.trim();
+        let value = parse_value(value.trim()).map_err(error)?;
+        let path = |file: &str| directory.join(file).to_string_lossy().into_owned();
+        match (section, key, value) {
+            (None, "sources", Value::Array(sources)) => manifest.sources = sources.iter().map(|source| path(source)).collect(),
+            (None, "output", Value::String(output)) => manifest.output = Some(path(&output)),
+            (None, "target", Value::String(target)) => {
+                if !TARGETS.contains(&target.as_str()) {
+                    return Err(error(format!("unknown target {}, there are {}", target, TARGETS.join(", "))));
+                }
+                manifest.target = Some(target);
+            },
+            (None, "optimize", Value::Bool(optimize)) => if optimize {
+                manifest.flags.push("-O".to_string());
+            },
+            (Some("extensions"), name, Value::Bool(on)) => match EXTENSIONS.iter().find(|(extension, _)| *extension == name) {
+                Some((_, flag)) => if on {
+                    manifest.flags.push(flag.to_string());
+                },
+                None => {
+                    let names: Vec<&str> = EXTENSIONS.iter().map(|(name, _)| *name).collect();
+                    return Err(error(format!("unknown extension {}, there are {}", name, names.join(", "))));
+                }
+            },
+            (Some("warnings"), "deny", Value::Bool(deny)) => if deny {
+                manifest.flags.push("--deny-warnings".to_string());
+            },
+            (Some("warnings"), name, Value::Bool(on)) => {
+                let flag = if on { name.to_string() } else { format!("no-{}", name) };
+                if Lints::default().set(&flag).is_none() {
+                    return Err(error(format!("unknown warning {}", name)));
+                }
+                manifest.flags.extend(["-W".to_string(), flag]);
+            },
+            (Some(_), _, _) => return Err(error(format!("{} has to be true or false", key))),
+            (None, "sources", _) => return Err(error("sources has to be an array of strings".to_string())),
+            (None, "output" | "target", _) => return Err(error(format!("{} has to be a string", key))),
+            (None, "optimize", _) => return Err(error("optimize has to be true or false".to_string())),
+            (None, key, _) => return Err(error(format!("unknown key {}", key)))
+        }
+    }
+    Ok(manifest)
+}
+
+// a `#` outside of a string starts a comment
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, char) in line.char_indices() {
+        match char {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Result<Value, String> {
+    match value {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(items) = value.strip_prefix('[') {
+        let items = items.strip_suffix(']').ok_or("expected ']' at the end of the array")?.trim();
+        let items = items.strip_suffix(',').unwrap_or(items);
+        if items.trim().is_empty() {
+            return Ok(Value::Array(Vec::new()));
+        }
+        return items.split(',').map(|item| parse_string(item.trim())).collect::<Result<_, _>>().map(Value::Array);
+    }
+    parse_string(value).map(Value::String)
+}
+
+// `"src"`, without escapes
+fn parse_string(value: &str) -> Result<String, String> {
+    match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+        Some(string) if !string.contains('"') && !string.contains('\\') => Ok(string.to_string()),
+        Some(_) => Err(format!("escapes are not supported, found {}", value)),
+        None if value.is_empty() => Err("expected a value after '='".to_string()),
+        None => Err(format!("expected a string in double quotes, true or false, found {}", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_become_flags_in_order() {
+        let manifest = parse("\
+# the game
+sources = [\"src\", \"lib/Util.jack\",]
+output = \"build\" # for the docs
+target = \"hack\"
+optimize = true
+
+[extensions]
+for = true
+const = false
+
+[warnings]
+all = false
+type-check = true
+deny = true
+", Path::new("games/pong")).unwrap();
+        assert_eq!(manifest, Manifest {
+            sources: vec!["games/pong/src".to_string(), "games/pong/lib/Util.jack".to_string()],
+            target: Some("hack".to_string()),
+            output: Some("games/pong/build".to_string()),
+            flags: ["-O", "--ext-for", "-W", "no-all", "-W", "type-check", "--deny-warnings"]
+                .iter().map(|flag| flag.to_string()).collect()
+        });
+        assert_eq!(parse("output = \"a # b\"", Path::new("")).unwrap().output.as_deref(), Some("a # b"));
+        assert_eq!(parse("sources = []", Path::new("")).unwrap(), Manifest::default());
+    }
+
+    #[test]
+    fn malformed_manifests_name_the_line() {
+        let error = |content: &str| parse(content, Path::new("")).unwrap_err();
+        assert_eq!(error("\n\noptimise = true"), (3, "unknown key optimise".to_string()));
+        assert_eq!(error("target = \"exe\""), (1, "unknown target exe, there are xml, vm, both, fmt, asm, hack, doc".to_string()));
+        assert_eq!(error("sources = \"src\""), (1, "sources has to be an array of strings".to_string()));
+        assert_eq!(error("sources = [\"src\""), (1, "expected ']' at the end of the array".to_string()));
+        assert_eq!(error("output = build"), (1, "expected a string in double quotes, true or false, found build".to_string()));
+        assert_eq!(error("output ="), (1, "expected a value after '='".to_string()));
+        assert_eq!(error("optimize"), (1, "expected key = value, found optimize".to_string()));
+        assert_eq!(error("[lints]"), (1, "unknown section [lints], there are [extensions] and [warnings]".to_string()));
+        assert_eq!(error("[extensions]\nwhile = true"), (2, "unknown extension while, there are string-escapes, short-circuit, for, break, field-init, const, precedence".to_string()));
+        assert_eq!(error("[warnings]\nshadowing = false"), (2, "unknown warning shadowing".to_string()));
+        assert_eq!(error("[warnings]\nunused = \"no\""), (2, "unused has to be true or false".to_string()));
+    }
+
+    #[test]
+    fn found_in_the_closest_directory_above() {
+        let directory = tempfile::tempdir().unwrap();
+        let nested = directory.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find(&nested), None);
+        fs::write(directory.path().join(MANIFEST), "").unwrap();
+        assert_eq!(find(&nested), Some(directory.path().join(MANIFEST)));
+        fs::write(nested.join(MANIFEST), "").unwrap();
+        assert_eq!(find(&nested), Some(nested.join(MANIFEST)));
+        let error = read(&directory.path().join("missing.toml")).unwrap_err();
+        assert!(error.starts_with(&format!("{}: ", directory.path().join("missing.toml").display())));
+    }
+}
@@ -0,0 +1,210 @@
+use std::fmt;
+use crate::ast::*;
+use crate::vm::VmCommand;
+
+/// How big a class is and what its VM code spends time on, see `--metrics`.
+///
+/// ```
+/// let metrics = jack_analyzer::metrics("
+///     class Circle {
+///         field int radius;
+///         method int area() { return radius * radius * 3; }
+///     }
+/// ").unwrap();
+/// assert_eq!((metrics.fields, metrics.methods, metrics.multiplications), (1, 1, 2));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub class_name: String,
+    pub fields: usize,
+    pub statics: usize,
+    pub constructors: usize,
+    pub functions: usize,
+    pub methods: usize,
+    // of all subroutines together
+    pub locals: usize,
+    pub vm_commands: usize,
+    pub string_constants: usize,
+    // each character is a push and a call of String.appendChar
+    pub string_characters: usize,
+    // calls of Math.multiply and Math.divide, which loop over the bits of their arguments on the Hack
+    pub multiplications: usize,
+    pub divisions: usize,
+    pub subroutines: Vec<SubroutineMetrics>
+}
+
+/// The size and branching of a subroutine, what `-W complexity` checks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubroutineMetrics {
+    pub name: String,
+    /// 1 and one for each if and while, for loops being while loops
+    pub complexity: usize,
+    /// nested ones included
+    pub statements: usize
+}
+
+impl SubroutineMetrics {
+    pub fn new(subroutine_dec: &SubroutineDec) -> Self {
+        let mut metrics = SubroutineMetrics { name: subroutine_dec.name.0.clone(), complexity: 1, statements: 0 };
+        walk(Node::SubroutineDec(subroutine_dec), &mut metrics);
+        metrics
+    }
+}
+
+impl<'a> Visitor<'a> for SubroutineMetrics {
+    fn enter(&mut self, node: Node<'a>, _depth: usize) -> bool {
+        if let Node::Statement(statement) = node {
+            self.statements += 1;
+            if matches!(statement, Statement::If(_) | Statement::While(_)) {
+                self.complexity += 1;
+            }
+        }
+        // there are no statements in expressions
+        matches!(node, Node::SubroutineDec(_) | Node::Statement(_) | Node::Else(_))
+    }
+}
+
+impl Metrics {
+    pub fn new(class: &Class, commands: &[VmCommand]) -> Self {
+        let mut metrics = Metrics { class_name: class.name.0.clone(), ..Metrics::default() };
+        for class_var_dec in class.class_var_decs.iter() {
+            let count = class_var_dec.var_names().count();
+            match class_var_dec.dec_type {
+                ClassVarDecType::Field => metrics.fields += count,
+                ClassVarDecType::Static => metrics.statics += count,
+                ClassVarDecType::Const => {}
+            }
+        }
+        for subroutine_dec in class.subroutine_decs.iter() {
+            match subroutine_dec.subroutine_type {
+                SubroutineType::Constructor => metrics.constructors += 1,
+                SubroutineType::Function => metrics.functions += 1,
+                SubroutineType::Method => metrics.methods += 1
+            }
+            metrics.locals += subroutine_dec.body.var_decs.iter().map(|var_dec| var_dec.var_names().count()).sum::<usize>();
+            for length in string_lengths(&subroutine_dec.body.statements) {
+                metrics.string_constants += 1;
+                metrics.string_characters += length;
+            }
+            metrics.subroutines.push(SubroutineMetrics::new(subroutine_dec));
+        }
+        for command in commands.iter() {
+            match command {
+                VmCommand::Comment(_) | VmCommand::Origin(_) => continue,
+                VmCommand::Call(name, 2) if name == "Math.multiply" => metrics.multiplications += 1,
+                VmCommand::Call(name, 2) if name == "Math.divide" => metrics.divisions += 1,
+                _ => {}
+            }
+            metrics.vm_commands += 1;
+        }
+        metrics
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = [
+            ("fields", self.fields),
+            ("statics", self.statics),
+            ("constructors", self.constructors),
+            ("functions", self.functions),
+            ("methods", self.methods),
+            ("locals", self.locals),
+            ("VM commands", self.vm_commands),
+            ("string constants", self.string_constants),
+            ("string characters", self.string_characters),
+            ("Math.multiply calls", self.multiplications),
+            ("Math.divide calls", self.divisions)
+        ];
+        write!(f, "Metrics: {}", self.class_name)?;
+        for (name, count) in rows {
+            write!(f, "\n  {:<20}{:>6}", name, count)?;
+        }
+        write!(f, "\n  {:<20}{:>12}{:>12}", "subroutine", "complexity", "statements")?;
+        for subroutine in self.subroutines.iter() {
+            write!(f, "\n  {:<20}{:>12}{:>12}", subroutine.name, subroutine.complexity, subroutine.statements)?;
+        }
+        Ok(())
+    }
+}
+
+// the number of characters of each string constant in the statements
+fn string_lengths(statements: &Statements) -> Vec<usize> {
+    let mut expressions: Vec<&Expression> = Vec::new();
+    let mut pending = vec![statements];
+    while let Some(statements) = pending.pop() {
+        for statement in statements.iter() {
+            match statement {
+                Statement::Let(statement) => expressions.extend(statement.index_expression.iter().chain([&statement.expression])),
+                Statement::If(statement) => {
+                    expressions.push(&statement.expression);
+                    pending.push(&statement.if_statements);
+                    pending.extend(statement.else_statements.iter());
+                },
+                Statement::While(statement) => {
+                    expressions.push(&statement.expression);
+                    pending.push(&statement.statements);
+                },
+                Statement::Do(subroutine_call, _) => expressions.extend(subroutine_call.expression_list.iter()),
+                Statement::Return(expression, _) => expressions.extend(expression.iter()),
+                Statement::Break(_) | Statement::Continue(_) => {}
+            }
+        }
+    }
+    let mut lengths = Vec::new();
+    while let Some(expression) = expressions.pop() {
+        let mut terms: Vec<&Term> = expression.terms().collect();
+        while let Some(term) = terms.pop() {
+            match term {
+                Term::StringConstant(content, _) => lengths.push(content.chars().count()),
+                Term::Call(subroutine_call) => expressions.extend(subroutine_call.expression_list.iter()),
+                Term::IndexVar(_, expression, _) | Term::Expression(expression) | Term::Nested(expression) => expressions.push(expression),
+                Term::WithUnary(_, term) => terms.push(term),
+                Term::IntegerConstant(_) | Term::KeywordConstant(..) | Term::VarName(..) | Term::ClassConstant(..) => {}
+            }
+        }
+    }
+    lengths
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LanguageOptions;
+    use crate::parser::VM;
+
+    #[test]
+    fn counts_of_a_class() {
+        let class = crate::parse_str("\
+class Game {
+    field int score, lives;
+    static Game instance;
+    constructor Game new() { let score = 0; return this; }
+    method void show(int x) {
+        var int half, y;
+        let half = x / 2;
+        let y = half * (x * x);
+        let y = y * 4;
+        do Output.printString(\"Score: \");
+        if (score > 9) { do Output.printString(\"!\"); }
+        do Output.printInt(Math.max(score, lives));
+        do Output.printString(\"\");
+        return;
+    }
+    function void reset() { var Game game; return; }
+}").unwrap();
+        let compiled = VM::compile_with_symbols(&class, LanguageOptions::default(), None, None, None).unwrap();
+        let metrics = compiled.metrics;
+        assert_eq!((metrics.fields, metrics.statics), (2, 1));
+        assert_eq!((metrics.constructors, metrics.methods, metrics.functions, metrics.locals), (1, 1, 1, 3));
+        assert_eq!((metrics.string_constants, metrics.string_characters), (3, 8));
+        assert_eq!((metrics.multiplications, metrics.divisions), (3, 1));
+        assert_eq!(metrics.vm_commands, compiled.vm.lines().count());
+        assert!(metrics.to_string().starts_with("Metrics: Game\n  fields                   2\n  statics                  1\n"));
+        assert!(metrics.to_string().ends_with("\n  subroutine            complexity  statements\n  new                            1           2\n  show                           2           9\n  reset                          1           1"));
+
+        // `y * 4` is additions with -O
+        let options = LanguageOptions { optimize: true, ..LanguageOptions::default() };
+        let optimized = VM::compile_with_symbols(&class, options, None, None, None).unwrap().metrics;
+        assert_eq!((optimized.multiplications, optimized.divisions), (2, 1));
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashSet;
+use crate::ast::*;
+use crate::error::SemanticError;
+use crate::diagnostics::{Diagnostics, Lint};
+use crate::tokenizer::Span;
+
+// `-W naming`: the conventions of the course's code. Classes are PascalCase, subroutines, fields,
+// parameters and locals lowerCamelCase, statics and constants UPPER_SNAKE_CASE so they do not read
+// like locals, and only loop indices are a single letter. That a class is in the file of its name
+// is `-W class-file-name`.
+pub fn check_names(class: &Class, diagnostics: &mut Diagnostics) {
+    let class_name = class.name.as_str();
+    if !is_pascal_case(class_name) {
+        let name = format!("class {}", class_name);
+        rename(diagnostics, Lint::NamingClasses, &name, pascal_case(class_name), class.span, "class names are PascalCase");
+    }
+    for class_var_dec in class.class_var_decs.iter() {
+        let kind = match class_var_dec.dec_type {
+            ClassVarDecType::Field => "field",
+            ClassVarDecType::Static => "static",
+            ClassVarDecType::Const => "constant"
+        };
+        for var_name in class_var_dec.var_names() {
+            if class_var_dec.dec_type == ClassVarDecType::Field {
+                check_variable(kind, class_name, var_name, diagnostics);
+            } else if !is_upper_snake_case(var_name.as_str()) {
+                let name = format!("{} {} of {}", kind, var_name.0, class_name);
+                let note = "statics and constants are UPPER_SNAKE_CASE, in lowerCamelCase they look like locals";
+                rename(diagnostics, Lint::NamingStatics, &name, upper_snake_case(var_name.as_str()), var_name.1, note);
+            }
+            check_length(kind, class_name, var_name, diagnostics);
+        }
+    }
+    for subroutine_dec in class.subroutine_decs.iter() {
+        let name = subroutine_dec.name.as_str();
+        let function_name = format!("{}.{}", class_name, name);
+        if !is_lower_camel_case(name) {
+            let note = "subroutine names are lowerCamelCase";
+            rename(diagnostics, Lint::NamingSubroutines, &format!("subroutine {}", function_name), lower_camel_case(name), subroutine_dec.span, note);
+        }
+        let loop_indices = loop_indices(&subroutine_dec.body.statements);
+        let parameters = subroutine_dec.parameters.iter().map(|parameter| ("parameter", &parameter.1));
+        let locals = subroutine_dec.body.var_decs.iter().flat_map(|var_dec| var_dec.var_names().map(|var_name| ("local", var_name)));
+        for (kind, var_name) in parameters.chain(locals) {
+            check_variable(kind, &function_name, var_name, diagnostics);
+            if !loop_indices.contains(var_name.as_str()) {
+                check_length(kind, &function_name, var_name, diagnostics);
+            }
+        }
+    }
+}
+
+// fields, parameters and locals
+fn check_variable(kind: &str, owner: &str, var_name: &VarName, diagnostics: &mut Diagnostics) {
+    if !is_lower_camel_case(var_name.as_str()) {
+        let name = format!("{} {} of {}", kind, var_name.0, owner);
+        rename(diagnostics, Lint::NamingVariables, &name, lower_camel_case(var_name.as_str()), var_name.1, "variable names are lowerCamelCase");
+    }
+}
+
+fn check_length(kind: &str, owner: &str, var_name: &VarName, diagnostics: &mut Diagnostics) {
+    if var_name.as_str().chars().count() == 1 {
+        let message = format!("{} {} of {} is a single letter", kind, var_name.0, owner);
+        let note = "a name that says what it holds reads better, one letter is for loop indices";
+        diagnostics.warn(Lint::NamingShort, SemanticError::new(&message, var_name.1).with_note(note));
+    }
+}
+
+// `class my_game should be named MyGame`. A name that is only underscores has no spelling to suggest.
+fn rename(diagnostics: &mut Diagnostics, lint: Lint, name: &str, suggestion: String, span: Span, note: &str) {
+    if !suggestion.is_empty() {
+        let message = format!("{} should be named {}", name, suggestion);
+        diagnostics.warn(lint, SemanticError::new(&message, span).with_note(note));
+    }
+}
+
+// The variables a loop counts with, those its condition reads and its body sets: `i` of
+// `while (i < n) { ...; let i = i + 1; }`, also of a for loop.
+fn loop_indices(statements: &Statements) -> HashSet<&str> {
+    let mut loops = Collector::new(|node| match node {
+        Node::Statement(Statement::While(statement)) => Some(statement),
+        _ => None
+    });
+    for statement in statements.iter() {
+        walk(Node::Statement(statement), &mut loops);
+    }
+    let mut indices = HashSet::new();
+    for statement in loops.found {
+        let mut read = Collector::new(|node| match node {
+            Node::Term(Term::VarName(name, _)) => Some(name.as_str()),
+            _ => None
+        });
+        walk(Node::expression(&statement.expression), &mut read);
+        let mut set = Collector::new(|node| match node {
+            Node::Statement(Statement::Let(statement)) if statement.index_expression.is_none() => Some(statement.var_name.as_str()),
+            _ => None
+        });
+        for statement in statement.statements.iter() {
+            walk(Node::Statement(statement), &mut set);
+        }
+        indices.extend(read.found.into_iter().filter(|name| set.found.contains(name)));
+    }
+    indices
+}
+
+// what `pick` finds in the nodes it is walked over
+struct Collector<T, F> {
+    pick: F,
+    found: Vec<T>
+}
+
+impl<T, F> Collector<T, F> {
+    fn new(pick: F) -> Self {
+        Collector { pick, found: Vec::new() }
+    }
+}
+
+impl<'a, T, F: FnMut(Node<'a>) -> Option<T>> Visitor<'a> for Collector<T, F> {
+    fn enter(&mut self, node: Node<'a>, _depth: usize) -> bool {
+        self.found.extend((self.pick)(node));
+        true
+    }
+}
+
+// `drawSquare` and `XMLParser` are left alone, `draw_square` and `square` are not
+fn is_pascal_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase()) && !name.contains('_')
+}
+
+fn is_lower_camel_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase()) && !name.contains('_')
+}
+
+fn is_upper_snake_case(name: &str) -> bool {
+    !name.contains(|c: char| c.is_ascii_lowercase())
+}
+
+fn pascal_case(name: &str) -> String {
+    words(name).iter().map(|word| capitalized(word)).collect()
+}
+
+fn lower_camel_case(name: &str) -> String {
+    let words = words(name);
+    let Some((first, rest)) = words.split_first() else { return String::new() };
+    std::iter::once(first.to_ascii_lowercase()).chain(rest.iter().map(|word| capitalized(word))).collect()
+}
+
+fn upper_snake_case(name: &str) -> String {
+    words(name).iter().map(|word| word.to_ascii_uppercase()).collect::<Vec<_>>().join("_")
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => std::iter::once(first.to_ascii_uppercase()).chain(chars.map(|c| c.to_ascii_lowercase())).collect(),
+        None => String::new()
+    }
+}
+
+// `draw_square`, `drawSquare` and `DRAW_SQUARE` are all draw and square, `XMLParser` is XML and Parser
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let previous = index.checked_sub(1).map(|index| chars[index]);
+        let next = chars.get(index + 1);
+        // a capital after a lowercase letter or a digit starts a word, as does the last capital of an acronym
+        let after_lowercase = previous.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit());
+        let ends_acronym = previous.is_some_and(|p| p.is_ascii_uppercase()) && next.is_some_and(|n| n.is_ascii_lowercase());
+        if c.is_ascii_uppercase() && !word.is_empty() && (after_lowercase || ends_acronym) {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Lints;
+    use crate::parse_str;
+
+    fn naming_warnings(source: &str, lints: Lints) -> Vec<String> {
+        let mut diagnostics = Diagnostics::new(lints);
+        check_names(&parse_str(source).unwrap(), &mut diagnostics);
+        diagnostics.finish().unwrap().iter().map(|warning| warning.to_string().lines().next().unwrap().to_string()).collect()
+    }
+
+    fn naming() -> Lints {
+        Lints::default().set("naming").unwrap()
+    }
+
+    #[test]
+    fn spellings() {
+        assert_eq!(words("XMLParser"), vec!["XML", "Parser"]);
+        assert_eq!(words("__draw_Square2x"), vec!["draw", "Square2x"]);
+        assert_eq!(pascal_case("my_game"), "MyGame");
+        assert_eq!(lower_camel_case("Draw_Square"), "drawSquare");
+        assert_eq!(lower_camel_case("MAX"), "max");
+        assert_eq!(upper_snake_case("maxBalls"), "MAX_BALLS");
+        assert_eq!(upper_snake_case("XMLParser"), "XML_PARSER");
+    }
+
+    #[test]
+    fn names_against_the_conventions() {
+        let source = "\
+class my_game {
+    field int Pos_x, y;
+    static int count;
+    method void Draw(int Size) {
+        var int t, i, total;
+        let t = 0;
+        while (i < Size) { let i = i + 1; }
+        return;
+    }
+}";
+        assert_eq!(naming_warnings(source, naming()), vec![
+            "1:7: class my_game should be named MyGame [-W naming-classes]",
+            "2:15: field Pos_x of my_game should be named posX [-W naming-variables]",
+            "2:22: field y of my_game is a single letter [-W naming-short]",
+            "3:16: static count of my_game should be named COUNT [-W naming-statics]",
+            "4:17: subroutine my_game.Draw should be named draw [-W naming-subroutines]",
+            "4:26: parameter Size of my_game.Draw should be named size [-W naming-variables]",
+            // i counts the loop
+            "5:17: local t of my_game.Draw is a single letter [-W naming-short]"
+        ]);
+        // one lint of the group at a time
+        let lints = naming().without(Lint::NamingShort).without(Lint::NamingVariables);
+        assert_eq!(naming_warnings(source, lints).len(), 3);
+        assert!(naming_warnings(source, Lints::default()).is_empty());
+    }
+
+    #[test]
+    fn conforming_names_pass() {
+        let source = "\
+class XMLParser {
+    field int position, lineCount;
+    static int MAX_DEPTH;
+    function int parseAll(int n) {
+        var int index, j;
+        let index = 0;
+        let j = 9;
+        while (~(j = 0)) { let j = j - 1; }
+        while (index < n) { let index = index + 1; let n = n - 1; }
+        return index;
+    }
+}";
+        assert!(naming_warnings(source, naming()).is_empty());
+    }
+}
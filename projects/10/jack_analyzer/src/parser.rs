@@ -1,32 +1,356 @@
-use std::fs::File;
 use std::iter::Peekable;
-use std::error::Error;
-use std::io::Write;
+use std::io;
+use std::io::{Read, Write};
+use std::time::Duration;
+use std::vec::IntoIter;
+use crate::LanguageOptions;
+use crate::ast::*;
+use crate::error::{SyntaxError, SemanticError, CompileError};
 use crate::tokenizer::Tokenizer;
 use crate::tokenizer::Token;
+use crate::tokenizer::Span;
+use crate::tokenizer::Trivia;
+use crate::tokenizer::INTEGER_RANGE;
 use crate::utils::Padding;
 use crate::utils::Symbol;
-use crate::utils::SymbolTable;
+use crate::utils::Scopes;
 use crate::utils::SymbolKind;
 use crate::utils::CharSet;
 use crate::utils::LabelGenerator;
+use crate::signatures::{Accessor, Signature, Signatures, OS_CLASSES};
+use crate::analysis;
+use crate::naming;
+use crate::peephole;
+use crate::metrics::Metrics;
+use crate::diagnostics::{Diagnostics, Lint, Warning};
+use crate::vm::{self, Operator, Segment, VmCommand};
+
+// Tokens of a whole file, each with the position it was found at
+pub struct Tokens {
+    tokens: Peekable<IntoIter<(Token, Span)>>,
+    last_span: Span,
+    // statement errors the parser recovered from
+    errors: Vec<SyntaxError>,
+    trivia: Trivia,
+    options: LanguageOptions,
+    // how many terms and blocks the parser is inside of, each one is a recursive call
+    depth: usize,
+    max_depth: usize,
+    // the variables declared in the class and in the current subroutine, with their positions
+    scopes: Vec<Vec<(String, Span)>>,
+    // the term being parsed follows a unary minus, so it can be 32768
+    negated: bool
+}
 
-pub struct XML;
+// parsing gives up on a file after this many errors
+const MAX_ERRORS: usize = 20;
 
-impl XML {
-    pub fn compile(file: File, output: &mut File) -> Result<(), Box<dyn Error>> {
-        let mut tokenizer = Tokenizer::new(file)?.peekable();
-        let parser = ClassParser::new(&mut tokenizer);
-        let mut padding = Padding::new();
-        for class in parser {
-            println!("Parsing: {}", class.name.0);
-            write!(output, "{}", class.to_xml(&mut padding))?;
+// deeper nesting gives an error instead of overflowing the stack
+pub const MAX_DEPTH: usize = 256;
+
+// the Sys.error codes of `--bounds-check` and `--null-check`, after the ones the OS uses
+pub const INDEX_ERROR: i16 = 30;
+pub const NULL_ERROR: i16 = 31;
+
+impl Tokens {
+    pub fn trivia(&self) -> &Trivia {
+        &self.trivia
+    }
+
+    pub fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek().map(|(token, _)| token)
+    }
+
+    // position of the next token, or of the last one at the end of the file
+    pub fn span(&mut self) -> Span {
+        match self.tokens.peek() {
+            Some((_, span)) => *span,
+            None => self.last_span
+        }
+    }
+
+    pub fn error(&mut self, expected: &str) -> SyntaxError {
+        let found = self.found();
+        SyntaxError::new(expected, &found, self.span())
+    }
+
+    fn found(&mut self) -> String {
+        match self.peek() {
+            Some(token) => token.to_string(),
+            None => "end of file".to_string()
+        }
+    }
+
+    pub fn expect_symbol(&mut self, symbol: char, context: &str) -> Result<(), SyntaxError> {
+        match self.peek() {
+            Some(Token::Symbol(v)) if *v == symbol => {
+                self.next();
+                Ok(())
+            },
+            _ => Err(self.error(&format!("'{}' {}", symbol, context)))
+        }
+    }
+
+    // the text of the next token, moved out of it rather than copied from the peeked one
+    fn take_text(&mut self) -> String {
+        match self.next() {
+            Some(Token::Keyword(v) | Token::Identifier(v) | Token::String(v)) => v,
+            _ => String::new()
+        }
+    }
+
+    pub fn expect_identifier(&mut self, expected: &str) -> Result<String, SyntaxError> {
+        match self.peek() {
+            Some(Token::Identifier(_)) => Ok(self.take_text()),
+            // `var int class;`, expected is "a variable name", "a class name after ..." and so on
+            Some(Token::Keyword(v)) if expected.starts_with("a ") && expected.contains(" name") => {
+                let what = &expected[2..expected.find(" name").unwrap() + " name".len()];
+                let message = format!("'{}' is a reserved keyword and cannot be used as a {}", v, what);
+                let found = format!("'{}'", v);
+                let error = SyntaxError::with_message(&message, &found, self.span());
+                // or recovery would take `let do = 1;` for a do statement
+                self.next();
+                Err(error)
+            },
+            _ => Err(self.error(expected))
+        }
+    }
+
+    // a variable name being declared, a second one with the same name in the scope is an error
+    fn declare_variable(&mut self, expected: &str) -> Result<VarName, SyntaxError> {
+        let span = self.span();
+        let name = self.expect_identifier(expected)?;
+        if let Some(scope) = self.scopes.last_mut() {
+            match scope.iter().find(|(declared, _)| *declared == name) {
+                Some((_, first)) => {
+                    let message = format!("{} is already declared at {}", name, first);
+                    let error = SyntaxError::with_message(&message, &format!("'{}'", name), span);
+                    self.errors.push(error.with_related(*first, &format!("{} is first declared here", name)));
+                },
+                None => scope.push((name.clone(), span))
+            }
+        }
+        Ok(VarName(name, span))
+    }
+
+    fn open_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    fn close_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    // The doc comment of the declaration the next token starts: the text of a `/** */` comment
+    // between the last token and the next one, without the comment markers and leading `*`.
+    fn doc_comment(&self) -> Option<String> {
+        let next = self.trivia.token_spans.len() - self.tokens.len();
+        let start = match next.checked_sub(1) {
+            Some(last) => self.trivia.token_spans[last],
+            None => Span::new(0, 0)
+        };
+        let end = self.trivia.token_spans.get(next).copied()?;
+        let comment = self.trivia.comments.iter().rev()
+            .find(|comment| comment.span < end)
+            .filter(|comment| comment.span > start)?;
+        if !comment.lines[0].starts_with("/**") {
+            return None;
+        }
+        let last = comment.lines.len() - 1;
+        let lines: Vec<&str> = comment.lines.iter().enumerate().map(|(i, line)| {
+            let line = if i == 0 { &line[3..] } else { line.as_str() };
+            let line = if i == last { line.strip_suffix("*/").unwrap_or(line) } else { line };
+            let line = line.trim();
+            line.strip_prefix('*').unwrap_or(line).trim()
+        }).collect();
+        let text = lines.join("\n").trim().to_string();
+        if text.is_empty() { None } else { Some(text) }
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    // runs a recursive part of the parser one level deeper
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Tokens) -> Result<T, SyntaxError>) -> Result<T, SyntaxError> {
+        if self.depth >= self.max_depth {
+            let found = self.found();
+            return Err(SyntaxError::with_message("expression nesting too deep", &found, self.span()));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+}
+
+impl Tokens {
+    // Records a statement level error and skips ahead to where parsing can resume.
+    // Gives the error back when there is no point going on.
+    pub fn recover(&mut self, error: SyntaxError) -> Result<(), SyntaxError> {
+        if self.errors.len() + 1 >= MAX_ERRORS || !self.synchronize() {
+            return Err(error);
         }
+        self.errors.push(error);
         Ok(())
     }
 
+    // Skips to the next statement: past a `;`, or up to a `}` or statement keyword, not counting
+    // nested blocks. Returns false at the end of the file or the start of the next subroutine,
+    // a block was left open then and everything after would be reported again.
+    fn synchronize(&mut self) -> bool {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None => return false,
+                Some(Token::Keyword(v)) if depth == 0 => match v.as_str() {
+                    "let" | "if" | "while" | "for" | "do" | "return" | "break" | "continue" => return true,
+                    "constructor" | "function" | "method" => return false,
+                    _ => {}
+                },
+                Some(Token::Symbol('}')) if depth == 0 => return true,
+                Some(Token::Symbol('}')) => depth -= 1,
+                Some(Token::Symbol('{')) => depth += 1,
+                Some(Token::Symbol(';')) if depth == 0 => {
+                    self.next();
+                    return true;
+                },
+                _ => {}
+            }
+            self.next();
+        }
+    }
+}
+
+impl Tokens {
+    // the tokens left, in the format of the course's FooT.xml files
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from("<tokens>\n");
+        for (token, _) in self.tokens.clone() {
+            xml.push_str(&match token {
+                Token::Keyword(v) => XML::keyword(&v),
+                Token::Symbol(v) => XML::symbol(v),
+                Token::Int(v) => format!("<integerConstant> {} </integerConstant>\n", v as u16),
+                Token::String(v) => XML::string_constant(&v),
+                Token::Identifier(v) => XML::identifier(&v)
+            });
+        }
+        xml.push_str("</tokens>\n");
+        xml
+    }
+}
+
+impl Iterator for Tokens {
+    type Item=Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (token, span) = self.tokens.next()?;
+        self.last_span = span;
+        Some(token)
+    }
+}
+
+pub fn tokenize(input: impl Read + 'static, options: LanguageOptions) -> Result<Tokens, CompileError> {
+    let mut tokenizer = Tokenizer::new(input, options)?;
+    let mut tokens = Vec::new();
+    while let Some(token) = tokenizer.next() {
+        match token {
+            Ok(token) => tokens.push((token, tokenizer.span())),
+            Err(e) => return Err(lexical_error(e))
+        }
+    }
+    let trivia = Trivia {
+        comments: tokenizer.comments().to_vec(),
+        token_spans: tokens.iter().map(|(_, span)| *span).collect()
+    };
+    Ok(Tokens { tokens: tokens.into_iter().peekable(), last_span: Span::new(1, 1), errors: Vec::new(), trivia, options, depth: 0, max_depth: MAX_DEPTH, scopes: Vec::new(), negated: false })
+}
+
+// unwraps the SyntaxError the tokenizer reports through io::Error
+fn lexical_error(error: io::Error) -> CompileError {
+    match error.get_ref().and_then(|e| e.downcast_ref::<SyntaxError>()) {
+        Some(syntax_error) => CompileError::from(syntax_error.clone()),
+        None => CompileError::Io(error)
+    }
+}
+
+// adds the statement errors recovered from on the way, sorted by position
+fn with_recovered_errors<T>(tokenizer: &mut Tokens, result: Result<T, SyntaxError>) -> Result<T, Vec<SyntaxError>> {
+    let mut errors = std::mem::take(&mut tokenizer.errors);
+    match result {
+        Ok(v) if errors.is_empty() => return Ok(v),
+        Ok(_) => {},
+        Err(e) => errors.push(e)
+    }
+    errors.sort_by_key(|e| e.span);
+    Err(errors)
+}
+
+fn parse_next_class(tokenizer: &mut Tokens) -> Result<Class, SyntaxError> {
+    match ClassParser::new(tokenizer).next() {
+        Some(class) => class,
+        None => Err(tokenizer.error("'class'"))
+    }
+}
+
+// parses the next class, reporting the first error if there are several
+pub fn parse_class(tokenizer: &mut Tokens) -> Result<Class, SyntaxError> {
+    let class = parse_next_class(tokenizer);
+    with_recovered_errors(tokenizer, class).map_err(|mut errors| errors.remove(0))
+}
+
+// parses a file consisting of exactly one class
+pub fn parse_single_class(tokenizer: &mut Tokens) -> Result<Class, CompileError> {
+    if tokenizer.peek().is_none() {
+        return Err(CompileError::NoClass);
+    }
+    // a second class, stray statements or a file pasted twice would be silently dropped
+    let class = parse_next_class(tokenizer).and_then(|class| match tokenizer.peek() {
+        None => Ok(class),
+        Some(_) => Err(tokenizer.error(&format!("end of file after class {}", class.name.0)))
+    });
+    with_recovered_errors(tokenizer, class).map_err(CompileError::Syntax)
+}
+
+// The symbol tables the VM code is generated with, the class's and one per subroutine
+pub fn analyze(class: &Class) -> String {
+    let mut scopes = Scopes::for_class(class);
+    let mut sections = vec![class_symbols(&class.name.0, &scopes)];
+    for subroutine_dec in class.subroutine_decs.iter() {
+        scopes.push_subroutine(&class.name.0, subroutine_dec);
+        sections.push(subroutine_symbols(&class.name.0, &subroutine_dec.name.0, &scopes));
+        scopes.pop_scope();
+    }
+    sections.join("\n")
+}
+
+// the sections of Foo.sym
+fn class_symbols(class_name: &str, scopes: &Scopes) -> String {
+    format!("class {}\n{}", class_name, scopes)
+}
+
+fn subroutine_symbols(class_name: &str, subroutine_name: &str, scopes: &Scopes) -> String {
+    format!("subroutine {}.{}\n{}", class_name, subroutine_name, scopes)
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct XML;
+
+impl XML {
+    // the XML of the class into a file, stdout or a `Vec<u8>`, and the number of bytes written
+    pub fn compile<W: Write>(class: &Class, sink: &mut W) -> io::Result<usize> {
+        let xml = XML::text(class);
+        sink.write_all(xml.as_bytes())?;
+        Ok(xml.len())
+    }
+
+    // the XML as a string, for the outputs that are made from it
+    pub fn text(class: &Class) -> String {
+        class.to_xml(&mut Padding::new())
+    }
+
     pub fn symbol(symbol: char) -> String {
-        format!("<symbol> {} </symbol>\n", symbol)
+        format!("<symbol> {} </symbol>\n", XML::escape(&symbol.to_string()))
     }
 
     pub fn keyword(keywrod: &str) -> String {
@@ -34,462 +358,1357 @@ impl XML {
     }
 
     pub fn identifier(identifier: &str) -> String {
-        format!("<identifier> {} </identifier>\n", identifier)
+        format!("<identifier> {} </identifier>\n", XML::escape(identifier))
+    }
+
+    pub fn string_constant(content: &str) -> String {
+        format!("<stringConstant> {} </stringConstant>\n", XML::escape(content))
+    }
+
+    // text content may not contain markup characters, `a < b` becomes `a &lt; b`
+    pub fn escape(text: &str) -> String {
+        let mut escaped = String::new();
+        for char in text.chars() {
+            match char {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                _ => escaped.push(char)
+            }
+        }
+        escaped
     }
 }
 
-pub struct VM {
-    class_table: SymbolTable,
-    subroutine_table: SymbolTable,
+// the source of a class for `--annotate`, its lines go before the code of their statements
+pub struct Listing<'a> {
+    pub file: &'a str,
+    pub lines: &'a [&'a str]
+}
+
+pub struct VM<'a> {
+    scopes: Scopes,
     label_generator: LabelGenerator,
     charset: CharSet,
-    class_name: String
+    class_name: String,
+    // the subroutine being compiled, for error messages and field access
+    subroutine_name: String,
+    subroutine_type: SubroutineType,
+    n_parameters: usize,
+    // where tail calls of the subroutine jump back to, right after its prologue
+    restart_label: Option<String>,
+    options: LanguageOptions,
+    // the subroutines of the whole project, calls are checked against them
+    signatures: Option<&'a Signatures>,
+    // the subroutines of the OS and this class, for when the project is not known
+    class_signatures: Signatures,
+    diagnostics: Diagnostics,
+    // the symbol tables as they were used, for `--emit-symbols`
+    symbols: Vec<String>,
+    listing: Option<Listing<'a>>,
+    // the commands are marked with the positions they were made for, for `--source-map`
+    source_map: bool,
+    // how long the checks of the class took, for `--timings`
+    analysis_time: Duration,
+    // the loops around the statement being compiled, innermost last
+    loops: Vec<Loop>,
+    // the `let` of each field initializer, which every constructor starts with
+    field_initializers: Vec<Statement>
+}
+
+// Where break and continue go in a loop, each label made when the first of them needs it
+#[derive(Default)]
+struct Loop {
+    // the test, or the increment of a for loop
+    next: Option<String>,
+    exit: Option<String>
 }
 
-impl VM {
+// the VM code of a class, with what else its compilation made
+pub struct Compiled {
+    pub vm: String,
+    // the same code as commands, for `vm_eval`
+    pub commands: Vec<VmCommand>,
+    // the symbol tables the code was made with, in the format of `--stop-after analyze`
+    pub symbols: String,
+    // the positions of the Jack file each line was made for, when asked for
+    pub source_map: Option<String>,
+    pub metrics: Metrics,
+    pub warnings: Vec<Warning>,
+    // the part of the compilation spent checking the class rather than making its code
+    pub analysis_time: Duration
+}
+
+impl<'a> VM<'a> {
     pub fn new(class_name: &str) -> Self {
         VM {
-            class_table: SymbolTable::new(),
-            subroutine_table: SymbolTable::new(),
+            scopes: Scopes::new(),
             label_generator: LabelGenerator::new(class_name),
             charset: CharSet::new(),
-            class_name: class_name.to_string()
+            class_name: class_name.to_string(),
+            subroutine_name: String::new(),
+            subroutine_type: SubroutineType::Function,
+            n_parameters: 0,
+            restart_label: None,
+            options: LanguageOptions::default(),
+            signatures: None,
+            class_signatures: Signatures::with_os(),
+            diagnostics: Diagnostics::default(),
+            symbols: Vec::new(),
+            listing: None,
+            source_map: false,
+            analysis_time: Duration::ZERO,
+            loops: Vec::new(),
+            field_initializers: Vec::new()
         }
     }
 
-    pub fn compile(file: File, output: &mut File) -> Result<(), Box<dyn Error>> {
-        let mut tokenizer = Tokenizer::new(file)?.peekable();
-        let parser = ClassParser::new(&mut tokenizer);
-        for class in parser {
-            println!("Compiling: {}", class.name.0);
-            let mut vm = VM::new(&class.name.0);
-            write!(output, "{}", vm.compile_class(&class))?;
+    // the VM code into a file, stdout or a `Vec<u8>`, the number of bytes written and the warnings
+    // about the code. Nothing is written when the class does not compile.
+    pub fn compile<W: Write>(
+        class: &Class, options: LanguageOptions, signatures: Option<&Signatures>, sink: &mut W
+    ) -> Result<(usize, Vec<Warning>), CompileError> {
+        let compiled = VM::compile_with_symbols(class, options, signatures, None, None)?;
+        sink.write_all(compiled.vm.as_bytes())?;
+        Ok((compiled.vm.len(), compiled.warnings))
+    }
+
+    // also the symbol tables, the source lines as comments when there is a listing, and the
+    // source map of the code when given the name of the Jack file
+    pub fn compile_with_symbols(
+        class: &Class,
+        options: LanguageOptions,
+        signatures: Option<&'a Signatures>,
+        listing: Option<Listing<'a>>,
+        source_map: Option<&str>
+    ) -> Result<Compiled, CompileError> {
+        let mut vm = VM::new(&class.name.0);
+        vm.options = options;
+        vm.signatures = signatures;
+        vm.listing = listing;
+        vm.source_map = source_map.is_some();
+        vm.diagnostics = Diagnostics::new(options.warnings);
+        let mut commands = vm.compile_class(class);
+        if options.optimize {
+            commands = peephole::optimize(commands);
         }
-        Ok(())
+        let warnings = vm.diagnostics.finish()?;
+        Ok(Compiled {
+            vm: vm::to_text(&commands),
+            symbols: vm.symbols.join("\n"),
+            source_map: source_map.map(|file| vm::source_map(&commands, file)),
+            metrics: Metrics::new(class, &commands),
+            warnings,
+            commands,
+            analysis_time: vm.analysis_time
+        })
+    }
+
+    pub fn generate_label(&mut self) -> String {
+        self.label_generator.generate()
     }
 
-    pub fn push(segment: &str, value: i16) -> String {
-        format!("push {} {}\n", segment, value)
+    // `// Foo.jack:42: let sum = sum + a[i];`
+    fn annotation(&self, span: Span) -> Option<VmCommand> {
+        let listing = self.listing.as_ref()?;
+        let line = listing.lines.get(span.line.checked_sub(1)?)?;
+        Some(VmCommand::Comment(format!("{}:{}: {}", listing.file, span.line, line.trim())))
     }
 
-    pub fn pop(segment: &str, index: i16) -> String {
-        format!("pop {} {}\n", segment, index)
+    // marks where the code for a position starts, with `--source-map`
+    fn origin(&self, span: Span) -> Option<VmCommand> {
+        self.source_map.then_some(VmCommand::Origin(span))
     }
 
-    pub fn op(name: &str) -> String {
-        format!("{}\n", name)
+    fn in_function(&self) -> bool {
+        self.subroutine_type == SubroutineType::Function
     }
 
-    pub fn call(function_name: &str, n_args: i16) -> String {
-        format!("call {} {}\n", function_name, n_args)
+    // fields are not visible in functions
+    pub fn find_by(&self, name: &str) -> Option<&Symbol> {
+        match self.scopes.find_by(name) {
+            Some(symbol) if self.in_function() && matches!(symbol.kind(), SymbolKind::Field) => None,
+            symbol => symbol
+        }
     }
 
-    pub fn build(instructions: Vec<String>) -> String {
-        let mut vm = String::new();
-        for instruction in instructions.iter() {
-            vm.push_str(instruction);
+    // segment and index of a variable, an undefined one is reported and the output discarded
+    fn resolve(&mut self, name: &str, span: Span) -> (Segment, i16) {
+        if let Some(symbol) = self.find_by(name) {
+            return (symbol.vm_memory_segment(), symbol.index());
         }
-        vm
+        if self.signatures().constant(&self.class_name, name).is_some() {
+            let message = format!("{} is a constant of {}, which cannot be assigned or indexed", name, self.class_name);
+            self.diagnostics.error(SemanticError::new(&message, span));
+            return (Segment::Temp, 0);
+        }
+        let message = format!("undefined variable {} in {}.{}", name, self.class_name, self.subroutine_name);
+        let mut error = SemanticError::new(&message, span);
+        if self.scopes.find_by(name).is_some() {
+            let note = format!("{} is a field of {}, which a function cannot access", name, self.class_name);
+            error = error.with_note(&note);
+        }
+        self.diagnostics.error(error);
+        (Segment::Temp, 0)
     }
 
-    pub fn label(label: &str) -> String {
-        format!("label {}\n", label)
+    // the project's signatures, or those of the OS and the class when it is compiled on its own
+    fn signatures(&self) -> &Signatures {
+        self.signatures.unwrap_or(&self.class_signatures)
     }
 
-    pub fn generate_label(&mut self) -> String {
-        self.label_generator.generate()
+    // the value of `Ball.MAX_BALLS`, which is only known for the classes that are compiled
+    fn class_constant(&mut self, class_name: &str, name: &str, span: Span) -> Option<Term> {
+        let signatures = self.signatures();
+        if let Some(value) = signatures.constant(class_name, name) {
+            return Some(value.clone());
+        }
+        let message = if signatures.has_class(class_name) {
+            format!("undefined constant {}.{}", class_name, name)
+        } else {
+            format!("the value of {}.{} is only known when the whole project is compiled", class_name, name)
+        };
+        self.diagnostics.error(SemanticError::new(&message, span));
+        None
     }
 
-    pub fn goto(label: &str) -> String {
-        format!("goto {}\n", label)
+    // `a[i]` reads memory from the address in a, which only an Array is meant to hold
+    fn check_indexed(&mut self, name: &str, span: Span) {
+        let Some(symbol) = self.find_by(name) else { return };
+        let declared = format!("{} is declared at {}", name, symbol.declared());
+        let declared_here = format!("{} is declared here", name);
+        let message = format!("indexing {}, which is declared {}, not Array", name, symbol.var_type().as_str());
+        match symbol.var_type() {
+            Type::ClassName(class_name) if class_name == "Array" => {},
+            // an object is an address too, so only with `--type-check`
+            Type::ClassName(class_name) => if self.options.type_check() {
+                let note = format!("{}[i] reads the fields of the {} as an array; {}", name, class_name, declared);
+                let error = SemanticError::new(&message, span).with_note(&note).with_related(symbol.declared(), &declared_here);
+                self.diagnostics.warn(Lint::IndexNonArray, error);
+            },
+            _ => {
+                let error = SemanticError::new(&message, span).with_note(&declared).with_related(symbol.declared(), &declared_here);
+                self.diagnostics.warn(Lint::IndexNonArray, error);
+            }
+        }
     }
 
-    pub fn ifgoto(label: &str) -> String {
-        format!("if-goto {}\n", label)
+    pub fn compile_string(&mut self, content: &str, span: Span) -> Vec<VmCommand> {
+        // the OS stops the program with Sys.error(14) for String.new(0), so "" gets room for one
+        // character and keeps its length of 0
+        let capacity = content.chars().count().max(1) as i16;
+        let mut commands = vec![
+            VmCommand::Push(Segment::Constant, capacity),
+            VmCommand::Call("String.new".to_string(), 1)
+        ];
+        for char in content.chars() {
+            let char_number = match self.charset.decode(char) {
+                Ok(char_number) => char_number,
+                Err(message) => {
+                    let error = SemanticError::new(&format!("{} in string constant {:?}", message, content), span);
+                    if self.options.lossy_strings {
+                        self.diagnostics.warn(Lint::LossyString, error.with_note("printed as '?' because of --lossy-strings"));
+                    } else {
+                        self.diagnostics.error(error);
+                    }
+                    // '?'
+                    63
+                }
+            };
+            commands.push(VmCommand::Push(Segment::Constant, char_number));
+            commands.push(VmCommand::Call("String.appendChar".to_string(), 2));
+        }
+        commands
     }
 
-    pub fn function(name: &str, n_vars: i16) -> String {
-        format!("function {} {}\n", name, n_vars)
+    // runs checks that are not part of making the code, `--timings` counts them apart
+    #[cfg(feature = "fs")]
+    fn analyze(&mut self, checks: impl FnOnce(&mut Self)) {
+        let start = std::time::Instant::now();
+        checks(self);
+        self.analysis_time += start.elapsed();
     }
 
-    pub fn find_by(&self, name: &str) -> Option<&Symbol> {
-        self.subroutine_table.find_by(name).or_else(|| self.class_table.find_by(name))
+    // there is no clock on wasm32, and no `--timings` to read one without the command line
+    #[cfg(not(feature = "fs"))]
+    fn analyze(&mut self, checks: impl FnOnce(&mut Self)) {
+        checks(self);
     }
 
-    pub fn compile_string(&self, content: &str) -> String {
-        let mut push_chars = String::new();
-        for char in content.chars() {
-            let char_number = self.charset.decode(char);
-            push_chars.push_str(&VM::push("constant", char_number));
-            push_chars.push_str(&VM::call("String.appendChar", 2));
-        }
-        VM::build(vec![
-            VM::push("constant", content.len() as i16),
-            VM::call("String.new", 1),
-            push_chars
-        ])
-    }
-
-    fn compile_class(&mut self, class: &Class) -> String {
-        let mut instructions = String::new();
-        // mapping class variables to the symbol table
-        for var_dec in class.class_var_decs.iter() {
-            self.class_table.push(
-                &var_dec.var_name.0,
-                var_dec.var_type.clone(),
-                var_dec.dec_type.to_symbol_kind()
-            );
-            for extra_var_name in &var_dec.extra_var_names {
-                self.class_table.push(
-                    &extra_var_name.0,
-                    var_dec.var_type.clone(),
-                    var_dec.dec_type.to_symbol_kind()
-                );
+    fn compile_class(&mut self, class: &Class) -> Vec<VmCommand> {
+        let mut commands = Vec::new();
+        self.scopes = Scopes::for_class(class);
+        self.symbols.push(class_symbols(&self.class_name, &self.scopes));
+        self.analyze(|vm| {
+            analysis::unused_class_vars(class, &mut vm.diagnostics);
+            naming::check_names(class, &mut vm.diagnostics);
+            analysis::os_conformance(class, &mut vm.diagnostics);
+            // which subroutines are called is only known for the whole project
+            if let Some(signatures) = vm.signatures {
+                analysis::uncalled_subroutines(class, signatures, &mut vm.diagnostics);
             }
-        }
+            // the file a class has to be in
+            vm.class_signatures.add_class(class, &format!("{}.jack", class.name.0));
+        });
+        self.field_initializers = class.initializers(ClassVarDecType::Field);
         // adding subroutine vm instructions
         for subroutine_dec in class.subroutine_decs.iter() {
-            instructions.push_str(&self.compile_subroutine(&subroutine_dec))
-        }
-        instructions
-    }
-
-    fn compile_subroutine(&mut self, subroutine_dec: &SubroutineDec) -> String {
-        self.subroutine_table = SymbolTable::new();
-        // add method to the subroutine symbol table 
-        if let SubroutineType::Method = subroutine_dec.subroutine_type {
-            self.subroutine_table.push(
-                "this",
-                Type::ClassName(self.class_name.clone()),
-                SymbolKind::Argument
-            )
-        }
-        // add parameters to the subroutine symbol table
-        for parameter in subroutine_dec.parameters.iter() {
-            self.subroutine_table.push(
-                &parameter.1.0,
-                parameter.0.clone(),
-                SymbolKind::Argument
-            );
+            commands.extend(self.compile_subroutine(subroutine_dec));
         }
-        // handle local variables
-        let mut n_vars = 0;
-        for var_dec in subroutine_dec.body.var_decs.iter() {
-            n_vars += 1;
-            self.subroutine_table.push(
-                &var_dec.var_name.0,
-                var_dec.var_type.clone(),
-                SymbolKind::Local
-            );
-            for extra_var_name in var_dec.extra_var_names.iter() {
-                n_vars += 1;
-                self.subroutine_table.push(
-                    &extra_var_name.0,
-                    var_dec.var_type.clone(),
-                    SymbolKind::Local
-                );
-            }
+        if let Some(static_initializer) = class.static_initializer() {
+            commands.extend(self.compile_subroutine(&static_initializer));
         }
+        commands
+    }
+
+    fn compile_subroutine(&mut self, subroutine_dec: &SubroutineDec) -> Vec<VmCommand> {
+        if self.options.optimize {
+            // a local that is neither read nor written would only be set to 0 on every call
+            let mut used = analysis::referenced_names(&subroutine_dec.body.statements);
+            // a constructor also runs the field initializers
+            let initializers = match subroutine_dec.subroutine_type {
+                SubroutineType::Constructor => Statements(self.field_initializers.clone()),
+                _ => Statements(Vec::new())
+            };
+            used.extend(analysis::referenced_names(&initializers));
+            self.scopes.push_subroutine_without(&self.class_name, subroutine_dec, |name| !used.contains(name));
+        } else {
+            self.scopes.push_subroutine(&self.class_name, subroutine_dec);
+        }
+        self.subroutine_name = subroutine_dec.name.0.clone();
+        self.symbols.push(subroutine_symbols(&self.class_name, &self.subroutine_name, &self.scopes));
+        self.subroutine_type = subroutine_dec.subroutine_type.clone();
+        self.n_parameters = subroutine_dec.parameters.len();
+        self.analyze(|vm| {
+            if let Some(error) = analysis::check_returns(&vm.class_name, subroutine_dec) {
+                vm.diagnostics.error(error);
+            }
+            analysis::unreachable_statements(&subroutine_dec.body.statements, &mut vm.diagnostics);
+            analysis::check_complexity(&vm.class_name, subroutine_dec, vm.options.limits, &mut vm.diagnostics);
+        });
+        let n_vars = self.scopes.local_vars_count();
 
-        let mut instructions = Vec::new();
         // function functionName nVars
         let function_name = format!("{}.{}", self.class_name, subroutine_dec.name.0);
-        instructions.push(VM::function(&function_name, n_vars));
+        let mut commands: Vec<VmCommand> = self.origin(subroutine_dec.span).into_iter().collect();
+        if self.listing.is_some() {
+            commands.push(VmCommand::Comment(signature_comment(&function_name, subroutine_dec)));
+        }
+        let trace = self.trace(&function_name, subroutine_dec.span);
+        commands.push(VmCommand::Function(function_name, n_vars));
+        commands.extend(trace);
 
         match subroutine_dec.subroutine_type {
             SubroutineType::Constructor => {
-                let field_vars_count = self.class_table.field_vars_count();
-                instructions.push(VM::push("constant", field_vars_count));
-                instructions.push(VM::call("Memory.alloc", 1));
-                instructions.push(VM::pop("pointer", 0));
+                // the OS rejects Memory.alloc(0), an object without fields still takes a word
+                let field_vars_count = self.scopes.field_vars_count().max(1);
+                commands.push(VmCommand::Push(Segment::Constant, field_vars_count));
+                commands.push(VmCommand::Call("Memory.alloc".to_string(), 1));
+                commands.push(VmCommand::Pop(Segment::Pointer, 0));
+                let initializers = self.field_initializers.clone();
+                commands.extend(self.compile_statement_list(&initializers, &SubroutineReturnType::Void));
             },
             SubroutineType::Method => {
                 // set THIS pointer to the value of argument 0
-                instructions.push(VM::push("argument", 0));
-                instructions.push(VM::pop("pointer", 0));
+                commands.push(VmCommand::Push(Segment::Argument, 0));
+                commands.push(VmCommand::Pop(Segment::Pointer, 0));
             },
-            SubroutineType::Function => {}
+            SubroutineType::Function => commands.extend(self.static_initializer_calls()),
         }
         // handle statements
-        instructions.push(
-            self.compile_statements(&subroutine_dec.body.statements, &subroutine_dec.return_type)
-        );
-        VM::build(instructions)
+        let prologue = commands.len();
+        commands.extend(self.compile_statements(&subroutine_dec.body.statements, &subroutine_dec.return_type));
+        if let Some(label) = self.restart_label.take() {
+            commands.insert(prologue, VmCommand::Label(label));
+        }
+        // a void subroutine may just end, its VM function would run into the next one without a return
+        if subroutine_dec.return_type == SubroutineReturnType::Void && analysis::falls_through(&subroutine_dec.body.statements) {
+            if self.options.strict {
+                let message = format!("void {}.{} can end without a return statement", self.class_name, subroutine_dec.name.0);
+                self.diagnostics.error(SemanticError::new(&message, subroutine_dec.body.end));
+            }
+            commands.extend(self.origin(subroutine_dec.body.end));
+            commands.push(VmCommand::Push(Segment::Constant, 0));
+            commands.push(VmCommand::Return);
+        }
+        self.scopes.pop_scope();
+        commands
+    }
+
+    // Main.main starts with `do Foo.init_statics();` for every class of the project with static
+    // initializers, in the order of the class names. A class compiled on its own doesn't know them.
+    fn static_initializer_calls(&self) -> Vec<VmCommand> {
+        let Some(signatures) = self.signatures else { return Vec::new() };
+        if self.class_name != "Main" || self.subroutine_name != "main" {
+            return Vec::new();
+        }
+        signatures.class_names()
+            .filter_map(|class_name| signatures.find(class_name, STATIC_INITIALIZER))
+            .filter(|signature| signature.static_initializer)
+            .flat_map(|signature| [
+                VmCommand::Call(format!("{}.{}", signature.class_name, STATIC_INITIALIZER), 0),
+                VmCommand::Pop(Segment::Temp, 0)
+            ])
+            .collect()
+    }
+
+    fn compile_statements(&mut self, statements: &Statements, return_type: &SubroutineReturnType) -> Vec<VmCommand> {
+        self.compile_statement_list(&statements.0, return_type)
     }
 
-    fn compile_statements(&mut self, statements: &Statements, return_type: &SubroutineReturnType) -> String {
-        let mut instructions = Vec::new();
-        for statement in statements.0.iter() {
+    fn compile_statement_list(&mut self, statements: &[Statement], return_type: &SubroutineReturnType) -> Vec<VmCommand> {
+        let mut commands = Vec::new();
+        for statement in statements.iter() {
+            commands.extend(self.origin(statement.span()));
+            commands.extend(self.annotation(statement.span()));
             match statement {
-                Statement::Do(subroutine_call) => {
-                    instructions.push(self.compile_subroutine_call(subroutine_call));
-                    instructions.push(VM::pop("temp", 0));
+                Statement::Do(subroutine_call, _) => {
+                    commands.extend(self.compile_subroutine_call(subroutine_call, false));
+                    commands.push(VmCommand::Pop(Segment::Temp, 0));
                 },
                 Statement::If(statement) => {
-                    instructions.push(self.compile_if_statement(statement, return_type));
+                    commands.extend(self.compile_if_statement(statement, return_type));
                 },
                 Statement::While(statement) => {
-                    instructions.push(self.compile_while_statement(statement, return_type));
+                    commands.extend(self.compile_while_statement(statement, return_type));
                 },
                 Statement::Let(statement) => {
-                    instructions.push(self.compile_let_statement(statement));
+                    commands.extend(self.compile_let_statement(statement));
                 },
-                Statement::Return(expression) => {
-                    if let Some(expression) = expression {
-                        instructions.push(self.compile_expression(expression));
-                    } else if let SubroutineReturnType::Void = return_type {
-                        instructions.push(VM::push("constant", 0));
+                Statement::Break(span) => commands.extend(self.compile_jump(true, *span)),
+                Statement::Continue(span) => commands.extend(self.compile_jump(false, *span)),
+                Statement::Return(expression, span) => {
+                    self.check_return(expression.as_ref(), return_type, *span);
+                    if let Some(tail_call) = self.compile_tail_call(expression.as_ref()) {
+                        commands.extend(tail_call);
+                        continue;
+                    }
+                    match expression {
+                        Some(expression) => commands.extend(self.compile_expression(expression)),
+                        // the caller pops a value even from a void subroutine
+                        None => commands.push(VmCommand::Push(Segment::Constant, 0))
                     }
-                    instructions.push("return\n".to_string())
+                    commands.push(VmCommand::Return)
                 }
             }
         }
-        VM::build(instructions)
+        commands
     }
 
-    fn compile_subroutine_call(&self, subroutine_call: &SubroutineCall) -> String {
-        let mut instructions = String::new();
-        for expression in subroutine_call.expression_list.iter() {
-            instructions.push_str(&self.compile_expression(expression));
+    // Under `-O`, `return Main.count(n - 1);` in the function Main.count, or `return count(n - 1);` in
+    // the method, puts the new arguments in place of the old ones and jumps back to the start, so
+    // recursing in return position takes no stack. The object of a method stays the same.
+    fn compile_tail_call(&mut self, expression: Option<&Expression>) -> Option<Vec<VmCommand>> {
+        if !self.options.optimize {
+            return None;
         }
-        match &subroutine_call.caller {
-            None => {
-                let command = format!("{}.{}", self.class_name, subroutine_call.subroutine_name.0);
-                VM::build(vec![
-                    VM::push("pointer", 0),
-                    instructions,
-                    VM::call(&command, subroutine_call.expression_list.len() as i16 + 1)
-                ])
-            },
-            Some(caller) => {
-                if let Some(symbol) = self.find_by(&caller) {
-                    // handle method call
-                    let segment = symbol.vm_memory_segment();
-                    let index = symbol.index();
-                    let command = format!("{}.{}", symbol.class_name(), subroutine_call.subroutine_name.0);
-                    VM::build(vec![
-                        VM::push(&segment, index),
-                        instructions,
-                        VM::call(&command, subroutine_call.expression_list.len() as i16 + 1)
-                    ])
-                } else {
-                    // handle function calls and constructor calls
-                    let command = format!("{}.{}", caller, subroutine_call.subroutine_name.0);
-                    VM::build(vec![
-                        instructions,
-                        VM::call(&command, subroutine_call.expression_list.len() as i16)
-                    ])
-                }
-            }
+        let Some(Expression { term: Term::Call(subroutine_call), extra_op_terms }) = expression else { return None };
+        let n_args = subroutine_call.expression_list.len();
+        if !extra_op_terms.is_empty() || subroutine_call.subroutine_name.0 != self.subroutine_name || n_args != self.n_parameters {
+            return None;
         }
-    }
-
-    fn compile_if_statement(&mut self, statement: &IfStatement, return_type: &SubroutineReturnType) -> String {
-        let l1 = self.generate_label();
-        let l2 = self.generate_label();
-
-        let mut instructions = Vec::new();
-        instructions.push(self.compile_expression(&statement.expression));
-        instructions.push(VM::op("not"));
-        instructions.push(VM::ifgoto(&l1));
-        instructions.push(self.compile_statements(&statement.if_statements, return_type));
-        instructions.push(VM::goto(&l2));
-        instructions.push(VM::label(&l1));
-        if let Some(statements) = &statement.else_statements {
-            instructions.push(self.compile_statements(statements, return_type));
+        let first_argument = match (&self.subroutine_type, subroutine_call.caller.as_deref()) {
+            (SubroutineType::Method, None) => 1,
+            (SubroutineType::Function, Some(caller)) if caller == self.class_name && self.find_by(caller).is_none() => 0,
+            _ => return None
+        };
+        let mut commands = self.compile_subroutine_call(subroutine_call, true);
+        commands.pop();
+        if first_argument == 1 {
+            // `push pointer 0`
+            commands.remove(0);
+        }
+        for index in (0..n_args as i16).rev() {
+            commands.push(VmCommand::Pop(Segment::Argument, first_argument + index));
         }
-        instructions.push(VM::label(&l2));
-        VM::build(instructions)
+        // the locals start at 0 again like in a new call
+        for index in 0..self.scopes.local_vars_count() {
+            commands.push(VmCommand::Push(Segment::Constant, 0));
+            commands.push(VmCommand::Pop(Segment::Local, index));
+        }
+        if self.restart_label.is_none() {
+            self.restart_label = Some(self.generate_label());
+        }
+        commands.push(VmCommand::GoTo(self.restart_label.clone()?));
+        Some(commands)
     }
 
-    fn compile_while_statement(&mut self, statement: &WhileStatement, return_type: &SubroutineReturnType) -> String {
-        let l1 = self.generate_label();
-        let l2 = self.generate_label();
-
-        let mut instructions = Vec::new();
-        instructions.push(VM::label(&l1));
-        instructions.push(self.compile_expression(&statement.expression));
-        instructions.push(VM::op("not"));
-        instructions.push(VM::ifgoto(&l2));
-        instructions.push(self.compile_statements(&statement.statements, return_type));
-        instructions.push(VM::goto(&l1));
-        instructions.push(VM::label(&l2));
-        VM::build(instructions)
+    // void subroutines return no value, the others one, and constructors `this`
+    fn check_return(&mut self, expression: Option<&Expression>, return_type: &SubroutineReturnType, span: Span) {
+        let subroutine = format!("{} {}.{}", self.subroutine_type.as_str(), self.class_name, self.subroutine_name);
+        let returns_this = matches!(
+            expression,
+            Some(Expression { term: Term::KeywordConstant(KeywordConstant::This, _), extra_op_terms }) if extra_op_terms.is_empty()
+        );
+        let message = match (expression, return_type) {
+            (Some(_), SubroutineReturnType::Void) => format!("{} is declared void but returns a value", subroutine),
+            (None, SubroutineReturnType::General(return_type)) => {
+                format!("{} is declared to return {} but returns no value", subroutine, return_type.as_str())
+            },
+            (Some(_), SubroutineReturnType::General(return_type))
+                if self.subroutine_type == SubroutineType::Constructor && !returns_this => {
+                format!("{} is declared to return {} and has to return this", subroutine, return_type.as_str())
+            },
+            _ => return
+        };
+        self.diagnostics.error(SemanticError::new(&message, span));
     }
 
-    fn compile_let_statement(&self, statement: &LetStatement) -> String {
-        let symbol = self.find_by(&statement.var_name.0).unwrap_or_else(|| {
-            panic!("Var {} not found!", &statement.var_name.0);
-        });
-        if let Some(expression) = &statement.index_expression {
-            // handle array index assignment
-            VM::build(vec![
-                VM::push(&symbol.vm_memory_segment(), symbol.index()),
-                self.compile_expression(expression),
-                VM::op("add"),
-                self.compile_expression(&statement.expression),
-                VM::pop("temp", 0),
-                VM::pop("pointer", 1),
-                VM::push("temp", 0),
-                VM::pop("that", 0)
-            ])
-        } else {
-            VM::build(vec![
-                self.compile_expression(&statement.expression),
-                VM::pop(&symbol.vm_memory_segment(), symbol.index())
-            ])
+    // the declaration a call goes to when the project is known, calls that do not fit it are reported
+    fn check_call(&mut self, subroutine_call: &SubroutineCall) -> Option<Signature> {
+        let (class_name, on_object) = match self.called_class(subroutine_call) {
+            Some(called) => called,
+            None => {
+                if self.options.type_check() {
+                    let caller = subroutine_call.caller.as_deref().unwrap_or_default();
+                    let var_type = self.find_by(caller).map(|symbol| symbol.var_type().as_str().to_string()).unwrap_or_default();
+                    let message = format!(
+                        "{}.{}() calls a method on {}, which is declared {}",
+                        caller, subroutine_call.subroutine_name.0, caller, var_type
+                    );
+                    self.diagnostics.warn(Lint::TypeCheck, SemanticError::new(&message, subroutine_call.span));
+                }
+                return None;
+            }
+        };
+        let signature = match self.signatures {
+            Some(signatures) => self.check_project_call(signatures, &class_name, subroutine_call)?.clone(),
+            // the class's own subroutines are known without the project
+            None if class_name == self.class_name => {
+                let signature = self.class_signatures.find(&class_name, &subroutine_call.subroutine_name.0)?.clone();
+                self.check_arguments(subroutine_call, &signature);
+                signature
+            },
+            None => return None
+        };
+        if self.options.type_check() {
+            self.check_argument_types(subroutine_call, &signature);
         }
+        let call = format!("{}.{}", signature.class_name, signature.name);
+        let message = match (&signature.kind, on_object) {
+            (SubroutineType::Method, false) => format!("{} is a method; call it on an instance", call),
+            (SubroutineType::Method, true) if subroutine_call.caller.is_none() && self.in_function() => format!(
+                "{} is a method; function {}.{} has no instance to call it on", call, self.class_name, self.subroutine_name
+            ),
+            (SubroutineType::Function | SubroutineType::Constructor, true) => {
+                format!("{} is a {}; call it as {}()", call, signature.kind.as_str(), call)
+            },
+            _ => return Some(signature)
+        };
+        self.diagnostics.error(SemanticError::new(&message, subroutine_call.span).with_note(&signature.declared_at()));
+        Some(signature)
     }
 
-    fn compile_expression(&self, expression: &Expression) -> String {
-        let mut instructions = Vec::new();
-        instructions.push(self.compile_term(&expression.term));
-        for op_term in expression.extra_op_terms.iter() {
-            instructions.push(self.compile_term(&op_term.1));
-            instructions.push(self.compile_operation(&op_term.0));
+    // the class a call goes to, and whether it is called on an object, `draw()` is `this.draw()`.
+    // None for a call on a variable that is not an object.
+    fn called_class(&self, subroutine_call: &SubroutineCall) -> Option<(String, bool)> {
+        match &subroutine_call.caller {
+            None => Some((self.class_name.clone(), true)),
+            Some(caller) => match self.find_by(caller).map(|symbol| symbol.var_type()) {
+                Some(Type::ClassName(class_name)) => Some((class_name.clone(), true)),
+                Some(_) => None,
+                None => Some((caller.clone(), false))
+            }
         }
-        VM::build(instructions)
     }
 
-    fn compile_operation(&self, operation: &Op) -> String {
-        match operation {
-            Op::Plus => VM::op("add"),
-            Op::Minus => VM::op("sub"),
-            Op::Multiply => VM::call("Math.multiply", 2),
-            Op::Divide => VM::call("Math.divide", 2),
-            Op::And => VM::op("and"),
-            Op::Or => VM::op("or"),
-            Op::Lt => VM::op("lt"),
-            Op::Gt => VM::op("gt"),
-            Op::Eq => VM::op("eq")
+    // arguments of a type the parameter cannot take
+    fn check_argument_types(&mut self, subroutine_call: &SubroutineCall, signature: &Signature) {
+        for (i, (argument, parameter)) in subroutine_call.expression_list.iter().zip(&signature.parameters).enumerate() {
+            match self.type_of(argument) {
+                Some(found) if incompatible(parameter, &found) => {
+                    let message = format!(
+                        "{}.{} takes {} as argument {}, found {}",
+                        signature.class_name, signature.name, parameter.as_str(), i + 1, found.as_str()
+                    );
+                    self.diagnostics.warn(Lint::TypeCheck, SemanticError::new(&message, subroutine_call.span).with_note(&signature.declared_at()));
+                },
+                _ => {}
+            }
         }
     }
 
-    fn compile_unary_op(&self, unary_operation: &UnaryOp) -> String {
-        match unary_operation {
-            UnaryOp::Negative => VM::op("neg"),
-            UnaryOp::Not => VM::op("not"),
+    // the type of an expression as far as it is known, `null` and array elements have none
+    fn type_of(&self, expression: &Expression) -> Option<Type> {
+        let mut result = self.type_of_term(&expression.term);
+        for OpTerm(op, _, _) in expression.extra_op_terms.iter() {
+            result = match op {
+                Op::Plus | Op::Minus | Op::Multiply | Op::Divide => Some(Type::Int),
+                Op::Lt | Op::Gt | Op::Eq => Some(Type::Boolean),
+                // bitwise on ints, logical on booleans
+                Op::And | Op::Or => result
+            };
         }
+        result
     }
 
-    fn compile_term(&self, term: &Term) -> String {
+    fn type_of_term(&self, term: &Term) -> Option<Type> {
         match term {
-            Term::IntegerConstant(v) => VM::push("constant", *v),
-            Term::VarName(v) => {
-                let symbol = self.find_by(v).unwrap();
-                VM::push(&symbol.vm_memory_segment(), symbol.index())
+            Term::IntegerConstant(_) => Some(Type::Int),
+            Term::StringConstant(..) => Some(Type::ClassName("String".to_string())),
+            Term::KeywordConstant(KeywordConstant::True | KeywordConstant::False, _) => Some(Type::Boolean),
+            Term::KeywordConstant(KeywordConstant::This, _) => Some(Type::ClassName(self.class_name.clone())),
+            Term::KeywordConstant(KeywordConstant::Null, _) | Term::IndexVar(..) => None,
+            Term::VarName(name, _) => match self.find_by(name) {
+                Some(symbol) => Some(symbol.var_type().clone()),
+                None => self.type_of_term(self.signatures().constant(&self.class_name, name)?)
             },
-            Term::KeywordConstant(v) => {
-                match v {
-                    KeywordConstant::Null => VM::push("constant", 0),
-                    KeywordConstant::False => VM::push("constant", 0),
-                    KeywordConstant::True => {
-                        VM::build(vec![
-                            VM::push("constant", 1),
-                            VM::op("neg")
-                        ])
-                    },
-                    KeywordConstant::This => VM::push("pointer", 0)
+            Term::ClassConstant(class_name, name, _) => self.type_of_term(self.signatures().constant(class_name, name)?),
+            Term::Call(subroutine_call) => {
+                let (class_name, _) = self.called_class(subroutine_call)?;
+                match &self.signatures().find(&class_name, &subroutine_call.subroutine_name.0)?.return_type {
+                    SubroutineReturnType::General(return_type) => Some(return_type.clone()),
+                    SubroutineReturnType::Void => None
                 }
             },
-            Term::StringConstant(v) => self.compile_string(v),
-            Term::Expression(expression) => self.compile_expression(expression),
-            Term::Call(subroutine_call) => self.compile_subroutine_call(subroutine_call),
-            Term::WithUnary(op, term) => {
-                VM::build(vec![
-                    self.compile_term(term),
-                    self.compile_unary_op(op)
-                ])
-            },
-            Term::IndexVar(var_name, expression) => {
-                let symbol = self.find_by(var_name).unwrap();
-                VM::build(vec![
-                    // sets THAT
-                    VM::push(&symbol.vm_memory_segment(), symbol.index()),
-                    self.compile_expression(expression),
-                    VM::op("add"),
-                    VM::pop("pointer", 1),
-                    VM::push("that", 0)
-                ])
-            }
+            Term::Expression(expression) | Term::Nested(expression) => self.type_of(expression),
+            Term::WithUnary(UnaryOp::Negative, _) => Some(Type::Int),
+            Term::WithUnary(UnaryOp::Not, term) => self.type_of_term(term)
         }
     }
-}
-
-// ClassParser
-
-struct ClassParser<'a> {
-    tokenizer: &'a mut Peekable<Tokenizer>
-}
 
-impl<'a> ClassParser<'a> {
-    pub fn new(tokenizer: &'a mut Peekable<Tokenizer>) -> Self {
-        ClassParser { tokenizer }
+    // `<` and `>` on objects compare their addresses
+    fn check_comparisons(&mut self, expression: &Expression) {
+        let mut left = (self.type_of_term(&expression.term), span_of(&expression.term));
+        for OpTerm(op, term, _) in expression.extra_op_terms.iter() {
+            let right = (self.type_of_term(term), span_of(term));
+            if matches!(op, Op::Lt | Op::Gt) {
+                for (operand, span) in [&left, &right] {
+                    if let (Some(Type::ClassName(class_name)), Some(span)) = (operand, span) {
+                        let message = format!("'{}' compares an instance of {}, not a number", op.symbol(), class_name);
+                        self.diagnostics.warn(Lint::TypeCheck, SemanticError::new(&message, *span));
+                    }
+                }
+            }
+            left = match op {
+                Op::Plus | Op::Minus | Op::Multiply | Op::Divide => (Some(Type::Int), left.1),
+                Op::Lt | Op::Gt | Op::Eq => (Some(Type::Boolean), left.1),
+                Op::And | Op::Or => left
+            };
+        }
     }
-}
-
-impl<'a> Iterator for ClassParser<'a> {
-    type Item=Class;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.tokenizer.peek()? {
-            Token::Keyword(v) if *v == "class".to_string() => {
-                // class keyword
-                self.tokenizer.next();
-                // className
-                let name = match self.tokenizer.next()? {
-                    Token::Identifier(v) => ClassName(v),
-                    _ => return None
-                };
-                // '{'
-                assert_symbol(&self.tokenizer.next()?, '{');
-                // classVarDec*
-                let class_var_decs = ClassVarDecParser::new(self.tokenizer).collect();
-                // subroutineDec*
-                let subroutine_decs = SubroutineDecParser::new(self.tokenizer).collect();
-                // '}'
-                assert_symbol(&self.tokenizer.next()?, '}');
-                Some(Class { name, class_var_decs, subroutine_decs })
-            },
-            _ => None
+    // calls to classes that do not exist, subroutines they do not have, and the wrong arguments
+    fn check_project_call(
+        &mut self, signatures: &'a Signatures, class_name: &str, subroutine_call: &SubroutineCall
+    ) -> Option<&'a Signature> {
+        let name = &subroutine_call.subroutine_name.0;
+        let span = subroutine_call.span;
+        if !signatures.has_class(class_name) {
+            if !OS_CLASSES.contains(&class_name) {
+                let message = format!("unknown class {} in call to {}.{}", class_name, class_name, name);
+                self.diagnostics.error(SemanticError::new(&message, span));
+            }
+            return None;
         }
+        let signature = match signatures.find(class_name, name) {
+            Some(signature) => signature,
+            None => {
+                let mut error = SemanticError::new(&format!("class {} has no subroutine {}", class_name, name), span);
+                if let Some(similar) = signatures.similar(class_name, name) {
+                    error = error.with_note(&format!("did you mean {}? {}", similar.name, similar.declared_at()));
+                }
+                self.diagnostics.error(error);
+                return None;
+            }
+        };
+        self.check_arguments(subroutine_call, signature);
+        Some(signature)
     }
-}
-
-// ClassVarDecParser
 
-struct ClassVarDecParser<'a> {
-    tokenizer: &'a mut Peekable<Tokenizer>
-}
-
-impl<'a> ClassVarDecParser<'a> {
-    pub fn new(tokenizer: &'a mut Peekable<Tokenizer>) -> Self {
-        ClassVarDecParser { tokenizer }
+    // the number of arguments, and constants that can never be the declared type
+    fn check_arguments(&mut self, subroutine_call: &SubroutineCall, signature: &Signature) {
+        let (class_name, name, span) = (&signature.class_name, &signature.name, subroutine_call.span);
+        let (arguments, parameters) = (subroutine_call.expression_list.len(), signature.parameters.len());
+        if arguments != parameters {
+            let message = format!(
+                "{}.{} takes {} argument{}, found {}",
+                class_name, name, parameters, if parameters == 1 { "" } else { "s" }, arguments
+            );
+            self.diagnostics.error(SemanticError::new(&message, span).with_note(&signature.declared_at()));
+        }
+        for (i, (argument, parameter)) in subroutine_call.expression_list.iter().zip(&signature.parameters).enumerate() {
+            let found = match (&argument.term, parameter) {
+                _ if !argument.extra_op_terms.is_empty() => continue,
+                (Term::StringConstant(..), Type::Int | Type::Char | Type::Boolean) => "a string constant",
+                (Term::IntegerConstant(_), Type::ClassName(class_name)) if class_name == "String" => "an integer constant",
+                _ => continue
+            };
+            let message = format!("{}.{} takes {} as argument {}, found {}", class_name, name, parameter.as_str(), i + 1, found);
+            self.diagnostics.error(SemanticError::new(&message, span).with_note(&signature.declared_at()));
+        }
     }
-}
-
-impl<'a> Iterator for ClassVarDecParser<'a> {
-    type Item=ClassVarDec;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.tokenizer.peek()? {
-            Token::Keyword(v)  => {
-                // static | field
-                let dec_type = ClassVarDecType::new(&v)?;
-                self.tokenizer.next();
-                // Type
-                let token = self.tokenizer.next()?;
-                let var_type = Type::new(&token)?;
-                // var_name
-                let var_name = match self.tokenizer.next()? {
-                    Token::Identifier(v) => VarName(v),
-                    _ => return None
-                };
-                // exta_var_names
-                let extra_var_names = ExtraVarNameParser::new(self.tokenizer).collect();
-                // `;`
-                assert_symbol(&self.tokenizer.next()?, ';');
-                Some(ClassVarDec { dec_type, var_type, var_name, extra_var_names })
+    // `result_used` is false for a do statement, which throws the result away
+    fn compile_subroutine_call(&mut self, subroutine_call: &SubroutineCall, result_used: bool) -> Vec<VmCommand> {
+        let errors = self.diagnostics.error_count();
+        let signature = self.check_call(subroutine_call);
+        // `draw()` is `this.draw()`, undeclared subroutines are taken for methods
+        if signature.is_none() && subroutine_call.caller.is_none() && self.in_function() && self.diagnostics.error_count() == errors {
+            let message = format!(
+                "method call {}() in function {}.{}, which has no instance",
+                subroutine_call.subroutine_name.0, self.class_name, self.subroutine_name
+            );
+            self.diagnostics.error(SemanticError::new(&message, subroutine_call.span));
+        }
+        // without the project the type checker still knows the OS
+        if signature.is_none() && self.signatures.is_none() && self.options.type_check() {
+            let os_signature = self.called_class(subroutine_call)
+                .and_then(|(class_name, _)| self.class_signatures.find(&class_name, &subroutine_call.subroutine_name.0))
+                .filter(|signature| signature.class_name != self.class_name)
+                .cloned();
+            if let Some(signature) = os_signature {
+                self.check_argument_types(subroutine_call, &signature);
+                if result_used && signature.return_type == SubroutineReturnType::Void {
+                    let message = format!("{}.{} returns nothing, its result cannot be used", signature.class_name, signature.name);
+                    self.diagnostics.warn(Lint::TypeCheck, SemanticError::new(&message, subroutine_call.span).with_note(&signature.declared_at()));
+                }
+            }
+        }
+        if let Some(signature) = &signature {
+            let message = match (result_used, &signature.kind, &signature.return_type) {
+                (false, SubroutineType::Constructor, _) => Some("the object made by {} is never used"),
+                (true, _, SubroutineReturnType::Void) => Some("{} returns nothing, its result cannot be used"),
+                _ => None
+            };
+            if let Some(message) = message {
+                let message = message.replace("{}", &format!("{}.{}", signature.class_name, signature.name));
+                self.diagnostics.error(SemanticError::new(&message, subroutine_call.span).with_note(&signature.declared_at()));
+            }
+        }
+        if let Some(commands) = self.inline_accessor(subroutine_call, signature.as_ref()) {
+            return commands;
+        }
+        let n_args = subroutine_call.expression_list.len() as i16;
+        let (mut commands, function_name, n_args) = match &subroutine_call.caller {
+            None => {
+                let function_name = format!("{}.{}", self.class_name, subroutine_call.subroutine_name.0);
+                (vec![VmCommand::Push(Segment::Pointer, 0)], function_name, n_args + 1)
+            },
+            Some(caller) => {
+                if let Some(symbol) = self.find_by(caller) {
+                    // handle method call
+                    // `int` has no methods, such a call is compiled as written and `--type-check` warns about it
+                    let function_name = format!("{}.{}", symbol.var_type().as_str(), subroutine_call.subroutine_name.0);
+                    let (segment, index) = (symbol.vm_memory_segment(), symbol.index());
+                    let mut commands = self.check_null(segment, index);
+                    commands.push(VmCommand::Push(segment, index));
+                    (commands, function_name, n_args + 1)
+                } else {
+                    // handle function calls and constructor calls
+                    (Vec::new(), format!("{}.{}", caller, subroutine_call.subroutine_name.0), n_args)
+                }
+            }
+        };
+        for expression in subroutine_call.expression_list.iter() {
+            commands.extend(self.compile_expression(expression));
+        }
+        commands.push(VmCommand::Call(function_name, n_args));
+        commands
+    }
+
+    // with `--inline-accessors`, `p.getX()` of `method int getX() { return x; }` reads the field of p
+    // through THAT instead of calling the method
+    fn inline_accessor(&mut self, subroutine_call: &SubroutineCall, signature: Option<&Signature>) -> Option<Vec<VmCommand>> {
+        if !self.options.inline_accessors || !subroutine_call.expression_list.is_empty() {
+            return None;
+        }
+        let accessor = signature.filter(|signature| signature.kind == SubroutineType::Method)?.accessor.clone()?;
+        let symbol = self.find_by(subroutine_call.caller.as_deref()?)?;
+        let (segment, symbol_index) = (symbol.vm_memory_segment(), symbol.index());
+        match accessor {
+            Accessor::Field(index) => {
+                let mut commands = self.check_null(segment, symbol_index);
+                commands.extend([
+                    VmCommand::Push(segment, symbol_index),
+                    VmCommand::Pop(Segment::Pointer, 1),
+                    VmCommand::Push(Segment::That, index)
+                ]);
+                Some(commands)
+            },
+            Accessor::Constant(term) => Some(self.compile_term(&term))
+        }
+    }
+
+    fn compile_if_statement(&mut self, statement: &IfStatement, return_type: &SubroutineReturnType) -> Vec<VmCommand> {
+        if let Some(condition) = analysis::constant_condition(&statement.expression) {
+            let (taken, dead, branch) = if condition {
+                (Some(&statement.if_statements), statement.else_statements.as_ref(), "else branch")
+            } else {
+                (statement.else_statements.as_ref(), Some(&statement.if_statements), "if branch")
+            };
+            if let Some(dead) = dead {
+                let message = format!("the condition is always {}, the {} never runs", condition, branch);
+                self.diagnostics.warn(Lint::ConstantCondition, SemanticError::new(&message, statement.span));
+                if self.options.optimize {
+                    // still compiled for its errors
+                    self.compile_statements(dead, return_type);
+                }
+            }
+            // only the branch that runs, without the labels and the jump
+            if self.options.optimize {
+                return taken.map(|statements| self.compile_statements(statements, return_type)).unwrap_or_default();
+            }
+        }
+        let l1 = self.generate_label();
+        let l2 = self.generate_label();
+
+        let mut commands = self.compile_expression(&statement.expression);
+        commands.push(VmCommand::Arithmetic(Operator::Not));
+        commands.push(VmCommand::IfGoTo(l1.clone()));
+        commands.extend(self.compile_statements(&statement.if_statements, return_type));
+        // the jumps after the branches are the if's again
+        commands.extend(self.origin(statement.span));
+        commands.push(VmCommand::GoTo(l2.clone()));
+        commands.push(VmCommand::Label(l1));
+        if let Some(statements) = &statement.else_statements {
+            commands.extend(self.compile_statements(statements, return_type));
+            commands.extend(self.origin(statement.span));
+        }
+        commands.push(VmCommand::Label(l2));
+        commands
+    }
+
+    // `break` goes to the exit of the innermost loop and `continue` to its next round
+    fn compile_jump(&mut self, is_break: bool, span: Span) -> Vec<VmCommand> {
+        let Some(innermost) = self.loops.last() else {
+            let keyword = if is_break { "break" } else { "continue" };
+            self.diagnostics.error(SemanticError::new(&format!("{} outside of a loop", keyword), span));
+            return Vec::new();
+        };
+        let known = if is_break { innermost.exit.clone() } else { innermost.next.clone() };
+        let label = match known {
+            Some(label) => label,
+            None => {
+                let label = self.generate_label();
+                let innermost = self.loops.last_mut().unwrap();
+                *(if is_break { &mut innermost.exit } else { &mut innermost.next }) = Some(label.clone());
+                label
+            }
+        };
+        vec![VmCommand::GoTo(label)]
+    }
+
+    // The body of a loop, with the labels break and continue go to where they're already known.
+    // The ones made by a break or continue in the body are given back, the exit to be placed after
+    // the loop, and the next round of a for loop is placed here, before its increment.
+    fn compile_loop_body(&mut self, statement: &WhileStatement, innermost: Loop, return_type: &SubroutineReturnType) -> (Vec<VmCommand>, Loop) {
+        self.loops.push(innermost);
+        let body = &statement.statements.0;
+        let (body, increment) = match body.split_last() {
+            Some((increment, body)) if statement.increment => (body, Some(increment)),
+            _ => (&body[..], None)
+        };
+        let mut commands = self.compile_statement_list(body, return_type);
+        if let Some(increment) = increment {
+            commands.extend(self.loops.last().and_then(|innermost| innermost.next.clone()).map(VmCommand::Label));
+            commands.extend(self.compile_statement_list(std::slice::from_ref(increment), return_type));
+        }
+        (commands, self.loops.pop().unwrap())
+    }
+
+    fn compile_while_statement(&mut self, statement: &WhileStatement, return_type: &SubroutineReturnType) -> Vec<VmCommand> {
+        match analysis::constant_condition(&statement.expression) {
+            Some(false) => {
+                self.diagnostics.warn(Lint::ConstantCondition, SemanticError::new("the condition is always false, the loop never runs", statement.span));
+                if self.options.optimize {
+                    // still compiled for its errors
+                    self.compile_loop_body(statement, Loop::default(), return_type);
+                    return Vec::new();
+                }
+            },
+            // a loop that only a return or a break leaves does not test its condition
+            Some(true) if self.options.optimize => {
+                let l1 = self.generate_label();
+                let mut commands = vec![VmCommand::Label(l1.clone())];
+                let next = (!statement.increment).then(|| l1.clone());
+                let (body, innermost) = self.compile_loop_body(statement, Loop { next, exit: None }, return_type);
+                commands.extend(body);
+                commands.extend(self.origin(statement.span));
+                commands.push(VmCommand::GoTo(l1));
+                commands.extend(innermost.exit.map(VmCommand::Label));
+                return commands;
+            },
+            _ => {}
+        }
+        if self.options.optimize {
+            return self.compile_rotated_loop(statement, return_type);
+        }
+        let l1 = self.generate_label();
+        let l2 = self.generate_label();
+
+        let mut commands = vec![VmCommand::Label(l1.clone())];
+        commands.extend(self.compile_expression(&statement.expression));
+        commands.push(VmCommand::Arithmetic(Operator::Not));
+        commands.push(VmCommand::IfGoTo(l2.clone()));
+        let next = (!statement.increment).then(|| l1.clone());
+        commands.extend(self.compile_loop_body(statement, Loop { next, exit: Some(l2.clone()) }, return_type).0);
+        commands.extend(self.origin(statement.span));
+        commands.push(VmCommand::GoTo(l1));
+        commands.push(VmCommand::Label(l2));
+        commands
+    }
+
+    // Under `-O` the condition comes after the body, a round of the loop then takes one jump
+    // instead of two: `goto TEST / label BODY / body / label TEST / cond / if-goto BODY`
+    fn compile_rotated_loop(&mut self, statement: &WhileStatement, return_type: &SubroutineReturnType) -> Vec<VmCommand> {
+        let body = self.generate_label();
+        let test = self.generate_label();
+
+        let mut commands = vec![VmCommand::GoTo(test.clone()), VmCommand::Label(body.clone())];
+        let next = (!statement.increment).then(|| test.clone());
+        let (loop_body, innermost) = self.compile_loop_body(statement, Loop { next, exit: None }, return_type);
+        commands.extend(loop_body);
+        commands.extend(self.origin(statement.span));
+        commands.push(VmCommand::Label(test));
+        commands.extend(self.compile_expression(&statement.expression));
+        commands.push(VmCommand::IfGoTo(body));
+        commands.extend(innermost.exit.map(VmCommand::Label));
+        commands
+    }
+
+    fn compile_let_statement(&mut self, statement: &LetStatement) -> Vec<VmCommand> {
+        let (segment, index) = self.resolve(&statement.var_name.0, statement.var_name.1);
+        if self.options.type_check() && statement.index_expression.is_none() {
+            let declared = self.find_by(&statement.var_name.0).map(|symbol| symbol.var_type().clone());
+            if let (Some(declared), Some(found)) = (declared, self.type_of(&statement.expression)) {
+                if incompatible(&declared, &found) {
+                    let message = format!(
+                        "let assigns {} to {}, which is declared {}", found.as_str(), statement.var_name.0, declared.as_str()
+                    );
+                    self.diagnostics.warn(Lint::TypeCheck, SemanticError::new(&message, statement.var_name.1));
+                }
+            }
+        }
+        if let Some(expression) = &statement.index_expression {
+            self.check_indexed(&statement.var_name.0, statement.var_name.1);
+            // handle array index assignment
+            let mut commands = self.check_null(segment, index);
+            commands.push(VmCommand::Push(segment, index));
+            commands.extend(self.compile_expression(expression));
+            commands.extend(self.check_index(expression));
+            commands.push(VmCommand::Arithmetic(Operator::Add));
+            commands.extend(self.compile_expression(&statement.expression));
+            commands.extend([
+                VmCommand::Pop(Segment::Temp, 0),
+                VmCommand::Pop(Segment::Pointer, 1),
+                VmCommand::Push(Segment::Temp, 0),
+                VmCommand::Pop(Segment::That, 0)
+            ]);
+            commands
+        } else {
+            let mut commands = self.compile_expression(&statement.expression);
+            commands.push(VmCommand::Pop(segment, index));
+            commands
+        }
+    }
+
+    fn compile_expression(&mut self, expression: &Expression) -> Vec<VmCommand> {
+        if self.options.type_check() {
+            self.check_comparisons(expression);
+        }
+        let mut op_terms = &expression.extra_op_terms[..];
+        // `8 * x` as `x * 8` under -O, so the peephole pass finds the constant right before the
+        // call. A constant does nothing that has to stay in order with the other operand. Jack
+        // goes from left to right, so only the first term can be a constant on the left of `*`:
+        // in `a + 8 * x` it is `a + 8`, and a constant on the right is already next to the call.
+        let mut commands = match (&expression.term, op_terms.first()) {
+            (Term::IntegerConstant(_), Some(OpTerm(Op::Multiply, right, _))) if self.options.optimize => {
+                op_terms = &op_terms[1..];
+                let mut commands = self.compile_term(right);
+                commands.extend(self.compile_term(&expression.term));
+                commands.push(self.compile_operation(&Op::Multiply));
+                commands
+            },
+            _ => self.compile_term(&expression.term)
+        };
+        // what the operands so far make, as in type_of
+        let mut left = self.type_of_term(&expression.term);
+        for op_term in op_terms.iter() {
+            // Math.divide would stop the program with Sys.error
+            if op_term.0 == Op::Divide && is_zero(&op_term.1) {
+                self.diagnostics.error(SemanticError::new("division by zero", op_term.2));
+            }
+            let booleans = left == Some(Type::Boolean) && self.type_of_term(&op_term.1) == Some(Type::Boolean);
+            if self.options.short_circuit && booleans && matches!(op_term.0, Op::And | Op::Or) {
+                commands.extend(self.compile_short_circuit(&op_term.0, &op_term.1));
+            } else {
+                commands.extend(self.compile_term(&op_term.1));
+                commands.push(self.compile_operation(&op_term.0));
+            }
+            left = match op_term.0 {
+                Op::Plus | Op::Minus | Op::Multiply | Op::Divide => Some(Type::Int),
+                Op::Lt | Op::Gt | Op::Eq => Some(Type::Boolean),
+                Op::And | Op::Or => left
+            };
+        }
+        commands
+    }
+
+    // With `--trace-calls`, the commands that print `Main.main` and a new line. The string goes
+    // through THAT, which is saved across the calls, to be disposed of after it is printed.
+    fn trace(&mut self, function_name: &str, span: Span) -> Vec<VmCommand> {
+        if !self.options.trace_calls || OS_CLASSES.contains(&self.class_name.as_str()) {
+            return Vec::new();
+        }
+        let mut commands = self.compile_string(function_name, span);
+        commands.extend([
+            VmCommand::Pop(Segment::Pointer, 1),
+            VmCommand::Push(Segment::Pointer, 1),
+            VmCommand::Call("Output.printString".to_string(), 1),
+            VmCommand::Pop(Segment::Temp, 0),
+            VmCommand::Push(Segment::Pointer, 1),
+            VmCommand::Call("String.dispose".to_string(), 1),
+            VmCommand::Pop(Segment::Temp, 0),
+            VmCommand::Call("Output.println".to_string(), 0),
+            VmCommand::Pop(Segment::Temp, 0)
+        ]);
+        commands
+    }
+
+    // With `--null-check`, the commands that stop the program before the variable at segment and index
+    // is used for a method call or an array while it's null
+    fn check_null(&mut self, segment: Segment, index: i16) -> Vec<VmCommand> {
+        if !self.options.null_check {
+            return Vec::new();
+        }
+        let ok = self.generate_label();
+        vec![
+            VmCommand::Push(segment, index),
+            VmCommand::IfGoTo(ok.clone()),
+            VmCommand::Push(Segment::Constant, NULL_ERROR),
+            VmCommand::Call("Sys.error".to_string(), 1),
+            VmCommand::Pop(Segment::Temp, 0),
+            VmCommand::Label(ok)
+        ]
+    }
+
+    // With `--bounds-check` the index on the stack goes through temp 1 to be compared with 0,
+    // nothing else runs while it is there. Integer constants can't be negative.
+    fn check_index(&mut self, expression: &Expression) -> Vec<VmCommand> {
+        let constant = expression.extra_op_terms.is_empty() && matches!(expression.term, Term::IntegerConstant(..));
+        if !self.options.bounds_check || constant {
+            return Vec::new();
+        }
+        let ok = self.generate_label();
+        vec![
+            VmCommand::Pop(Segment::Temp, 1),
+            VmCommand::Push(Segment::Temp, 1),
+            VmCommand::Push(Segment::Constant, 0),
+            VmCommand::Arithmetic(Operator::Lt),
+            VmCommand::Arithmetic(Operator::Not),
+            VmCommand::IfGoTo(ok.clone()),
+            VmCommand::Push(Segment::Constant, INDEX_ERROR),
+            VmCommand::Call("Sys.error".to_string(), 1),
+            VmCommand::Pop(Segment::Temp, 0),
+            VmCommand::Label(ok),
+            VmCommand::Push(Segment::Temp, 1)
+        ]
+    }
+
+    // With `--ext-short-circuit` the left operand on the stack decides `&` when it is false and
+    // `|` when it is true, the right one is only computed otherwise:
+    // `if-goto RIGHT / push constant 0 / goto END / label RIGHT / right / label END` for `&`
+    fn compile_short_circuit(&mut self, op: &Op, right: &Term) -> Vec<VmCommand> {
+        let decided = self.generate_label();
+        let end = self.generate_label();
+        let mut commands = Vec::new();
+        let mut right = self.compile_term(right);
+        right.push(VmCommand::GoTo(end.clone()));
+        let mut result = match op {
+            Op::And => vec![VmCommand::Push(Segment::Constant, 0)],
+            _ => vec![VmCommand::Push(Segment::Constant, 1), VmCommand::Arithmetic(Operator::Neg)]
+        };
+        result.push(VmCommand::GoTo(end.clone()));
+        // true jumps, so for `&` the right operand is what follows the jump
+        let (fallthrough, jumped) = match op {
+            Op::And => (result, right),
+            _ => (right, result)
+        };
+        commands.push(VmCommand::IfGoTo(decided.clone()));
+        commands.extend(fallthrough);
+        commands.push(VmCommand::Label(decided));
+        commands.extend(jumped);
+        // the last jump goes right to the label after it
+        commands.pop();
+        commands.push(VmCommand::Label(end));
+        commands
+    }
+
+    fn compile_operation(&self, operation: &Op) -> VmCommand {
+        match operation {
+            Op::Plus => VmCommand::Arithmetic(Operator::Add),
+            Op::Minus => VmCommand::Arithmetic(Operator::Sub),
+            Op::Multiply => VmCommand::Call("Math.multiply".to_string(), 2),
+            Op::Divide => VmCommand::Call("Math.divide".to_string(), 2),
+            Op::And => VmCommand::Arithmetic(Operator::And),
+            Op::Or => VmCommand::Arithmetic(Operator::Or),
+            Op::Lt => VmCommand::Arithmetic(Operator::Lt),
+            Op::Gt => VmCommand::Arithmetic(Operator::Gt),
+            Op::Eq => VmCommand::Arithmetic(Operator::Eq)
+        }
+    }
+
+    fn compile_unary_op(&self, unary_operation: &UnaryOp) -> VmCommand {
+        match unary_operation {
+            UnaryOp::Negative => VmCommand::Arithmetic(Operator::Neg),
+            UnaryOp::Not => VmCommand::Arithmetic(Operator::Not),
+        }
+    }
+
+    fn compile_term(&mut self, term: &Term) -> Vec<VmCommand> {
+        match term {
+            // `push constant` only takes 0 to 32767
+            Term::IntegerConstant(i16::MIN) => vec![
+                VmCommand::Push(Segment::Constant, 32767),
+                VmCommand::Push(Segment::Constant, 1),
+                VmCommand::Arithmetic(Operator::Add)
+            ],
+            Term::IntegerConstant(v) => vec![VmCommand::Push(Segment::Constant, *v)],
+            Term::VarName(v, span) => {
+                // a constant of the class, unless a variable hides it
+                if self.find_by(v).is_none() {
+                    if let Some(value) = self.signatures().constant(&self.class_name, v).cloned() {
+                        return self.compile_term(&value);
+                    }
+                }
+                let (segment, index) = self.resolve(v, *span);
+                vec![VmCommand::Push(segment, index)]
+            },
+            Term::ClassConstant(class_name, name, span) => match self.class_constant(class_name, name, *span) {
+                Some(value) => self.compile_term(&value),
+                None => vec![VmCommand::Push(Segment::Constant, 0)]
+            },
+            Term::KeywordConstant(v, span) => {
+                match v {
+                    KeywordConstant::Null => vec![VmCommand::Push(Segment::Constant, 0)],
+                    KeywordConstant::False => vec![VmCommand::Push(Segment::Constant, 0)],
+                    KeywordConstant::True => vec![
+                        VmCommand::Push(Segment::Constant, 1),
+                        VmCommand::Arithmetic(Operator::Neg)
+                    ],
+                    KeywordConstant::This => {
+                        if self.in_function() {
+                            let message = format!(
+                                "this used in function {}.{}, which has no instance", self.class_name, self.subroutine_name
+                            );
+                            self.diagnostics.error(SemanticError::new(&message, *span));
+                        }
+                        vec![VmCommand::Push(Segment::Pointer, 0)]
+                    }
+                }
+            },
+            Term::StringConstant(v, span) => self.compile_string(v, *span),
+            Term::Expression(expression) => self.compile_expression(expression),
+            Term::Nested(expression) => self.compile_expression(expression),
+            Term::Call(subroutine_call) => self.compile_subroutine_call(subroutine_call, true),
+            Term::WithUnary(op, term) => {
+                let mut commands = self.compile_term(term);
+                commands.push(self.compile_unary_op(op));
+                commands
+            },
+            Term::IndexVar(var_name, expression, span) => {
+                let (segment, index) = self.resolve(var_name, *span);
+                self.check_indexed(var_name, *span);
+                // sets THAT
+                let mut commands = self.check_null(segment, index);
+                commands.push(VmCommand::Push(segment, index));
+                commands.extend(self.compile_expression(expression));
+                commands.extend(self.check_index(expression));
+                commands.extend([
+                    VmCommand::Arithmetic(Operator::Add),
+                    VmCommand::Pop(Segment::Pointer, 1),
+                    VmCommand::Push(Segment::That, 0)
+                ]);
+                commands
+            }
+        }
+    }
+}
+
+// Types that cannot stand for each other. Ints and chars can, ints and objects are
+// mixed on purpose in Jack code, like `let a = 0` for an Array, and an Array takes any object,
+// like `Memory.deAlloc(this)`.
+// `method void Ball.move(int dx)`
+fn signature_comment(function_name: &str, subroutine_dec: &SubroutineDec) -> String {
+    let return_type = match &subroutine_dec.return_type {
+        SubroutineReturnType::Void => "void",
+        SubroutineReturnType::General(return_type) => return_type.as_str()
+    };
+    let parameters: Vec<String> = subroutine_dec.parameters.iter()
+        .map(|Parameter(var_type, var_name)| format!("{} {}", var_type.as_str(), var_name.as_str()))
+        .collect();
+    format!("{} {} {}({})", subroutine_dec.subroutine_type.as_str(), return_type, function_name, parameters.join(", "))
+}
+
+fn incompatible(declared: &Type, found: &Type) -> bool {
+    match (declared, found) {
+        (Type::Boolean, Type::Int | Type::Char) | (Type::Int | Type::Char, Type::Boolean) => true,
+        (Type::ClassName(declared), Type::ClassName(found)) => declared != found && declared != "Array",
+        _ => false
+    }
+}
+
+// position of a term, integer constants have none
+// `0` or `(0)`
+fn is_zero(term: &Term) -> bool {
+    match term {
+        Term::IntegerConstant(0) => true,
+        Term::Expression(expression) | Term::Nested(expression) => {
+            expression.extra_op_terms.is_empty() && is_zero(&expression.term)
+        },
+        _ => false
+    }
+}
+
+fn span_of(term: &Term) -> Option<Span> {
+    match term {
+        Term::IntegerConstant(_) => None,
+        Term::StringConstant(_, span) | Term::KeywordConstant(_, span) | Term::VarName(_, span) | Term::IndexVar(_, _, span)
+        | Term::ClassConstant(_, _, span) => Some(*span),
+        Term::Call(subroutine_call) => Some(subroutine_call.span),
+        Term::Expression(expression) | Term::Nested(expression) => span_of(&expression.term),
+        Term::WithUnary(_, term) => span_of(term)
+    }
+}
+
+// what `--ext-const` takes for the value of a constant, `8`, `-1`, `true`, `false` or `null`
+fn is_literal(expression: &Expression) -> bool {
+    expression.extra_op_terms.is_empty() && match &expression.term {
+        Term::IntegerConstant(_) | Term::KeywordConstant(KeywordConstant::True | KeywordConstant::False | KeywordConstant::Null, _) => true,
+        Term::WithUnary(UnaryOp::Negative, term) => matches!(**term, Term::IntegerConstant(_)),
+        _ => false
+    }
+}
+
+// ClassParser
+
+struct ClassParser<'a> {
+    tokenizer: &'a mut Tokens
+}
+
+impl<'a> ClassParser<'a> {
+    pub fn new(tokenizer: &'a mut Tokens) -> Self {
+        ClassParser { tokenizer }
+    }
+
+    fn parse(&mut self) -> Result<Class, SyntaxError> {
+        let doc = self.tokenizer.doc_comment();
+        // class keyword
+        self.tokenizer.next();
+        self.tokenizer.open_scope();
+        // className
+        let span = self.tokenizer.span();
+        let name = ClassName(self.tokenizer.expect_identifier("a class name")?);
+        // '{'
+        self.tokenizer.expect_symbol('{', "after class name")?;
+        // classVarDec*
+        let class_var_decs = ClassVarDecParser::new(self.tokenizer).collect::<Result<_, _>>()?;
+        // subroutineDec*
+        let subroutine_decs: Vec<SubroutineDec> = SubroutineDecParser::new(self.tokenizer).collect::<Result<_, _>>()?;
+        self.check_duplicate_subroutines(&name, &subroutine_decs);
+        let class = Class { name, class_var_decs, subroutine_decs, doc, span };
+        if let (Some(initializer), Some(declared)) = (class.static_initializer(), class.subroutine(STATIC_INITIALIZER)) {
+            let message = format!("{}.{} is made for the static initializers", class.name.as_str(), STATIC_INITIALIZER);
+            let error = SyntaxError::with_message(&message, STATIC_INITIALIZER, declared.span)
+                .with_related(initializer.span, "the first static initializer is here");
+            self.tokenizer.errors.push(error);
+        }
+        // '}'
+        if !matches!(self.tokenizer.peek(), Some(Token::Symbol('}')) | None) {
+            return Err(self.tokenizer.error("a subroutine declaration or '}' at end of class"));
+        }
+        self.tokenizer.expect_symbol('}', "at end of class")?;
+        self.tokenizer.close_scope();
+        Ok(class)
+    }
+
+    // both would compile to the same VM function, calls would go to the first one
+    fn check_duplicate_subroutines(&mut self, class_name: &ClassName, subroutine_decs: &[SubroutineDec]) {
+        for (i, subroutine_dec) in subroutine_decs.iter().enumerate() {
+            let name = subroutine_dec.name.as_str();
+            if let Some(first) = subroutine_decs[..i].iter().find(|s| s.name.as_str() == name) {
+                let message = format!(
+                    "subroutine {}.{} is already declared at {}", class_name.as_str(), name, first.span
+                );
+                let error = SyntaxError::with_message(&message, name, subroutine_dec.span)
+                    .with_related(first.span, &format!("{}.{} is first declared here", class_name.as_str(), name));
+                self.tokenizer.errors.push(error);
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for ClassParser<'a> {
+    type Item=Result<Class, SyntaxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.tokenizer.peek()? {
+            Token::Keyword(v) if v == "class" => Some(self.parse()),
+            // anything left after a class would be silently dropped
+            _ => Some(Err(self.tokenizer.error("'class'")))
+        }
+    }
+}
+
+// ClassVarDecParser
+
+struct ClassVarDecParser<'a> {
+    tokenizer: &'a mut Tokens
+}
+
+impl<'a> ClassVarDecParser<'a> {
+    pub fn new(tokenizer: &'a mut Tokens) -> Self {
+        ClassVarDecParser { tokenizer }
+    }
+
+    fn parse(&mut self, dec_type: ClassVarDecType, doc: Option<String>) -> Result<ClassVarDec, SyntaxError> {
+        // Type
+        let var_type = Type::parse(self.tokenizer)?;
+        // var_name
+        let var_name = self.tokenizer.declare_variable("a variable name")?;
+        // exta_var_names
+        let extra_var_names: Vec<VarName> = ExtraVarNameParser::new(self.tokenizer).collect::<Result<_, _>>()?;
+        // `= expression` with `--ext-field-init`, and the value of a constant
+        let constant = dec_type == ClassVarDecType::Const;
+        let field_initializers = self.tokenizer.options.field_initializers || constant;
+        let initializer = match self.tokenizer.peek() {
+            Some(Token::Symbol('=')) if field_initializers => {
+                let span = self.tokenizer.span();
+                if !extra_var_names.is_empty() {
+                    return Err(SyntaxError::with_message("an initializer needs a declaration of its own", "'='", span));
+                }
+                self.tokenizer.next();
+                let span = self.tokenizer.span();
+                let expression = Expression::parse(self.tokenizer)?;
+                if constant && !is_literal(&expression) {
+                    return Err(SyntaxError::with_message(
+                        "the value of a constant has to be a number, true, false or null", "an expression", span
+                    ));
+                }
+                Some(expression)
+            },
+            _ if constant => return Err(self.tokenizer.error("'=' and the value of the constant")),
+            _ => None
+        };
+        // `;`
+        self.tokenizer.expect_symbol(';', "after variable declaration")?;
+        Ok(ClassVarDec { dec_type, var_type, var_name, extra_var_names, initializer, doc })
+    }
+}
+
+impl<'a> Iterator for ClassVarDecParser<'a> {
+    type Item=Result<ClassVarDec, SyntaxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.tokenizer.peek()? {
+            Token::Keyword(v)  => {
+                // static | field
+                let dec_type = ClassVarDecType::new(v)?;
+                let doc = self.tokenizer.doc_comment();
+                self.tokenizer.next();
+                Some(self.parse(dec_type, doc))
             },
             _ => None
         }
@@ -499,72 +1718,74 @@ impl<'a> Iterator for ClassVarDecParser<'a> {
 // SubroutineDecParser
 
 struct SubroutineDecParser<'a> {
-    tokenizer: &'a mut Peekable<Tokenizer>
+    tokenizer: &'a mut Tokens
 }
 
 impl<'a> SubroutineDecParser<'a> {
-    pub fn new(tokenizer: &'a mut Peekable<Tokenizer>) -> Self {
+    pub fn new(tokenizer: &'a mut Tokens) -> Self {
         SubroutineDecParser { tokenizer }
     }
+
+    fn parse(&mut self, subroutine_type: SubroutineType, doc: Option<String>) -> Result<SubroutineDec, SyntaxError> {
+        self.tokenizer.open_scope();
+        // return type
+        let return_type = SubroutineReturnType::parse(self.tokenizer)?;
+        // name
+        let span = self.tokenizer.span();
+        let name = SubroutineName(self.tokenizer.expect_identifier("a subroutine name")?);
+        // `(`
+        self.tokenizer.expect_symbol('(', "after subroutine name")?;
+        // Parameter list
+        let mut parameters = Vec::new();
+        match self.tokenizer.peek() {
+            Some(Token::Symbol(')')) => {},
+            _ => {
+                // First parameter
+                let parameter_type = Type::parse(self.tokenizer)?;
+                let var_name = self.tokenizer.declare_variable("a parameter name")?;
+                parameters.push(Parameter(parameter_type, var_name));
+                // Extra parameters
+                for paramter in ExtraParameterParser::new(self.tokenizer) {
+                    parameters.push(paramter?);
+                }
+            }
+        }
+        // `)`
+        self.tokenizer.expect_symbol(')', "after parameter list")?;
+        // subroutineBody
+        // `{`
+        self.tokenizer.expect_symbol('{', "before subroutine body")?;
+        // varDec*
+        let var_decs = VarDecParser::new(self.tokenizer).collect::<Result<_, _>>()?;
+        // statements
+        let statements = Statements::parse(self.tokenizer)?;
+        let body = SubroutineBody { var_decs, statements, end: self.tokenizer.span() };
+        // `}`
+        self.tokenizer.expect_symbol('}', "at end of subroutine body")?;
+        self.tokenizer.close_scope();
+        Ok(SubroutineDec {
+            subroutine_type,
+            return_type,
+            name,
+            parameters,
+            body,
+            span,
+            doc
+        })
+    }
 }
 
 impl<'a> Iterator for SubroutineDecParser<'a> {
-    type Item=SubroutineDec;
+    type Item=Result<SubroutineDec, SyntaxError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.tokenizer.peek()? {
             Token::Keyword(v) => {
                 // constructor | function | method
-                let subroutine_type = SubroutineType::new(&v)?;
+                let subroutine_type = SubroutineType::new(v)?;
+                let doc = self.tokenizer.doc_comment();
                 self.tokenizer.next();
-                // return type
-                let token = self.tokenizer.next()?;
-                let return_type = SubroutineReturnType::new(&token)?;
-                // name
-                let name = match self.tokenizer.next()? {
-                    Token::Identifier(v) => SubroutineName(v),
-                    _ => return None
-                };
-                // `(`
-                assert_symbol(&self.tokenizer.next()?, '(');
-                // Parameter list
-                let mut parameters = Vec::new();
-                match self.tokenizer.peek()? {
-                    Token::Symbol(')') => {},
-                    _ => {
-                        // First parameter
-                        let token = self.tokenizer.next()?;
-                        let parameter_type = Type::new(&token)?;
-                        let var_name = match self.tokenizer.next()? {
-                            Token::Identifier(v) => VarName(v),
-                            _ => return None
-                        };
-                        parameters.push(Parameter(parameter_type, var_name));
-                        // Extra parameters
-                        for paramter in ExtraParameterParser::new(self.tokenizer) {
-                            parameters.push(paramter);
-                        }
-                    }
-                }
-                // `)`
-                assert_symbol(&self.tokenizer.next()?, ')');
-                // subroutineBody
-                // `{`
-                assert_symbol(&self.tokenizer.next()?, '{');
-                // varDec*
-                let var_decs = VarDecParser::new(self.tokenizer).collect();
-                // statements
-                let statements = Statements::parse(self.tokenizer);
-                let body = SubroutineBody { var_decs, statements };
-                // `}`
-                assert_symbol(&self.tokenizer.next()?, '}');
-                Some(SubroutineDec {
-                    subroutine_type,
-                    return_type,
-                    name,
-                    parameters,
-                    body
-                })
+                Some(self.parse(subroutine_type, doc))
             },
             _ => None
         }
@@ -574,37 +1795,35 @@ impl<'a> Iterator for SubroutineDecParser<'a> {
 // VarDecParser
 
 struct VarDecParser<'a> {
-    tokenizer: &'a mut Peekable<Tokenizer>
+    tokenizer: &'a mut Tokens
 }
 
 impl<'a> VarDecParser<'a> {
-    pub fn new(tokenizer: &'a mut Peekable<Tokenizer>) -> Self {
+    pub fn new(tokenizer: &'a mut Tokens) -> Self {
         VarDecParser { tokenizer }
     }
+
+    fn parse(&mut self) -> Result<VarDec, SyntaxError> {
+        // var
+        self.tokenizer.next();
+        // type
+        let var_type = Type::parse(self.tokenizer)?;
+        // varName
+        let var_name = self.tokenizer.declare_variable("a variable name")?;
+        // extra var names
+        let extra_var_names = ExtraVarNameParser::new(self.tokenizer).collect::<Result<_, _>>()?;
+        // `;`
+        self.tokenizer.expect_symbol(';', "after variable declaration")?;
+        Ok(VarDec { var_type, var_name, extra_var_names })
+    }
 }
 
 impl<'a> Iterator for VarDecParser<'a> {
-    type Item=VarDec;
+    type Item=Result<VarDec, SyntaxError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.tokenizer.peek()? {
-            Token::Keyword(v) if *v == "var".to_string() => {
-                // var
-                self.tokenizer.next();
-                // type
-                let token = self.tokenizer.next()?;
-                let var_type = Type::new(&token)?;
-                // varName
-                let var_name = match self.tokenizer.next()? {
-                    Token::Identifier(v) => VarName(v),
-                    _ => return None
-                };
-                // extra var names
-                let extra_var_names = ExtraVarNameParser::new(self.tokenizer).collect();
-                // `;`
-                assert_symbol(&self.tokenizer.next()?, ';');
-                Some(VarDec { var_type, var_name, extra_var_names })
-            },
+            Token::Keyword(v) if v == "var" => Some(self.parse()),
             _ => None
         }
     }
@@ -613,17 +1832,17 @@ impl<'a> Iterator for VarDecParser<'a> {
 // ExtraVarNameParser
 
 struct ExtraVarNameParser<'a> {
-    tokenizer: &'a mut Peekable<Tokenizer>
+    tokenizer: &'a mut Tokens
 }
 
 impl<'a> ExtraVarNameParser<'a> {
-    pub fn new(tokenizer: &'a mut Peekable<Tokenizer>) -> Self {
+    pub fn new(tokenizer: &'a mut Tokens) -> Self {
         ExtraVarNameParser { tokenizer }
     }
 }
 
 impl<'a> Iterator for ExtraVarNameParser<'a> {
-    type Item=VarName;
+    type Item=Result<VarName, SyntaxError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.tokenizer.peek()? {
@@ -631,10 +1850,7 @@ impl<'a> Iterator for ExtraVarNameParser<'a> {
                 // `,`
                 self.tokenizer.next();
                 // varName
-                match self.tokenizer.next()? {
-                    Token::Identifier(v) => Some(VarName(v)),
-                    _ => None
-                }
+                Some(self.tokenizer.declare_variable("a variable name"))
             },
             _ => None
         }
@@ -643,167 +1859,245 @@ impl<'a> Iterator for ExtraVarNameParser<'a> {
 
 // Parameter parser
 struct ExtraParameterParser<'a> {
-    tokenizer: &'a mut Peekable<Tokenizer>
+    tokenizer: &'a mut Tokens
 }
 
 impl<'a> ExtraParameterParser<'a> {
-    pub fn new(tokenizer: &'a mut Peekable<Tokenizer>) -> Self {
+    pub fn new(tokenizer: &'a mut Tokens) -> Self {
         ExtraParameterParser { tokenizer }
     }
+
+    fn parse(&mut self) -> Result<Parameter, SyntaxError> {
+        // `,`
+        self.tokenizer.next();
+        // type varName
+        let var_type = Type::parse(self.tokenizer)?;
+        let var_name = self.tokenizer.declare_variable("a parameter name")?;
+        Ok(Parameter(var_type, var_name))
+    }
 }
 
 impl<'a> Iterator for ExtraParameterParser<'a> {
-    type Item=Parameter;
+    type Item=Result<Parameter, SyntaxError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.tokenizer.peek()? {
-            Token::Symbol(',') => {
-                // `,`
-                self.tokenizer.next();
-                // type varName
-                let token = self.tokenizer.next()?;
-                let var_type = Type::new(&token)?;
-                match self.tokenizer.next()? {
-                    Token::Identifier(v) => {
-                        Some(Parameter(var_type, VarName(v)))
-                    },
-                    _ => None
-                }
-            },
+            Token::Symbol(',') => Some(self.parse()),
             _ => None
         }
-        
     }
 }
 
 // StatementParser
 
 struct StatementParser<'a> {
-    tokenizer: &'a mut Peekable<Tokenizer>
+    tokenizer: &'a mut Tokens
 }
 
 impl<'a> StatementParser<'a> {
-    pub fn new(tokenizer: &'a mut Peekable<Tokenizer>) -> Self {
+    pub fn new(tokenizer: &'a mut Tokens) -> Self {
         StatementParser { tokenizer }
     }
+
+    fn parse_let(&mut self) -> Result<Statement, SyntaxError> {
+        self.parse_let_until(';', "after let statement")
+    }
+
+    // a let statement that ends with `end`, the increment of a for loop ends with its `)`
+    fn parse_let_until(&mut self, end: char, context: &str) -> Result<Statement, SyntaxError> {
+        // let
+        let span = self.tokenizer.span();
+        self.tokenizer.next();
+        // varName
+        let name_span = self.tokenizer.span();
+        let var_name = VarName(self.tokenizer.expect_identifier("a variable name after let")?, name_span);
+        // [ expression ]
+        let index_expression = match self.tokenizer.peek() {
+            Some(Token::Symbol('[')) => {
+                // '['
+                self.tokenizer.next();
+                // expression
+                let expression = Expression::parse(self.tokenizer)?;
+                // ']'
+                self.tokenizer.expect_symbol(']', "after array index")?;
+                Some(expression)
+            },
+            _ => None
+        };
+        // `=`
+        self.tokenizer.expect_symbol('=', "in let statement")?;
+        // expression
+        let expression = Expression::parse(self.tokenizer)?;
+        // `;`
+        self.tokenizer.expect_symbol(end, context)?;
+        let statement = LetStatement {
+            var_name,
+            span,
+            index_expression,
+            expression
+        };
+        Ok(Statement::Let(statement))
+    }
+
+    fn parse_if(&mut self) -> Result<Statement, SyntaxError> {
+        // if
+        let span = self.tokenizer.span();
+        self.tokenizer.next();
+        // `(`
+        self.tokenizer.expect_symbol('(', "after if")?;
+        // expression
+        let expression = Expression::parse(self.tokenizer)?;
+        // `)`
+        self.tokenizer.expect_symbol(')', "after if condition")?;
+        // `{`
+        self.tokenizer.expect_symbol('{', "before if body")?;
+        // if statements
+        let if_statements = Statements::parse(self.tokenizer)?;
+        // `}`
+        self.tokenizer.expect_symbol('}', "after if body")?;
+        // else statements
+        let else_statements = match self.tokenizer.peek() {
+            Some(Token::Keyword(v)) if v.as_str() == "else" => {
+                // else
+                self.tokenizer.next();
+                // `{`
+                self.tokenizer.expect_symbol('{', "after else")?;
+                // statements
+                let statements = Statements::parse(self.tokenizer)?;
+                // `}`
+                self.tokenizer.expect_symbol('}', "after else body")?;
+                Some(statements)
+            },
+            _ => None
+        };
+        let statement = IfStatement {
+            expression,
+            if_statements,
+            else_statements,
+            span
+        };
+        Ok(Statement::If(Box::new(statement)))
+    }
+
+    fn parse_while(&mut self) -> Result<Statement, SyntaxError> {
+        // while
+        let span = self.tokenizer.span();
+        self.tokenizer.next();
+        // `(`
+        self.tokenizer.expect_symbol('(', "after while")?;
+        // expression
+        let expression = Expression::parse(self.tokenizer)?;
+        // `)`
+        self.tokenizer.expect_symbol(')', "after while condition")?;
+        // `{`
+        self.tokenizer.expect_symbol('{', "before while body")?;
+        // statements
+        let statements = Statements::parse(self.tokenizer)?;
+        // `}`
+        self.tokenizer.expect_symbol('}', "after while body")?;
+        let statement = WhileStatement {
+            expression,
+            statements,
+            span,
+            increment: false
+        };
+        Ok(Statement::While(Box::new(statement)))
+    }
+
+    // `for (init; condition; increment) { body }` as `init` and `while (condition) { body increment }`,
+    // with `while (true)` for a missing condition
+    fn parse_for(&mut self) -> Result<Vec<Statement>, SyntaxError> {
+        // for
+        let span = self.tokenizer.span();
+        self.tokenizer.next();
+        // `(`
+        self.tokenizer.expect_symbol('(', "after for")?;
+        // init, a let statement with its `;`
+        let mut statements = Vec::new();
+        match self.tokenizer.peek() {
+            Some(Token::Keyword(v)) if v == "let" => statements.push(self.parse_let()?),
+            _ => self.tokenizer.expect_symbol(';', "or a let statement after for (")?
+        }
+        // condition
+        let expression = match self.tokenizer.peek() {
+            Some(Token::Symbol(';')) => Expression {
+                term: Term::KeywordConstant(KeywordConstant::True, self.tokenizer.span()),
+                extra_op_terms: Vec::new()
+            },
+            _ => Expression::parse(self.tokenizer)?
+        };
+        // `;`
+        self.tokenizer.expect_symbol(';', "after for condition")?;
+        // increment, a let statement ending with the `)`
+        let increment = match self.tokenizer.peek() {
+            Some(Token::Keyword(v)) if v == "let" => Some(self.parse_let_until(')', "after for increment")?),
+            _ => {
+                self.tokenizer.expect_symbol(')', "or a let statement after for condition")?;
+                None
+            }
+        };
+        // `{`
+        self.tokenizer.expect_symbol('{', "before for body")?;
+        // statements
+        let Statements(mut body) = Statements::parse(self.tokenizer)?;
+        // `}`
+        self.tokenizer.expect_symbol('}', "after for body")?;
+        let has_increment = increment.is_some();
+        body.extend(increment);
+        let statement = WhileStatement { expression, statements: Statements(body), span, increment: has_increment };
+        statements.push(Statement::While(Box::new(statement)));
+        Ok(statements)
+    }
+
+    // `break;` or `continue;`, whether there is a loop around it is checked when compiling
+    fn parse_jump(&mut self) -> Result<Statement, SyntaxError> {
+        let span = self.tokenizer.span();
+        let statement = match self.tokenizer.take_text().as_str() {
+            "break" => Statement::Break(span),
+            _ => Statement::Continue(span)
+        };
+        self.tokenizer.expect_symbol(';', "after break or continue")?;
+        Ok(statement)
+    }
+
+    fn parse_do(&mut self) -> Result<Statement, SyntaxError> {
+        // do
+        let span = self.tokenizer.span();
+        self.tokenizer.next();
+        // subroutineCall
+        let subroutine_call = SubroutineCall::parse(self.tokenizer)?;
+        // `;`
+        self.tokenizer.expect_symbol(';', "after do statement")?;
+        Ok(Statement::Do(subroutine_call, span))
+    }
+
+    fn parse_return(&mut self) -> Result<Statement, SyntaxError> {
+        // return
+        let span = self.tokenizer.span();
+        self.tokenizer.next();
+        // expression
+        let expression = match self.tokenizer.peek() {
+            Some(Token::Symbol(';')) => None,
+            _ => Some(Expression::parse(self.tokenizer)?)
+        };
+        // `;`
+        self.tokenizer.expect_symbol(';', "after return statement")?;
+        Ok(Statement::Return(expression, span))
+    }
 }
 
 impl<'a> Iterator for StatementParser<'a> {
-    type Item=Statement;
+    type Item=Result<Statement, SyntaxError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Token::Keyword(v) = self.tokenizer.peek()? {
             match v.as_str() {
-                "let" => {
-                    // let
-                    self.tokenizer.next();
-                    // varName
-                    let var_name = match self.tokenizer.next()? {
-                        Token::Identifier(v) => VarName(v),
-                        _ => return None
-                    };
-                    // [ expression ]
-                    let index_expression = match self.tokenizer.peek()? {
-                        Token::Symbol('[') => {
-                            // '['
-                            self.tokenizer.next();
-                            // expression
-                            let expression: Expression = Expression::parse(self.tokenizer)?;
-                            // ']'
-                            assert_symbol(&self.tokenizer.next()?, ']');
-                            Some(expression)
-                        },
-                        _ => None
-                    };
-                    // `=`
-                    assert_symbol(&self.tokenizer.next()?, '=');
-                    // expression
-                    let expression: Expression = Expression::parse(self.tokenizer)?;
-                    // `;`
-                    assert_symbol(&self.tokenizer.next()?, ';');
-                    let statement = LetStatement {
-                        var_name,
-                        index_expression,
-                        expression
-                    };
-                    Some(Statement::Let(statement))
-                },
-                "if" => {
-                    // if
-                    self.tokenizer.next()?;
-                    // `(`
-                    assert_symbol(&self.tokenizer.next()?, '(');
-                    // expression
-                    let expression = Expression::parse(self.tokenizer)?;
-                    // `)`
-                    assert_symbol(&self.tokenizer.next()?, ')');
-                    // `{`
-                    assert_symbol(&self.tokenizer.next()?, '{');
-                    // if statements
-                    let if_statements = Statements::parse(self.tokenizer);
-                    // `}`
-                    assert_symbol(&self.tokenizer.next()?, '}');
-                    // else statements
-                    let else_statements = match self.tokenizer.peek()? {
-                        Token::Keyword(v) if v.as_str() == "else" => {
-                            // else
-                            self.tokenizer.next();
-                            // `{`
-                            assert_symbol(&self.tokenizer.next()?, '{');
-                            // statements
-                            let statements = Statements::parse(self.tokenizer);
-                            // `}`
-                            assert_symbol(&self.tokenizer.next()?, '}');
-                            Some(statements)
-                        },
-                        _ => None
-                    };
-                    let statement = IfStatement {
-                        expression,
-                        if_statements,
-                        else_statements,
-                    };
-                    Some(Statement::If(Box::new(statement)))
-                },
-                "while" => {
-                    // while
-                    self.tokenizer.next();
-                    // `(`
-                    assert_symbol(&self.tokenizer.next()?, '(');
-                    // expression
-                    let expression = Expression::parse(self.tokenizer)?;
-                    // `)`
-                    assert_symbol(&self.tokenizer.next()?, ')');
-                    // `{`
-                    assert_symbol(&self.tokenizer.next()?, '{');
-                    // statements
-                    let statements = Statements::parse(self.tokenizer);
-                    // `}`
-                    assert_symbol(&self.tokenizer.next()?, '}');
-                    let statement = WhileStatement {
-                        expression,
-                        statements,
-                    };
-                    Some(Statement::While(Box::new(statement)))
-                },
-                "do" => {
-                    // do
-                    self.tokenizer.next();
-                    // subroutineCall
-                    let subroutine_call = SubroutineCall::parse(self.tokenizer)?;
-                    // `;`
-                    assert_symbol(&self.tokenizer.next()?, ';');
-                    Some(Statement::Do(subroutine_call))
-                },
-                "return" => {
-                    // return
-                    self.tokenizer.next();
-                    // expression
-                    let expression = Expression::parse(self.tokenizer);
-                    // `;`
-                    assert_symbol(&self.tokenizer.next()?, ';');
-                    Some(Statement::Return(expression))
-                },
+                "let" => Some(self.parse_let()),
+                "if" => Some(self.parse_if()),
+                "while" => Some(self.parse_while()),
+                "do" => Some(self.parse_do()),
+                "return" => Some(self.parse_return()),
+                "break" | "continue" => Some(self.parse_jump()),
                 _ => None
             }
         } else {
@@ -815,24 +2109,24 @@ impl<'a> Iterator for StatementParser<'a> {
 // ExtraExpressionParser
 
 struct ExtraExpressionParser<'a> {
-    tokenizer: &'a mut Peekable<Tokenizer>
+    tokenizer: &'a mut Tokens
 }
 
 impl<'a> ExtraExpressionParser<'a> {
-    pub fn new(tokenizer: &'a mut Peekable<Tokenizer>) -> Self {
+    pub fn new(tokenizer: &'a mut Tokens) -> Self {
         ExtraExpressionParser { tokenizer }
     }
 }
 
 impl<'a> Iterator for ExtraExpressionParser<'a> {
-    type Item=Expression;
+    type Item=Result<Expression, SyntaxError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.tokenizer.peek()? {
             Token::Symbol(',') => {
                 // `,`
                 self.tokenizer.next();
-                Expression::parse(self.tokenizer)
+                Some(Expression::parse(self.tokenizer))
             },
             _ => None
         }
@@ -842,104 +2136,28 @@ impl<'a> Iterator for ExtraExpressionParser<'a> {
 // ExtraOpTermsParser
 
 struct ExtraOpTermsParser<'a> {
-    tokenizer: &'a mut Peekable<Tokenizer>
+    tokenizer: &'a mut Tokens
 }
 
 impl<'a> ExtraOpTermsParser<'a> {
-    pub fn new(tokenizer: &'a mut Peekable<Tokenizer>) -> Self {
+    pub fn new(tokenizer: &'a mut Tokens) -> Self {
         ExtraOpTermsParser { tokenizer }
     }
 }
 
 impl<'a> Iterator for ExtraOpTermsParser<'a> {
-    type Item=OpTerm;
+    type Item=Result<OpTerm, SyntaxError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.tokenizer.peek()? {
-            Token::Symbol('+') => {
-                // `unaryOp`
-                self.tokenizer.next();
-                // term
-                let term = Term::parse(self.tokenizer)?;
-                Some(OpTerm(Op::Plus, term))
-            },
-            Token::Symbol('-') => {
-                // `unaryOp`
-                self.tokenizer.next();
-                // term
-                let term = Term::parse(self.tokenizer)?;
-                Some(OpTerm(Op::Minus, term))
-            },
-            Token::Symbol('*') => {
-                // `unaryOp`
-                self.tokenizer.next();
-                // term
-                let term = Term::parse(self.tokenizer)?;
-                Some(OpTerm(Op::Multiply, term))
-            },
-            Token::Symbol('/') => {
-                // `unaryOp`
-                self.tokenizer.next();
-                // term
-                let term = Term::parse(self.tokenizer)?;
-                Some(OpTerm(Op::Divide, term))
-            },
-            Token::Symbol('&') => {
-                // `unaryOp`
-                self.tokenizer.next();
-                // term
-                let term = Term::parse(self.tokenizer)?;
-                Some(OpTerm(Op::And, term))
-            },
-            Token::Symbol('|') => {
-                // `unaryOp`
-                self.tokenizer.next();
-                // term
-                let term = Term::parse(self.tokenizer)?;
-                Some(OpTerm(Op::Or, term))
-            },
-            Token::Symbol('<') => {
-                // `unaryOp`
-                self.tokenizer.next();
-                // term
-                let term = Term::parse(self.tokenizer)?;
-                Some(OpTerm(Op::Lt, term))
-            },
-            Token::Symbol('>') => {
-                // `unaryOp`
-                self.tokenizer.next();
-                // term
-                let term = Term::parse(self.tokenizer)?;
-                Some(OpTerm(Op::Gt, term))
-            },
-            Token::Symbol('=') => {
-                // `unaryOp`
-                self.tokenizer.next();
-                // term
-                let term = Term::parse(self.tokenizer)?;
-                Some(OpTerm(Op::Eq, term))
-            },
-            _ => None
-        }
-    }
-}
-
-// Helpers
-fn assert_symbol(token: &Token, symbol: char) {
-    match token {
-        Token::Symbol(v) if *v == symbol => {},
-        _ => panic!("{} doesn't match {:?}", symbol, token)
+        let op = Op::peek(self.tokenizer)?;
+        // op
+        let span = self.tokenizer.span();
+        self.tokenizer.next();
+        // term
+        Some(Term::parse(self.tokenizer).map(|term| OpTerm(op, term, span)))
     }
 }
 
-// Program structure
-
-struct Class {
-    name: ClassName,
-    class_var_decs: Vec<ClassVarDec>,
-    subroutine_decs: Vec<SubroutineDec>
-}
-
 impl Class {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -976,16 +2194,13 @@ impl Class {
     }
 }
 
-enum ClassVarDecType {
-    Static,
-    Field
-}
-
 impl ClassVarDecType {
-    pub fn to_symbol_kind(&self) -> SymbolKind {
+    // none for a constant, which has no storage
+    pub fn to_symbol_kind(&self) -> Option<SymbolKind> {
         match self {
-            ClassVarDecType::Static => SymbolKind::Static,
-            ClassVarDecType::Field => SymbolKind::Field
+            ClassVarDecType::Static => Some(SymbolKind::Static),
+            ClassVarDecType::Field => Some(SymbolKind::Field),
+            ClassVarDecType::Const => None
         }
     }
 
@@ -993,6 +2208,7 @@ impl ClassVarDecType {
         match v {
             "static" => Some(Self::Static),
             "field" => Some(Self::Field),
+            "const" => Some(Self::Const),
             _ => None
         }
     }
@@ -1000,18 +2216,12 @@ impl ClassVarDecType {
     pub fn to_xml(&self) -> String {
         match self {
             ClassVarDecType::Field => "<keyword> field </keyword>\n".to_string(),
-            ClassVarDecType::Static => "<keyword> static </keyword>\n".to_string()
+            ClassVarDecType::Static => "<keyword> static </keyword>\n".to_string(),
+            ClassVarDecType::Const => "<keyword> const </keyword>\n".to_string()
         }
     }
 }
 
-struct ClassVarDec {
-    dec_type: ClassVarDecType,
-    var_type: Type,
-    var_name: VarName,
-    extra_var_names: Vec<VarName>
-}
-
 impl ClassVarDec {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1036,6 +2246,14 @@ impl ClassVarDec {
             xml.push_str(&var_name.to_xml());
         }
 
+        // `--ext-field-init`, like the `=` and expression of a let statement
+        if let Some(initializer) = &self.initializer {
+            xml.push_str(&padding.to_spaces());
+            xml.push_str(&XML::symbol('='));
+
+            xml.push_str(&initializer.to_xml(padding));
+        }
+
         xml.push_str(&padding.to_spaces());
         xml.push_str(&XML::symbol(';'));
 
@@ -1047,41 +2265,37 @@ impl ClassVarDec {
     }
 }
 
-#[derive(Clone)]
-pub enum Type {
-    Int,
-    Char,
-    Boolean,
-    ClassName(String)
-}
-
 impl Type {
     pub fn new(token: &Token) -> Option<Self> {
         match token {
-            Token::Keyword(v) if *v == "int".to_string() => Some(Type::Int),
-            Token::Keyword(v) if *v == "char".to_string() => Some(Type::Char),
-            Token::Keyword(v) if *v == "boolean".to_string() => Some(Type::Boolean),
+            Token::Keyword(v) if v == "int" => Some(Type::Int),
+            Token::Keyword(v) if v == "char" => Some(Type::Char),
+            Token::Keyword(v) if v == "boolean" => Some(Type::Boolean),
             Token::Identifier(v) => Some(Type::ClassName((*v).clone())),
             _ => None
         }
     }
 
+    pub fn parse(tokenizer: &mut Tokens) -> Result<Self, SyntaxError> {
+        match tokenizer.peek().and_then(Type::new) {
+            Some(var_type) => {
+                tokenizer.next();
+                Ok(var_type)
+            },
+            None => Err(tokenizer.error("a type (int, char, boolean or a class name)"))
+        }
+    }
+
     pub fn to_xml(&self) -> String {
         match self {
             Type::Int => "<keyword> int </keyword>\n".to_string(),
             Type::Char => "<keyword> char </keyword>\n".to_string(),
             Type::Boolean => "<keyword> boolean </keyword>\n".to_string(),
-            Type::ClassName(v) => format!("<identifier> {} </identifier>\n", v)
+            Type::ClassName(v) => XML::identifier(v)
         }
     }
 }
 
-enum SubroutineType {
-    Constructor,
-    Function,
-    Method
-}
-
 impl SubroutineType {
     pub fn new(v: &str) -> Option<Self> {
         match v {
@@ -1101,15 +2315,10 @@ impl SubroutineType {
     }
 }
 
-enum SubroutineReturnType {
-    Void,
-    General(Type)
-}
-
 impl SubroutineReturnType {
     pub fn new(token: &Token) -> Option<Self> {
         match token {
-            Token::Keyword(v) if *v == "void".to_string() => Some(Self::Void),
+            Token::Keyword(v) if v == "void" => Some(Self::Void),
             _ => {
                 let kind = Type::new(token)?;
                 Some(Self::General(kind))
@@ -1117,6 +2326,16 @@ impl SubroutineReturnType {
         }
     }
 
+    pub fn parse(tokenizer: &mut Tokens) -> Result<Self, SyntaxError> {
+        match tokenizer.peek().and_then(SubroutineReturnType::new) {
+            Some(return_type) => {
+                tokenizer.next();
+                Ok(return_type)
+            },
+            None => Err(tokenizer.error("a return type (void or a type)"))
+        }
+    }
+
     pub fn to_xml(&self) -> String {
         match self {
             SubroutineReturnType::Void => XML::keyword("void"),
@@ -1125,14 +2344,6 @@ impl SubroutineReturnType {
     }
 }
 
-struct SubroutineDec {
-    subroutine_type: SubroutineType,
-    return_type: SubroutineReturnType,
-    name: SubroutineName,
-    parameters: Vec<Parameter>,
-    body: SubroutineBody
-}
-
 impl SubroutineDec {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1156,7 +2367,7 @@ impl SubroutineDec {
         xml.push_str("<parameterList>\n");
 
         padding.increment();
-        if self.parameters.len() > 0 {
+        if !self.parameters.is_empty() {
             let mut parameters = self.parameters.iter();
             let first_parameter = parameters.next().unwrap();
             
@@ -1185,8 +2396,6 @@ impl SubroutineDec {
     }
 }
 
-struct Parameter(Type, VarName);
-
 impl Parameter {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1202,11 +2411,6 @@ impl Parameter {
     }
 }
 
-struct SubroutineBody {
-    var_decs: Vec<VarDec>,
-    statements: Statements
-}
-
 impl SubroutineBody {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1234,12 +2438,6 @@ impl SubroutineBody {
     }
 }
 
-struct VarDec {
-    var_type: Type,
-    var_name: VarName,
-    extra_var_names: Vec<VarName>
-}
-
 impl VarDec {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1276,67 +2474,89 @@ impl VarDec {
     }
 }
 
-struct ClassName(String);
 impl ClassName {
     pub fn to_xml(&self) -> String {
-        format!("<identifier> {} </identifier>\n", self.0)
+        XML::identifier(&self.0)
     }
 }
 
-struct SubroutineName(String);
 impl SubroutineName {
     pub fn to_xml(&self) -> String {
-        format!("<identifier> {} </identifier>\n", self.0)
+        XML::identifier(&self.0)
     }
 }
 
-struct VarName(String);
 impl VarName {
     pub fn to_xml(&self) -> String {
-        format!("<identifier> {} </identifier>\n", self.0)
+        XML::identifier(&self.0)
     }
 }
 
-// Statements
-
-struct Statements(Vec<Statement>);
-
 impl Statements {
-    pub fn parse(tokenizer: &mut Peekable<Tokenizer>) -> Self {
-        Statements(
-            StatementParser::new(tokenizer).collect()
-        )
+    pub fn parse(tokenizer: &mut Tokens) -> Result<Self, SyntaxError> {
+        tokenizer.nested(Statements::parse_block)
+    }
+
+    fn parse_block(tokenizer: &mut Tokens) -> Result<Self, SyntaxError> {
+        let mut statements = Vec::new();
+        loop {
+            // a for loop is two statements
+            if matches!(tokenizer.peek(), Some(Token::Keyword(v)) if v == "for") {
+                match StatementParser::new(tokenizer).parse_for() {
+                    Ok(for_statements) => statements.extend(for_statements),
+                    Err(error) => tokenizer.recover(error)?
+                }
+                continue;
+            }
+            match StatementParser::new(tokenizer).next() {
+                Some(Ok(statement)) => statements.push(statement),
+                Some(Err(error)) => tokenizer.recover(error)?,
+                // statements can only be followed by the closing brace of their block
+                None => match tokenizer.peek() {
+                    Some(Token::Symbol('}')) => return Ok(Statements(statements)),
+                    Some(Token::Keyword(v)) if v == "var" => {
+                        let span = tokenizer.span();
+                        match VarDecParser::new(tokenizer).next() {
+                            Some(Ok(var_dec)) => {
+                                let message = format!(
+                                    "variable declarations must precede statements (declaration of '{}' at line {})",
+                                    var_dec.var_name.as_str(), span.line
+                                );
+                                tokenizer.errors.push(SyntaxError::with_message(&message, "'var'", span));
+                            },
+                            Some(Err(error)) => tokenizer.recover(error)?,
+                            None => {}
+                        }
+                    },
+                    _ => {
+                        let error = tokenizer.error("a statement or '}'");
+                        tokenizer.recover(error)?;
+                    }
+                }
+            }
+        }
     }
 
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
 
-        if self.0.len() > 0 {
-            xml.push_str(&padding.to_spaces());
-            xml.push_str("<statements>\n");
-            padding.increment();
-
-            for statement in self.0.iter() {
-                xml.push_str(&statement.to_xml(padding));
-            }
+        // emitted even when empty, like in the course compare files
+        xml.push_str(&padding.to_spaces());
+        xml.push_str("<statements>\n");
+        padding.increment();
 
-            padding.decrement();
-            xml.push_str(&padding.to_spaces());
-            xml.push_str("</statements>\n");
+        for statement in self.0.iter() {
+            xml.push_str(&statement.to_xml(padding));
         }
 
+        padding.decrement();
+        xml.push_str(&padding.to_spaces());
+        xml.push_str("</statements>\n");
+
         xml
     }
 }
 
-enum Statement {
-    Let(LetStatement),
-    If(Box<IfStatement>),
-    While(Box<WhileStatement>),
-    Do(SubroutineCall),
-    Return(Option<Expression>)
-}
-
 impl Statement {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1351,7 +2571,7 @@ impl Statement {
             Statement::While(statement) => {
                 xml.push_str(&statement.to_xml(padding));
             },
-            Statement::Do(subroutine_call) => {
+            Statement::Do(subroutine_call, _) => {
                 xml.push_str(&padding.to_spaces());
                 xml.push_str("<doStatement>\n");
                 padding.increment();
@@ -1368,7 +2588,24 @@ impl Statement {
                 xml.push_str(&padding.to_spaces());
                 xml.push_str("</doStatement>\n");
             },
-            Statement::Return(expression) => {
+            // not in the course's grammar, named like the other statements
+            Statement::Break(_) | Statement::Continue(_) => {
+                let keyword = if let Statement::Break(_) = self { "break" } else { "continue" };
+                xml.push_str(&padding.to_spaces());
+                xml.push_str(&format!("<{}Statement>\n", keyword));
+                padding.increment();
+
+                xml.push_str(&padding.to_spaces());
+                xml.push_str(&XML::keyword(keyword));
+
+                xml.push_str(&padding.to_spaces());
+                xml.push_str(&XML::symbol(';'));
+
+                padding.decrement();
+                xml.push_str(&padding.to_spaces());
+                xml.push_str(&format!("</{}Statement>\n", keyword));
+            },
+            Statement::Return(expression, _) => {
                 xml.push_str(&padding.to_spaces());
                 xml.push_str("<returnStatement>\n");
                 padding.increment();
@@ -1393,12 +2630,6 @@ impl Statement {
     }
 }
 
-struct LetStatement {
-    var_name: VarName,
-    index_expression: Option<Expression>,
-    expression: Expression
-}
-
 impl LetStatement {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1439,12 +2670,6 @@ impl LetStatement {
     }
 }
 
-struct IfStatement {
-    expression: Expression,
-    if_statements: Statements,
-    else_statements: Option<Statements>
-}
-
 impl IfStatement {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1493,11 +2718,6 @@ impl IfStatement {
     }
 }
 
-struct WhileStatement {
-    expression: Expression,
-    statements: Statements
-}
-
 impl WhileStatement {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1533,10 +2753,6 @@ impl WhileStatement {
     }
 }
 
-// Expressions
-
-struct OpTerm(Op, Term);
-
 impl OpTerm {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1549,32 +2765,64 @@ impl OpTerm {
     }
 }
 
-struct Expression {
-    term: Term,
-    extra_op_terms: Vec<OpTerm>
-}
-
 impl Expression {
-    pub fn parse_list(tokenizer: &mut Peekable<Tokenizer>) -> Vec<Expression> {
+    pub fn parse_list(tokenizer: &mut Tokens) -> Result<Vec<Expression>, SyntaxError> {
         let mut expression_list: Vec<Expression> = Vec::new();
-        if let Some(expression) = Expression::parse(tokenizer) {
-            expression_list.push(expression);
-            for expression in ExtraExpressionParser::new(tokenizer) {
-                expression_list.push(expression);
-            }
+        if let Some(Token::Symbol(')')) = tokenizer.peek() {
+            return Ok(expression_list);
+        }
+        expression_list.push(Expression::parse(tokenizer)?);
+        for expression in ExtraExpressionParser::new(tokenizer) {
+            expression_list.push(expression?);
         }
-        expression_list
+        Ok(expression_list)
     }
 
-    pub fn parse(tokenizer: &mut Peekable<Tokenizer>) -> Option<Self> {
+    pub fn parse(tokenizer: &mut Tokens) -> Result<Self, SyntaxError> {
+        if tokenizer.options.precedence {
+            return Expression::parse_level(tokenizer, 1);
+        }
         let term = Term::parse(tokenizer)?;
-        let extra_op_terms = ExtraOpTermsParser::new(tokenizer).collect();
-        Some(Expression {
+        let extra_op_terms = ExtraOpTermsParser::new(tokenizer).collect::<Result<_, _>>()?;
+        Ok(Expression {
             term,
             extra_op_terms,
         })
     }
 
+    // Precedence climbing: `a + b * c` parses `b * c` as the second operand of `+`.
+    // An expression only has the operators of a single level.
+    fn parse_level(tokenizer: &mut Tokens, min_level: u8) -> Result<Self, SyntaxError> {
+        let mut expression = Expression { term: Term::parse(tokenizer)?, extra_op_terms: Vec::new() };
+        let mut level = None;
+        while let Some(op) = Op::peek(tokenizer) {
+            if op.precedence() < min_level {
+                break;
+            }
+            // op
+            let span = tokenizer.span();
+            tokenizer.next();
+            // operand, binding tighter
+            let operand = Expression::parse_level(tokenizer, op.precedence() + 1)?.into_term();
+            // operators of a lower level can only come after, the operand took the higher ones
+            if level.is_some_and(|level| op.precedence() < level) {
+                expression = Expression { term: expression.into_term(), extra_op_terms: Vec::new() };
+            }
+            level = Some(op.precedence());
+            expression.extra_op_terms.push(OpTerm(op, operand, span));
+        }
+        Ok(expression)
+    }
+
+    // an operand, nested unless it is a single term
+    fn into_term(self) -> Term {
+        if self.extra_op_terms.is_empty() {
+            self.term
+        } else {
+            Term::Nested(Box::new(self))
+        }
+    }
+
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
 
@@ -1596,17 +2844,6 @@ impl Expression {
     }
 }
 
-enum Term {
-    IntegerConstant(i16),
-    StringConstant(String),
-    KeywordConstant(KeywordConstant),
-    VarName(String),
-    IndexVar(String, Box<Expression>),
-    Call(SubroutineCall),
-    Expression(Box<Expression>),
-    WithUnary(UnaryOp, Box<Term>)
-}
-
 impl Term {
     pub fn to_xml(&self, padding: &mut Padding) -> String {
         let mut xml = String::new();
@@ -1618,23 +2855,33 @@ impl Term {
         match self {
             Term::IntegerConstant(v) => {
                 xml.push_str(&padding.to_spaces());
-                xml.push_str(&format!("<integerConstant> {} </integerConstant>\n", v));
+                xml.push_str(&format!("<integerConstant> {} </integerConstant>\n", *v as u16));
             },
-            Term::StringConstant(v) => {
+            Term::StringConstant(v, _) => {
                 xml.push_str(&padding.to_spaces());
-                xml.push_str(&format!("<stringConstant> {} </stringConstant>\n", v));
+                xml.push_str(&XML::string_constant(v));
             },
-            Term::KeywordConstant(v) => {
+            Term::KeywordConstant(v, _) => {
                 xml.push_str(&padding.to_spaces());
                 xml.push_str(&v.to_xml());
             },
-            Term::VarName(v) => {
+            Term::VarName(v, _) => {
+                xml.push_str(&padding.to_spaces());
+                xml.push_str(&XML::identifier(v));
+            },
+            Term::ClassConstant(class_name, name, _) => {
+                xml.push_str(&padding.to_spaces());
+                xml.push_str(&XML::identifier(class_name));
+
+                xml.push_str(&padding.to_spaces());
+                xml.push_str(&XML::symbol('.'));
+
                 xml.push_str(&padding.to_spaces());
-                xml.push_str(&format!("<identifier> {} </identifier>\n", v));
+                xml.push_str(&XML::identifier(name));
             },
-            Term::IndexVar(v, expression) => {
+            Term::IndexVar(v, expression, _) => {
                 xml.push_str(&padding.to_spaces());
-                xml.push_str(&format!("<identifier> {} </identifier>\n", v));
+                xml.push_str(&XML::identifier(v));
 
                 xml.push_str(&padding.to_spaces());
                 xml.push_str(&XML::symbol('['));
@@ -1656,6 +2903,9 @@ impl Term {
                 xml.push_str(&padding.to_spaces());
                 xml.push_str(&XML::symbol(')'));
             },
+            Term::Nested(expression) => {
+                xml.push_str(&expression.to_xml(padding));
+            },
             Term::WithUnary(op, term) => {
                 xml.push_str(&padding.to_spaces());
                 xml.push_str(&op.to_xml());
@@ -1671,35 +2921,35 @@ impl Term {
         xml
     }
 
-    pub fn parse(tokenizer: &mut Peekable<Tokenizer>) -> Option<Self> {
-        let token = (*tokenizer.peek()?).clone();
-        match token {
-            Token::Int(v) => {
-                tokenizer.next();
-                Some(Term::IntegerConstant(v))
-            },
-            Token::String(v) => {
-                tokenizer.next();
-                Some(Term::StringConstant(v))
-            },
-            Token::Keyword(v) if v.as_str() == "true" => {
-                tokenizer.next();
-                Some(Term::KeywordConstant(KeywordConstant::True))
-            },
-            Token::Keyword(v) if v.as_str() == "false" => {
-                tokenizer.next();
-                Some(Term::KeywordConstant(KeywordConstant::False))
-            },
-            Token::Keyword(v) if v.as_str() == "null" => {
+    // every term can hold an expression or another term
+    pub fn parse(tokenizer: &mut Tokens) -> Result<Self, SyntaxError> {
+        tokenizer.nested(Term::parse_term)
+    }
+
+    fn parse_term(tokenizer: &mut Tokens) -> Result<Self, SyntaxError> {
+        let negated = std::mem::take(&mut tokenizer.negated);
+        let span = tokenizer.span();
+        match tokenizer.peek() {
+            None => Err(tokenizer.error("an expression")),
+            Some(Token::Int(i16::MIN)) if !negated => Err(tokenizer.error(INTEGER_RANGE)),
+            Some(&Token::Int(v)) => {
                 tokenizer.next();
-                Some(Term::KeywordConstant(KeywordConstant::Null))
+                Ok(Term::IntegerConstant(v))
             },
-            Token::Keyword(v) if v.as_str() == "this" => {
+            Some(Token::String(_)) => Ok(Term::StringConstant(tokenizer.take_text(), span)),
+            Some(Token::Keyword(v)) if matches!(v.as_str(), "true" | "false" | "null" | "this") => {
+                let constant = match v.as_str() {
+                    "true" => KeywordConstant::True,
+                    "false" => KeywordConstant::False,
+                    "null" => KeywordConstant::Null,
+                    _ => KeywordConstant::This
+                };
                 tokenizer.next();
-                Some(Term::KeywordConstant(KeywordConstant::This))
+                Ok(Term::KeywordConstant(constant, span))
             },
-            Token::Identifier(v) => {
-                tokenizer.next();
+            Some(Token::Identifier(_)) => {
+                let v = tokenizer.take_text();
+                let constants = tokenizer.options.constants;
                 match tokenizer.peek() {
                     Some(Token::Symbol('[')) => {
                         // `[`
@@ -1707,79 +2957,66 @@ impl Term {
                         // expression
                         let expression = Expression::parse(tokenizer)?;
                         // `]`
-                        assert_symbol(&tokenizer.next()?, ']');
-                        Some(Term::IndexVar(v, Box::new(expression)))
-                    },
-                    Some(Token::Symbol('(')) => {
-                        // `(`
-                        tokenizer.next();
-                        // expressionList
-                        let expression_list = Expression::parse_list(tokenizer);
-                        // `)`
-                        assert_symbol(&tokenizer.next()?, ')');
-                        let subroutine_call = SubroutineCall {
-                            caller: None,
-                            subroutine_name: SubroutineName(v),
-                            expression_list
-                        };
-                        Some(Term::Call(subroutine_call))
+                        tokenizer.expect_symbol(']', "after array index")?;
+                        Ok(Term::IndexVar(v, Box::new(expression), span))
                     },
-                    Some(Token::Symbol('.')) => {
-                        // `.`
-                        tokenizer.next();
-                        // subroutineName
-                        let subroutine_name = match tokenizer.next()? {
-                            Token::Identifier(v) => SubroutineName(v),
-                            _ => return None
-                        };
-                        // `(`
-                        assert_symbol(&tokenizer.next()?, '(');
-                        // expressionList
-                        let expression_list = Expression::parse_list(tokenizer);
-                        // `)`
-                        assert_symbol(&tokenizer.next()?, ')');
-                        let subroutine_call = SubroutineCall {
-                            caller: Some(v),
-                            subroutine_name,
-                            expression_list
-                        };
-                        Some(Term::Call(subroutine_call))
+                    Some(Token::Symbol('.')) if constants => Term::parse_after_dot(v, span, tokenizer),
+                    Some(Token::Symbol('(')) | Some(Token::Symbol('.')) => {
+                        let subroutine_call = SubroutineCall::parse_after_name(v, span, tokenizer)?;
+                        Ok(Term::Call(subroutine_call))
                     },
-                    _ => Some(Term::VarName(v))
+                    _ => Ok(Term::VarName(v, span))
                 }
             },
-            Token::Symbol('(') => {
+            Some(Token::Symbol('(')) => {
                 // `(`
                 tokenizer.next();
+                // `-(32768)`
+                if negated && matches!(tokenizer.peek(), Some(Token::Int(i16::MIN))) {
+                    let span = tokenizer.span();
+                    tokenizer.next();
+                    if !matches!(tokenizer.peek(), Some(Token::Symbol(')'))) {
+                        return Err(SyntaxError::new(INTEGER_RANGE, "'32768'", span));
+                    }
+                    tokenizer.next();
+                    let expression = Expression { term: Term::IntegerConstant(i16::MIN), extra_op_terms: Vec::new() };
+                    return Ok(Term::Expression(Box::new(expression)));
+                }
                 // expression
                 let expression = Expression::parse(tokenizer)?;
                 // `)`
-                assert_symbol(&tokenizer.next()?, ')');
-                Some(Term::Expression(Box::new(expression)))
+                tokenizer.expect_symbol(')', "after expression")?;
+                Ok(Term::Expression(Box::new(expression)))
             },
-            Token::Symbol('-') => {
-                // unaryOp
-                tokenizer.next();
-                // term
-                let term = Term::parse(tokenizer)?;
-                Some(Term::WithUnary(UnaryOp::Negative, Box::new(term)))
-            },
-            Token::Symbol('~') => {
-                // unaryOp
-                tokenizer.next();
-                // term
-                let term = Term::parse(tokenizer)?;
-                Some(Term::WithUnary(UnaryOp::Not, Box::new(term)))
+            Some(&Token::Symbol(symbol)) => match UnaryOp::from_symbol(symbol) {
+                Some(unary_op) => {
+                    // unaryOp
+                    tokenizer.next();
+                    // term
+                    tokenizer.negated = unary_op == UnaryOp::Negative;
+                    let term = Term::parse(tokenizer)?;
+                    Ok(Term::WithUnary(unary_op, Box::new(term)))
+                },
+                None => Err(tokenizer.error("an expression"))
             },
-            _ => return None
+            _ => Err(tokenizer.error("an expression"))
         }
     }
-}
 
-struct SubroutineCall {
-    caller: Option<String>,
-    subroutine_name: SubroutineName,
-    expression_list: Vec<Expression>,
+    // `Ball.MAX_BALLS` or a call `Ball.new()` with `--ext-const`, apart from `parse_term`, whose frame
+    // every level of nesting adds to the stack
+    fn parse_after_dot(class_name: String, span: Span, tokenizer: &mut Tokens) -> Result<Self, SyntaxError> {
+        // `.`
+        tokenizer.next();
+        let name = tokenizer.expect_identifier("a subroutine or constant name after '.'")?;
+        match tokenizer.peek() {
+            Some(Token::Symbol('(')) => {
+                let subroutine_call = SubroutineCall::parse_arguments(Some(class_name), SubroutineName(name), span, tokenizer)?;
+                Ok(Term::Call(subroutine_call))
+            },
+            _ => Ok(Term::ClassConstant(class_name, name, span))
+        }
+    }
 }
 
 impl SubroutineCall {
@@ -1788,7 +3025,7 @@ impl SubroutineCall {
 
         if let Some(caller) = &self.caller {
             xml.push_str(&padding.to_spaces());
-            xml.push_str(&XML::identifier(&caller));
+            xml.push_str(&XML::identifier(caller));
 
             xml.push_str(&padding.to_spaces());
             xml.push_str(&XML::symbol('.'));
@@ -1825,58 +3062,44 @@ impl SubroutineCall {
         xml
     }
 
-    pub fn parse(tokenizer: &mut Peekable<Tokenizer>) -> Option<Self> {
-        match tokenizer.next()? {
-            Token::Identifier(v) => {
-                match tokenizer.peek()? {
-                    Token::Symbol('(') => {
-                        // `(`
-                        assert_symbol(&tokenizer.next()?, '(');
-                        // expressionList
-                        let expression_list = Expression::parse_list(tokenizer);
-                        // `)`
-                        assert_symbol(&tokenizer.next()?, ')');
-                        let subroutine_call = SubroutineCall {
-                            caller: None,
-                            subroutine_name: SubroutineName(v),
-                            expression_list
-                        };
-                        Some(subroutine_call)
-                    },
-                    Token::Symbol('.') => {
-                        // `.`
-                        assert_symbol(&tokenizer.next()?, '.');
-                        // subroutineName
-                        let subroutine_name = match tokenizer.next()? {
-                            Token::Identifier(v) => SubroutineName(v),
-                            _ => return None
-                        };
-                        // `(`
-                        assert_symbol(&tokenizer.next()?, '(');
-                        // expressionList
-                        let expression_list = Expression::parse_list(tokenizer);
-                        // `)`
-                        assert_symbol(&tokenizer.next()?, ')');
-                        let subroutine_call = SubroutineCall {
-                            caller: Some(v),
-                            subroutine_name,
-                            expression_list
-                        };
-                        Some(subroutine_call)
-                    },
-                    _ => None
-                }
+    pub fn parse(tokenizer: &mut Tokens) -> Result<Self, SyntaxError> {
+        let span = tokenizer.span();
+        let name = tokenizer.expect_identifier("a subroutine call")?;
+        SubroutineCall::parse_after_name(name, span, tokenizer)
+    }
+
+    // the rest of a call once its first identifier has been consumed
+    fn parse_after_name(name: String, span: Span, tokenizer: &mut Tokens) -> Result<Self, SyntaxError> {
+        let (caller, subroutine_name) = match tokenizer.peek() {
+            Some(Token::Symbol('.')) => {
+                // `.`
+                tokenizer.next();
+                // subroutineName
+                let subroutine_name = tokenizer.expect_identifier("a subroutine name after '.'")?;
+                (Some(name), SubroutineName(subroutine_name))
             },
-            _ => None
-        }
+            _ => (None, SubroutineName(name))
+        };
+        SubroutineCall::parse_arguments(caller, subroutine_name, span, tokenizer)
     }
-}
 
-enum KeywordConstant {
-    True,
-    False,
-    Null,
-    This
+    // `(expressionList)` after the names
+    fn parse_arguments(
+        caller: Option<String>, subroutine_name: SubroutineName, span: Span, tokenizer: &mut Tokens
+    ) -> Result<Self, SyntaxError> {
+        // `(`
+        tokenizer.expect_symbol('(', "after subroutine name")?;
+        // expressionList
+        let expression_list = Expression::parse_list(tokenizer)?;
+        // `)`
+        tokenizer.expect_symbol(')', "after argument list")?;
+        Ok(SubroutineCall {
+            caller,
+            subroutine_name,
+            expression_list,
+            span
+        })
+    }
 }
 
 impl KeywordConstant {
@@ -1890,75 +3113,106 @@ impl KeywordConstant {
     }
 }
 
-enum UnaryOp {
-    Negative,
-    Not
-}
-
 impl UnaryOp {
     pub fn to_xml(&self) -> String {
-        match self {
-            UnaryOp::Negative => XML::symbol('-'),
-            UnaryOp::Not => XML::symbol('~'),
-        }
+        XML::symbol(self.symbol())
     }
 }
 
-enum Op {
-    Plus,
-    Minus,
-    Multiply,
-    Divide,
-    And,
-    Or,
-    Lt,
-    Gt,
-    Eq
-}
-
 impl Op {
-    pub fn to_xml(&self) -> String {
+    pub fn peek(tokenizer: &mut Tokens) -> Option<Op> {
+        match tokenizer.peek()? {
+            Token::Symbol(symbol) => Op::from_symbol(*symbol),
+            _ => None
+        }
+    }
+
+    // `--precedence` levels, binding tighter as they go up
+    pub fn precedence(&self) -> u8 {
         match self {
-            Op::Plus => XML::symbol('+'),
-            Op::Minus => XML::symbol('-'),
-            Op::Multiply => XML::symbol('*'),
-            Op::Divide => XML::symbol('/'),
-            Op::And => "<symbol> &amp; </symbol>\n".to_string(),
-            Op::Or => XML::symbol('|'),
-            Op::Lt => "<symbol> &lt; </symbol>\n".to_string(),
-            Op::Gt => "<symbol> &gt; </symbol>\n".to_string(),
-            Op::Eq => XML::symbol('=')
+            Op::And | Op::Or => 1,
+            Op::Lt | Op::Gt | Op::Eq => 2,
+            Op::Plus | Op::Minus => 3,
+            Op::Multiply | Op::Divide => 4
         }
     }
+
+    pub fn to_xml(&self) -> String {
+        XML::symbol(self.symbol())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs::File;
     use tempfile::tempfile;
     use core::panic;
     use std::io::SeekFrom;
     use std::io::prelude::*;
+    use crate::vm_eval::{self, Stop};
 
-    fn fixture_tokenizer(content: &str) -> Peekable<Tokenizer> {
+    fn fixture(content: &str) -> File {
         let mut file = tempfile().unwrap();
         for line in content.lines() {
             writeln!(file, "{}", line).unwrap();
         }
         file.seek(SeekFrom::Start(0)).unwrap();
-        Tokenizer::new(file).unwrap().peekable()
+        file
+    }
+
+    fn parse_file(file: File, options: LanguageOptions) -> Result<Class, CompileError> {
+        let mut tokenizer = tokenize(file, options)?;
+        parse_single_class(&mut tokenizer)
+    }
+
+    fn fixture_tokenizer(content: &str) -> Tokens {
+        tokenize(fixture(content), LanguageOptions::default()).unwrap()
+    }
+
+    // the output is compiled into memory
+    fn compile_xml(content: &str, options: LanguageOptions) -> String {
+        let mut xml = Vec::new();
+        let written = XML::compile(&parse_file(fixture(content), options).unwrap(), &mut xml).unwrap();
+        assert_eq!(written, xml.len());
+        String::from_utf8(xml).unwrap()
+    }
+
+    fn semantic_errors(content: &str) -> Vec<String> {
+        let mut vm = Vec::new();
+        let result = VM::compile(&parse_file(fixture(content), LanguageOptions::default()).unwrap(), LanguageOptions::default(), None, &mut vm);
+        // a class that does not compile writes nothing
+        assert!(vm.is_empty());
+        match result {
+            Err(CompileError::Semantic(errors)) => errors.iter().map(|e| e.to_string()).collect(),
+            other => panic!("expected semantic errors, got {:?}", other)
+        }
+    }
+
+    // the VM code compiled into memory, and its warnings
+    fn compiled_with(class: &Class, options: LanguageOptions, signatures: Option<&Signatures>) -> (String, Vec<Warning>) {
+        let mut vm = Vec::new();
+        let (_, warnings) = VM::compile(class, options, signatures, &mut vm).unwrap();
+        (String::from_utf8(vm).unwrap(), warnings)
+    }
+
+    fn compile_vm(content: &str, options: LanguageOptions) -> String {
+        let mut vm = Vec::new();
+        let (written, _) = VM::compile(&parse_file(fixture(content), options).unwrap(), options, None, &mut vm).unwrap();
+        assert_eq!(written, vm.len());
+        String::from_utf8(vm).unwrap()
     }
 
     #[test]
     fn extra_var_names_parser() {
         let mut tokenizer = fixture_tokenizer(", hello, world");
         let mut parser = ExtraVarNameParser::new(&mut tokenizer);
-        match parser.next() {
-            Some(VarName(v)) if v == "hello".to_string() => {},
+        match parser.next().transpose().unwrap() {
+            Some(VarName(v, _)) if v == "hello" => {},
             _ => panic!("error parsing var `hello`")
         }
-        match parser.next() {
-            Some(VarName(v)) if v == "world".to_string() => {},
+        match parser.next().transpose().unwrap() {
+            Some(VarName(v, _)) if v == "world" => {},
             _ => panic!("error parsing var `world`")
         }
         assert!(parser.next().is_none());
@@ -1968,16 +3222,16 @@ mod tests {
     fn extra_paramters_parser() {
         let mut tokenizer = fixture_tokenizer(", int a, boolean isTrue, People bran");
         let mut parser = ExtraParameterParser::new(&mut tokenizer);
-        match parser.next() {
-            Some(Parameter(Type::Int, VarName(v))) if v == "a".to_string() => {},
+        match parser.next().transpose().unwrap() {
+            Some(Parameter(Type::Int, VarName(v, _))) if v == "a" => {},
             _ => panic!("error parsing int parameter a")
         }
-        match parser.next() {
-            Some(Parameter(Type::Boolean, VarName(v))) if v == "isTrue".to_string() => {},
+        match parser.next().transpose().unwrap() {
+            Some(Parameter(Type::Boolean, VarName(v, _))) if v == "isTrue" => {},
             _ => panic!("error parsing boolean parameter isTrue")
         }
-        match parser.next() {
-            Some(Parameter(Type::ClassName(c), VarName(v))) if c == "People" && v == "bran".to_string() => {},
+        match parser.next().transpose().unwrap() {
+            Some(Parameter(Type::ClassName(c), VarName(v, _))) if c == "People" && v == "bran" => {},
             _ => panic!("error parsing classname parameter bran")
         }
         assert!(parser.next().is_none());
@@ -1995,8 +3249,9 @@ mod tests {
             dec_type,
             var_type,
             var_name,
-            extra_var_names
-        } = parser.next().unwrap();
+            extra_var_names,
+            ..
+        } = parser.next().unwrap().unwrap();
         match dec_type {
             ClassVarDecType::Static => {},
             _ => panic!("error parsing dec_type")
@@ -2006,11 +3261,11 @@ mod tests {
             _ => panic!("error parsing var_type")
         }
         match var_name {
-            VarName(v) if v == "a".to_string() => {},
+            VarName(v, _) if v == "a" => {},
             _ => panic!("error parsing int a")
         }
         match extra_var_names.first().unwrap() {
-            VarName(v) if *v == "b".to_string() => {},
+            VarName(v, _) if v == "b" => {},
             _ => panic!("error parsing int b")
         }
 
@@ -2018,8 +3273,9 @@ mod tests {
             dec_type,
             var_type,
             var_name,
-            extra_var_names
-        } = parser.next().unwrap();
+            extra_var_names,
+            ..
+        } = parser.next().unwrap().unwrap();
         match dec_type {
             ClassVarDecType::Field => {},
             _ => panic!("error parsing dec_type")
@@ -2029,11 +3285,11 @@ mod tests {
             _ => panic!("error parsing var_type")
         }
         match var_name {
-            VarName(v) if v == "c".to_string() => {},
+            VarName(v, _) if v == "c" => {},
             _ => panic!("error parsing int c")
         }
         match extra_var_names.first().unwrap() {
-            VarName(v) if *v == "d".to_string() => {},
+            VarName(v, _) if v == "d" => {},
             _ => panic!("error parsing int d")
         }
 
@@ -2051,7 +3307,7 @@ mod tests {
         ");
         let mut parser = SubroutineDecParser::new(&mut tokenizer);
 
-        match parser.next().unwrap() {
+        match parser.next().unwrap().unwrap() {
             SubroutineDec {
                 subroutine_type: SubroutineType::Constructor,
                 return_type: SubroutineReturnType::General(
@@ -2061,18 +3317,20 @@ mod tests {
                 parameters,
                 body: SubroutineBody {
                     var_decs,
-                    statements: Statements(statements)
-                }
+                    statements: Statements(statements),
+                    ..
+                },
+                ..
             } => {
                 assert_eq!(a.as_str(), "People");
                 assert_eq!(v.as_str(), "new");
                 let mut parameters = parameters.iter();
                 match parameters.next().unwrap() {
-                    Parameter(Type::Int, VarName(n)) if *n == "age".to_string() => {},
+                    Parameter(Type::Int, VarName(n, _)) if n == "age" => {},
                     _ => panic!("error parsing parameter int age")
                 }
                 match parameters.next().unwrap() {
-                    Parameter(Type::ClassName(c), VarName(n)) if *c == "String".to_string() && *n == "name".to_string() => {},
+                    Parameter(Type::ClassName(c), VarName(n, _)) if c == "String" && n == "name" => {},
                     _ => panic!("error parsing parameter String name")
                 }
                 assert_eq!(1, var_decs.len());
@@ -2081,7 +3339,7 @@ mod tests {
             _ => panic!()
         }
 
-        match parser.next().unwrap() {
+        match parser.next().unwrap().unwrap() {
             SubroutineDec {
                 subroutine_type: SubroutineType::Method,
                 return_type: SubroutineReturnType::General(
@@ -2091,8 +3349,10 @@ mod tests {
                 parameters,
                 body: SubroutineBody {
                     var_decs,
-                    statements: Statements(statements)
-                }
+                    statements: Statements(statements),
+                    ..
+                },
+                ..
             } => {
                 assert!(parameters.is_empty());
                 assert_eq!(v.as_str(), "age");
@@ -2114,23 +3374,24 @@ mod tests {
         let VarDec {
             var_type,
             var_name,
-            extra_var_names
-        } = parser.next().unwrap();
+            extra_var_names,
+            ..
+        } = parser.next().unwrap().unwrap();
         match var_type {
             Type::Int => {},
             _ => panic!("error parsing var type")
         }
         match var_name {
-            VarName(v) if v == "age".to_string() => {},
+            VarName(v, _) if v == "age" => {},
             _ => panic!("error parsing var_name")
         }
         let mut extra_var_names = extra_var_names.iter();
         match extra_var_names.next().unwrap() {
-            VarName(v) if *v == "weight".to_string() => {},
+            VarName(v, _) if v == "weight" => {},
             _ => panic!("errpr parsing weight")
         }
         match extra_var_names.next().unwrap() {
-            VarName(v) if *v == "height".to_string() => {},
+            VarName(v, _) if v == "height" => {},
             _ => panic!("errpr parsing weight")
         }
         assert!(extra_var_names.next().is_none());
@@ -2138,14 +3399,15 @@ mod tests {
         let VarDec {
             var_type,
             var_name,
-            extra_var_names
-        } = parser.next().unwrap();
+            extra_var_names,
+            ..
+        } = parser.next().unwrap().unwrap();
         match var_type {
-            Type::ClassName(v) if v == "String".to_string() => {},
+            Type::ClassName(v) if v == "String" => {},
             _ => panic!("error parsing var type")
         }
         match var_name {
-            VarName(v) if v == "name".to_string() => {},
+            VarName(v, _) if v == "name" => {},
             _ => panic!("error parsing var_name")
         }
         assert!(extra_var_names.is_empty());
@@ -2156,10 +3418,10 @@ mod tests {
         let mut tokenizer = fixture_tokenizer("a+b");
         let expression = Expression::parse(&mut tokenizer).unwrap();
         match expression {
-            Expression { term: Term::VarName(a), extra_op_terms } if a == "a".to_string() => {
+            Expression { term: Term::VarName(a, _), extra_op_terms } if a == "a" => {
                 let mut iter = extra_op_terms.iter();
                 match iter.next().unwrap() {
-                    OpTerm(Op::Plus, Term::VarName(v)) if v.as_str() == "b" => {},
+                    OpTerm(Op::Plus, Term::VarName(v, _), _) if v.as_str() == "b" => {},
                     _ => panic!("error parsing op term `+b`")
                 }
                 assert!(iter.next().is_none());
@@ -2177,7 +3439,7 @@ mod tests {
         match expression {
             Expression { term: Term::WithUnary(UnaryOp::Negative, t), extra_op_terms } => {
                 match *t {
-                    Term::VarName(v) => assert_eq!(v.as_str(), "a"),
+                    Term::VarName(v, _) => assert_eq!(v.as_str(), "a"),
                     _ => panic!("error parsing term `-a`")
                 }
                 let mut iter = extra_op_terms.into_iter();
@@ -2188,9 +3450,11 @@ mod tests {
                             SubroutineCall {
                                 caller, 
                                 subroutine_name: SubroutineName(v),
-                                expression_list
+                                expression_list,
+                                ..
                             }
-                        )
+                        ),
+                        _
                     ) => {
                         assert_eq!(caller, Some("bob".to_string()));
                         assert_eq!(v, "age".to_string());
@@ -2201,7 +3465,8 @@ mod tests {
                 match iter.next().unwrap() {
                     OpTerm(
                         Op::Divide,
-                        Term::Expression(expression)
+                        Term::Expression(expression),
+                        _
                     ) => {
                         match *expression {
                             Expression {
@@ -2210,6 +3475,7 @@ mod tests {
                                         caller,
                                         subroutine_name: SubroutineName(v),
                                         expression_list,
+                                        ..
                                     }
                                 ),
                                 extra_op_terms,
@@ -2218,7 +3484,7 @@ mod tests {
                                 assert_eq!(v, "get_max".to_string());
                                 let mut iter = expression_list.into_iter();
                                 match iter.next().unwrap() {
-                                    Expression { term: Term::VarName(v), extra_op_terms } => {
+                                    Expression { term: Term::VarName(v, _), extra_op_terms } => {
                                         assert_eq!(v, "size".to_string());
                                         assert!(extra_op_terms.is_empty());
                                     },
@@ -2233,7 +3499,7 @@ mod tests {
                                 }
                                 let mut iter = extra_op_terms.into_iter();
                                 match iter.next().unwrap() {
-                                    OpTerm(Op::Plus, Term::IndexVar(v, expression)) => {
+                                    OpTerm(Op::Plus, Term::IndexVar(v, expression, _), _) => {
                                         assert_eq!(v.as_str(), "alex");
                                         match *expression {
                                             Expression { term: Term::IntegerConstant(2), extra_op_terms } => {
@@ -2264,10 +3530,11 @@ mod tests {
             let b[1] = 2;
         ");
         let mut iter = StatementParser::new(&mut tokenizer);
-        match iter.next().unwrap() {
+        match iter.next().unwrap().unwrap() {
             Statement::Let(
                 LetStatement {
-                    var_name: VarName(v),
+                    var_name: VarName(v, _),
+                    span: _,
                     index_expression: None,
                     expression: Expression {
                         term: Term::IntegerConstant(1),
@@ -2280,10 +3547,11 @@ mod tests {
             },
             _ => panic!()
         }
-        match iter.next().unwrap() {
+        match iter.next().unwrap().unwrap() {
             Statement::Let(
                 LetStatement {
-                    var_name: VarName(v),
+                    var_name: VarName(v, _),
+                    span: _,
                     index_expression: Some(
                         Expression {
                             term: Term::IntegerConstant(1),
@@ -2314,13 +3582,13 @@ mod tests {
             }
         ");
         let mut iter = StatementParser::new(&mut tokenizer);
-        match iter.next().unwrap() {
+        match iter.next().unwrap().unwrap() {
             Statement::If(statement) => {
                 match *statement {
                     IfStatement {
                         expression: Expression {
                             term: Term::KeywordConstant(
-                                KeywordConstant::True
+                                KeywordConstant::True, _
                             ),
                             extra_op_terms,
                         },
@@ -2328,6 +3596,7 @@ mod tests {
                         else_statements: Some(
                             Statements(else_statements)
                         ),
+                        span: _
                     } => {
                         assert!(extra_op_terms.is_empty());
                         assert_eq!(1, if_statements.len());
@@ -2356,17 +3625,18 @@ mod tests {
             }
         ");
         let mut iter = StatementParser::new(&mut tokenizer);
-        match iter.next().unwrap() {
+        match iter.next().unwrap().unwrap() {
             Statement::While(statement) => {
                 match *statement {
                     WhileStatement {
                         expression: Expression {
                             term: Term::KeywordConstant(
-                                KeywordConstant::True
+                                KeywordConstant::True, _
                             ),
                             extra_op_terms
                         },
-                        statements: Statements(statements)
+                        statements: Statements(statements),
+                        ..
                     } => {
                         assert!(extra_op_terms.is_empty());
                         assert_eq!(1, statements.len());
@@ -2384,13 +3654,15 @@ mod tests {
             do get_max();
         ");
         let mut iter = StatementParser::new(&mut tokenizer);
-        match iter.next().unwrap() {
+        match iter.next().unwrap().unwrap() {
             Statement::Do(
                 SubroutineCall {
                     caller,
                     subroutine_name: SubroutineName(v),
                     expression_list,
-                }
+                    ..
+                },
+                _
             ) => {
                 assert_eq!(caller, None);
                 assert_eq!(v.as_str(), "get_max");
@@ -2406,18 +3678,1727 @@ mod tests {
             return 1;
         ");
         let mut iter = StatementParser::new(&mut tokenizer);
-        match iter.next().unwrap() {
+        match iter.next().unwrap().unwrap() {
             Statement::Return(
                 Some(
                     Expression {
                         term: Term::IntegerConstant(1),
                         extra_op_terms,
                     }
-                )
+                ),
+                _
             ) => {
                 assert!(extra_op_terms.is_empty());
             },
             _ => panic!()
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn string_escapes_xml() {
+        let options = LanguageOptions { string_escapes: true, ..Default::default() };
+        let xml = compile_xml(r#"class A { function void f() { do Output.printString("a\tb\\c"); return; } }"#, options);
+        assert!(xml.contains("<stringConstant> a\tb\\c </stringConstant>"));
+    }
+
+    // the string a function of the class returns, run by vm_eval
+    fn returned_string(content: &str, options: LanguageOptions) -> Result<String, Stop> {
+        let class = parse_file(fixture(content), options).unwrap();
+        let compiled = VM::compile_with_symbols(&class, options, None, None, None).unwrap();
+        let outcome = vm_eval::call(&compiled.commands, "A.f", &[])?;
+        Ok(outcome.string(outcome.value).unwrap())
+    }
+
+    #[test]
+    fn string_escapes_vm() {
+        let options = LanguageOptions { string_escapes: true, ..Default::default() };
+        // no tab in the Hack character set
+        assert_eq!(returned_string(r#"class A { function String f() { return "\"\n\t"; } }"#, options), Ok("\"\n ".to_string()));
+    }
+
+    #[test]
+    fn invalid_string_escape_fails_compilation() {
+        let options = LanguageOptions { string_escapes: true, ..Default::default() };
+        let source = fixture(r#"class A { function void f() { do Output.printString("\x"); return; } }"#);
+        assert!(parse_file(source, options).is_err());
+    }
+
+    fn syntax_errors(content: &str) -> Vec<SyntaxError> {
+        let mut tokenizer = fixture_tokenizer(content);
+        match parse_single_class(&mut tokenizer) {
+            Err(CompileError::Syntax(errors)) => errors,
+            _ => panic!("expected a syntax error")
+        }
+    }
+
+    fn reported_errors(error: CompileError) -> Vec<SyntaxError> {
+        match error {
+            CompileError::Syntax(errors) => errors,
+            _ => panic!("expected a syntax error, got {}", error)
+        }
+    }
+
+    fn syntax_error(content: &str) -> SyntaxError {
+        let mut errors = syntax_errors(content);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        errors.remove(0)
+    }
+
+    #[test]
+    fn missing_semicolon() {
+        let error = syntax_error("\
+class A {
+    function void f() {
+        let x = 1
+        return;
+    }
+}");
+        assert_eq!(error.expected, "';' after let statement");
+        assert_eq!(error.found, "'return'");
+        assert_eq!(error.span, Span::new(4, 9));
+    }
+
+    #[test]
+    fn missing_closing_brace() {
+        let error = syntax_error("\
+class A {
+    function void f() {
+        return;
+    }");
+        assert_eq!(error.expected, "'}' at end of class");
+        assert_eq!(error.found, "end of file");
+        assert_eq!(error.span, Span::new(4, 5));
+    }
+
+    #[test]
+    fn missing_parenthesis_after_if() {
+        let error = syntax_error("\
+class A {
+    function void f() {
+        if x) {
+            return;
+        }
+    }
+}");
+        assert_eq!(error.expected, "'(' after if");
+        assert_eq!(error.found, "'x'");
+        assert_eq!(error.to_string(), "3:12: expected '(' after if, found 'x'");
+    }
+
+    #[test]
+    fn integer_constant_out_of_range() {
+        let source = fixture("class A { function int f() { return 40000; } }");
+        let error = &reported_errors(parse_file(source, LanguageOptions::default()).unwrap_err())[0];
+        assert_eq!(error.found, "'40000'");
+        assert_eq!(error.span, Span::new(1, 37));
+    }
+
+    #[test]
+    fn negated_32768() {
+        let vm = "\
+function A.f 0
+push constant 32767
+push constant 1
+add
+neg
+return
+";
+        for expression in ["-32768", "- 32768", "-(32768)", "-( 32768 )"] {
+            let content = format!("class A {{ function int f() {{ return {}; }} }}", expression);
+            assert_eq!(compile_vm(&content, LanguageOptions::default()), vm, "{}", expression);
+        }
+        for (expression, column) in [("32768", 37), ("~32768", 38), ("-(32768 + 1)", 39), ("-(1 + 32768)", 43), ("1 - 32768", 41)] {
+            let content = format!("class A {{ function int f() {{ return {}; }} }}", expression);
+            let error = &reported_errors(parse_file(fixture(&content), LanguageOptions::default()).unwrap_err())[0];
+            assert_eq!((error.found.as_str(), error.span), ("'32768'", Span::new(1, column)), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn empty_string_constant() {
+        // String.new(0) would stop the program
+        let content = "class A { function String f() { return \"\"; } }";
+        assert_eq!(returned_string(content, LanguageOptions::default()), Ok(String::new()));
+    }
+
+    #[test]
+    fn method_calls_push_the_object_first() {
+        let class = parse_file(fixture("\
+class Ball {
+    field Ball next;
+    method void move(int dx) {
+        do next.move(dx + 1);
+        do move(dx);
+        return;
+    }
+}"), LanguageOptions::default()).unwrap();
+        let mut vm = VM::new("Ball");
+        let commands = vm.compile_class(&class);
+        assert_eq!(commands[3..], [
+            VmCommand::Push(Segment::This, 0),
+            VmCommand::Push(Segment::Argument, 1),
+            VmCommand::Push(Segment::Constant, 1),
+            VmCommand::Arithmetic(Operator::Add),
+            VmCommand::Call("Ball.move".to_string(), 2),
+            VmCommand::Pop(Segment::Temp, 0),
+            VmCommand::Push(Segment::Pointer, 0),
+            VmCommand::Push(Segment::Argument, 1),
+            VmCommand::Call("Ball.move".to_string(), 2),
+            VmCommand::Pop(Segment::Temp, 0),
+            VmCommand::Push(Segment::Constant, 0),
+            VmCommand::Return
+        ]);
+    }
+
+    fn compile_errors(content: &str) -> Vec<SyntaxError> {
+        reported_errors(parse_file(fixture(content), LanguageOptions::default()).unwrap_err())
+    }
+
+    fn compile_error(content: &str) -> SyntaxError {
+        let mut errors = compile_errors(content);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        errors.remove(0)
+    }
+
+    #[test]
+    fn misspelled_statement_keyword() {
+        let error = compile_error("\
+class A {
+    function void f() {
+        var int x;
+        let x = 1;
+        whlie (x < 10) {
+            let x = x + 1;
+        }
+        return;
+    }
+}");
+        assert_eq!(error.to_string(), "5:9: expected a statement or '}', found 'whlie'");
+    }
+
+    #[test]
+    fn declaration_after_statements() {
+        let error = compile_error("\
+class A {
+    function void f() {
+        do Output.printInt(1);
+        var int x;
+        return;
+    }
+}");
+        assert_eq!(error.to_string(), "4:9: variable declarations must precede statements (declaration of 'x' at line 4)");
+    }
+
+    #[test]
+    fn class_var_dec_after_subroutines() {
+        let error = compile_error("\
+class A {
+    function void f() {
+        return;
+    }
+    field int x;
+}");
+        assert_eq!(error.to_string(), "5:5: expected a subroutine declaration or '}' at end of class, found 'field'");
+    }
+
+    #[test]
+    fn stray_closing_brace() {
+        let error = compile_error("\
+class A {
+    function void f() {
+        return;
+    }
+    }
+    function void g() {
+        return;
+    }
+}");
+        assert_eq!(error.to_string(), "6:5: expected end of file after class A, found 'function'");
+    }
+
+    #[test]
+    fn xml_escapes_string_constants() {
+        for (content, escaped) in [("a < b", "a &lt; b"), ("a > b", "a &gt; b"), ("a & b", "a &amp; b")] {
+            let xml = compile_xml(
+                &format!("class A {{ function void f() {{ do Output.printString(\"{}\"); return; }} }}", content),
+                LanguageOptions::default()
+            );
+            assert!(xml.contains(&format!("<stringConstant> {} </stringConstant>", escaped)));
+            let document = roxmltree::Document::parse(&xml).unwrap();
+            let string_constant = document.descendants().find(|n| n.has_tag_name("stringConstant")).unwrap();
+            assert_eq!(string_constant.text(), Some(format!(" {} ", content).as_str()));
+        }
+    }
+
+    #[test]
+    fn xml_escapes_quotes_in_string_constants() {
+        let options = LanguageOptions { string_escapes: true, ..Default::default() };
+        let xml = compile_xml("class A { function void f() { do Output.printString(\"say \\\"hi\\\" <&>\"); return; } }", options);
+        assert!(xml.contains("<stringConstant> say &quot;hi&quot; &lt;&amp;&gt; </stringConstant>"));
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let string_constant = document.descendants().find(|n| n.has_tag_name("stringConstant")).unwrap();
+        assert_eq!(string_constant.text(), Some(" say \"hi\" <&> "));
+    }
+
+    #[test]
+    fn xml_output_is_well_formed() {
+        let xml = compile_xml("\
+class A {
+    function boolean f(int x) {
+        return (x < 1) & (x > 0) | ~(x = 2);
+    }
+}", LanguageOptions::default());
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let symbols: Vec<&str> = document.descendants()
+            .filter(|n| n.has_tag_name("symbol"))
+            .filter_map(|n| n.text())
+            .collect();
+        assert!(symbols.contains(&" < "));
+        assert!(symbols.contains(&" > "));
+        assert!(symbols.contains(&" & "));
+    }
+
+    #[test]
+    fn empty_statements_element() {
+        let xml = compile_xml("class A { function void f() { if (true) { } else { } return; } }", LanguageOptions::default());
+        assert_eq!(xml.matches("<statements>").count(), 3);
+        assert_eq!(xml.matches("</statements>").count(), 3);
+    }
+
+    // the official compare files, modulo their CRLF line endings
+    #[test]
+    fn expression_less_square_compare_files() {
+        let files = [
+            (include_str!("../../ExpressionLessSquare/Main.jack"), include_str!("../../ExpressionLessSquare/Main.xml")),
+            (include_str!("../../ExpressionLessSquare/Square.jack"), include_str!("../../ExpressionLessSquare/Square.xml")),
+            (include_str!("../../ExpressionLessSquare/SquareGame.jack"), include_str!("../../ExpressionLessSquare/SquareGame.xml"))
+        ];
+        for (jack, compare) in files {
+            assert_eq!(compile_xml(jack, LanguageOptions::default()), compare.replace('\r', ""));
+        }
+    }
+
+    fn error_messages(errors: Vec<SyntaxError>) -> Vec<String> {
+        errors.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn recovers_after_missing_semicolons() {
+        let errors = compile_errors("\
+class A {
+    function void f() {
+        var int x, y;
+        let x = 1
+        let y = 2;
+        do Output.printInt(x)
+        if (x < y) {
+            let x = y
+        }
+        return;
+    }
+}");
+        assert_eq!(error_messages(errors), vec![
+            "5:9: expected ';' after let statement, found 'let'",
+            "7:9: expected ';' after do statement, found 'if'",
+            "9:9: expected ';' after let statement, found '}'"
+        ]);
+    }
+
+    #[test]
+    fn recovers_in_every_subroutine() {
+        let errors = compile_errors("\
+class A {
+    function void f() {
+        let x = ;
+        return;
+    }
+    function void g() {
+        whlie (true) {
+            let x = 1;
+        }
+        return;
+    }
+}");
+        assert_eq!(error_messages(errors), vec![
+            "3:17: expected an expression, found ';'",
+            "7:9: expected a statement or '}', found 'whlie'"
+        ]);
+    }
+
+    #[test]
+    fn skips_nested_blocks_of_broken_statements() {
+        let errors = compile_errors("\
+class A {
+    function void f() {
+        if (x {
+            let x = 1;
+        }
+        let y = 2
+        return;
+    }
+}");
+        assert_eq!(error_messages(errors), vec![
+            "3:15: expected ')' after if condition, found '{'",
+            "7:9: expected ';' after let statement, found 'return'"
+        ]);
+    }
+
+    #[test]
+    fn unclosed_block_is_reported_once() {
+        let error = compile_error("\
+class A {
+    function void f() {
+        if (true) {
+            return;
+    }
+    function void g() {
+        return;
+    }
+}");
+        assert_eq!(error.to_string(), "6:5: expected a statement or '}', found 'function'");
+    }
+
+    #[test]
+    fn declaration_errors_end_parsing() {
+        let error = compile_error("\
+class A {
+    function void f( {
+        let x = 1
+        return;
+    }
+}");
+        assert_eq!(error.expected, "a type (int, char, boolean or a class name)");
+    }
+
+    #[test]
+    fn gives_up_after_max_errors() {
+        let mut content = String::from("class A { function void f() {\n");
+        for _ in 0..50 {
+            content.push_str("let x = 1\n");
+        }
+        content.push_str("return; } }");
+        let errors = compile_errors(&content);
+        assert_eq!(errors.len(), MAX_ERRORS);
+        assert_eq!(errors[0].span, Span::new(3, 1));
+    }
+
+    #[test]
+    fn parse_class_reports_the_first_error() {
+        let mut tokenizer = fixture_tokenizer("\
+class A {
+    function void f() {
+        let x = 1
+        let y = 2
+        return;
+    }
+}");
+        let error = parse_class(&mut tokenizer).unwrap_err();
+        assert_eq!(error.span, Span::new(4, 9));
+    }
+
+    #[test]
+    fn parse_single_class_rejects_trailing_tokens() {
+        let mut tokenizer = fixture_tokenizer("class A { } class B { }");
+        match parse_single_class(&mut tokenizer) {
+            Err(CompileError::Syntax(errors)) => assert_eq!(errors[0].to_string(), "1:13: expected end of file after class A, found 'class'"),
+            _ => panic!("expected a syntax error")
+        }
+        let mut tokenizer = fixture_tokenizer("class A { } class B { }");
+        assert_eq!(parse_class(&mut tokenizer).unwrap().name.as_str(), "A");
+        assert_eq!(parse_class(&mut tokenizer).unwrap().name.as_str(), "B");
+    }
+
+    #[test]
+    fn trailing_statement_after_class() {
+        let error = compile_error("\
+class Square {
+    function void f() {
+        return;
+    }
+}
+let x = 1;");
+        assert_eq!(error.to_string(), "6:1: expected end of file after class Square, found 'let'");
+    }
+
+    // VM code of `return <expression>;`
+    fn return_vm(expression: &str, options: LanguageOptions) -> Vec<String> {
+        let vm = compile_vm(&format!("class A {{ function int f(int a, int b) {{ return {}; }} }}", expression), options);
+        vm.lines().skip(1).take_while(|line| *line != "return").map(|line| line.to_string()).collect()
+    }
+
+    fn precedence() -> LanguageOptions {
+        LanguageOptions { precedence: true, ..Default::default() }
+    }
+
+    // the value of the expression for arguments a and b
+    fn evaluate(expression: &str, options: LanguageOptions, a: i16, b: i16) -> Result<i16, Stop> {
+        call(&format!("class A {{ function int f(int a, int b) {{ return {}; }} }}", expression), options, "A.f", &[a, b])
+    }
+
+    #[test]
+    fn expressions_evaluate_left_to_right_by_default() {
+        assert_eq!(evaluate("2 + 3 * 4", LanguageOptions::default(), 0, 0), Ok(20));
+        // ((a < b) & b) < 10
+        assert_eq!(evaluate("a < b & b < 10", LanguageOptions::default(), 5, 1), Ok(-1));
+        assert_eq!(return_vm("a < b & b < 10", LanguageOptions::default()), vec![
+            "push argument 0", "push argument 1", "lt", "push argument 1", "and", "push constant 10", "lt"
+        ]);
+    }
+
+    #[test]
+    fn precedence_climbing() {
+        assert_eq!(evaluate("2 + 3 * 4", precedence(), 0, 0), Ok(14));
+        assert_eq!(evaluate("a < b & b < 10", precedence(), 5, 1), Ok(0));
+        assert_eq!(evaluate("-a * b + 1 = 7", precedence(), -2, 3), Ok(-1));
+        assert_eq!(evaluate("-a * b + 1 = 7", precedence(), 2, 3), Ok(0));
+    }
+
+    #[test]
+    fn precedence_keeps_left_associativity() {
+        for options in [LanguageOptions::default(), precedence()] {
+            assert_eq!(evaluate("10 - 2 - 3", options, 0, 0), Ok(5));
+            assert_eq!(evaluate("(2 + 3) * 4", options, 0, 0), Ok(20));
+            assert_eq!(evaluate("a / b / 2", options, 100, 5), Ok(10));
+        }
+    }
+
+    #[test]
+    fn precedence_xml_nests_expressions() {
+        let xml = compile_xml("class A { function int f() { return 2 + 3 * 4; } }", precedence());
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let outer = document.descendants().find(|n| n.has_tag_name("expression")).unwrap();
+        let symbols = |node: roxmltree::Node| -> Vec<String> {
+            node.children().filter(|n| n.has_tag_name("symbol")).map(|n| n.text().unwrap().trim().to_string()).collect()
+        };
+        assert_eq!(symbols(outer), vec!["+"]);
+        let inner = outer.descendants().skip(1).find(|n| n.has_tag_name("expression")).unwrap();
+        assert_eq!(symbols(inner), vec!["*"]);
+        // the grouping is not written as parentheses
+        assert!(outer.descendants().filter(|n| n.has_tag_name("term")).all(|term| symbols(term).is_empty()));
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let prefix = "class A { function int f() { return ";
+        let content = format!("{}{}1{}; }} }}", prefix, "(".repeat(10000), ")".repeat(10000));
+        let errors = syntax_errors(&content);
+        // the body's statements take one level, the 255 terms before are fine
+        let column = prefix.len() + 256;
+        assert_eq!(error_messages(errors), vec![format!("1:{}: expression nesting too deep", column)]);
+
+        let content = format!("{}{}1; }} }}", prefix, "-".repeat(10000));
+        assert_eq!(syntax_error(&content).to_string(), format!("1:{}: expression nesting too deep", column));
+    }
+
+    #[test]
+    fn nesting_below_the_limit_compiles() {
+        let content = format!("class A {{ function int f() {{ return {}1{}; }} }}", "(".repeat(200), ")".repeat(200));
+        let vm = compile_vm(&content, precedence());
+        assert!(vm.contains("push constant 1"));
+
+        let mut tokens = fixture_tokenizer(&content);
+        tokens.set_max_depth(100);
+        assert!(parse_class(&mut tokens).unwrap_err().to_string().ends_with("expression nesting too deep"));
+    }
+
+    #[test]
+    fn file_without_a_class() {
+        for content in ["", "\n  \n", "// Foo.jack\n/** nothing here yet */\n"] {
+            let mut tokenizer = fixture_tokenizer(content);
+            assert!(matches!(parse_single_class(&mut tokenizer), Err(CompileError::NoClass)));
+        }
+        assert_eq!(syntax_error("function void f() { return; }").to_string(), "1:1: expected 'class', found 'function'");
+    }
+
+    #[test]
+    fn duplicate_subroutines() {
+        let errors = syntax_errors("\
+class Foo {
+    method void draw() { return; }
+    function int size() { return 1; }
+    method void draw() { return; }
+    method void size(int x) { return; }
+}");
+        assert_eq!(error_messages(errors), vec![
+            "4:17: subroutine Foo.draw is already declared at 2:17",
+            "5:17: subroutine Foo.size is already declared at 3:18"
+        ]);
+    }
+
+    #[test]
+    fn duplicate_variables() {
+        let errors = syntax_errors("\
+class Foo {
+    field int x, y;
+    static boolean x;
+    method int f(int a, int a) {
+        var int b, a;
+        var char b;
+        return x;
+    }
+    function void g(int x, int y) {
+        var int a;
+        return;
+    }
+}");
+        assert_eq!(error_messages(errors), vec![
+            "3:20: x is already declared at 2:15",
+            "4:29: a is already declared at 4:22",
+            "5:20: a is already declared at 4:22",
+            "6:18: b is already declared at 5:17"
+        ]);
+    }
+
+    #[test]
+    fn keywords_as_identifiers() {
+        let error = |declaration: &str, statement: &str| syntax_error(&format!(
+            "class A {{\n    function void f() {{\n        {}\n        {}\n        return;\n    }}\n}}",
+            declaration, statement
+        )).to_string();
+        assert_eq!(error("var int class;", ""), "3:17: 'class' is a reserved keyword and cannot be used as a variable name");
+        assert_eq!(error("var int x, while;", ""), "3:20: 'while' is a reserved keyword and cannot be used as a variable name");
+        assert_eq!(error("", "let do = 1;"), "4:13: 'do' is a reserved keyword and cannot be used as a variable name");
+        assert_eq!(error("", "do Output.return();"), "4:19: 'return' is a reserved keyword and cannot be used as a subroutine name");
+        assert_eq!(
+            syntax_error("class A { function void f(int this) { return; } }").to_string(),
+            "1:31: 'this' is a reserved keyword and cannot be used as a parameter name"
+        );
+        assert_eq!(
+            syntax_error("class void { }").to_string(),
+            "1:7: 'void' is a reserved keyword and cannot be used as a class name"
+        );
+    }
+
+    #[test]
+    fn undefined_variables() {
+        assert_eq!(semantic_errors("class A {
+    function int f() {
+        let y = 1;
+        return x + 1;
+    }
+}"), vec![
+            "3:13: undefined variable y in A.f",
+            "4:16: undefined variable x in A.f"
+        ]);
+        assert_eq!(
+            semantic_errors("class A { method void f() { let a[0] = b[1]; return; } }"),
+            vec!["1:33: undefined variable a in A.f", "1:40: undefined variable b in A.f"]
+        );
+    }
+
+    #[test]
+    fn fields_are_undefined_in_functions() {
+        assert_eq!(semantic_errors("class A {
+    field int size;
+    function int f() {
+        return size;
+    }
+}"), vec!["4:16: undefined variable size in A.f\n  note: size is a field of A, which a function cannot access"]);
+        // methods and constructors can use them
+        compile_vm("class A { field int size; method int f() { return size; } }", LanguageOptions::default());
+    }
+
+    #[test]
+    fn characters_outside_the_hack_character_set() {
+        let content = r#"class A { function void f() { do Output.printString("café"); return; } }"#;
+        assert_eq!(
+            semantic_errors(content),
+            vec!["1:53: 'é' (U+00E9) is not in the Hack character set in string constant \"café\""]
+        );
+
+        let options = LanguageOptions { lossy_strings: true, ..LanguageOptions::default() };
+        let mut vm = Vec::new();
+        let (_, warnings) = VM::compile(&parse_file(fixture(content), options).unwrap(), options, None, &mut vm).unwrap();
+        let vm = String::from_utf8(vm).unwrap();
+        assert!(vm.starts_with("function A.f 0\npush constant 4\ncall String.new 1\n"));
+        assert!(vm.contains("push constant 102\ncall String.appendChar 2\npush constant 63\ncall String.appendChar 2\n"));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].error.note.as_deref(), Some("printed as '?' because of --lossy-strings"));
+    }
+
+    #[test]
+    fn slash_and_backslash() {
+        let vm = compile_vm(r#"class A { function void f() { do Output.printString("a/b\c"); return; } }"#, LanguageOptions::default());
+        assert!(vm.contains("\
+push constant 97
+call String.appendChar 2
+push constant 47
+call String.appendChar 2
+push constant 98
+call String.appendChar 2
+push constant 92
+call String.appendChar 2
+push constant 99
+call String.appendChar 2
+"));
+    }
+
+    fn project_errors(classes: &[&str]) -> Vec<String> {
+        let classes: Vec<Class> = classes.iter().map(|content| crate::parse_str(content).unwrap()).collect();
+        let mut signatures = Signatures::new();
+        for class in classes.iter() {
+            signatures.add_class(class, &format!("{}.jack", class.name.0));
+        }
+        classes.iter()
+            .flat_map(|class| match VM::compile(class, LanguageOptions::default(), Some(&signatures), &mut io::sink()) {
+                Err(CompileError::Semantic(errors)) => errors,
+                _ => Vec::new()
+            })
+            .map(|error| error.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn calls_between_classes() {
+        let ball = "\
+class Ball {
+    constructor Ball new() { return this; }
+    method void move(int dx, int dy) { return; }
+}";
+        assert_eq!(project_errors(&[ball, "\
+class Main {
+    function void main() {
+        var Ball ball;
+        let ball = Ball.new();
+        do ball.move(1);
+        do ball.mvoe(1, 2);
+        do Ball.new();
+        do Bat.new();
+        do Output.printInt(ball.move(1, 2));
+        return;
+    }
+}"]), vec![
+            "5:12: Ball.move takes 2 arguments, found 1\n  note: Ball.move is declared at Ball.jack:3:17",
+            "6:12: class Ball has no subroutine mvoe\n  note: did you mean move? Ball.move is declared at Ball.jack:3:17",
+            "7:12: the object made by Ball.new is never used\n  note: Ball.new is declared at Ball.jack:2:22",
+            "8:12: unknown class Bat in call to Bat.new",
+            "9:28: Ball.move returns nothing, its result cannot be used\n  note: Ball.move is declared at Ball.jack:3:17"
+        ]);
+    }
+
+    #[test]
+    fn methods_and_functions_at_call_sites() {
+        assert_eq!(semantic_errors("\
+class Foo {
+    constructor Foo new() { do draw(); return this; }
+    method void draw() { do helper(); return; }
+    function void helper() { return; }
+    function void main() {
+        var Foo foo;
+        do draw();
+        do Foo.draw();
+        let foo = Foo.new();
+        do foo.helper();
+        do foo.draw();
+        do Foo.helper();
+        return;
+    }
+}"), vec![
+            "3:29: Foo.helper is a function; call it as Foo.helper()\n  note: Foo.helper is declared at Foo.jack:4:19",
+            "7:12: Foo.draw is a method; function Foo.main has no instance to call it on\n  note: Foo.draw is declared at Foo.jack:3:17",
+            "8:12: Foo.draw is a method; call it on an instance\n  note: Foo.draw is declared at Foo.jack:3:17",
+            "10:12: Foo.helper is a function; call it as Foo.helper()\n  note: Foo.helper is declared at Foo.jack:4:19"
+        ]);
+    }
+
+    #[test]
+    fn argument_counts_of_own_calls_without_the_project() {
+        assert_eq!(semantic_errors("\
+class Square {
+    method void draw(int x, int y) { return; }
+    function void moveRight() { return; }
+    method void run() {
+        do draw(1);
+        do Square.moveRight(2, \"far\");
+        do draw(1, \"2\");
+        do Ball.move(1, 2, 3);
+        do draw(1, 2);
+        return;
+    }
+}"), vec![
+            "5:12: Square.draw takes 2 arguments, found 1\n  note: Square.draw is declared at Square.jack:2:17",
+            "6:12: Square.moveRight takes 0 arguments, found 2\n  note: Square.moveRight is declared at Square.jack:3:19",
+            "7:12: Square.draw takes int as argument 2, found a string constant\n  note: Square.draw is declared at Square.jack:2:17"
+        ]);
+    }
+
+    #[test]
+    fn methods_of_other_classes_need_an_instance() {
+        assert_eq!(project_errors(&[
+            "class Ball { method void move() { return; } function Ball make() { return null; } }",
+            "class Main { function void main() { var Ball b; do Ball.move(); let b = b.make(); return; } }"
+        ]), vec![
+            "1:52: Ball.move is a method; call it on an instance\n  note: Ball.move is declared at Ball.jack:1:26",
+            "1:73: Ball.make is a function; call it as Ball.make()\n  note: Ball.make is declared at Ball.jack:1:59"
+        ]);
+    }
+
+    #[test]
+    fn this_in_functions() {
+        assert_eq!(semantic_errors("\
+class A {
+    field int x;
+    constructor A new() { let x = 1; return this; }
+    method A get() { return this; }
+    function A make() { return this; }
+    function void run() { do undeclared(); return; }
+}"), vec![
+            "5:32: this used in function A.make, which has no instance",
+            "6:30: method call undeclared() in function A.run, which has no instance"
+        ]);
+    }
+
+    #[test]
+    fn returns_match_the_declared_type() {
+        assert_eq!(semantic_errors("\
+class A {
+    constructor A new() { return 0; }
+    constructor A make() { return this; }
+    method int size() { return; }
+    method void clear() { return 1 + 2; }
+    function void run() { return; }
+}"), vec![
+            "2:27: constructor A.new is declared to return A and has to return this",
+            "4:25: method A.size is declared to return int but returns no value",
+            "5:27: method A.clear is declared void but returns a value"
+        ]);
+    }
+
+    #[test]
+    fn void_subroutines_without_return() {
+        let content = "class A { field int x; method void clear() { let x = 1; } }";
+        let vm = compile_vm(content, LanguageOptions::default());
+        assert_eq!(vm, "function A.clear 0\npush argument 0\npop pointer 0\npush constant 1\npop this 0\npush constant 0\nreturn\n");
+        // a return at the end is not doubled
+        let vm = compile_vm("class A { function void f() { if (true) { return; } else { return; } } }", LanguageOptions::default());
+        assert_eq!(vm.matches("return").count(), 2);
+
+        let strict = LanguageOptions { strict: true, ..LanguageOptions::default() };
+        match VM::compile(&parse_file(fixture(content), strict).unwrap(), strict, None, &mut io::sink()) {
+            Err(error) => assert_eq!(error.to_string(), "1:57: void A.clear can end without a return statement"),
+            Ok(_) => panic!("expected an error under --strict")
+        }
+    }
+
+    #[test]
+    fn constructors_without_fields() {
+        let vm = compile_vm("class A { constructor A new() { do Output.printInt(1); return this; } }", LanguageOptions::default());
+        assert_eq!(vm, "\
+function A.new 0
+push constant 1
+call Memory.alloc 1
+pop pointer 0
+push constant 1
+call Output.printInt 1
+pop temp 0
+push pointer 0
+return
+");
+    }
+
+    fn type_warnings(content: &str) -> Vec<String> {
+        let options = LanguageOptions { warnings: crate::Lints::default().with(Lint::TypeCheck), ..LanguageOptions::default() };
+        let (_, warnings) = VM::compile(&parse_file(fixture(content), options).unwrap(), options, None, &mut io::sink()).unwrap();
+        warnings.iter().map(|warning| warning.error.to_string()).collect()
+    }
+
+    #[test]
+    fn type_check() {
+        assert_eq!(type_warnings("\
+class A {
+    method void f(int n, Array a, A other) {
+        var boolean done;
+        let n = done | (n = 1);
+        let done = n + 1;
+        if (other < this) { let n = 1; }
+        do n.draw();
+        do Output.printString(a);
+        let n = Output.printInt(3);
+        do Memory.deAlloc(this);
+        let a = 0;
+        return;
+    }
+}"), vec![
+            "4:13: let assigns boolean to n, which is declared int",
+            "5:13: let assigns int to done, which is declared boolean",
+            "6:13: '<' compares an instance of A, not a number",
+            "6:21: '<' compares an instance of A, not a number",
+            "7:12: n.draw() calls a method on n, which is declared int",
+            "8:12: Output.printString takes String as argument 1, found Array\n  note: Output.printString is part of the Jack OS",
+            "9:17: Output.printInt returns nothing, its result cannot be used\n  note: Output.printInt is part of the Jack OS"
+        ]);
+        // only with --type-check
+        compile_vm("class A { function void f(int n) { let n = true; return; } }", LanguageOptions::default());
+    }
+
+    #[test]
+    fn indexing_non_arrays() {
+        let content = "\
+class A {
+    function void f(int n, Array a, A b) {
+        let n[1] = a[n];
+        let a[0] = b[1];
+        return;
+    }
+}";
+        let options = LanguageOptions::default();
+        let (_, warnings) = VM::compile(&parse_file(fixture(content), options).unwrap(), options, None, &mut io::sink()).unwrap();
+        let warnings: Vec<String> = warnings.iter().map(|warning| warning.error.to_string()).collect();
+        assert_eq!(warnings, vec!["3:13: indexing n, which is declared int, not Array\n  note: n is declared at 2:25"]);
+        // objects only with --type-check
+        assert_eq!(type_warnings(content), vec![
+            "3:13: indexing n, which is declared int, not Array\n  note: n is declared at 2:25",
+            "4:20: indexing b, which is declared A, not Array\n  note: b[i] reads the fields of the A as an array; b is declared at 2:39"
+        ]);
+    }
+
+    const CONSTANT_CONDITIONS: &str = "\
+class A {
+    function void f(int x) {
+        if (false) { let x = 1; } else { let x = 2; }
+        if (true) { let x = 3; } else { let x = 4; }
+        if (true) { let x = 5; }
+        while (false) { let x = 6; }
+        while (true) { return; }
+    }
+}";
+
+    #[test]
+    fn constant_conditions_warn_about_dead_branches() {
+        let options = LanguageOptions::default();
+        let (_, warnings) = VM::compile(&parse_file(fixture(CONSTANT_CONDITIONS), options).unwrap(), options, None, &mut io::sink()).unwrap();
+        let warnings: Vec<String> = warnings.iter().map(|warning| warning.error.to_string()).collect();
+        assert_eq!(warnings, vec![
+            "3:9: the condition is always false, the if branch never runs",
+            "4:9: the condition is always true, the else branch never runs",
+            "6:9: the condition is always false, the loop never runs"
+        ]);
+    }
+
+    #[test]
+    fn optimize_leaves_out_dead_branches() {
+        let options = LanguageOptions { optimize: true, ..LanguageOptions::default() };
+        assert_eq!(compile_vm(CONSTANT_CONDITIONS, options), "\
+function A.f 0
+push constant 2
+pop argument 0
+push constant 3
+pop argument 0
+push constant 5
+pop argument 0
+label A_0
+push constant 0
+return
+goto A_0
+");
+    }
+
+    #[test]
+    fn division_by_a_literal_zero() {
+        assert_eq!(semantic_errors("\
+class A {
+    function int f(int y) {
+        let y = y / 0;
+        let y = 5 / (0) + y;
+        let y = 0 / y;
+        return y / (3 - 3);
+    }
+}"), vec!["3:19: division by zero", "4:19: division by zero"]);
+    }
+
+    #[test]
+    fn inline_accessors() {
+        let point = parse_file(fixture("\
+class Point {
+    field int x, y;
+    method int getY() { return y; }
+    method boolean isOrigin() { return false; }
+    method int twice() { return y + y; }
+    method int scaled(int factor) { return y; }
+    method int again() { return getY(); }
+}"), LanguageOptions::default()).unwrap();
+        let main = parse_file(fixture("\
+class Main {
+    function int f(Point p) {
+        var Point q;
+        let q = p;
+        return q.getY() + p.isOrigin() + p.twice() + p.scaled(2);
+    }
+}"), LanguageOptions::default()).unwrap();
+        let mut signatures = Signatures::with_os();
+        for class in [&point, &main] {
+            signatures.add_class(class, &format!("{}.jack", class.name.0));
+        }
+        let options = LanguageOptions { inline_accessors: true, ..LanguageOptions::default() };
+        let (vm, _) = compiled_with(&main, options, Some(&signatures));
+        assert_eq!(vm, "\
+function Main.f 1
+push argument 0
+pop local 0
+push local 0
+pop pointer 1
+push that 1
+push constant 0
+add
+push argument 0
+call Point.twice 1
+add
+push argument 0
+push constant 2
+call Point.scaled 2
+add
+return
+");
+        // the same as the calls, which read the field through THIS
+        let (plain, _) = compiled_with(&main, LanguageOptions::default(), Some(&signatures));
+        assert!(plain.contains("push local 0\ncall Point.getY 1\n") && plain.contains("push argument 0\ncall Point.isOrigin 1\n"));
+        let (methods, _) = compiled_with(&point, LanguageOptions::default(), Some(&signatures));
+        assert!(methods.contains("function Point.getY 0\npush argument 0\npop pointer 0\npush this 1\nreturn\n"));
+        // `getY()` on this is not a variable
+        let (methods, _) = compiled_with(&point, options, Some(&signatures));
+        assert!(methods.contains("push pointer 0\ncall Point.getY 1\n"));
+    }
+
+    // what a call of the compiled class returns, run by vm_eval
+    fn call(content: &str, options: LanguageOptions, function: &str, args: &[i16]) -> Result<i16, Stop> {
+        let class = parse_file(fixture(content), options).unwrap();
+        let compiled = VM::compile_with_symbols(&class, options, None, None, None).unwrap();
+        vm_eval::call(&compiled.commands, function, args).map(|outcome| outcome.value)
+    }
+
+    #[test]
+    fn locals_and_arguments_shadow_class_variables() {
+        let content = "\
+class A {
+    field int x;
+    static int y;
+    constructor A new() { let x = 1; let y = 2; return this; }
+    method int f(int x) { var int y; let y = 20; return x + y; }
+    method int g() { return x + y; }
+    function int h(int x) { return x + y; }
+    function int run() {
+        var A a;
+        let a = A.new();
+        return a.f(10) + a.g() + A.h(100);
+    }
+}";
+        // 30 from the shadowing names, 3 from the class's own and 102 from the function's argument
+        assert_eq!(call(content, LanguageOptions::default(), "A.run", &[]), Ok(135));
+    }
+
+    #[test]
+    fn tail_calls_jump_back_under_optimize() {
+        let content = "\
+class Main {
+    function int count(int n, int total) {
+        var int step;
+        let step = step + 2;
+        if (n = 0) {
+            return total;
+        }
+        return Main.count(n - 1, total + step);
+    }
+}";
+        let options = LanguageOptions { optimize: true, ..LanguageOptions::default() };
+        let plain = compile_vm(content, LanguageOptions::default());
+        let optimized = compile_vm(content, options);
+        assert!(plain.contains("call Main.count 2\nreturn\n"));
+        assert!(!optimized.contains("call Main.count"));
+        assert!(optimized.starts_with("function Main.count 1\nlabel MAIN_2\n"));
+        assert!(optimized.ends_with("\
+push argument 0
+push constant 1
+sub
+push argument 1
+push local 0
+add
+pop argument 1
+pop argument 0
+push constant 0
+pop local 0
+goto MAIN_2
+"));
+        assert_eq!(call(content, LanguageOptions::default(), "Main.count", &[10, 0]), Ok(20));
+        assert_eq!(call(content, options, "Main.count", &[10, 0]), Ok(20));
+        // a frame of seven words for each round does not fit the stack
+        assert_eq!(call(content, LanguageOptions::default(), "Main.count", &[1000, 0]), Err(Stop::StackOverflow));
+        assert_eq!(call(content, options, "Main.count", &[1000, 0]), Ok(2000));
+    }
+
+    #[test]
+    fn locals_never_used_are_dropped_under_optimize() {
+        let content = "\
+class Main {
+    function int f() {
+        var int unused, total;
+        var Array spare;
+        var boolean written;
+        let written = true;
+        let total = 3;
+        return total;
+    }
+}";
+        let options = LanguageOptions { optimize: true, ..LanguageOptions::default() };
+        let plain = compile_vm(content, LanguageOptions::default());
+        assert!(plain.starts_with("function Main.f 4\n"));
+        assert!(plain.contains("pop local 3\n") && plain.contains("pop local 1\n"));
+        // written is only written, which still takes its local
+        let class = parse_file(fixture(content), options).unwrap();
+        let compiled = VM::compile_with_symbols(&class, options, None, None, Some("Main.jack")).unwrap();
+        assert_eq!(compiled.vm, "\
+function Main.f 2
+push constant 0
+not
+pop local 1
+push constant 3
+pop local 0
+push local 0
+return
+");
+        assert_eq!(compiled.symbols, "class Main\n\nsubroutine Main.f\ntotal int local 0\nwritten boolean local 1\n");
+        // a record for each line, and one for the function
+        assert_eq!(compiled.source_map.unwrap().lines().count(), compiled.vm.lines().count() + 1);
+        assert_eq!(call(content, options, "Main.f", &[]), Ok(3));
+    }
+
+    #[test]
+    fn only_self_calls_in_return_position_are_tail_calls() {
+        let options = LanguageOptions { optimize: true, ..LanguageOptions::default() };
+        let method = compile_vm("\
+class List {
+    field int size;
+    method int sum(int n, int total) {
+        if (n = 0) {
+            return total;
+        }
+        return sum(n - 1, total + n);
+    }
+}", options);
+        assert!(method.contains("push argument 2\npush argument 1\nadd\npop argument 2\npop argument 1\ngoto LIST_2\n"));
+        assert!(method.starts_with("function List.sum 0\npush argument 0\npop pointer 0\nlabel LIST_2\n"));
+
+        for content in [
+            "class A { function int f(int n) { return A.g(n); } function int g(int n) { return n; } }",
+            "class A { function int f(int n) { return A.f(n) + 1; } }",
+            "class A { method int f(A other) { return other.f(this); } }",
+            "class A { function int f(int n) { var A a; return A.f(n, n); } }"
+        ] {
+            let mut vm = Vec::new();
+            if VM::compile(&parse_file(fixture(content), options).unwrap(), options, None, &mut vm).is_ok() {
+                assert!(!String::from_utf8(vm).unwrap().contains("goto"), "{}", content);
+            }
+        }
+    }
+
+    #[test]
+    fn loops_test_their_condition_at_the_end_under_optimize() {
+        let options = LanguageOptions { optimize: true, ..LanguageOptions::default() };
+        let content = "\
+class A {
+    function int f(int n) {
+        while (n > 0) {
+            let n = n - 2;
+        }
+        return n;
+    }
+}";
+        assert_eq!(compile_vm(content, options), "\
+function A.f 0
+goto A_1
+label A_0
+push argument 0
+push constant 2
+sub
+pop argument 0
+label A_1
+push argument 0
+push constant 0
+gt
+if-goto A_0
+push argument 0
+return
+");
+
+        let nested = "\
+class A {
+    function int f(int n) {
+        var int i, j, sum;
+        while (i < n) {
+            let j = 0;
+            while (j < i) {
+                let sum = sum + j;
+                let j = j + 1;
+            }
+            let i = i + 1;
+        }
+        return sum;
+    }
+}";
+        let rotated = compile_vm(nested, options);
+        assert_eq!(rotated.matches("goto").count(), 4);
+        for n in [0, 1, 2, 5, 10] {
+            assert_eq!(call(nested, options, "A.f", &[n]), call(nested, LanguageOptions::default(), "A.f", &[n]));
+        }
+        assert_eq!(call(nested, options, "A.f", &[10]), Ok(120));
+    }
+
+    #[test]
+    fn short_circuit_skips_the_right_operand() {
+        let options = LanguageOptions { short_circuit: true, ..LanguageOptions::default() };
+        let content = "\
+class A {
+    static int calls;
+    function boolean check(int n) {
+        let calls = calls + 1;
+        return n < 5;
+    }
+    function int f(int n) {
+        var boolean b;
+        let calls = 0;
+        let b = (n > 0) & A.check(n);
+        let b = (n > 0) | A.check(n);
+        return calls;
+    }
+    function boolean g(int n) {
+        return ((n > 1) & (n < 5)) | (n = 7);
+    }
+    function int h(int x) {
+        return x & 1;
+    }
+}";
+        let short = compile_vm(content, options);
+        assert_eq!(call(content, LanguageOptions::default(), "A.f", &[0]), Ok(2));
+        assert_eq!(call(content, LanguageOptions::default(), "A.f", &[3]), Ok(2));
+        assert_eq!(call(content, options, "A.f", &[0]), Ok(1));
+        assert_eq!(call(content, options, "A.f", &[3]), Ok(1));
+        for n in 0..10 {
+            assert_eq!(call(content, options, "A.g", &[n]), call(content, LanguageOptions::default(), "A.g", &[n]));
+        }
+        assert!(short.contains("\
+function A.g 0
+push argument 0
+push constant 1
+gt
+if-goto A_4
+push constant 0
+goto A_5
+label A_4
+push argument 0
+push constant 5
+lt
+label A_5
+if-goto A_6
+push argument 0
+push constant 7
+eq
+goto A_7
+label A_6
+push constant 1
+neg
+label A_7
+return
+"));
+        // ints stay bitwise
+        assert!(short.ends_with("function A.h 0\npush argument 0\npush constant 1\nand\nreturn\n"));
+    }
+
+    #[test]
+    fn bounds_check_stops_negative_indexes() {
+        let options = LanguageOptions { bounds_check: true, ..LanguageOptions::default() };
+        let content = "\
+class A {
+    function int get(Array a, int i) {
+        return a[i];
+    }
+    function void set(Array a, int i, int x) {
+        let a[i] = x;
+        return;
+    }
+    function int first(Array a) {
+        return a[0];
+    }
+}";
+        let plain = compile_vm(content, LanguageOptions::default());
+        let checked = compile_vm(content, options);
+        assert!(!plain.contains("Sys.error"));
+        assert!(checked.starts_with("\
+function A.get 0
+push argument 0
+push argument 1
+pop temp 1
+push temp 1
+push constant 0
+lt
+not
+if-goto A_0
+push constant 30
+call Sys.error 1
+pop temp 0
+label A_0
+push temp 1
+add
+pop pointer 1
+push that 0
+return
+"));
+        assert_eq!(checked.matches("call Sys.error 1").count(), 2);
+        assert!(checked.ends_with("function A.first 0\npush argument 0\npush constant 0\nadd\npop pointer 1\npush that 0\nreturn\n"));
+
+        assert_eq!(call(content, LanguageOptions::default(), "A.get", &[3000, -1]), Ok(0));
+        assert_eq!(call(content, options, "A.get", &[3000, -1]), Err(Stop::Error(INDEX_ERROR)));
+        assert_eq!(call(content, options, "A.get", &[3000, 2]), Ok(0));
+        assert_eq!(call(content, options, "A.set", &[3000, -5, 7]), Err(Stop::Error(INDEX_ERROR)));
+        assert_eq!(call(content, options, "A.set", &[3000, 5, 7]), Ok(0));
+        assert_eq!(call(content, LanguageOptions { optimize: true, ..options }, "A.get", &[3000, -1]), Err(Stop::Error(INDEX_ERROR)));
+    }
+
+    #[test]
+    fn trace_calls_prints_each_subroutine() {
+        let options = LanguageOptions { trace_calls: true, ..LanguageOptions::default() };
+        let content = "\
+class Ab {
+    field int x;
+    constructor Ab new() {
+        return this;
+    }
+    method void run() {
+        while (x < 3) {
+            let x = x + 1;
+        }
+        return;
+    }
+    function void main() {
+        var Ab ab;
+        let ab = Ab.new();
+        do ab.run();
+        return;
+    }
+}";
+        let plain = compile_vm(content, LanguageOptions::default());
+        let traced = compile_vm(content, options);
+        let trace = |name: &str| {
+            let mut text = format!("push constant {}\ncall String.new 1\n", name.len());
+            for char in name.chars() {
+                text.push_str(&format!("push constant {}\ncall String.appendChar 2\n", char as u8));
+            }
+            text + "\
+pop pointer 1
+push pointer 1
+call Output.printString 1
+pop temp 0
+push pointer 1
+call String.dispose 1
+pop temp 0
+call Output.println 0
+pop temp 0
+"
+        };
+        for (function, n_vars) in [("Ab.new", 0), ("Ab.run", 0), ("Ab.main", 1)] {
+            let prologue = format!("function {} {}\n{}", function, n_vars, trace(function));
+            assert!(traced.contains(&prologue), "{}", function);
+        }
+        // the rest is as without the flag, labels and all
+        let mut untraced = traced.clone();
+        for function in ["Ab.new", "Ab.run", "Ab.main"] {
+            untraced = untraced.replace(&trace(function), "");
+        }
+        assert_eq!(untraced, plain);
+
+        let os = compile_vm("class Output { function void println() { return; } }", options);
+        assert!(!os.contains("String.new"));
+    }
+
+    #[test]
+    fn null_check_stops_calls_on_null() {
+        let options = LanguageOptions { null_check: true, ..LanguageOptions::default() };
+        let content = "\
+class A {
+    field int x;
+    method int getX() {
+        return x;
+    }
+    function int f(A a) {
+        return a.getX();
+    }
+    function int g(Array a, int i) {
+        return a[i];
+    }
+}";
+        let plain = compile_vm(content, LanguageOptions::default());
+        let checked = compile_vm(content, options);
+        assert!(!plain.contains("Sys.error"));
+        assert!(checked.contains("\
+function A.f 0
+push argument 0
+if-goto A_0
+push constant 31
+call Sys.error 1
+pop temp 0
+label A_0
+push argument 0
+call A.getX 1
+return
+"));
+        assert!(call(content, LanguageOptions::default(), "A.f", &[0]).is_ok());
+        assert_eq!(call(content, options, "A.f", &[0]), Err(Stop::Error(NULL_ERROR)));
+        assert_eq!(call(content, options, "A.f", &[3000]), Ok(0));
+        assert_eq!(call(content, options, "A.g", &[0, 1]), Err(Stop::Error(NULL_ERROR)));
+
+        // the array is checked before its index
+        assert_eq!(call(content, LanguageOptions { bounds_check: true, ..options }, "A.g", &[0, -1]), Err(Stop::Error(NULL_ERROR)));
+        assert_eq!(call(content, LanguageOptions { bounds_check: true, ..options }, "A.g", &[3000, -1]), Err(Stop::Error(INDEX_ERROR)));
+        assert_eq!(call(content, LanguageOptions { bounds_check: true, ..options }, "A.g", &[3000, 1]), Ok(0));
+        assert_eq!(call(content, LanguageOptions { inline_accessors: true, ..options }, "A.f", &[0]), Err(Stop::Error(NULL_ERROR)));
+    }
+
+    #[test]
+    fn for_loops_are_while_loops() {
+        let options = LanguageOptions { for_loops: true, ..LanguageOptions::default() };
+        let for_loop = "\
+class A {
+    function int sum(int n) {
+        var int i, s;
+        for (let i = 0; i < n; let i = i + 1) {
+            let s = s + i;
+        }
+        return s;
+    }
+}";
+        let while_loop = "\
+class A {
+    function int sum(int n) {
+        var int i, s;
+        let i = 0;
+        while (i < n) {
+            let s = s + i;
+            let i = i + 1;
+        }
+        return s;
+    }
+}";
+        assert_eq!(compile_vm(for_loop, options), compile_vm(while_loop, LanguageOptions::default()));
+        assert_eq!(compile_xml(for_loop, options), compile_xml(while_loop, LanguageOptions::default()));
+        assert_eq!(call(for_loop, options, "A.sum", &[5]), Ok(10));
+        assert_eq!(call(for_loop, options, "A.sum", &[0]), Ok(0));
+        // without the extension `for` is a name
+        assert!(parse_file(fixture(for_loop), LanguageOptions::default()).is_err());
+    }
+
+    #[test]
+    fn nested_for_loops() {
+        let options = LanguageOptions { for_loops: true, ..LanguageOptions::default() };
+        let content = "\
+class A {
+    function int pairs(int n) {
+        var int i, j, count;
+        for (let i = 0; i < n; let i = i + 1) {
+            for (let j = 0; j < i; let j = j + 1) {
+                let count = count + 1;
+            }
+        }
+        return count;
+    }
+}";
+        assert_eq!(call(content, options, "A.pairs", &[5]), Ok(10));
+    }
+
+    #[test]
+    fn for_clauses_can_be_left_out() {
+        let options = LanguageOptions { for_loops: true, ..LanguageOptions::default() };
+        let content = "\
+class A {
+    function int count(int n) {
+        var int i;
+        for (; i < n;) {
+            let i = i + 1;
+        }
+        return i;
+    }
+    function int forever(int n) {
+        for (;;) {
+            let n = n - 1;
+            if (n < 0) {
+                return n;
+            }
+        }
+        return 1;
+    }
+}";
+        assert_eq!(call(content, options, "A.count", &[3]), Ok(3));
+        assert_eq!(call(content, options, "A.forever", &[3]), Ok(-1));
+
+        // the first error, the rest of the loop may give more
+        let error = |content: &str| reported_errors(parse_file(fixture(content), options).unwrap_err())[0].to_string();
+        assert_eq!(
+            error("class A { function void f() { var int i; for (let i = 0; i < 3 let i = i + 1) {} return; } }"),
+            "1:64: expected ';' after for condition, found 'let'"
+        );
+        assert_eq!(
+            error("class A { function void f() { var int i; for (i < 3;;) {} return; } }"),
+            "1:47: expected ';' or a let statement after for (, found 'i'"
+        );
+        assert_eq!(
+            error("class A { function void f() { var int i; for (;; let i = i + 1;) {} return; } }"),
+            "1:63: expected ')' after for increment, found ';'"
+        );
+    }
+
+    #[test]
+    fn break_and_continue_bind_to_the_innermost_loop() {
+        let options = LanguageOptions { break_continue: true, for_loops: true, ..LanguageOptions::default() };
+        let content = "\
+class A {
+    function int root(int n) {
+        var int i;
+        while (true) {
+            if (i * i > n) {
+                break;
+            }
+            let i = i + 1;
+        }
+        return i - 1;
+    }
+    function int odd(int n) {
+        var int i, sum;
+        for (let i = 0; i < n; let i = i + 1) {
+            if ((i & 1) = 0) {
+                continue;
+            }
+            let sum = sum + i;
+        }
+        return sum;
+    }
+    function int pairs(int n) {
+        var int i, j, count;
+        while (i < n) {
+            let i = i + 1;
+            let j = 0;
+            while (true) {
+                let j = j + 1;
+                if (j > 2) {
+                    break;
+                }
+                if (j = 1) {
+                    continue;
+                }
+                let count = count + 1;
+            }
+            if (i = 3) {
+                break;
+            }
+        }
+        return count * 10 + i;
+    }
+}";
+        // each way of compiling a loop
+        for options in [options, LanguageOptions { optimize: true, ..options }] {
+            assert_eq!(call(content, options, "A.root", &[50]), Ok(7));
+            assert_eq!(call(content, options, "A.odd", &[10]), Ok(25));
+            assert_eq!(call(content, options, "A.pairs", &[5]), Ok(33));
+        }
+        let xml = compile_xml("class A { function void f() { while (true) { break; } return; } }", options);
+        assert!(xml.contains("<breakStatement>\n              <keyword> break </keyword>\n              <symbol> ; </symbol>\n            </breakStatement>\n"));
+    }
+
+    #[test]
+    fn break_outside_of_a_loop() {
+        let options = LanguageOptions { break_continue: true, ..LanguageOptions::default() };
+        let content = "\
+class A {
+    function void f(int n) {
+        if (n > 0) {
+            break;
+        }
+        while (n > 0) {
+            let n = n - 1;
+        }
+        continue;
+    }
+}";
+        match VM::compile(&parse_file(fixture(content), options).unwrap(), options, None, &mut io::sink()) {
+            Err(CompileError::Semantic(errors)) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                assert_eq!(errors, vec!["4:13: break outside of a loop", "9:9: continue outside of a loop"]);
+            },
+            other => panic!("expected semantic errors, got {:?}", other)
+        }
+        // a break leaves a while (true) loop, so the function does not return on every path
+        let content = "class A { function int f() { while (true) { break; } } }";
+        let error = VM::compile(&parse_file(fixture(content), options).unwrap(), options, None, &mut io::sink()).unwrap_err();
+        assert!(error.to_string().contains("not every path through A.f returns a value"), "{}", error);
+    }
+
+    #[test]
+    fn field_and_static_initializers() {
+        let options = LanguageOptions { field_initializers: true, ..LanguageOptions::default() };
+        let content = "\
+class Counter {
+    field int count = 5;
+    field int step = 1 + 1;
+    static int made = 10;
+    static boolean ready = true;
+    field Array unset;
+
+    constructor Counter new() {
+        let made = made + 1;
+        return this;
+    }
+    constructor Counter starting(int n) {
+        let count = count + n;
+        return this;
+    }
+    method int next() {
+        let count = count + step;
+        return count;
+    }
+    function int counters() {
+        var Counter a, b;
+        do Counter.init_statics();
+        let a = Counter.new();
+        let b = Counter.starting(100);
+        do a.next();
+        return (a.next() * 1000) + b.next();
+    }
+    function int made() {
+        var Counter a;
+        do Counter.init_statics();
+        let a = Counter.new();
+        let a = Counter.new();
+        if (ready) {
+            return made;
+        }
+        return -1;
+    }
+}";
+        // every constructor sets the fields first
+        assert_eq!(call(content, options, "Counter.counters", &[]), Ok(9107));
+        assert_eq!(call(content, options, "Counter.made", &[]), Ok(12));
+        let vm = compile_vm(content, options);
+        assert!(vm.contains("\
+function Counter.starting 0
+push constant 3
+call Memory.alloc 1
+pop pointer 0
+push constant 5
+pop this 0
+push constant 1
+push constant 1
+add
+pop this 1
+push this 0
+"));
+        assert!(vm.ends_with("\
+function Counter.init_statics 0
+push constant 10
+pop static 0
+push constant 1
+neg
+pop static 1
+push constant 0
+return
+"));
+        // the declaration is kept in the XML
+        assert!(compile_xml(content, options).contains("<identifier> count </identifier>\n    <symbol> = </symbol>\n    <expression>\n"));
+        assert!(parse_file(fixture(content), LanguageOptions::default()).is_err());
+    }
+
+    #[test]
+    fn main_calls_the_static_initializers_of_the_project() {
+        let options = LanguageOptions { field_initializers: true, ..LanguageOptions::default() };
+        let main = parse_file(fixture("class Main { function void main() { do Output.printInt(Game.score()); return; } }"), options).unwrap();
+        let game = parse_file(fixture("class Game { static int score = 7; function int score() { return score; } }"), options).unwrap();
+        let mut signatures = Signatures::with_os();
+        signatures.add_class(&main, "Main.jack");
+        signatures.add_class(&game, "Game.jack");
+        let (vm, _) = compiled_with(&main, options, Some(&signatures));
+        assert!(vm.starts_with("function Main.main 0\ncall Game.init_statics 0\npop temp 0\ncall Game.score 0\n"));
+        // on its own Main does not know the other classes
+        let (vm, _) = compiled_with(&main, options, None);
+        assert!(vm.starts_with("function Main.main 0\ncall Game.score 0\n"));
+    }
+
+    #[test]
+    fn initializer_errors() {
+        let options = LanguageOptions { field_initializers: true, ..LanguageOptions::default() };
+        let errors = |content: &str| -> Vec<String> {
+            reported_errors(parse_file(fixture(content), options).unwrap_err()).iter().map(|error| error.to_string()).collect()
+        };
+        assert_eq!(errors("class A { field int x, y = 0; }"), vec!["1:26: an initializer needs a declaration of its own"]);
+        assert_eq!(
+            errors("class A { static int x = 0; function void init_statics() { return; } }"),
+            vec!["1:43: A.init_statics is made for the static initializers"]
+        );
+    }
+
+    #[test]
+    fn constants_are_compiled_to_their_values() {
+        let options = LanguageOptions { constants: true, ..LanguageOptions::default() };
+        let content = "class A {
+            const int MAX = 8;
+            const int MIN = -3;
+            const boolean DEBUG = false;
+            function int f(int x) {
+                if (DEBUG) { return 0; }
+                return (x * MAX) + MIN;
+            }
+            function int g() { var int MAX; let MAX = 2; return MAX; }
+        }";
+        assert_eq!(call(content, options, "A.f", &[2]), Ok(13));
+        // a local of the same name hides the constant
+        assert_eq!(call(content, options, "A.g", &[]), Ok(2));
+        let vm = compile_vm(content, options);
+        assert!(vm.starts_with("function A.f 0\npush constant 0\n"));
+        assert!(vm.contains("push argument 0\npush constant 8\ncall Math.multiply 2\npush constant 3\nneg\nadd\n"));
+        // the class has no storage for them
+        assert!(!vm.contains("static"));
+        assert!(compile_xml(content, options).contains("<keyword> const </keyword>\n    <keyword> int </keyword>\n    <identifier> MAX </identifier>\n    <symbol> = </symbol>\n"));
+        assert!(parse_file(fixture(content), LanguageOptions::default()).is_err());
+    }
+
+    #[test]
+    fn constants_of_other_classes() {
+        let options = LanguageOptions { constants: true, ..LanguageOptions::default() };
+        let main = parse_file(fixture("class Main { function int main() { return Ball.MAX_BALLS + Ball.count(); } }"), options).unwrap();
+        let ball = parse_file(fixture("class Ball { const int MAX_BALLS = 8; function int count() { return MAX_BALLS; } }"), options).unwrap();
+        let mut signatures = Signatures::with_os();
+        signatures.add_class(&main, "Main.jack");
+        signatures.add_class(&ball, "Ball.jack");
+        let (vm, _) = compiled_with(&main, options, Some(&signatures));
+        assert_eq!(vm, "function Main.main 0\npush constant 8\ncall Ball.count 0\nadd\nreturn\n");
+        // on its own Main does not know the value
+        match VM::compile(&main, options, None, &mut io::sink()) {
+            Err(CompileError::Semantic(errors)) => assert_eq!(
+                errors[0].to_string(), "1:43: the value of Ball.MAX_BALLS is only known when the whole project is compiled"
+            ),
+            other => panic!("{:?}", other)
+        }
+    }
+
+    #[test]
+    fn constant_errors() {
+        let options = LanguageOptions { constants: true, ..LanguageOptions::default() };
+        let semantic = |content: &str| -> Vec<String> {
+            match VM::compile(&parse_file(fixture(content), options).unwrap(), options, None, &mut io::sink()) {
+                Err(CompileError::Semantic(errors)) => errors.iter().map(|e| e.to_string()).collect(),
+                other => panic!("expected semantic errors, got {:?}", other)
+            }
+        };
+        assert_eq!(
+            semantic("class A { const int N = 1; function void f() { let N = 2; return; } }"),
+            vec!["1:52: N is a constant of A, which cannot be assigned or indexed"]
+        );
+        assert_eq!(
+            semantic("class A { const int N = 1; function int f() { return N[0]; } }"),
+            vec!["1:54: N is a constant of A, which cannot be assigned or indexed"]
+        );
+        assert_eq!(
+            semantic("class A { function int f() { return Math.PI; } }"),
+            vec!["1:37: undefined constant Math.PI"]
+        );
+        let syntax = |content: &str| -> Vec<String> {
+            reported_errors(parse_file(fixture(content), options).unwrap_err()).iter().map(|error| error.to_string()).collect()
+        };
+        assert_eq!(syntax("class A { const int N = 1 + 1; }"), vec!["1:25: the value of a constant has to be a number, true, false or null"]);
+        assert_eq!(syntax("class A { const int N; }")[0], "1:22: expected '=' and the value of the constant, found ';'");
+    }
+}
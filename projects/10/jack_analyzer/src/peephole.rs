@@ -0,0 +1,420 @@
+use crate::vm::{Operator, Segment, VmCommand};
+
+// `-O` on the generated code: commands that undo each other or do nothing are taken out, and
+// multiplications by constants become additions, until nothing changes. Only neighbours are
+// looked at, and a label is never removed, so nothing moves across a jump target.
+pub fn optimize(mut commands: Vec<VmCommand>) -> Vec<VmCommand> {
+    loop {
+        let optimized = pass(&commands);
+        if optimized == commands {
+            return optimized;
+        }
+        commands = optimized;
+    }
+}
+
+// the most commands an add chain for a factor that is not a power of two may take,
+// `call Math.multiply 2` loops over all 16 bits
+const MAX_CHAIN: usize = 32;
+
+fn pass(commands: &[VmCommand]) -> Vec<VmCommand> {
+    let mut optimized: Vec<VmCommand> = Vec::with_capacity(commands.len());
+    for command in commands.iter() {
+        match (optimized.last(), command) {
+            // `let x = x;`
+            (Some(VmCommand::Push(pushed, i)), VmCommand::Pop(popped, j)) if pushed == popped && i == j => {
+                optimized.pop();
+            },
+            (Some(VmCommand::Arithmetic(Operator::Not)), VmCommand::Arithmetic(Operator::Not)) => {
+                optimized.pop();
+            },
+            // x + 0 and x - 0
+            (Some(VmCommand::Push(Segment::Constant, 0)), VmCommand::Arithmetic(Operator::Add | Operator::Sub)) => {
+                optimized.pop();
+            },
+            // true, as `not` then cancels out against a following `not`
+            (Some(VmCommand::Push(Segment::Constant, 1)), VmCommand::Arithmetic(Operator::Neg)) => {
+                optimized.pop();
+                optimized.push(VmCommand::Push(Segment::Constant, 0));
+                optimized.push(VmCommand::Arithmetic(Operator::Not));
+            },
+            // a jump to the next command
+            (Some(VmCommand::GoTo(target)), VmCommand::Label(label)) if target == label => {
+                optimized.pop();
+                optimized.push(command.clone());
+            },
+            (Some(VmCommand::Push(Segment::Constant, factor)), VmCommand::Call(name, 2)) if name == "Math.multiply" => {
+                match multiply_by(*factor) {
+                    Some(chain) => {
+                        optimized.pop();
+                        optimized.extend(chain);
+                    },
+                    None => optimized.push(command.clone())
+                }
+            },
+            // x / 1
+            (Some(VmCommand::Push(Segment::Constant, 1)), VmCommand::Call(name, 2)) if name == "Math.divide" => {
+                optimized.pop();
+            },
+            // x / -1, which is `push constant 0 / not` by now
+            (Some(VmCommand::Arithmetic(Operator::Not)), VmCommand::Call(name, 2))
+                if name == "Math.divide" && optimized.get(optimized.len().wrapping_sub(2)) == Some(&VmCommand::Push(Segment::Constant, 0)) => {
+                optimized.truncate(optimized.len() - 2);
+                optimized.push(VmCommand::Arithmetic(Operator::Neg));
+            },
+            _ => optimized.push(command.clone())
+        }
+    }
+    optimized
+}
+
+// `x * factor` as additions, which wrap around at 16 bits just like Math.multiply.
+// The value is doubled for each bit of the factor below the highest, and x is added back
+// for each set bit, x is kept in temp 1 and the value being doubled goes through temp 2.
+// Nothing else runs in between, so the compiler's own use of temp 0 is not in the way.
+// A power of two is only doublings, at most 14 of them, and is never left to the call.
+fn multiply_by(factor: i16) -> Option<Vec<VmCommand>> {
+    if factor == 1 {
+        return Some(Vec::new());
+    }
+    if factor < 2 {
+        return None;
+    }
+    let highest = 15 - factor.leading_zeros();
+    let mut chain = Vec::new();
+    if factor.count_ones() > 1 {
+        chain.extend([VmCommand::Pop(Segment::Temp, 1), VmCommand::Push(Segment::Temp, 1)]);
+    }
+    for bit in (0..highest).rev() {
+        chain.extend([
+            VmCommand::Pop(Segment::Temp, 2),
+            VmCommand::Push(Segment::Temp, 2),
+            VmCommand::Push(Segment::Temp, 2),
+            VmCommand::Arithmetic(Operator::Add)
+        ]);
+        if factor >> bit & 1 == 1 {
+            chain.extend([VmCommand::Push(Segment::Temp, 1), VmCommand::Arithmetic(Operator::Add)]);
+        }
+    }
+    (factor.count_ones() == 1 || chain.len() <= MAX_CHAIN).then_some(chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+    use crate::vm::to_text;
+    use crate::LanguageOptions;
+
+    fn parse(text: &str) -> Vec<VmCommand> {
+        text.lines().map(|line| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let segment = |name: &str| match name {
+                "argument" => Segment::Argument,
+                "local" => Segment::Local,
+                "static" => Segment::Static,
+                "this" => Segment::This,
+                "that" => Segment::That,
+                "constant" => Segment::Constant,
+                "pointer" => Segment::Pointer,
+                _ => Segment::Temp
+            };
+            match words[..] {
+                ["push", name, index] => VmCommand::Push(segment(name), index.parse().unwrap()),
+                ["pop", name, index] => VmCommand::Pop(segment(name), index.parse().unwrap()),
+                ["label", label] => VmCommand::Label(label.to_string()),
+                ["goto", label] => VmCommand::GoTo(label.to_string()),
+                ["if-goto", label] => VmCommand::IfGoTo(label.to_string()),
+                ["function", name, n_vars] => VmCommand::Function(name.to_string(), n_vars.parse().unwrap()),
+                ["call", name, n_args] => VmCommand::Call(name.to_string(), n_args.parse().unwrap()),
+                ["return"] => VmCommand::Return,
+                [name] => VmCommand::Arithmetic(
+                    [Operator::Add, Operator::Sub, Operator::Neg, Operator::Eq, Operator::Gt, Operator::Lt, Operator::And, Operator::Or, Operator::Not]
+                        .into_iter()
+                        .find(|operator| operator.to_string() == name)
+                        .unwrap()
+                ),
+                _ => panic!("{} is not a VM command", line)
+            }
+        }).collect()
+    }
+
+    fn optimized(text: &str) -> String {
+        to_text(&optimize(parse(text)))
+    }
+
+    // the VM code of a class, compiled into memory
+    fn compiled(class: &crate::ast::Class, options: LanguageOptions) -> String {
+        let mut vm = Vec::new();
+        crate::parser::VM::compile(class, options, None, &mut vm).unwrap();
+        String::from_utf8(vm).unwrap()
+    }
+
+    #[test]
+    fn push_then_pop_of_the_same_place() {
+        assert_eq!(optimized("push local 0\npop local 0\nreturn"), "return\n");
+        assert_eq!(optimized("push local 0\npop local 1\n"), "push local 0\npop local 1\n");
+        assert_eq!(optimized("push local 0\npop argument 0\n"), "push local 0\npop argument 0\n");
+    }
+
+    #[test]
+    fn double_not() {
+        assert_eq!(optimized("push local 0\nnot\nnot\nif-goto L"), "push local 0\nif-goto L\n");
+        assert_eq!(optimized("push local 0\nnot\nnot\nnot"), "push local 0\nnot\n");
+    }
+
+    #[test]
+    fn adding_zero() {
+        assert_eq!(optimized("push local 0\npush constant 0\nadd\npush constant 0\nsub"), "push local 0\n");
+        assert_eq!(optimized("push constant 0\npush local 0\nsub"), "push constant 0\npush local 0\nsub\n");
+    }
+
+    #[test]
+    fn true_is_not_zero() {
+        assert_eq!(optimized("push constant 1\nneg\npop local 0"), "push constant 0\nnot\npop local 0\n");
+        // `~true`
+        assert_eq!(optimized("push constant 1\nneg\nnot\npop local 0"), "push constant 0\npop local 0\n");
+    }
+
+    #[test]
+    fn jump_to_the_next_label() {
+        assert_eq!(optimized("goto L\nlabel L\nreturn"), "label L\nreturn\n");
+        assert_eq!(optimized("goto L\nlabel M\nlabel L"), "goto L\nlabel M\nlabel L\n");
+    }
+
+    #[test]
+    fn patterns_do_not_reach_across_labels() {
+        assert_eq!(optimized("not\nlabel L\nnot"), "not\nlabel L\nnot\n");
+        assert_eq!(optimized("push local 0\nlabel L\npop local 0"), "push local 0\nlabel L\npop local 0\n");
+    }
+
+    // runs straight-line code on x in local 0, the OS calls as Jack computes them
+    fn evaluate(commands: &[VmCommand], x: i16) -> i16 {
+        let mut stack = Vec::new();
+        let mut temp = [0; 8];
+        for command in commands.iter() {
+            match command {
+                VmCommand::Push(Segment::Constant, value) => stack.push(*value),
+                VmCommand::Push(Segment::Local, 0) => stack.push(x),
+                VmCommand::Push(Segment::Temp, index) => stack.push(temp[*index as usize]),
+                VmCommand::Pop(Segment::Temp, index) => temp[*index as usize] = stack.pop().unwrap(),
+                VmCommand::Arithmetic(Operator::Neg) => {
+                    let value = stack.pop().unwrap();
+                    stack.push(value.wrapping_neg());
+                },
+                VmCommand::Arithmetic(Operator::Not) => {
+                    let value = stack.pop().unwrap();
+                    stack.push(!value);
+                },
+                command => {
+                    let (b, a) = (stack.pop().unwrap(), stack.pop().unwrap());
+                    stack.push(match command {
+                        VmCommand::Arithmetic(Operator::Add) => a.wrapping_add(b),
+                        VmCommand::Arithmetic(Operator::Sub) => a.wrapping_sub(b),
+                        VmCommand::Call(name, 2) if name == "Math.multiply" => a.wrapping_mul(b),
+                        VmCommand::Call(name, 2) if name == "Math.divide" => a.wrapping_div(b),
+                        command => panic!("{} is not in these tests", command)
+                    });
+                }
+            }
+        }
+        assert_eq!(stack.len(), 1);
+        stack[0]
+    }
+
+    #[test]
+    fn multiplying_by_constants() {
+        assert_eq!(optimized("push local 0\npush constant 8\ncall Math.multiply 2"), "\
+push local 0
+pop temp 2
+push temp 2
+push temp 2
+add
+pop temp 2
+push temp 2
+push temp 2
+add
+pop temp 2
+push temp 2
+push temp 2
+add
+");
+        assert_eq!(optimized("push local 0\npush constant 5\ncall Math.multiply 2"), "\
+push local 0
+pop temp 1
+push temp 1
+pop temp 2
+push temp 2
+push temp 2
+add
+pop temp 2
+push temp 2
+push temp 2
+add
+push temp 1
+add
+");
+        assert_eq!(optimized("push local 0\npush constant 1\ncall Math.multiply 2"), "push local 0\n");
+        // ten doublings, longer than any other chain may be
+        let doubling = "pop temp 2\npush temp 2\npush temp 2\nadd\n";
+        assert_eq!(optimized("push local 0\npush constant 1024\ncall Math.multiply 2"), format!("push local 0\n{}", doubling.repeat(10)));
+        // too long as additions
+        assert_eq!(optimized("push local 0\npush constant 1025\ncall Math.multiply 2"), "push local 0\npush constant 1025\ncall Math.multiply 2\n");
+        assert_eq!(optimized("push local 0\npush constant 32767\ncall Math.multiply 2"), "push local 0\npush constant 32767\ncall Math.multiply 2\n");
+        assert_eq!(optimized("push local 0\npush constant 0\ncall Math.multiply 2"), "push local 0\npush constant 0\ncall Math.multiply 2\n");
+
+        for factor in [2, 3, 4, 5, 7, 8, 10, 15, 16, 31, 64, 100, 255, 256, 1024, 16384] {
+            let plain = parse(&format!("push local 0\npush constant {}\ncall Math.multiply 2", factor));
+            let optimized = optimize(plain.clone());
+            for x in [0, 1, -1, 2, 7, -9, 300, 1234, -4321, i16::MAX, i16::MIN] {
+                assert_eq!(evaluate(&optimized, x), evaluate(&plain, x), "{} * {}", x, factor);
+            }
+        }
+    }
+
+    #[test]
+    fn dividing_by_one() {
+        assert_eq!(optimized("push local 0\npush constant 1\ncall Math.divide 2"), "push local 0\n");
+        assert_eq!(optimized("push local 0\npush constant 1\nneg\ncall Math.divide 2"), "push local 0\nneg\n");
+        assert_eq!(optimized("push local 0\npush constant 2\ncall Math.divide 2"), "push local 0\npush constant 2\ncall Math.divide 2\n");
+        for divisor in ["push constant 1", "push constant 1\nneg"] {
+            let plain = parse(&format!("push local 0\n{}\ncall Math.divide 2", divisor));
+            for x in [0, 1, -1, 7, -9, i16::MAX, i16::MIN] {
+                assert_eq!(evaluate(&optimize(plain.clone()), x), evaluate(&plain, x), "{} / {}", x, divisor);
+            }
+        }
+    }
+
+    #[test]
+    fn multiplications_in_jack_are_optimized_only_with_the_flag() {
+        let options = LanguageOptions { optimize: true, ..LanguageOptions::default() };
+        let vm = |source: &str, options| {
+            let class = crate::parse_str(source).unwrap();
+            compiled(&class, options)
+        };
+        for source in ["class A { function int f(int x) { return x * 4; } }", "class A { function int f(int x) { return 4 * x; } }"] {
+            assert!(vm(source, LanguageOptions::default()).contains("call Math.multiply 2"));
+            assert_eq!(vm(source, options), "\
+function A.f 0
+push argument 0
+pop temp 2
+push temp 2
+push temp 2
+add
+pop temp 2
+push temp 2
+push temp 2
+add
+return
+");
+        }
+        // only the first operation can have a constant on its left, `x + 4 * y` is `(x + 4) * y`
+        assert!(vm("class A { function int f(int x, int y) { return x + 4 * y; } }", options).contains("call Math.multiply 2"));
+        let plain = vm("class A { function int f(int x) { return x * 1024; } }", LanguageOptions::default());
+        let optimized = vm("class A { function int f(int x) { return x * 1024; } }", options);
+        assert!(plain.contains("push constant 1024\ncall Math.multiply 2"));
+        assert!(!optimized.contains("call Math.multiply 2"));
+
+        // both orders compute what the call does, the function's x as local 0 of `evaluate`
+        let body = |source: &str, options| {
+            let vm = vm(source, options).replace("push argument 0", "push local 0");
+            let lines: Vec<&str> = vm.lines().collect();
+            parse(&lines[1..lines.len() - 1].join("\n"))
+        };
+        let plain = body("class A { function int f(int x) { return x * 3; } }", LanguageOptions::default());
+        for source in ["class A { function int f(int x) { return x * 3; } }", "class A { function int f(int x) { return 3 * x; } }"] {
+            let optimized = body(source, options);
+            assert!(!optimized.contains(&VmCommand::Call("Math.multiply".to_string(), 2)), "{}", source);
+            for x in [0, 1, -1, 7, -9, 10923, -10923, i16::MAX, i16::MIN] {
+                assert_eq!(evaluate(&optimized, x), evaluate(&plain, x), "{}, x = {}", source, x);
+            }
+        }
+    }
+
+    // Follows the stack depth through every path of each function: it has to be the same
+    // wherever the paths meet at a label, never negative, and one value at each return.
+    fn check_stack(commands: &[VmCommand]) {
+        let mut depth = Some(0);
+        let mut at_labels: HashMap<String, i32> = HashMap::new();
+        for command in commands.iter() {
+            if let VmCommand::Function(..) = command {
+                depth = Some(0);
+                at_labels.clear();
+                continue;
+            }
+            if let VmCommand::Label(label) = command {
+                match (depth, at_labels.get(label.as_str())) {
+                    (Some(depth), Some(expected)) => assert_eq!(depth, *expected, "stack depth at {}", label),
+                    (None, expected) => depth = expected.copied(),
+                    (Some(depth), None) => {
+                        at_labels.insert(label.clone(), depth);
+                    }
+                }
+                continue;
+            }
+            let Some(current) = depth else { continue };
+            depth = match command {
+                VmCommand::Push(..) => Some(current + 1),
+                VmCommand::Pop(..) => Some(current - 1),
+                VmCommand::Arithmetic(Operator::Neg | Operator::Not) | VmCommand::Comment(_) | VmCommand::Origin(_) => Some(current),
+                VmCommand::Arithmetic(_) => Some(current - 1),
+                VmCommand::Call(_, n_args) => Some(current - *n_args as i32 + 1),
+                VmCommand::GoTo(label) => {
+                    jump_to(label, current, &mut at_labels);
+                    None
+                },
+                VmCommand::IfGoTo(label) => {
+                    jump_to(label, current - 1, &mut at_labels);
+                    Some(current - 1)
+                },
+                VmCommand::Return => {
+                    assert_eq!(current, 1, "stack depth at return");
+                    None
+                },
+                VmCommand::Label(_) | VmCommand::Function(..) => unreachable!()
+            };
+            assert!(depth.unwrap_or_default() >= 0);
+        }
+    }
+
+    // the commands of each function by its name, the `function` command first
+    fn functions(commands: &[VmCommand]) -> HashMap<String, &[VmCommand]> {
+        let starts: Vec<usize> = (0..commands.len()).filter(|&i| matches!(commands[i], VmCommand::Function(..))).collect();
+        starts.iter().enumerate().map(|(n, &start)| {
+            let end = starts.get(n + 1).copied().unwrap_or(commands.len());
+            let VmCommand::Function(name, _) = &commands[start] else { unreachable!() };
+            (name.clone(), &commands[start..end])
+        }).collect()
+    }
+
+    fn jump_to(label: &str, depth: i32, at_labels: &mut HashMap<String, i32>) {
+        assert_eq!(*at_labels.entry(label.to_string()).or_insert(depth), depth, "stack depth at {}", label);
+    }
+
+    #[test]
+    fn optimized_pong_keeps_the_stack_balanced() {
+        let sources = [
+            include_str!("../../../11/Pong/Main.jack"),
+            include_str!("../../../11/Pong/PongGame.jack"),
+            include_str!("../../../11/Pong/Ball.jack"),
+            include_str!("../../../11/Pong/Bat.jack")
+        ];
+        let options = LanguageOptions { optimize: true, ..LanguageOptions::default() };
+        let mut changed = false;
+        for source in sources {
+            let class = crate::parse_str(source).unwrap();
+            let plain = parse(&compiled(&class, LanguageOptions::default()));
+            check_stack(&plain);
+            let optimized = parse(&compiled(&class, options));
+            check_stack(&optimized);
+            // longer only by the add chains, each at most 14 doublings for the constant and the call
+            let optimized_functions = functions(&optimized);
+            for (name, plain) in functions(&plain) {
+                let multiplications = plain.iter().filter(|command| matches!(command, VmCommand::Call(name, 2) if name == "Math.multiply")).count();
+                assert!(optimized_functions[&name].len() <= plain.len() + multiplications * (14 * 4 - 2), "{}", name);
+            }
+            changed |= optimized != plain;
+        }
+        assert!(changed);
+    }
+}
@@ -0,0 +1,485 @@
+use std::io::{self, IsTerminal};
+use crate::diagnostics::{Lint, Warning};
+use crate::error::{CompileError, Related, SemanticError, SyntaxError};
+use crate::tokenizer::Span;
+
+// `--color=always`, `--color=never` or `--color=auto`, which colors when stderr is a terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    Always,
+    Never,
+    #[default]
+    Auto
+}
+
+impl Color {
+    pub fn new(name: &str) -> Option<Self> {
+        match name {
+            "always" => Some(Color::Always),
+            "never" => Some(Color::Never),
+            "auto" => Some(Color::Auto),
+            _ => None
+        }
+    }
+
+    // the tests compare plain text wherever they run
+    fn enabled(&self) -> bool {
+        match self {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => !cfg!(test) && io::stderr().is_terminal()
+        }
+    }
+}
+
+// How much the compiler tells on stderr besides the errors: `--quiet` nothing else, by default the
+// summary of the files, and `--verbose` also a line for each file as it goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose
+}
+
+// `--message-format=human` or `--message-format=json`, one JSON object per diagnostic on stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageFormat {
+    #[default]
+    Human,
+    Json
+}
+
+impl MessageFormat {
+    pub fn new(name: &str) -> Option<Self> {
+        match name {
+            "human" => Some(MessageFormat::Human),
+            "json" => Some(MessageFormat::Json),
+            _ => None
+        }
+    }
+}
+
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+// An error or a warning, where it is and what else it points at
+struct Diagnostic<'e> {
+    warning: bool,
+    // `syntax` and `semantic` for errors, the name `-W` takes for warnings
+    code: &'e str,
+    message: String,
+    // none for what is about the whole file
+    span: Option<Span>,
+    note: Option<&'e str>,
+    related: Option<&'e Related>
+}
+
+impl<'e> Diagnostic<'e> {
+    fn syntax(error: &'e SyntaxError) -> Self {
+        let message = match &error.message {
+            Some(message) => message.clone(),
+            None => format!("expected {}, found {}", error.expected, error.found)
+        };
+        Diagnostic { warning: false, code: "syntax", message, span: Some(error.span), note: None, related: error.related.as_deref() }
+    }
+
+    fn semantic(error: &'e SemanticError, code: &'e str, warning: bool) -> Self {
+        Diagnostic {
+            warning, code, message: error.message.clone(), span: Some(error.span),
+            note: error.note.as_deref(), related: error.related.as_deref()
+        }
+    }
+
+    fn file(code: &'e str, message: &str, warning: bool) -> Self {
+        Diagnostic { warning, code, message: message.to_string(), span: None, note: None, related: None }
+    }
+}
+
+// The diagnostics of one file with the lines they point at:
+//
+// Foo.jack:5:13: indexing n, which is declared int, not Array [-W index-non-array]
+//   |
+// 5 |         let n[1] = 1;
+//   |             ^
+//   note: n is declared at 3:25
+//   |
+// 3 |     function void f(int n) {
+//   |                         - n is declared here
+//
+// or with `--message-format=json` one object per line, positions are 1-based and the end is just past the token,
+// the fields stay as they are:
+//
+// {"severity":"warning","code":"index-non-array","message":"indexing n, which is declared int, not Array",
+//  "file":"Foo.jack","start":{"line":5,"column":13},"end":{"line":5,"column":14},
+//  "notes":["n is declared at 3:25"],
+//  "related":[{"message":"n is declared here","file":"Foo.jack","start":{"line":3,"column":25},"end":{"line":3,"column":26}}]}
+//
+// severity is error or warning, start and end are null for what is about the whole file
+pub struct Renderer<'a> {
+    filename: &'a str,
+    lines: Vec<&'a str>,
+    color: bool,
+    format: MessageFormat
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(filename: &'a str, source: &'a str, color: Color, format: MessageFormat) -> Self {
+        let lines = source.lines().map(|line| line.trim_end_matches('\r')).collect();
+        Renderer { filename, lines, color: color.enabled() && format == MessageFormat::Human, format }
+    }
+
+    pub fn filename(&self) -> &str {
+        self.filename
+    }
+
+    pub fn lines(&self) -> &[&'a str] {
+        &self.lines
+    }
+
+    // The errors of a file that could not be compiled, in the order they were found.
+    // As JSON they are printed and only their count is left.
+    pub fn compile_error(&self, error: &CompileError) -> String {
+        let json = self.format == MessageFormat::Json;
+        let diagnostics = match error {
+            CompileError::Syntax(errors) => errors.iter().map(Diagnostic::syntax).collect(),
+            CompileError::Semantic(errors) => errors.iter().map(|error| Diagnostic::semantic(error, "semantic", false)).collect(),
+            CompileError::NoClass if !json => return format!("no class declaration found in {}", self.filename),
+            CompileError::NoClass => vec![Diagnostic::file("no-class", "no class declaration found", false)],
+            CompileError::Io(e) if json => vec![Diagnostic::file("io", &e.to_string(), false)],
+            error if json => vec![Diagnostic::file("class-name", &error.to_string(), false)],
+            error => return error.to_string()
+        };
+        if json {
+            for diagnostic in diagnostics.iter() {
+                println!("{}", self.json(diagnostic));
+            }
+            let count = diagnostics.len();
+            return format!("{}: {} error{}", self.filename, count, if count == 1 { "" } else { "s" });
+        }
+        let rendered: Vec<String> = diagnostics.iter().map(|diagnostic| self.human(diagnostic)).collect();
+        rendered.join("\n")
+    }
+
+    // Printed, or the error that fails the file when they are denied
+    pub fn warnings(&self, warnings: &[Warning], denied: bool) -> Result<(), String> {
+        let diagnostics = warnings.iter()
+            .map(|warning| Diagnostic::semantic(&warning.error, warning.lint.name(), true))
+            .collect();
+        self.report_warnings(diagnostics, denied)
+    }
+
+    // a warning about the file as a whole, `Foo.jack: class Bar should be declared in Bar.jack`
+    pub fn file_warning(&self, lint: Lint, message: &str, denied: bool) -> Result<(), String> {
+        self.report_warnings(vec![Diagnostic::file(lint.name(), message, true)], denied)
+    }
+
+    fn report_warnings(&self, diagnostics: Vec<Diagnostic>, denied: bool) -> Result<(), String> {
+        if diagnostics.is_empty() {
+            return Ok(());
+        }
+        if self.format == MessageFormat::Json {
+            for diagnostic in diagnostics.iter() {
+                println!("{}", self.json(diagnostic));
+            }
+            if denied {
+                let count = diagnostics.len();
+                return Err(format!("{}: {} warning{} denied", self.filename, count, if count == 1 { "" } else { "s" }));
+            }
+            return Ok(());
+        }
+        let rendered: Vec<String> = diagnostics.iter().map(|diagnostic| self.human(diagnostic)).collect();
+        if denied {
+            return Err(rendered.join("\n"));
+        }
+        for warning in rendered {
+            eprintln!("{} {}", self.paint(YELLOW, "warning:"), warning);
+        }
+        Ok(())
+    }
+
+    fn human(&self, diagnostic: &Diagnostic) -> String {
+        let mut header = match diagnostic.span {
+            Some(span) => format!("{}:{}: {}", self.filename, span, diagnostic.message),
+            None => format!("{}: {}", self.filename, diagnostic.message)
+        };
+        if diagnostic.warning {
+            header.push_str(&format!(" [-W {}]", diagnostic.code));
+        }
+        let mut lines = vec![self.paint(BOLD, &header)];
+        if let Some(span) = diagnostic.span {
+            lines.extend(self.snippet(span, '^', None, if diagnostic.warning { YELLOW } else { RED }));
+        }
+        if let Some(note) = diagnostic.note {
+            lines.push(format!("  {} {}", self.paint(BOLD, "note:"), note));
+        }
+        if let Some(related) = diagnostic.related {
+            lines.extend(self.snippet(related.span, '-', Some(&related.label), BLUE));
+        }
+        lines.join("\n")
+    }
+
+    fn json(&self, diagnostic: &Diagnostic) -> String {
+        let notes: Vec<String> = diagnostic.note.iter().map(|note| json_string(note)).collect();
+        let related: Vec<String> = diagnostic.related.iter()
+            .map(|related| format!(
+                "{{\"message\":{},\"file\":{},{}}}",
+                json_string(&related.label), json_string(self.filename), self.json_range(Some(related.span))
+            ))
+            .collect();
+        format!(
+            "{{\"severity\":\"{}\",\"code\":{},\"message\":{},\"file\":{},{},\"notes\":[{}],\"related\":[{}]}}",
+            if diagnostic.warning { "warning" } else { "error" }, json_string(diagnostic.code),
+            json_string(&diagnostic.message), json_string(self.filename), self.json_range(diagnostic.span),
+            notes.join(","), related.join(",")
+        )
+    }
+
+    // `"start":{...},"end":{...}`, the end is the column after the token
+    fn json_range(&self, span: Option<Span>) -> String {
+        let Some(span) = span else { return "\"start\":null,\"end\":null".to_string() };
+        let width = span.line.checked_sub(1).and_then(|index| self.lines.get(index))
+            .map_or(0, |line| token_width(line, span.column));
+        format!(
+            "\"start\":{{\"line\":{},\"column\":{}}},\"end\":{{\"line\":{},\"column\":{}}}",
+            span.line, span.column, span.line, span.column + width
+        )
+    }
+
+    // the line of the span with a marker under its token, nothing for a position past the end of the file
+    fn snippet(&self, span: Span, marker: char, label: Option<&str>, style: &str) -> Vec<String> {
+        let Some(line) = span.line.checked_sub(1).and_then(|index| self.lines.get(index)) else { return Vec::new() };
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let before: String = line.chars().take(span.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let mut marked = marker.to_string().repeat(token_width(line, span.column));
+        if let Some(label) = label {
+            marked = format!("{} {}", marked, label);
+        }
+        vec![
+            format!("{} {}", gutter, self.paint(BLUE, "|")),
+            format!("{} {}", self.paint(BLUE, &number), self.paint(BLUE, "|")) + &format!(" {}", line),
+            format!("{} {} {}{}", gutter, self.paint(BLUE, "|"), before, self.paint(style, &marked))
+        ]
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+// the characters of the token at the 1-based column: a word, a string constant, or a single symbol
+fn token_width(line: &str, column: usize) -> usize {
+    let rest: Vec<char> = line.chars().skip(column.saturating_sub(1)).collect();
+    let word = |c: &&char| c.is_ascii_alphanumeric() || **c == '_';
+    match rest.first() {
+        Some('"') => rest[1..].iter().position(|c| *c == '"').map_or(rest.len(), |end| end + 2),
+        Some(c) if word(&c) => rest.iter().take_while(word).count(),
+        _ => 1
+    }
+}
+
+pub(crate) fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+class Foo {
+    function void f(int n) {
+        var int n;
+        let n[1] = \"abc\";
+        return
+    }
+}";
+
+    #[test]
+    fn syntax_errors_point_at_their_token() {
+        let error = crate::compile_to_vm(SOURCE, None).unwrap_err();
+        assert_eq!(Renderer::new("Foo.jack", SOURCE, Color::Never, MessageFormat::Human).compile_error(&error), "\
+Foo.jack:3:17: n is already declared at 2:25
+  |
+3 |         var int n;
+  |                 ^
+  |
+2 |     function void f(int n) {
+  |                         - n is first declared here
+Foo.jack:6:5: expected an expression, found '}'
+  |
+6 |     }
+  |     ^");
+    }
+
+    #[test]
+    fn warnings_with_notes() {
+        let source = SOURCE.replace("var int n;", "var int m;").replace("return\n", "return;\n");
+        let class = crate::parse_str(&source).unwrap();
+        let (_, warnings) = crate::parser::VM::compile(&class, Default::default(), None, &mut std::io::sink()).unwrap();
+        let renderer = Renderer::new("Foo.jack", &source, Color::Never, MessageFormat::Human);
+        assert_eq!(renderer.warnings(&warnings, true).unwrap_err(), "\
+Foo.jack:4:13: indexing n, which is declared int, not Array [-W index-non-array]
+  |
+4 |         let n[1] = \"abc\";
+  |             ^
+  note: n is declared at 2:25
+  |
+2 |     function void f(int n) {
+  |                         - n is declared here");
+    }
+
+    #[test]
+    fn colors() {
+        let error = CompileError::Semantic(vec![crate::error::SemanticError::new("division by zero", Span::new(4, 20))]);
+        let rendered = Renderer::new("Foo.jack", SOURCE, Color::Always, MessageFormat::Human).compile_error(&error);
+        assert!(rendered.starts_with("\x1b[1mFoo.jack:4:20: division by zero\x1b[0m"));
+        assert!(rendered.ends_with(&format!("{}^^^^^{}", RED, RESET)));
+        assert_eq!(token_width("  \"a b\" x", 3), 5);
+        assert_eq!(token_width("let x", 10), 1);
+    }
+
+    // just enough of JSON to read the diagnostics back
+    #[derive(Debug, PartialEq)]
+    enum Json {
+        Null,
+        Number(usize),
+        String(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>)
+    }
+
+    impl Json {
+        fn get(&self, key: &str) -> &Json {
+            match self {
+                Json::Object(fields) => &fields.iter().find(|(name, _)| name == key).unwrap().1,
+                _ => panic!("{:?} is not an object", self)
+            }
+        }
+
+        fn position(&self) -> (usize, usize) {
+            match (self.get("line"), self.get("column")) {
+                (Json::Number(line), Json::Number(column)) => (*line, *column),
+                _ => panic!("{:?} is not a position", self)
+            }
+        }
+    }
+
+    fn parse_json(chars: &mut std::iter::Peekable<std::str::Chars>) -> Json {
+        match chars.next().unwrap() {
+            'n' => {
+                assert_eq!(chars.by_ref().take(3).collect::<String>(), "ull");
+                Json::Null
+            },
+            '"' => {
+                let mut text = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => text.push(match chars.next().unwrap() {
+                            'n' => '\n',
+                            't' => '\t',
+                            c => c
+                        }),
+                        c => text.push(c)
+                    }
+                }
+                Json::String(text)
+            },
+            '[' => {
+                let mut items = Vec::new();
+                while chars.peek() != Some(&']') {
+                    items.push(parse_json(chars));
+                    if chars.peek() == Some(&',') {
+                        chars.next();
+                    }
+                }
+                chars.next();
+                Json::Array(items)
+            },
+            '{' => {
+                let mut fields = Vec::new();
+                while chars.peek() != Some(&'}') {
+                    let Json::String(key) = parse_json(chars) else { panic!("a key") };
+                    assert_eq!(chars.next(), Some(':'));
+                    fields.push((key, parse_json(chars)));
+                    if chars.peek() == Some(&',') {
+                        chars.next();
+                    }
+                }
+                chars.next();
+                Json::Object(fields)
+            },
+            c => {
+                let mut digits = c.to_string();
+                while chars.peek().is_some_and(char::is_ascii_digit) {
+                    digits.push(chars.next().unwrap());
+                }
+                Json::Number(digits.parse().unwrap())
+            }
+        }
+    }
+
+    fn json(line: &str) -> Json {
+        let mut chars = line.chars().peekable();
+        let json = parse_json(&mut chars);
+        assert_eq!(chars.next(), None);
+        json
+    }
+
+    #[test]
+    fn json_diagnostics() {
+        let renderer = Renderer::new("Foo.jack", SOURCE, Color::Always, MessageFormat::Json);
+        let CompileError::Syntax(errors) = crate::compile_to_vm(SOURCE, None).unwrap_err() else { panic!("syntax errors") };
+        let duplicate = json(&renderer.json(&Diagnostic::syntax(&errors[0])));
+        assert_eq!(duplicate.get("severity"), &Json::String("error".to_string()));
+        assert_eq!(duplicate.get("code"), &Json::String("syntax".to_string()));
+        assert_eq!(duplicate.get("message"), &Json::String("n is already declared at 2:25".to_string()));
+        assert_eq!(duplicate.get("file"), &Json::String("Foo.jack".to_string()));
+        assert_eq!(duplicate.get("start").position(), (3, 17));
+        assert_eq!(duplicate.get("end").position(), (3, 18));
+        assert_eq!(duplicate.get("notes"), &Json::Array(Vec::new()));
+        let Json::Array(related) = duplicate.get("related") else { panic!("related locations") };
+        assert_eq!(related[0].get("message"), &Json::String("n is first declared here".to_string()));
+        assert_eq!(related[0].get("start").position(), (2, 25));
+        assert_eq!(related[0].get("end").position(), (2, 26));
+
+        let source = SOURCE.replace("var int n;", "var int m;").replace("return\n", "return;\n");
+        let class = crate::parse_str(&source).unwrap();
+        let (_, warnings) = crate::parser::VM::compile(&class, Default::default(), None, &mut std::io::sink()).unwrap();
+        let renderer = Renderer::new("Foo.jack", &source, Color::Never, MessageFormat::Json);
+        let warning = json(&renderer.json(&Diagnostic::semantic(&warnings[0].error, warnings[0].lint.name(), true)));
+        assert_eq!(warning.get("severity"), &Json::String("warning".to_string()));
+        assert_eq!(warning.get("code"), &Json::String("index-non-array".to_string()));
+        assert_eq!(warning.get("start").position(), (4, 13));
+        assert_eq!(warning.get("end").position(), (4, 14));
+        assert_eq!(warning.get("notes"), &Json::Array(vec![Json::String("n is declared at 2:25".to_string())]));
+
+        let file = json(&renderer.json(&Diagnostic::file("class-file-name", "class \"Foo\"\tis\n", true)));
+        assert_eq!(file.get("message"), &Json::String("class \"Foo\"\tis\n".to_string()));
+        assert_eq!(file.get("start"), &Json::Null);
+        assert_eq!(file.get("end"), &Json::Null);
+    }
+}
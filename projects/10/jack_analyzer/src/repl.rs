@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+use std::io::Cursor;
+
+use crate::ast::*;
+use crate::parser::{self, tokenize, VM};
+use crate::vm::VmCommand;
+use crate::{CompileError, LanguageOptions};
+
+// `eval`: the function an expression or statements are compiled in
+pub const FUNCTION: &str = "Repl.eval";
+
+// The class around a snippet, `2 + 3` becomes `return 2 + 3;`. The snippet is on a line of its own,
+// so errors point at its columns.
+pub fn source(snippet: &str, statements: bool) -> String {
+    let body = if statements { snippet.to_string() } else { format!("return {};", snippet) };
+    format!("class Repl {{ function int eval() {{\n{}\n}} }}\n", body)
+}
+
+// The VM code of the snippet in `source`. Variables that are only ever set by `let x = 5;` are
+// taken for int locals, so a snippet needs no `var` of its own; reading any other name is an error.
+pub fn compile(source: &str, options: LanguageOptions) -> Result<(String, Vec<VmCommand>), CompileError> {
+    let mut tokens = tokenize(Cursor::new(source.to_string()), options)?;
+    let mut class = parser::parse_single_class(&mut tokens)?;
+    for subroutine_dec in class.subroutine_decs.iter_mut() {
+        declare_assigned(subroutine_dec);
+    }
+    let compiled = VM::compile_with_symbols(&class, options, None, None, None)?;
+    Ok((compiled.vm, compiled.commands))
+}
+
+fn declare_assigned(subroutine_dec: &mut SubroutineDec) {
+    let mut declared: HashSet<String> = subroutine_dec.body.var_decs.iter()
+        .flat_map(|var_dec| var_dec.var_names().map(|var_name| var_name.0.clone()))
+        .collect();
+    let mut assigned = Vec::new();
+    assigned_names(&subroutine_dec.body.statements, &mut assigned);
+    for var_name in assigned {
+        if declared.insert(var_name.0.clone()) {
+            subroutine_dec.body.var_decs.push(VarDec { var_type: Type::Int, var_name, extra_var_names: Vec::new() });
+        }
+    }
+}
+
+// the variables of `let x = ...`, in the order they are first set
+fn assigned_names(statements: &Statements, names: &mut Vec<VarName>) {
+    for statement in statements.iter() {
+        match statement {
+            Statement::Let(statement) if statement.index_expression.is_none() => names.push(statement.var_name.clone()),
+            Statement::If(statement) => {
+                assigned_names(&statement.if_statements, names);
+                if let Some(else_statements) = &statement.else_statements {
+                    assigned_names(else_statements, names);
+                }
+            },
+            Statement::While(statement) => assigned_names(&statement.statements, names),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm_eval;
+
+    fn eval(snippet: &str, statements: bool, options: LanguageOptions) -> i16 {
+        let (_, commands) = compile(&source(snippet, statements), options).unwrap();
+        vm_eval::call(&commands, FUNCTION, &[]).unwrap().value
+    }
+
+    #[test]
+    fn expressions_and_statements() {
+        // left to right, unless `--precedence`
+        assert_eq!(eval("2 + 3 * (4 - 1)", false, LanguageOptions::default()), 15);
+        assert_eq!(eval("2 + 3 * (4 - 1)", false, LanguageOptions { precedence: true, ..LanguageOptions::default() }), 11);
+        assert_eq!(eval("let x = 5; return x * x;", true, LanguageOptions::default()), 25);
+        let loop_sum = "var int i; let sum = 0; while (i < 4) { let i = i + 1; let sum = sum + i; } return sum;";
+        assert_eq!(eval(loop_sum, true, LanguageOptions::default()), 10);
+    }
+
+    #[test]
+    fn undefined_names_are_errors() {
+        let error = compile(&source("y + 1", false), LanguageOptions::default()).unwrap_err();
+        assert_eq!(error.to_string(), "2:8: undefined variable y in Repl.eval");
+    }
+}
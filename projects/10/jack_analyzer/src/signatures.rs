@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use crate::analysis;
+use crate::ast::*;
+use crate::interface::Interface;
+use crate::tokenizer::Span;
+use crate::utils::{Scopes, SymbolKind};
+
+// What a call has to agree with
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub class_name: String,
+    pub name: String,
+    pub kind: SubroutineType,
+    // without the implicit `this` of methods
+    pub parameters: Vec<Type>,
+    pub return_type: SubroutineReturnType,
+    // the file and position of the declaration, none for the OS
+    pub declared: Option<(String, Span)>,
+    // for `--inline-accessors`
+    pub accessor: Option<Accessor>,
+    // the subroutines its body calls, class and name
+    pub calls: Vec<(String, String)>,
+    // the number of its local variables
+    pub locals: i16,
+    // the init_statics of `--ext-field-init`, which Main.main calls
+    pub static_initializer: bool
+}
+
+// What a method gives back when its whole body is `return x;` of a field or `return 3;` of a constant,
+// taking no arguments and declaring no variables
+#[derive(Debug, Clone)]
+pub enum Accessor {
+    // the index of the field in the object
+    Field(i16),
+    Constant(Term)
+}
+
+impl Signature {
+    pub fn new(class_name: &str, subroutine_dec: &SubroutineDec, file: &str) -> Self {
+        Signature {
+            class_name: class_name.to_string(),
+            name: subroutine_dec.name.0.clone(),
+            kind: subroutine_dec.subroutine_type.clone(),
+            parameters: subroutine_dec.parameters.iter().map(|parameter| parameter.0.clone()).collect(),
+            return_type: subroutine_dec.return_type.clone(),
+            declared: Some((file.to_string(), subroutine_dec.span)),
+            accessor: None,
+            calls: Vec::new(),
+            locals: subroutine_dec.body.var_decs.iter().map(|var_dec| var_dec.var_names().count() as i16).sum(),
+            static_initializer: false
+        }
+    }
+
+    // `function int multiply(int, int)`, the parameters by their types
+    pub fn declaration(&self) -> String {
+        let return_type = match &self.return_type {
+            SubroutineReturnType::Void => "void",
+            SubroutineReturnType::General(return_type) => return_type.as_str()
+        };
+        let parameters: Vec<&str> = self.parameters.iter().map(Type::as_str).collect();
+        format!("{} {} {}({})", self.kind.as_str(), return_type, self.name, parameters.join(", "))
+    }
+
+    // `Ball.move is declared at Ball.jack:5:17`
+    pub fn declared_at(&self) -> String {
+        match &self.declared {
+            Some((file, span)) => format!("{}.{} is declared at {}:{}", self.class_name, self.name, file, span),
+            None => format!("{}.{} is part of the Jack OS", self.class_name, self.name)
+        }
+    }
+}
+
+// The subroutines of every class of a project, collected before any class is compiled
+#[derive(Debug, Default)]
+pub struct Signatures {
+    // by class name, so going through the classes has the same order on every run
+    classes: BTreeMap<String, Vec<Signature>>,
+    // the value of each constant of `--ext-const`, by class and name
+    constants: BTreeMap<String, BTreeMap<String, Term>>
+}
+
+impl Signatures {
+    pub fn new() -> Self {
+        Signatures::default()
+    }
+
+    // the OS classes, a project's own class of the same name replaces them
+    pub fn with_os() -> Self {
+        let mut signatures = Signatures::new();
+        for (class_name, kind, return_type, name, parameters) in OS_API {
+            let kind = match kind {
+                "constructor" => SubroutineType::Constructor,
+                "method" => SubroutineType::Method,
+                _ => SubroutineType::Function
+            };
+            let return_type = match return_type {
+                "void" => SubroutineReturnType::Void,
+                return_type => SubroutineReturnType::General(type_named(return_type))
+            };
+            signatures.classes.entry(class_name.to_string()).or_default().push(Signature {
+                class_name: class_name.to_string(),
+                name: name.to_string(),
+                kind,
+                parameters: parameters.iter().map(|parameter| type_named(parameter)).collect(),
+                return_type,
+                declared: None,
+                accessor: None,
+                calls: Vec::new(),
+                locals: 0,
+                static_initializer: false
+            });
+        }
+        signatures
+    }
+
+    pub fn add_class(&mut self, class: &Class, file: &str) {
+        let fields = Scopes::for_class(class);
+        let mut signatures: Vec<Signature> = class.subroutine_decs.iter()
+            .map(|subroutine_dec| Signature {
+                accessor: accessor(subroutine_dec, &fields),
+                calls: analysis::called_subroutines(class, subroutine_dec),
+                ..Signature::new(&class.name.0, subroutine_dec, file)
+            })
+            .collect();
+        if let Some(subroutine_dec) = class.static_initializer() {
+            signatures.push(Signature {
+                calls: analysis::called_subroutines(class, &subroutine_dec),
+                static_initializer: true,
+                ..Signature::new(&class.name.0, &subroutine_dec, file)
+            });
+        }
+        self.classes.insert(class.name.0.clone(), signatures);
+        let constants = class.constants().map(|(name, value)| (name.to_string(), value.clone())).collect();
+        self.constants.insert(class.name.0.clone(), constants);
+    }
+
+    // a class of `--with-interface`, which a class of the project replaces like those of the OS
+    pub fn add_interface(&mut self, interface: Interface) {
+        self.classes.insert(interface.class_name.clone(), interface.subroutines);
+        self.constants.insert(interface.class_name, interface.constants.into_iter().collect());
+    }
+
+    pub fn has_class(&self, class_name: &str) -> bool {
+        self.classes.contains_key(class_name)
+    }
+
+    pub fn class_names(&self) -> impl Iterator<Item = &str> {
+        self.classes.keys().map(String::as_str)
+    }
+
+    pub fn subroutines(&self, class_name: &str) -> &[Signature] {
+        self.classes.get(class_name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn find(&self, class_name: &str, name: &str) -> Option<&Signature> {
+        self.classes.get(class_name)?.iter().find(|signature| signature.name == name)
+    }
+
+    pub fn constant(&self, class_name: &str, name: &str) -> Option<&Term> {
+        self.constants.get(class_name)?.get(name)
+    }
+
+    // the subroutine `name` is most likely a typo of
+    pub fn similar(&self, class_name: &str, name: &str) -> Option<&Signature> {
+        self.classes.get(class_name)?.iter()
+            .map(|signature| (edit_distance(&signature.name, name), signature))
+            .filter(|(distance, _)| *distance <= 2)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, signature)| signature)
+    }
+}
+
+fn accessor(subroutine_dec: &SubroutineDec, class_table: &Scopes) -> Option<Accessor> {
+    let body = &subroutine_dec.body;
+    if subroutine_dec.subroutine_type != SubroutineType::Method || !subroutine_dec.parameters.is_empty() || !body.var_decs.is_empty() {
+        return None;
+    }
+    let [Statement::Return(Some(Expression { term, extra_op_terms }), _)] = &body.statements.0[..] else { return None };
+    if !extra_op_terms.is_empty() {
+        return None;
+    }
+    match term {
+        // a static is in the segment of its own file, so only fields
+        Term::VarName(name, _) => class_table.find_by(name)
+            .filter(|symbol| matches!(symbol.kind(), SymbolKind::Field))
+            .map(|symbol| Accessor::Field(symbol.index())),
+        Term::IntegerConstant(_) | Term::KeywordConstant(KeywordConstant::True | KeywordConstant::False | KeywordConstant::Null, _) => {
+            Some(Accessor::Constant(term.clone()))
+        },
+        _ => None
+    }
+}
+
+// without `--no-builtin-os` calls to these are checked against the OS API
+pub const OS_CLASSES: [&str; 8] = ["Math", "String", "Array", "Output", "Screen", "Keyboard", "Memory", "Sys"];
+
+// The documented Jack OS API: class, kind, return type, name and parameter types
+#[allow(clippy::type_complexity)]
+const OS_API: [(&str, &str, &str, &str, &[&str]); 49] = [
+    ("Math", "function", "void", "init", &[]),
+    ("Math", "function", "int", "abs", &["int"]),
+    ("Math", "function", "int", "multiply", &["int", "int"]),
+    ("Math", "function", "int", "divide", &["int", "int"]),
+    ("Math", "function", "int", "min", &["int", "int"]),
+    ("Math", "function", "int", "max", &["int", "int"]),
+    ("Math", "function", "int", "sqrt", &["int"]),
+    ("String", "constructor", "String", "new", &["int"]),
+    ("String", "method", "void", "dispose", &[]),
+    ("String", "method", "int", "length", &[]),
+    ("String", "method", "char", "charAt", &["int"]),
+    ("String", "method", "void", "setCharAt", &["int", "char"]),
+    ("String", "method", "String", "appendChar", &["char"]),
+    ("String", "method", "void", "eraseLastChar", &[]),
+    ("String", "method", "int", "intValue", &[]),
+    ("String", "method", "void", "setInt", &["int"]),
+    ("String", "function", "char", "backSpace", &[]),
+    ("String", "function", "char", "doubleQuote", &[]),
+    ("String", "function", "char", "newLine", &[]),
+    ("Array", "function", "Array", "new", &["int"]),
+    ("Array", "method", "void", "dispose", &[]),
+    ("Output", "function", "void", "init", &[]),
+    ("Output", "function", "void", "moveCursor", &["int", "int"]),
+    ("Output", "function", "void", "printChar", &["char"]),
+    ("Output", "function", "void", "printString", &["String"]),
+    ("Output", "function", "void", "printInt", &["int"]),
+    ("Output", "function", "void", "println", &[]),
+    ("Output", "function", "void", "backSpace", &[]),
+    ("Screen", "function", "void", "init", &[]),
+    ("Screen", "function", "void", "clearScreen", &[]),
+    ("Screen", "function", "void", "setColor", &["boolean"]),
+    ("Screen", "function", "void", "drawPixel", &["int", "int"]),
+    ("Screen", "function", "void", "drawLine", &["int", "int", "int", "int"]),
+    ("Screen", "function", "void", "drawRectangle", &["int", "int", "int", "int"]),
+    ("Screen", "function", "void", "drawCircle", &["int", "int", "int"]),
+    ("Keyboard", "function", "void", "init", &[]),
+    ("Keyboard", "function", "char", "keyPressed", &[]),
+    ("Keyboard", "function", "char", "readChar", &[]),
+    ("Keyboard", "function", "String", "readLine", &["String"]),
+    ("Keyboard", "function", "int", "readInt", &["String"]),
+    ("Memory", "function", "void", "init", &[]),
+    ("Memory", "function", "int", "peek", &["int"]),
+    ("Memory", "function", "void", "poke", &["int", "int"]),
+    ("Memory", "function", "Array", "alloc", &["int"]),
+    ("Memory", "function", "void", "deAlloc", &["Array"]),
+    ("Sys", "function", "void", "init", &[]),
+    ("Sys", "function", "void", "halt", &[]),
+    ("Sys", "function", "void", "error", &["int"]),
+    ("Sys", "function", "void", "wait", &["int"]),
+];
+
+// `int`, or the class of that name
+pub fn type_named(name: &str) -> Type {
+    match name {
+        "int" => Type::Int,
+        "char" => Type::Char,
+        "boolean" => Type::Boolean,
+        class_name => Type::ClassName(class_name.to_string())
+    }
+}
+
+// how many characters have to be inserted, removed or replaced to turn one word into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a_char != *b_char);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("drawRectangle", "drawRectangle"), 0);
+        assert_eq!(edit_distance("drawRectangle", "drawRectangel"), 2);
+        assert_eq!(edit_distance("move", "moves"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn finds_subroutines_and_similar_names() {
+        let class = crate::parse_str("\
+class Ball {
+    method void move(int dx, int dy) { return; }
+    function int count() { return 0; }
+}").unwrap();
+        let mut signatures = Signatures::new();
+        signatures.add_class(&class, "Ball.jack");
+
+        assert!(signatures.has_class("Ball"));
+        let signature = signatures.find("Ball", "move").unwrap();
+        assert_eq!(signature.parameters, vec![Type::Int, Type::Int]);
+        assert_eq!(signature.kind, SubroutineType::Method);
+        assert_eq!(signature.declared_at(), "Ball.move is declared at Ball.jack:2:17");
+        assert!(signatures.find("Ball", "mvoe").is_none());
+        assert_eq!(signatures.similar("Ball", "mvoe").unwrap().name, "move");
+        assert!(signatures.similar("Ball", "draw").is_none());
+    }
+
+    #[test]
+    fn os_classes_can_be_replaced() {
+        let mut signatures = Signatures::with_os();
+        let multiply = signatures.find("Math", "multiply").unwrap();
+        assert_eq!(multiply.parameters, vec![Type::Int, Type::Int]);
+        assert_eq!(multiply.declared_at(), "Math.multiply is part of the Jack OS");
+        assert_eq!(multiply.declaration(), "function int multiply(int, int)");
+        assert_eq!(signatures.find("String", "appendChar").unwrap().kind, SubroutineType::Method);
+
+        let math = crate::parse_str("class Math { function int multiply(int x, int y, int z) { return 0; } }").unwrap();
+        signatures.add_class(&math, "Math.jack");
+        assert_eq!(signatures.find("Math", "multiply").unwrap().parameters.len(), 3);
+        assert!(signatures.find("Math", "divide").is_none());
+    }
+}
@@ -0,0 +1,118 @@
+//! Golden-file checks of the compiler: helpers that compare its output the way the course's
+//! TextComparer does, and a few programs of the course with the output they are expected to give.
+//!
+//! ```
+//! jack_analyzer::testing::assert_vm_matches(
+//!     "class Main { function int one() { return 1; } }",
+//!     "function Main.one 0\n    push constant 1\n    return\n"
+//! );
+//! ```
+
+use crate::compare::first_difference;
+use crate::{compile_to_vm, compile_to_xml};
+
+/// A program of the course and the outputs it has to compile to, `jack_analyzer --selftest` runs them.
+pub struct Fixture {
+    /// `Seven/Main`, the path of its files under `fixtures/` without the extension
+    pub name: &'static str,
+    pub source: &'static str,
+    pub vm: &'static str,
+    pub xml: &'static str
+}
+
+macro_rules! fixture {
+    ($name:literal) => {
+        Fixture {
+            name: $name,
+            source: include_str!(concat!("../fixtures/", $name, ".jack")),
+            vm: include_str!(concat!("../fixtures/", $name, ".vm")),
+            xml: include_str!(concat!("../fixtures/", $name, ".xml"))
+        }
+    };
+}
+
+/// The fixtures, compiled into the crate so the binary can check itself without the sources at hand
+pub const FIXTURES: [Fixture; 3] = [fixture!("Seven/Main"), fixture!("ConvertToBin/Main"), fixture!("Square/Square")];
+
+/// Panics with the first differing line unless the source compiles to the expected VM code,
+/// ignoring whitespace at the start and end of each line.
+pub fn assert_vm_matches(jack_source: &str, expected_vm: &str) {
+    if let Err(error) = vm_matches(jack_source, expected_vm) {
+        panic!("{}", error);
+    }
+}
+
+/// Like [`assert_vm_matches`], for the course's XML parse tree.
+pub fn assert_xml_matches(jack_source: &str, expected_xml: &str) {
+    if let Err(error) = xml_matches(jack_source, expected_xml) {
+        panic!("{}", error);
+    }
+}
+
+/// Whether the fixture compiles to both of its goldens, or what went wrong, naming the golden.
+pub fn check(fixture: &Fixture) -> Result<(), String> {
+    vm_matches(fixture.source, fixture.vm).map_err(|error| format!("{}.vm: {}", fixture.name, error))?;
+    xml_matches(fixture.source, fixture.xml).map_err(|error| format!("{}.xml: {}", fixture.name, error))
+}
+
+/// For `cargo test`: every fixture against its goldens. With `UPDATE_GOLDENS=1` the goldens are
+/// written from the output of the compiler instead, and the next build compiles the new ones in.
+#[cfg(feature = "fs")]
+pub fn check_goldens() -> Result<(), String> {
+    if std::env::var("UPDATE_GOLDENS").as_deref() == Ok("1") {
+        return FIXTURES.iter().try_for_each(update);
+    }
+    let errors: Vec<String> = FIXTURES.iter().filter_map(|fixture| check(fixture).err()).collect();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(format!("{}\nrun with UPDATE_GOLDENS=1 to take the new output as the goldens", errors.join("\n")))
+}
+
+#[cfg(feature = "fs")]
+fn update(fixture: &Fixture) -> Result<(), String> {
+    let path = format!("{}/fixtures/{}", env!("CARGO_MANIFEST_DIR"), fixture.name);
+    let vm = compile_to_vm(fixture.source, None).map_err(|error| format!("{}.jack: {}", fixture.name, error))?;
+    let xml = compile_to_xml(fixture.source, None).map_err(|error| format!("{}.jack: {}", fixture.name, error))?;
+    for (extension, output) in [("vm", vm), ("xml", xml)] {
+        std::fs::write(format!("{}.{}", path, extension), output).map_err(|error| format!("{}.{}: {}", path, extension, error))?;
+    }
+    Ok(())
+}
+
+fn vm_matches(jack_source: &str, expected_vm: &str) -> Result<(), String> {
+    let vm = compile_to_vm(jack_source, None).map_err(|error| error.to_string())?;
+    match first_difference(expected_vm, &vm) {
+        Some(difference) => Err(difference.to_string()),
+        None => Ok(())
+    }
+}
+
+fn xml_matches(jack_source: &str, expected_xml: &str) -> Result<(), String> {
+    let xml = compile_to_xml(jack_source, None).map_err(|error| error.to_string())?;
+    match first_difference(expected_xml, &xml) {
+        Some(difference) => Err(difference.to_string()),
+        None => Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatches_name_the_golden_and_the_line() {
+        let fixture = Fixture { vm: "function Main.main 0\npush constant 2\n", ..fixture!("Seven/Main") };
+        assert_eq!(
+            check(&fixture).unwrap_err(),
+            "Seven/Main.vm: comparison failure at line 2\n  expected: push constant 2\n  found:    push constant 1"
+        );
+        let error = vm_matches("class Main { function void main() { return } }", "").unwrap_err();
+        assert_eq!(error, "1:44: expected an expression, found '}'");
+    }
+
+    #[test]
+    fn xml_ignores_indentation_and_line_endings() {
+        assert_xml_matches("class Main {}", "<class>\r\n<keyword> class </keyword>\r\n<identifier> Main </identifier>\r\n<symbol> { </symbol>\r\n<symbol> } </symbol>\r\n</class>\r\n");
+    }
+}
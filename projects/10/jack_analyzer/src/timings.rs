@@ -0,0 +1,125 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::render::json_string;
+
+// The phases `--timings` tells apart. Reading the sources and writing the outputs is I/O, checking
+// the project and the subroutines is analysis, and making the output of a stage is codegen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Io,
+    Tokenize,
+    Parse,
+    Analysis,
+    Codegen
+}
+
+impl Phase {
+    const ALL: [Phase; 5] = [Phase::Io, Phase::Tokenize, Phase::Parse, Phase::Analysis, Phase::Codegen];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Phase::Io => "io",
+            Phase::Tokenize => "tokenize",
+            Phase::Parse => "parse",
+            Phase::Analysis => "analysis",
+            Phase::Codegen => "codegen"
+        }
+    }
+}
+
+// `--timings`: the wall-clock time of each phase for each file, in the order the files came up
+#[derive(Default)]
+pub struct Timings(RefCell<Vec<(String, [Duration; 5])>>);
+
+impl Timings {
+    pub fn time<T>(&self, file: &str, phase: Phase, run: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = run();
+        self.add(file, phase, start.elapsed());
+        result
+    }
+
+    pub fn add(&self, file: &str, phase: Phase, duration: Duration) {
+        let mut files = self.0.borrow_mut();
+        let index = match files.iter().position(|(name, _)| name == file) {
+            Some(index) => index,
+            None => {
+                files.push((file.to_string(), [Duration::ZERO; 5]));
+                files.len() - 1
+            }
+        };
+        files[index].1[phase as usize] += duration;
+    }
+
+    fn total(&self) -> [Duration; 5] {
+        let mut total = [Duration::ZERO; 5];
+        for (_, durations) in self.0.borrow().iter() {
+            for (sum, duration) in total.iter_mut().zip(durations) {
+                *sum += *duration;
+            }
+        }
+        total
+    }
+
+    // `{"timings":{"files":[{"file":"Main.jack","io":0.1,...,"total":1.5}],"total":{...}}}` in milliseconds
+    pub fn json(&self) -> String {
+        let row = |durations: &[Duration; 5]| {
+            let phases: Vec<String> = Phase::ALL.iter()
+                .map(|phase| format!("\"{}\":{:.3}", phase.name(), milliseconds(durations[*phase as usize])))
+                .collect();
+            format!("{},\"total\":{:.3}", phases.join(","), milliseconds(durations.iter().sum()))
+        };
+        let files: Vec<String> = self.0.borrow().iter()
+            .map(|(file, durations)| format!("{{\"file\":{},{}}}", json_string(file), row(durations)))
+            .collect();
+        format!("{{\"timings\":{{\"files\":[{}],\"total\":{{{}}}}}}}", files.join(","), row(&self.total()))
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// a row for each file and one for all of them, in milliseconds
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let files = self.0.borrow();
+        let width = files.iter().map(|(file, _)| file.len()).chain(["total".len()]).max().unwrap_or_default();
+        write!(f, "{:width$}", "", width = width)?;
+        for phase in Phase::ALL {
+            write!(f, " {:>9}", phase.name())?;
+        }
+        writeln!(f, " {:>9}", "total")?;
+        let total = self.total();
+        for (file, durations) in files.iter().map(|(file, durations)| (file.as_str(), durations)).chain([("total", &total)]) {
+            write!(f, "{:width$}", file, width = width)?;
+            for duration in durations.iter() {
+                write!(f, " {:>9.3}", milliseconds(*duration))?;
+            }
+            writeln!(f, " {:>9.3}", milliseconds(durations.iter().sum()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_add_up_by_file_and_phase() {
+        let timings = Timings::default();
+        timings.add("Main.jack", Phase::Parse, Duration::from_millis(2));
+        timings.add("Ball.jack", Phase::Io, Duration::from_millis(1));
+        timings.add("Main.jack", Phase::Parse, Duration::from_millis(3));
+        // the header starts under the file names
+        assert_eq!(timings.to_string(), "                 io  tokenize     parse  analysis   codegen     total
+Main.jack     0.000     0.000     5.000     0.000     0.000     5.000
+Ball.jack     1.000     0.000     0.000     0.000     0.000     1.000
+total         1.000     0.000     5.000     0.000     0.000     6.000
+");
+        assert!(timings.json().ends_with("\"total\":{\"io\":1.000,\"tokenize\":0.000,\"parse\":5.000,\"analysis\":0.000,\"codegen\":0.000,\"total\":6.000}}}"));
+    }
+}
@@ -2,7 +2,12 @@ use std::io;
 use std::io::BufRead;
 use std::io::Lines;
 use std::io::BufReader;
-use std::fs::File;
+use std::io::Read;
+use std::fmt;
+use crate::LanguageOptions;
+use crate::error::SyntaxError;
+
+pub const INTEGER_RANGE: &str = "an integer constant between 0 and 32767";
 
 #[derive(Debug, Clone)]
 pub enum Token {
@@ -13,7 +18,53 @@ pub enum Token {
     String(String)
 }
 
-const KEYWORDS: [&'static str; 21] = [
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Keyword(v) => write!(f, "'{}'", v),
+            Token::Symbol(v) => write!(f, "'{}'", v),
+            Token::Identifier(v) => write!(f, "'{}'", v),
+            Token::Int(v) => write!(f, "'{}'", *v as u16),
+            Token::String(v) => write!(f, "\"{}\"", v)
+        }
+    }
+}
+
+// 1-based position of a token in the source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Self {
+        Span { line, column }
+    }
+}
+
+// A comment the parser skips, kept for tools that re-emit the source.
+// Block comments keep one trimmed string per line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub lines: Vec<String>,
+    pub span: Span
+}
+
+// Everything around the tokens: the comments, and token positions to find blank lines
+#[derive(Debug, Clone, Default)]
+pub struct Trivia {
+    pub comments: Vec<Comment>,
+    pub token_spans: Vec<Span>
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+const KEYWORDS: [&str; 21] = [
     "class",
     "method",
     "function",
@@ -37,6 +88,9 @@ const KEYWORDS: [&'static str; 21] = [
     "while"
 ];
 
+// `--strict` limit, longer names are most likely generated or a mistake
+const MAX_IDENTIFIER_LENGTH: usize = 64;
+
 const SYMBOLS: [char; 19] = [
     '{',
     '}',
@@ -59,173 +113,278 @@ const SYMBOLS: [char; 19] = [
     '~'
 ];
 
-#[derive(Debug)]
 pub struct Tokenizer {
-    lines: Lines<BufReader<File>>,
+    lines: Lines<BufReader<Box<dyn Read>>>,
+    line_number: usize,
     current_line: Line,
-    is_comment: bool
+    is_comment: bool,
+    comments: Vec<Comment>,
+    options: LanguageOptions
 }
 
 impl Tokenizer {
-    pub fn new(file: File) -> Result<Self, io::Error> {
-        let lines = BufReader::new(file).lines();
-        let current_line = Line::new("");
-        Ok(Self { lines, current_line, is_comment: false })
+    pub fn new(input: impl Read + 'static, options: LanguageOptions) -> Result<Self, io::Error> {
+        let input: Box<dyn Read> = Box::new(input);
+        let lines = BufReader::new(input).lines();
+        let current_line = Line::new("", Span::new(1, 1), options);
+        Ok(Self { lines, line_number: 0, current_line, is_comment: false, comments: Vec::new(), options })
+    }
+
+    // comments skipped so far, in source order
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    fn push_comment(&mut self, line: &str, column: usize) {
+        let span = Span::new(self.line_number, column);
+        self.comments.push(Comment { lines: vec![line.to_string()], span });
+    }
+
+    fn continue_comment(&mut self, line: &str) {
+        if let Some(comment) = self.comments.last_mut() {
+            comment.lines.push(line.to_string());
+        }
+    }
+
+    // position of the last token returned
+    pub fn span(&self) -> Span {
+        self.current_line.span()
     }
 }
 
 impl Iterator for Tokenizer {
-    type Item=Token;
+    type Item=io::Result<Token>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(token) = self.current_line.next() {
-            return Some(token);
-        } else {
-            let line = self.lines.next()?.unwrap();
-            let line = line.trim();
+        loop {
+            if let Some(token) = self.current_line.next() {
+                return Some(token);
+            }
+            let raw_line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e))
+            };
+            self.line_number += 1;
+            let line = raw_line.trim();
+            let indent = raw_line.chars().count() - raw_line.trim_start().chars().count();
 
             // handle /** comments */
             if line.starts_with("/** ") && line.ends_with(" */") {
-                return self.next();
+                self.push_comment(line, indent + 1);
+                continue;
             } else if line.starts_with("/**") {
                 self.is_comment = true;
-                return self.next();
+                self.push_comment(line, indent + 1);
+                continue;
             } else if line.ends_with("*/") {
+                if self.is_comment {
+                    self.continue_comment(line);
+                } else {
+                    self.push_comment(line, indent + 1);
+                }
                 self.is_comment = false;
-                return self.next();
+                continue;
             } else if self.is_comment {
-                return self.next();
+                self.continue_comment(line);
+                continue;
             }
 
-            let line = if let Some((non_comment, _comment)) = line.split_once("//") {
+            let line = if let Some((non_comment, comment)) = line.split_once("//") {
+                self.push_comment(&format!("//{}", comment.trim_end()), indent + non_comment.chars().count() + 1);
                 non_comment
             } else {
                 line
             };
-            self.current_line = Line::new(line);
-            self.next()
+            let start = Span::new(self.line_number, indent + 1);
+            self.current_line = Line::new(line, start, self.options);
         }
     }
 }
 
 #[derive(Debug)]
 struct Line {
-    raw_line: String,
+    chars: Vec<char>,
+    start: Span,
     index: usize,
+    token_index: usize,
     current_slice: String,
     current_is_string: bool,
-    current_symbol: Option<char>
+    current_symbol: Option<char>,
+    options: LanguageOptions
 }
 
 impl Line {
-    pub fn new(line: &str) -> Self {
+    pub fn new(line: &str, start: Span, options: LanguageOptions) -> Self {
         Self {
-            raw_line: line.to_string(),
+            chars: line.chars().collect(),
+            start,
             index: 0,
+            token_index: 0,
             current_slice: String::new(),
             current_is_string: false,
-            current_symbol: None
+            current_symbol: None,
+            options
         }
     }
 
-    pub fn token(&self) -> Token {
-        let slice = self.current_slice.clone();
+    // position of the token returned last
+    pub fn span(&self) -> Span {
+        Span::new(self.start.line, self.start.column + self.token_index)
+    }
+
+    // the keywords of the language extensions, identifiers without them
+    fn extension_keyword(&self, slice: &str) -> bool {
+        (slice == "for" && self.options.for_loops)
+            || (matches!(slice, "break" | "continue") && self.options.break_continue)
+            || (slice == "const" && self.options.constants)
+    }
+
+    // takes the text collected so far
+    pub fn token(&mut self) -> io::Result<Token> {
+        let slice = std::mem::take(&mut self.current_slice);
         if self.current_is_string {
-            return Token::String(slice);
+            return Ok(Token::String(slice));
         }
         if let Some(symbol) = self.current_symbol {
-            return Token::Symbol(symbol);
+            return Ok(Token::Symbol(symbol));
+        }
+        if KEYWORDS.contains(&&slice[..]) || self.extension_keyword(&slice) {
+            return Ok(Token::Keyword(slice));
         }
-        if KEYWORDS.contains(&&slice[..]) {
-            return Token::Keyword(slice);
+        if slice.starts_with(|ch: char| ch.is_ascii_digit()) && !slice.chars().all(|ch| ch.is_numeric()) {
+            return Err(self.malformed(&slice, "a number directly followed by letters"));
         }
         if slice.chars().all(|ch| ch.is_numeric()) {
-            let num: i16 = slice.parse().unwrap();
-            return Token::Int(num);
+            return match slice.parse::<i16>() {
+                Ok(num) => Ok(Token::Int(num)),
+                // only allowed after a unary minus, which the parser checks, kept as the same bits
+                Err(_) if slice == "32768" => Ok(Token::Int(i16::MIN)),
+                Err(_) => Err(self.error(INTEGER_RANGE, &format!("'{}'", slice)))
+            };
+        }
+        if self.options.strict && slice.chars().count() > MAX_IDENTIFIER_LENGTH {
+            let expected = format!("an identifier of at most {} characters", MAX_IDENTIFIER_LENGTH);
+            return Err(self.error(&expected, &format!("'{}'", slice)));
         }
-        Token::Identifier(slice)
+        Ok(Token::Identifier(slice))
+    }
+
+    // lexical errors travel through the io::Error as its inner SyntaxError
+    fn error(&self, expected: &str, found: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, SyntaxError::new(expected, found, self.span()))
+    }
+
+    fn malformed(&self, token: &str, description: &str) -> io::Error {
+        let message = format!("malformed token '{}', {}", token, description);
+        let error = SyntaxError::with_message(&message, &format!("'{}'", token), self.span());
+        io::Error::new(io::ErrorKind::InvalidData, error)
     }
 
     fn reset_current(&mut self) {
-        self.current_slice = "".to_string();
+        self.current_slice.clear();
         self.current_is_string = false;
         self.current_symbol = None;
     }
 }
 
 impl Iterator for Line {
-    type Item=Token;
+    type Item=io::Result<Token>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(symbol) = self.current_symbol {
+            self.token_index = self.index - 1;
             self.reset_current();
-            return Some(Token::Symbol(symbol));
-        }
-        let n = self.index;
-        let char = self.raw_line.chars().nth(n);
-        match char {
-            Some(' ') => {
-                self.index += 1;
-                if self.current_is_string {
-                    self.current_slice.push(' ');
-                    self.next()
-                } else if self.current_slice.len() > 0 {
-                    let token = self.token();
-                    self.reset_current();
-                    Some(token)
-                } else {
-                    self.next()
-                }
-            },
-            Some('"') => {
-                self.index += 1;
-                if self.current_slice.is_empty() {
-                    self.current_is_string = true;
-                    self.next()
-                } else {
-                    let token = self.token();
-                    self.reset_current();
-                    Some(token)
-                }
-            },
-            Some(ch) if SYMBOLS.contains(&ch) => {
-                self.index += 1;
-                if self.current_is_string {
+            return Some(Ok(Token::Symbol(symbol)));
+        }
+        loop {
+            let n = self.index;
+            match self.chars.get(n).copied() {
+                Some(' ') => {
+                    self.index += 1;
+                    if self.current_is_string {
+                        self.current_slice.push(' ');
+                    } else if !self.current_slice.is_empty() {
+                        let token = self.token();
+                        self.reset_current();
+                        return Some(token);
+                    }
+                },
+                Some('"') => {
+                    self.index += 1;
+                    if self.current_slice.is_empty() && !self.current_is_string {
+                        self.token_index = n;
+                        self.current_is_string = true;
+                    } else {
+                        // `"abc"def`
+                        let letters: String = self.chars[self.index..].iter()
+                            .take_while(|ch| ch.is_alphanumeric() || **ch == '_')
+                            .collect();
+                        if self.current_is_string && !letters.is_empty() {
+                            let token = format!("\"{}\"{}", self.current_slice, letters);
+                            return Some(Err(self.malformed(&token, "a string constant directly followed by letters")));
+                        }
+                        let token = self.token();
+                        self.reset_current();
+                        return Some(token);
+                    }
+                },
+                Some(ch) if SYMBOLS.contains(&ch) => {
+                    self.index += 1;
+                    if self.current_is_string {
+                        self.current_slice.push(ch);
+                    } else if !self.current_slice.is_empty() {
+                        let token = self.token();
+                        self.reset_current();
+                        self.current_symbol = Some(ch);
+                        return Some(token);
+                    } else {
+                        self.token_index = n;
+                        return Some(Ok(Token::Symbol(ch)));
+                    }
+                },
+                Some('\\') if self.current_is_string && self.options.string_escapes => {
+                    self.index += 2;
+                    match self.chars.get(n + 1) {
+                        Some('"') => self.current_slice.push('"'),
+                        Some('\\') => self.current_slice.push('\\'),
+                        Some('n') => self.current_slice.push('\n'),
+                        Some('t') => self.current_slice.push('\t'),
+                        escaped => {
+                            self.token_index = n;
+                            let found = match escaped {
+                                Some(ch) => format!("`\\{}`", ch),
+                                None => "`\\` at end of line".to_string()
+                            };
+                            return Some(Err(self.error("an escape sequence (\\\", \\\\, \\n or \\t)", &found)));
+                        }
+                    }
+                },
+                Some(ch) => {
+                    self.index += 1;
+                    if self.current_slice.is_empty() && !self.current_is_string {
+                        self.token_index = n;
+                    }
                     self.current_slice.push(ch);
-                    self.next()
-                } else if self.current_slice.len() > 0 {
-                    let token = self.token();
-                    self.reset_current();
-                    self.current_symbol = Some(ch);
-                    Some(token)
-                } else {
-                    self.current_symbol = Some(ch);
-                    self.next()
-                }
-            },
-            Some(ch) => {
-                self.index += 1;
-                self.current_slice.push(ch);
-                self.next()
-            },
-            None => {
-                self.index += 1;
-                if self.current_slice.is_empty() {
-                    None
-                } else {
+                },
+                None => {
+                    self.index += 1;
+                    if self.current_slice.is_empty() {
+                        return None;
+                    }
                     let token = self.token();
                     self.reset_current();
-                    Some(token)
+                    return Some(token);
                 }
             }
         }
     }
-    
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::{Op, UnaryOp};
+    use std::fs::File;
     use tempfile::tempfile;
     use std::io::SeekFrom;
     use std::io::prelude::*;
@@ -242,70 +401,70 @@ mod tests {
     #[test]
     fn test_line() {
         let line = "do Output.printString(\"The average is \");  let i = 1;";
-        let mut line = Line::new(line);
+        let mut line = Line::new(line, Span::new(1, 1), LanguageOptions::default());
 
-        match line.next().unwrap() {
-            Token::Keyword(k) if k == "do".to_string() => {},
+        match line.next().unwrap().unwrap() {
+            Token::Keyword(k) if k == "do" => {},
             _ => panic!("failed to parse keyword `do`")
         }
 
-        match line.next().unwrap() {
-            Token::Identifier(v) if v == "Output".to_string() => {},
+        match line.next().unwrap().unwrap() {
+            Token::Identifier(v) if v == "Output" => {},
             _ => panic!("failed to parse identifier `Output`")
         }
 
-        match line.next().unwrap() {
+        match line.next().unwrap().unwrap() {
             Token::Symbol('.') => {},
             _ => panic!("failed to parse the symbol `.`")
         }
 
-        match line.next().unwrap() {
-            Token::Identifier(v) if v == "printString".to_string() => {},
+        match line.next().unwrap().unwrap() {
+            Token::Identifier(v) if v == "printString" => {},
             _ => panic!("failed to parse identifier `printString`")
         }
 
-        match line.next().unwrap() {
+        match line.next().unwrap().unwrap() {
             Token::Symbol('(') => {},
             _ => panic!("failed to parse the symbol `(`")
         }
 
-        match line.next().unwrap() {
-            Token::String(v) if v == "The average is ".to_string() => {},
+        match line.next().unwrap().unwrap() {
+            Token::String(v) if v == "The average is " => {},
             Token::String(v) => panic!("failed to parse the string content: {}", v),
             _ => panic!("Unknown string parsing error")
         }
 
-        match line.next().unwrap() {
+        match line.next().unwrap().unwrap() {
             Token::Symbol(')') => {},
             _ => panic!("failed to parse the symbol `)`")
         }
 
-        match line.next().unwrap() {
+        match line.next().unwrap().unwrap() {
             Token::Symbol(';') => {},
             _ => panic!("failed to parse the symbol `;`")
         }
 
-        match line.next().unwrap() {
-            Token::Keyword(k) if k == "let".to_string() => {},
+        match line.next().unwrap().unwrap() {
+            Token::Keyword(k) if k == "let" => {},
             _ => panic!("failed to parse keyword `let`")
         }
 
-        match line.next().unwrap() {
-            Token::Identifier(v) if v == "i".to_string() => {},
+        match line.next().unwrap().unwrap() {
+            Token::Identifier(v) if v == "i" => {},
             _ => panic!("failed to parse identifier `i`")
         }
 
-        match line.next().unwrap() {
+        match line.next().unwrap().unwrap() {
             Token::Symbol('=') => {},
             _ => panic!("failed to parse the symbol `=`")
         }
 
-        match line.next().unwrap() {
+        match line.next().unwrap().unwrap() {
             Token::Int(1) => {},
             _ => panic!("failed to parse the int `1`")
         }
 
-        match line.next().unwrap() {
+        match line.next().unwrap().unwrap() {
             Token::Symbol(';') => {},
             _ => panic!("failed to parse the symbol `;`")
         }
@@ -325,85 +484,85 @@ mod tests {
             }
         ";
         let file = fixture(content);
-        let mut tokenizer = Tokenizer::new(file).unwrap();
+        let mut tokenizer = Tokenizer::new(file, LanguageOptions::default()).unwrap();
 
-        match tokenizer.next() {
-            Some(Token::Keyword(v)) if v == "if".to_string() => {},
+        match tokenizer.next().transpose().unwrap() {
+            Some(Token::Keyword(v)) if v == "if" => {},
             _ => panic!("error parsing keyword `if`")
         }
 
-        match tokenizer.next() {
+        match tokenizer.next().transpose().unwrap() {
             Some(Token::Symbol('(')) => {},
             _ => panic!("error parsing symbol `(`")
         }
 
-        match tokenizer.next() {
-            Some(Token::Identifier(v)) if v == "x".to_string() => {},
+        match tokenizer.next().transpose().unwrap() {
+            Some(Token::Identifier(v)) if v == "x" => {},
             _ => panic!("error parsing identifier `x`")
         }
 
-        match tokenizer.next() {
+        match tokenizer.next().transpose().unwrap() {
             Some(Token::Symbol('<')) => {},
             _ => panic!("error parsing symbol `<`")
         }
 
-        match tokenizer.next() {
+        match tokenizer.next().transpose().unwrap() {
             Some(Token::Int(0)) => {},
             _ => panic!("error parsing integer `0`")
         }
 
-        match tokenizer.next() {
+        match tokenizer.next().transpose().unwrap() {
             Some(Token::Symbol(')')) => {},
             _ => panic!("error parsing symbol `)`")
         }
 
-        match tokenizer.next() {
+        match tokenizer.next().transpose().unwrap() {
             Some(Token::Symbol('{')) => {},
             _ => panic!("error parsing symbol `{{`")
         }
 
-        match tokenizer.next() {
-            Some(Token::Keyword(v)) if v == "do".to_string() => {},
+        match tokenizer.next().transpose().unwrap() {
+            Some(Token::Keyword(v)) if v == "do" => {},
             Some(token) => panic!("error parsing: {:?}", token),
             _ => panic!("error parsing keyword `do`")
         }
 
-        match tokenizer.next() {
-            Some(Token::Identifier(v)) if v == "Output".to_string() => {},
+        match tokenizer.next().transpose().unwrap() {
+            Some(Token::Identifier(v)) if v == "Output" => {},
             _ => panic!("error parsing identifier `Output`")
         }
 
-        match tokenizer.next() {
+        match tokenizer.next().transpose().unwrap() {
             Some(Token::Symbol('.')) => {},
             _ => panic!("error parsing symbol `.`")
         }
 
-        match tokenizer.next() {
-            Some(Token::Identifier(v)) if v == "printString".to_string() => {},
+        match tokenizer.next().transpose().unwrap() {
+            Some(Token::Identifier(v)) if v == "printString" => {},
             _ => panic!("error parsing identifier `printString`")
         }
 
-        match tokenizer.next() {
+        match tokenizer.next().transpose().unwrap() {
             Some(Token::Symbol('(')) => {},
             _ => panic!("error parsing symbol `(`")
         }
 
-        match tokenizer.next() {
-            Some(Token::String(v)) if v == "hello world :)".to_string() => {},
+        match tokenizer.next().transpose().unwrap() {
+            Some(Token::String(v)) if v == "hello world :)" => {},
             _ => panic!("error parsing string")
         }
 
-        match tokenizer.next() {
+        match tokenizer.next().transpose().unwrap() {
             Some(Token::Symbol(')')) => {},
             _ => panic!("error parsing symbol `)`")
         }
 
-        match tokenizer.next() {
+        match tokenizer.next().transpose().unwrap() {
             Some(Token::Symbol(';')) => {},
             _ => panic!("error parsing symbol `;`")
         }
 
-        match tokenizer.next() {
+        match tokenizer.next().transpose().unwrap() {
             Some(Token::Symbol('}')) => {},
             _ => panic!("error parsing symbol `}}`")
         }
@@ -411,6 +570,143 @@ mod tests {
         assert!(tokenizer.next().is_none());
     }
 
+    fn string_constant(line: &str, options: LanguageOptions) -> io::Result<String> {
+        let mut line = Line::new(line, Span::new(1, 1), options);
+        match line.next().unwrap()? {
+            Token::String(v) => Ok(v),
+            token => panic!("expected a string constant, found {:?}", token)
+        }
+    }
+
+    #[test]
+    fn string_escapes() {
+        let options = LanguageOptions { string_escapes: true, ..Default::default() };
+        assert_eq!(string_constant(r#""say \"hi\"""#, options).unwrap(), "say \"hi\"");
+        assert_eq!(string_constant(r#""a\\b""#, options).unwrap(), "a\\b");
+        assert_eq!(string_constant(r#""line\n""#, options).unwrap(), "line\n");
+        assert_eq!(string_constant(r#""a\tb""#, options).unwrap(), "a\tb");
+    }
+
+    #[test]
+    fn invalid_string_escape() {
+        let options = LanguageOptions { string_escapes: true, ..Default::default() };
+        let error = string_constant(r#""bad \q""#, options).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("`\\q`"));
+    }
+
+    #[test]
+    fn backslash_is_literal_without_escapes() {
+        let options = LanguageOptions::default();
+        assert_eq!(string_constant(r#""a\nb\q""#, options).unwrap(), "a\\nb\\q");
+    }
+
+    #[test]
+    fn token_spans() {
+        let content = "\
+            // comment
+            let s = \"a b\";
+              do f(x);
+        ";
+        let mut tokenizer = Tokenizer::new(fixture(content), LanguageOptions::default()).unwrap();
+        let mut spans = Vec::new();
+        while let Some(token) = tokenizer.next() {
+            spans.push((token.unwrap().to_string(), tokenizer.span()));
+        }
+        assert_eq!(spans, vec![
+            ("'let'".to_string(), Span::new(2, 13)),
+            ("'s'".to_string(), Span::new(2, 17)),
+            ("'='".to_string(), Span::new(2, 19)),
+            ("\"a b\"".to_string(), Span::new(2, 21)),
+            ("';'".to_string(), Span::new(2, 26)),
+            ("'do'".to_string(), Span::new(3, 15)),
+            ("'f'".to_string(), Span::new(3, 18)),
+            ("'('".to_string(), Span::new(3, 19)),
+            ("'x'".to_string(), Span::new(3, 20)),
+            ("')'".to_string(), Span::new(3, 21)),
+            ("';'".to_string(), Span::new(3, 22)),
+        ]);
+    }
+
+    #[test]
+    fn many_comment_lines() {
+        let mut content = String::from("let x = 1;\n");
+        for _ in 0..100_000 {
+            content.push_str("// comment\n");
+        }
+        content.push_str("/**\n");
+        for _ in 0..100_000 {
+            content.push_str(" * comment\n");
+        }
+        content.push_str(" */\n\n");
+        content.push_str("return;\n");
+        let tokens: Vec<Token> = Tokenizer::new(fixture(&content), LanguageOptions::default())
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(tokens.len(), 7);
+        match tokens.last() {
+            Some(Token::Symbol(';')) => {},
+            token => panic!("error parsing trailing `;`: {:?}", token)
+        }
+    }
+
+    #[test]
+    fn long_line_of_spaces() {
+        let line = format!("{}x{}", " ".repeat(100_000), " ".repeat(100_000));
+        let mut line = Line::new(&line, Span::new(1, 1), LanguageOptions::default());
+        match line.next().unwrap().unwrap() {
+            Token::Identifier(v) if v == "x" => {},
+            token => panic!("error parsing identifier `x`: {:?}", token)
+        }
+        assert!(line.next().is_none());
+    }
+
+    #[test]
+    fn strict_identifier_length() {
+        let name = "a".repeat(MAX_IDENTIFIER_LENGTH + 1);
+        let mut line = Line::new(&name, Span::new(1, 1), LanguageOptions::default());
+        assert!(matches!(line.next(), Some(Ok(Token::Identifier(_)))));
+
+        let options = LanguageOptions { strict: true, ..Default::default() };
+        let mut line = Line::new(&name, Span::new(1, 1), options);
+        let error = line.next().unwrap().unwrap_err();
+        assert!(error.to_string().starts_with("1:1: expected an identifier of at most 64 characters"));
+        let mut line = Line::new(&name[1..], Span::new(1, 1), options);
+        assert!(matches!(line.next(), Some(Ok(Token::Identifier(_)))));
+    }
+
+    #[test]
+    fn operators_are_symbols() {
+        for op in Op::ALL {
+            assert!(SYMBOLS.contains(&op.symbol()), "{:?}", op);
+        }
+        for op in UnaryOp::ALL {
+            assert!(SYMBOLS.contains(&op.symbol()), "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn number_followed_by_letters() {
+        let mut line = Line::new("let x = 5foo;", Span::new(3, 5), LanguageOptions::default());
+        let tokens: Vec<io::Result<Token>> = line.by_ref().take(4).collect();
+        let error = tokens.into_iter().last().unwrap().unwrap_err();
+        assert_eq!(error.to_string(), "3:13: malformed token '5foo', a number directly followed by letters");
+
+        let mut line = Line::new("x[2_]", Span::new(1, 1), LanguageOptions::default());
+        let error = line.nth(2).unwrap().unwrap_err();
+        assert_eq!(error.to_string(), "1:3: malformed token '2_', a number directly followed by letters");
+    }
+
+    #[test]
+    fn string_followed_by_letters() {
+        let error = string_constant(r#""abc"def"#, LanguageOptions::default()).unwrap_err();
+        assert_eq!(error.to_string(), "1:1: malformed token '\"abc\"def', a string constant directly followed by letters");
+        assert_eq!(string_constant(r#""abc");"#, LanguageOptions::default()).unwrap(), "abc");
+        assert_eq!(string_constant(r#""abc" + d"#, LanguageOptions::default()).unwrap(), "abc");
+        assert_eq!(string_constant(r#""");"#, LanguageOptions::default()).unwrap(), "");
+    }
+
     #[test]
     fn test() {
         assert!(" */\n".trim().starts_with("*/"));
@@ -1,6 +1,9 @@
 use std::collections::HashMap;
+use std::fmt;
 
-use crate::parser::Type;
+use crate::ast::{Class, SubroutineDec, SubroutineType, Type};
+use crate::tokenizer::Span;
+use crate::vm::Segment;
 
 pub struct Padding(usize);
 
@@ -36,15 +39,17 @@ pub struct Symbol {
     var_type: Type,
     kind: SymbolKind,
     index: i16,
+    // position of the name in its declaration
+    declared: Span
 }
 
 impl Symbol {
-    pub fn vm_memory_segment(&self) -> String {
+    pub fn vm_memory_segment(&self) -> Segment {
         match self.kind {
-            SymbolKind::Field => "this".to_string(),
-            SymbolKind::Argument => "argument".to_string(),
-            SymbolKind::Local => "local".to_string(),
-            SymbolKind::Static => "static".to_string()
+            SymbolKind::Field => Segment::This,
+            SymbolKind::Argument => Segment::Argument,
+            SymbolKind::Local => Segment::Local,
+            SymbolKind::Static => Segment::Static
         }
     }
 
@@ -52,14 +57,20 @@ impl Symbol {
         self.index
     }
 
-    pub fn class_name(&self) -> String {
-        match &self.var_type {
-            Type::ClassName(v) => v.to_string(),
-            _ => panic!()
-        }
+    pub fn var_type(&self) -> &Type {
+        &self.var_type
+    }
+
+    pub fn kind(&self) -> &SymbolKind {
+        &self.kind
+    }
+
+    pub fn declared(&self) -> Span {
+        self.declared
     }
 }
 
+#[derive(Clone)]
 struct Counter {
     field_index: i16,
     static_index: i16,
@@ -96,40 +107,118 @@ impl Counter {
     }
 }
 
-pub struct SymbolTable {
-    counter: Counter,
-    symbols: Vec<Symbol>
+struct Scope {
+    symbols: HashMap<String, Symbol>,
+    // the indexes given so far, a scope goes on from the one around it
+    counter: Counter
 }
 
-impl SymbolTable {
+// The variables in sight, the class's scope with a subroutine's inside it. A name is looked up
+// from the innermost scope out, so a local or argument shadows a field or static of the same name.
+pub struct Scopes {
+    scopes: Vec<Scope>
+}
+
+impl Scopes {
     pub fn new() -> Self {
-        SymbolTable {
-            counter: Counter::new(),
-            symbols: Vec::new()
+        Scopes { scopes: vec![Scope { symbols: HashMap::new(), counter: Counter::new() }] }
+    }
+
+    // the fields and statics of a class
+    pub fn for_class(class: &Class) -> Self {
+        let mut scopes = Scopes::new();
+        for var_dec in class.class_var_decs.iter() {
+            for var_name in var_dec.var_names() {
+                // constants take no storage
+                let Some(kind) = var_dec.dec_type.to_symbol_kind() else { continue };
+                scopes.push(&var_name.0, var_dec.var_type.clone(), kind, var_name.1);
+            }
         }
+        scopes
+    }
+
+    pub fn push_scope(&mut self) {
+        let counter = self.innermost().counter.clone();
+        self.scopes.push(Scope { symbols: HashMap::new(), counter });
+    }
+
+    // the outermost scope stays
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    // a new scope with the arguments and locals of a subroutine, a method's object is argument 0
+    pub fn push_subroutine(&mut self, class_name: &str, subroutine_dec: &SubroutineDec) {
+        self.push_subroutine_without(class_name, subroutine_dec, |_| false);
+    }
+
+    // `-O`: the locals `unused` picks are left out, and the others numbered without gaps
+    pub fn push_subroutine_without(&mut self, class_name: &str, subroutine_dec: &SubroutineDec, unused: impl Fn(&str) -> bool) {
+        self.push_scope();
+        if let SubroutineType::Method = subroutine_dec.subroutine_type {
+            self.push("this", Type::ClassName(class_name.to_string()), SymbolKind::Argument, subroutine_dec.span);
+        }
+        for parameter in subroutine_dec.parameters.iter() {
+            self.push(&parameter.1.0, parameter.0.clone(), SymbolKind::Argument, parameter.1.1);
+        }
+        for var_dec in subroutine_dec.body.var_decs.iter() {
+            for var_name in var_dec.var_names().filter(|var_name| !unused(var_name.as_str())) {
+                self.push(&var_name.0, var_dec.var_type.clone(), SymbolKind::Local, var_name.1);
+            }
+        }
+    }
+
+    fn innermost(&self) -> &Scope {
+        self.scopes.last().unwrap()
     }
 
     pub fn find_by(&self, name: &str) -> Option<&Symbol> {
-        self.symbols.iter().find(|&s| s.var_name.as_str() == name)
+        self.scopes.iter().rev().find_map(|scope| scope.symbols.get(name))
     }
 
     pub fn field_vars_count(&self) -> i16 {
-        self.symbols.iter().filter(|&s| match s.kind {
-            SymbolKind::Field => true,
-            _ => false
-        }).count() as i16
+        self.innermost().counter.index_by_kind(&SymbolKind::Field)
     }
 
-    pub fn push(&mut self, var_name: &str, var_type: Type, kind: SymbolKind) {
-        let index = self.counter.index_by_kind(&kind);
-        self.counter.increment_by_kind(&kind);
+    pub fn local_vars_count(&self) -> i16 {
+        self.innermost().counter.index_by_kind(&SymbolKind::Local)
+    }
+
+    // a name declared twice in a scope keeps its first symbol, the parser reports the second
+    pub fn push(&mut self, var_name: &str, var_type: Type, kind: SymbolKind, declared: Span) {
+        let scope = self.scopes.last_mut().unwrap();
+        let index = scope.counter.index_by_kind(&kind);
+        scope.counter.increment_by_kind(&kind);
         let symbol = Symbol {
             var_name: var_name.to_string(),
             var_type,
             kind,
-            index
+            index,
+            declared
         };
-        self.symbols.push(symbol);
+        scope.symbols.entry(var_name.to_string()).or_insert(symbol);
+    }
+}
+
+// the innermost scope, one symbol a line: `name type kind index`
+// statics before fields and arguments before locals, each by index
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut symbols: Vec<&Symbol> = self.innermost().symbols.values().collect();
+        symbols.sort_by_key(|symbol| (matches!(symbol.kind, SymbolKind::Field | SymbolKind::Local), symbol.index));
+        for symbol in symbols {
+            let var_type = symbol.var_type.as_str();
+            let kind = match symbol.kind {
+                SymbolKind::Field => "field",
+                SymbolKind::Static => "static",
+                SymbolKind::Local => "local",
+                SymbolKind::Argument => "argument"
+            };
+            writeln!(f, "{} {} {} {}", symbol.var_name, var_type, kind, symbol.index)?;
+        }
+        Ok(())
     }
 }
 
@@ -198,7 +287,7 @@ impl CharSet {
         set.insert('Y', 89);
         set.insert('Z', 90);
         set.insert('[', 91);
-        set.insert('/', 92);
+        set.insert('\\', 92);
         set.insert(']', 93);
         set.insert('^', 94);
         set.insert('_', 95);
@@ -240,30 +329,75 @@ impl CharSet {
         set.insert('\u{2191}', 131); // upArrow
         set.insert('\u{2192}', 132); // rightArrow
         set.insert('\u{2193}', 133); // downArrow
+        set.insert('\t', 32); // no tab in the Hack character set, print a space
         CharSet(set)
     }
 
-    pub fn decode(&self, char: char) -> i16 {
-        *self.0.get(&char).unwrap()
+    pub fn decode(&self, char: char) -> Result<i16, String> {
+        match self.0.get(&char) {
+            Some(code) => Ok(*code),
+            None => Err(format!("{:?} (U+{:04X}) is not in the Hack character set", char, char as u32))
+        }
     }
 }
 
+// The labels of a class, `BALL_0`, `BALL_1` and so on in the order its code is generated.
+// Each class starts from 0, so its labels do not depend on the other files.
 pub struct LabelGenerator {
-    class_name: String,
+    // the class name in upper case
+    prefix: String,
     counter: i16
 }
 
 impl LabelGenerator {
     pub fn new(class_name: &str) -> Self {
         LabelGenerator {
-            class_name: class_name.to_string(),
+            prefix: class_name.to_uppercase(),
             counter: 0
         }
     }
 
     pub fn generate(&mut self) -> String {
-        let label = format!("{}_{}", self.class_name.to_uppercase(), self.counter);
+        let label = format!("{}_{}", self.prefix, self.counter);
         self.counter += 1;
         label
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inner_scopes_shadow_and_go_on_counting() {
+        let mut scopes = Scopes::new();
+        scopes.push("x", Type::Int, SymbolKind::Field, Span::new(1, 1));
+        scopes.push("y", Type::Int, SymbolKind::Static, Span::new(1, 2));
+        scopes.push_scope();
+        scopes.push("x", Type::Int, SymbolKind::Local, Span::new(2, 1));
+        scopes.push("z", Type::Int, SymbolKind::Local, Span::new(2, 2));
+        scopes.push_scope();
+        scopes.push("y", Type::Int, SymbolKind::Local, Span::new(3, 1));
+        let found = |scopes: &Scopes, name| scopes.find_by(name).map(|symbol| (symbol.vm_memory_segment(), symbol.index()));
+        assert_eq!(found(&scopes, "x"), Some((Segment::Local, 0)));
+        assert_eq!(found(&scopes, "y"), Some((Segment::Local, 2)));
+        assert_eq!(scopes.local_vars_count(), 3);
+        scopes.pop_scope();
+        assert_eq!(found(&scopes, "y"), Some((Segment::Static, 0)));
+        scopes.pop_scope();
+        assert_eq!(found(&scopes, "x"), Some((Segment::This, 0)));
+        assert_eq!(found(&scopes, "z"), None);
+        // the class's scope stays
+        scopes.pop_scope();
+        assert_eq!(scopes.field_vars_count(), 1);
+    }
+
+    #[test]
+    fn the_first_declaration_of_a_name_wins() {
+        let mut scopes = Scopes::new();
+        scopes.push("a", Type::Int, SymbolKind::Argument, Span::new(1, 1));
+        scopes.push("a", Type::Boolean, SymbolKind::Local, Span::new(1, 5));
+        assert_eq!(scopes.find_by("a").map(|symbol| symbol.vm_memory_segment()), Some(Segment::Argument));
+        assert_eq!(scopes.local_vars_count(), 1);
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt::{self, Write};
+use crate::tokenizer::Span;
+
+// The commands of the VM language, the same as the VM translator reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Argument,
+    Local,
+    Static,
+    This,
+    That,
+    Constant,
+    Pointer,
+    Temp
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmCommand {
+    Arithmetic(Operator),
+    Push(Segment, i16),
+    Pop(Segment, i16),
+    Label(String),
+    GoTo(String),
+    IfGoTo(String),
+    Function(String, i16),
+    Call(String, i16),
+    Return,
+    // `// text`, for `--annotate`
+    Comment(String),
+    // not a line of the output: the commands after it were made for the statement at this position,
+    // for `--source-map`
+    Origin(Span)
+}
+
+impl Segment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Segment::Argument => "argument",
+            Segment::Local => "local",
+            Segment::Static => "static",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Constant => "constant",
+            Segment::Pointer => "pointer",
+            Segment::Temp => "temp"
+        }
+    }
+}
+
+impl Operator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operator::Add => "add",
+            Operator::Sub => "sub",
+            Operator::Neg => "neg",
+            Operator::Eq => "eq",
+            Operator::Gt => "gt",
+            Operator::Lt => "lt",
+            Operator::And => "and",
+            Operator::Or => "or",
+            Operator::Not => "not"
+        }
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for VmCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmCommand::Arithmetic(operator) => write!(f, "{}", operator),
+            VmCommand::Push(segment, index) => write!(f, "push {} {}", segment, index),
+            VmCommand::Pop(segment, index) => write!(f, "pop {} {}", segment, index),
+            VmCommand::Label(label) => write!(f, "label {}", label),
+            VmCommand::GoTo(label) => write!(f, "goto {}", label),
+            VmCommand::IfGoTo(label) => write!(f, "if-goto {}", label),
+            VmCommand::Function(name, n_vars) => write!(f, "function {} {}", name, n_vars),
+            VmCommand::Call(name, n_args) => write!(f, "call {} {}", name, n_args),
+            VmCommand::Return => write!(f, "return"),
+            VmCommand::Comment(text) => write!(f, "// {}", text),
+            VmCommand::Origin(_) => Ok(())
+        }
+    }
+}
+
+// the text of a .vm file, one command per line
+pub fn to_text(commands: &[VmCommand]) -> String {
+    let mut text = String::new();
+    for command in commands.iter().filter(|command| !matches!(command, VmCommand::Origin(_))) {
+        // straight into the text, without a String for each command
+        writeln!(text, "{}", command).unwrap();
+    }
+    text
+}
+
+// The `--source-map` of the text, one record for each of its lines, `12 Main.jack:5:9` for the
+// statement line 12 was made for, and `function Main.main 1 Main.jack:2:14` before the record of
+// each function's first line, with the position of its name
+pub fn source_map(commands: &[VmCommand], file: &str) -> String {
+    let mut map = String::new();
+    let mut origin = None;
+    let mut line = 0;
+    for command in commands.iter() {
+        if let VmCommand::Origin(span) = command {
+            origin = Some(*span);
+            continue;
+        }
+        line += 1;
+        let Some(span) = origin else { continue };
+        if let VmCommand::Function(name, _) = command {
+            map.push_str(&format!("function {} {} {}:{}\n", name, line, file, span));
+        }
+        map.push_str(&format!("{} {}:{}\n", line, file, span));
+    }
+    map
+}
+
+// the static segment of the Hack platform, RAM 16 to 255
+const MAX_STATICS: usize = 240;
+
+// The `--merge-output` of several classes, `("Main.jack", text)` in order, as one file. The statics
+// of a VM file are its own, so `static i` of a class becomes `static offset + i`, after the statics
+// of the classes before it, and each class starts with a comment naming its file.
+pub fn merge(classes: &[(&str, &str)]) -> Result<String, String> {
+    let mut merged = String::new();
+    let mut offset = 0;
+    for (file, text) in classes.iter() {
+        let mut statics = 0;
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let static_index = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [command @ ("push" | "pop"), "static", index] => index.parse::<usize>().ok().map(|index| (command, index)),
+                _ => None
+            };
+            match static_index {
+                Some((command, index)) => {
+                    statics = statics.max(index + 1);
+                    lines.push(format!("{} static {}", command, offset + index));
+                },
+                None => lines.push(line.to_string())
+            }
+        }
+        merged.push_str(&format!("// {}, statics from {}\n", file, offset));
+        for line in lines {
+            merged.push_str(&line);
+            merged.push('\n');
+        }
+        offset += statics;
+    }
+    if offset > MAX_STATICS {
+        return Err(format!("the merged classes have {} statics, the Hack platform has room for {}", offset, MAX_STATICS));
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_classes_keep_their_statics_apart() {
+        let a = "function A.f 0\npush static 1\npop static 0\nreturn\n";
+        let b = "function B.f 0\npush static 0\nreturn\n";
+        let c = "function C.f 0\npush static 0\nreturn\n";
+        assert_eq!(merge(&[("A.jack", a), ("B.jack", b), ("C.jack", c)]).unwrap(), "\
+// A.jack, statics from 0
+function A.f 0
+push static 1
+pop static 0
+return
+// B.jack, statics from 2
+function B.f 0
+push static 2
+return
+// C.jack, statics from 3
+function C.f 0
+push static 3
+return
+");
+        let full = (0..MAX_STATICS).map(|index| format!("push static {}\n", index)).collect::<String>();
+        assert!(merge(&[("A.jack", &full)]).is_ok());
+        assert_eq!(
+            merge(&[("A.jack", &full), ("B.jack", b)]).unwrap_err(),
+            "the merged classes have 241 statics, the Hack platform has room for 240"
+        );
+    }
+
+    #[test]
+    fn lines_map_to_their_origin() {
+        let commands = vec![
+            VmCommand::Origin(Span::new(2, 14)),
+            VmCommand::Function("Main.main".to_string(), 0),
+            VmCommand::Origin(Span::new(3, 9)),
+            VmCommand::Push(Segment::Constant, 0),
+            VmCommand::Return
+        ];
+        assert_eq!(to_text(&commands), "function Main.main 0\npush constant 0\nreturn\n");
+        assert_eq!(source_map(&commands, "Main.jack"), "\
+function Main.main 1 Main.jack:2:14
+1 Main.jack:2:14
+2 Main.jack:3:9
+3 Main.jack:3:9
+");
+    }
+
+    #[test]
+    fn commands_as_text() {
+        let commands = vec![
+            VmCommand::Function("Main.main".to_string(), 1),
+            VmCommand::Push(Segment::Constant, 7),
+            VmCommand::Pop(Segment::Local, 0),
+            VmCommand::Label("Main_0".to_string()),
+            VmCommand::Arithmetic(Operator::Not),
+            VmCommand::IfGoTo("Main_1".to_string()),
+            VmCommand::GoTo("Main_0".to_string()),
+            VmCommand::Comment("Main.jack:3: do Output.printInt(x);".to_string()),
+            VmCommand::Call("Output.printInt".to_string(), 1),
+            VmCommand::Return
+        ];
+        assert_eq!(to_text(&commands), "\
+function Main.main 1
+push constant 7
+pop local 0
+label Main_0
+not
+if-goto Main_1
+goto Main_0
+// Main.jack:3: do Output.printInt(x);
+call Output.printInt 1
+return
+");
+    }
+}
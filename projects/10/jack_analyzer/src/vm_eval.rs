@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::vm::{Operator, Segment, VmCommand};
+
+// the stack is RAM 256 to 2047 and the heap 2048 to 16383, as on the Hack platform
+const STACK: usize = 256;
+const HEAP: usize = 2048;
+const HEAP_END: usize = 16384;
+// a call that has not returned by then is taken for an endless loop
+const MAX_STEPS: usize = 10_000_000;
+
+/// Why a call did not return.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// The stack grew past RAM 2047, like unbounded recursion does.
+    StackOverflow,
+    /// `Sys.error` with its code, by the program or the OS stubs, which use the codes of the course's OS.
+    Error(i16),
+    /// A call to a function that is neither in the code nor one of the stubs.
+    UnknownFunction(String),
+    /// A jump to a label that is not in the code.
+    UnknownLabel(String),
+    /// Ten million commands without returning.
+    StepLimit
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::StackOverflow => write!(f, "stack overflow"),
+            Stop::Error(code) => write!(f, "Sys.error({})", code),
+            Stop::UnknownFunction(name) => write!(f, "call to unknown function {}", name),
+            Stop::UnknownLabel(label) => write!(f, "jump to unknown label {}", label),
+            Stop::StepLimit => write!(f, "no return after {} commands", MAX_STEPS)
+        }
+    }
+}
+
+impl Error for Stop {}
+
+/// What a call returned, and the heap and screen output it left behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub value: i16,
+    /// RAM 2048 up to the end of the last allocation.
+    pub heap: Vec<i16>,
+    /// What went through `Output`, with `\n` for new lines.
+    pub output: String
+}
+
+impl Outcome {
+    /// The text of a string made by the `String` stubs at an address, like the returned value.
+    pub fn string(&self, address: i16) -> Option<String> {
+        let start = (address as usize).checked_sub(HEAP)?;
+        let length = *self.heap.get(start)? as usize;
+        let chars = self.heap.get(start + 2..start + 2 + length)?;
+        Some(chars.iter().map(|&char| char_of(char)).collect())
+    }
+}
+
+/// Runs a function of VM code with arguments, and the functions it calls, on a Hack-like machine.
+///
+/// The OS is not needed: calls to OS functions that are not in the code go to stubs for `Math`
+/// (multiply, divide, abs, min, max), `Memory` (alloc over a flat heap that is never reused,
+/// deAlloc, peek, poke), `Array`, `String` (new, appendChar, length, charAt, setCharAt, dispose),
+/// `Output` (printChar, printString, printInt, println) and `Sys.error`.
+///
+/// ```
+/// let source = "
+///     class Main {
+///         function int factorial(int n) {
+///             if (n < 2) { return 1; }
+///             return n * Main.factorial(n - 1);
+///         }
+///     }";
+/// let commands = jack_analyzer::compile_to_commands(source).unwrap();
+/// let outcome = jack_analyzer::vm_eval::call(&commands, "Main.factorial", &[6]).unwrap();
+/// assert_eq!(outcome.value, 720);
+/// ```
+pub fn call(commands: &[VmCommand], function: &str, args: &[i16]) -> Result<Outcome, Stop> {
+    let mut machine = Machine::new(commands);
+    let value = machine.call(function, args)?;
+    Ok(Outcome { value, heap: machine.ram[HEAP..machine.heap_top].to_vec(), output: machine.output })
+}
+
+struct Machine<'a> {
+    commands: &'a [VmCommand],
+    functions: HashMap<&'a str, usize>,
+    labels: HashMap<&'a str, usize>,
+    ram: Vec<i16>,
+    heap_top: usize,
+    // the statics of each class by index, apart from the RAM
+    statics: HashMap<(String, i16), i16>,
+    output: String
+}
+
+impl<'a> Machine<'a> {
+    fn new(commands: &'a [VmCommand]) -> Self {
+        let mut functions = HashMap::new();
+        let mut labels = HashMap::new();
+        for (index, command) in commands.iter().enumerate() {
+            match command {
+                VmCommand::Function(name, _) => { functions.insert(name.as_str(), index); },
+                VmCommand::Label(label) => { labels.insert(label.as_str(), index); },
+                _ => {}
+            }
+        }
+        let mut ram = vec![0; 32768];
+        ram[0] = STACK as i16;
+        Machine { commands, functions, labels, ram, heap_top: HEAP, statics: HashMap::new(), output: String::new() }
+    }
+
+    fn push(&mut self, value: i16) -> Result<(), Stop> {
+        let top = self.ram[0] as usize;
+        if top >= HEAP {
+            return Err(Stop::StackOverflow);
+        }
+        self.ram[top] = value;
+        self.ram[0] += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> i16 {
+        self.ram[0] -= 1;
+        self.ram[self.ram[0] as usize]
+    }
+
+    fn address(&self, segment: Segment, index: i16) -> usize {
+        let base = |register: usize| self.ram[register] as u16 as usize;
+        let address = match segment {
+            Segment::Local => base(1) + index as usize,
+            Segment::Argument => base(2) + index as usize,
+            Segment::This => base(3) + index as usize,
+            Segment::That => base(4) + index as usize,
+            Segment::Pointer => 3 + index as usize,
+            _ => 5 + index as usize
+        };
+        address & 0x7fff
+    }
+
+    // a static belongs to the class of the function that uses it
+    fn static_of(function: &str, index: i16) -> (String, i16) {
+        (function.split('.').next().unwrap_or_default().to_string(), index)
+    }
+
+    // the first call, which returns to nowhere
+    fn call(&mut self, function: &str, args: &[i16]) -> Result<i16, Stop> {
+        for &arg in args {
+            self.push(arg)?;
+        }
+        let mut pc = match self.enter(function, args.len() as i16, usize::MAX)? {
+            Some(pc) => pc,
+            None => return Ok(self.pop())
+        };
+        // the functions whose calls have not returned, the last one is running
+        let mut functions = vec![function.to_string()];
+        for _ in 0..MAX_STEPS {
+            let command = &self.commands[pc];
+            pc += 1;
+            let current = functions.last().map(String::as_str).unwrap_or_default();
+            match command {
+                VmCommand::Push(Segment::Constant, value) => self.push(*value)?,
+                VmCommand::Push(Segment::Static, index) => {
+                    let value = self.statics.get(&Machine::static_of(current, *index)).copied().unwrap_or_default();
+                    self.push(value)?
+                },
+                VmCommand::Push(segment, index) => self.push(self.ram[self.address(*segment, *index)])?,
+                VmCommand::Pop(Segment::Static, index) => {
+                    let value = self.pop();
+                    self.statics.insert(Machine::static_of(current, *index), value);
+                },
+                VmCommand::Pop(segment, index) => {
+                    let value = self.pop();
+                    let address = self.address(*segment, *index);
+                    self.ram[address] = value;
+                },
+                VmCommand::Arithmetic(operator) => {
+                    let y = self.pop();
+                    let value = match operator {
+                        Operator::Neg => y.wrapping_neg(),
+                        Operator::Not => !y,
+                        _ => {
+                            let x = self.pop();
+                            match operator {
+                                Operator::Add => x.wrapping_add(y),
+                                Operator::Sub => x.wrapping_sub(y),
+                                Operator::Eq => -((x == y) as i16),
+                                Operator::Gt => -((x > y) as i16),
+                                Operator::Lt => -((x < y) as i16),
+                                Operator::And => x & y,
+                                _ => x | y
+                            }
+                        }
+                    };
+                    self.push(value)?;
+                },
+                VmCommand::Label(_) | VmCommand::Comment(_) | VmCommand::Origin(_) => {},
+                VmCommand::GoTo(label) => pc = self.label(label)?,
+                VmCommand::IfGoTo(label) => if self.pop() != 0 {
+                    pc = self.label(label)?;
+                },
+                VmCommand::Function(_, n_vars) => {
+                    for _ in 0..*n_vars {
+                        self.push(0)?;
+                    }
+                },
+                VmCommand::Call(name, n_args) => {
+                    if let Some(target) = self.enter(name, *n_args, pc)? {
+                        functions.push(name.clone());
+                        pc = target;
+                    }
+                },
+                VmCommand::Return => {
+                    let frame = self.ram[1] as usize;
+                    let return_to = self.ram[frame - 5];
+                    let value = self.pop();
+                    let arg = self.ram[2] as usize;
+                    self.ram[arg] = value;
+                    self.ram[0] = self.ram[2] + 1;
+                    for (offset, register) in [4, 3, 2, 1].into_iter().enumerate() {
+                        self.ram[register] = self.ram[frame - 1 - offset];
+                    }
+                    if return_to == -1 {
+                        return Ok(value);
+                    }
+                    functions.pop();
+                    pc = return_to as usize;
+                }
+            }
+        }
+        Err(Stop::StepLimit)
+    }
+
+    fn label(&self, label: &str) -> Result<usize, Stop> {
+        self.labels.get(label).copied().ok_or_else(|| Stop::UnknownLabel(label.to_string()))
+    }
+
+    // Calls a function of the code, whose first command is where it goes on, or a stub, which
+    // leaves its value on the stack right away
+    fn enter(&mut self, name: &str, n_args: i16, return_to: usize) -> Result<Option<usize>, Stop> {
+        if let Some(&start) = self.functions.get(name) {
+            for value in [return_to as i16, self.ram[1], self.ram[2], self.ram[3], self.ram[4]] {
+                self.push(value)?;
+            }
+            self.ram[2] = self.ram[0] - 5 - n_args;
+            self.ram[1] = self.ram[0];
+            return Ok(Some(start));
+        }
+        let mut args: Vec<i16> = (0..n_args).map(|_| self.pop()).collect();
+        args.reverse();
+        let value = self.stub(name, &args)?;
+        self.push(value)?;
+        Ok(None)
+    }
+
+    fn stub(&mut self, name: &str, args: &[i16]) -> Result<i16, Stop> {
+        let arg = |index: usize| args.get(index).copied().unwrap_or_default();
+        match name {
+            "Math.multiply" => Ok(arg(0).wrapping_mul(arg(1))),
+            "Math.divide" if arg(1) == 0 => Err(Stop::Error(3)),
+            "Math.divide" => Ok(arg(0).wrapping_div(arg(1))),
+            "Math.abs" => Ok(arg(0).wrapping_abs()),
+            "Math.min" => Ok(arg(0).min(arg(1))),
+            "Math.max" => Ok(arg(0).max(arg(1))),
+            "Memory.alloc" if arg(0) <= 0 => Err(Stop::Error(5)),
+            "Memory.alloc" => self.alloc(arg(0) as usize, 6),
+            "Memory.deAlloc" | "Array.dispose" | "String.dispose" => Ok(0),
+            "Memory.peek" => Ok(self.ram[arg(0) as u16 as usize & 0x7fff]),
+            "Memory.poke" => {
+                self.ram[arg(0) as u16 as usize & 0x7fff] = arg(1);
+                Ok(0)
+            },
+            "Array.new" if arg(0) <= 0 => Err(Stop::Error(2)),
+            "Array.new" => self.alloc(arg(0) as usize, 6),
+            // the length, the room there is, then the characters
+            "String.new" if arg(0) <= 0 => Err(Stop::Error(14)),
+            "String.new" => {
+                let string = self.alloc(arg(0) as usize + 2, 6)?;
+                self.ram[string as usize + 1] = arg(0);
+                Ok(string)
+            },
+            "String.appendChar" => {
+                let string = arg(0) as usize;
+                let length = self.ram[string];
+                if length >= self.ram[string + 1] {
+                    return Err(Stop::Error(17));
+                }
+                self.ram[string + 2 + length as usize] = arg(1);
+                self.ram[string] += 1;
+                Ok(arg(0))
+            },
+            "String.length" => Ok(self.ram[arg(0) as usize]),
+            "String.charAt" | "String.setCharAt" if arg(1) < 0 || arg(1) >= self.ram[arg(0) as usize] => Err(Stop::Error(15)),
+            "String.charAt" => Ok(self.ram[arg(0) as usize + 2 + arg(1) as usize]),
+            "String.setCharAt" => {
+                self.ram[arg(0) as usize + 2 + arg(1) as usize] = arg(2);
+                Ok(0)
+            },
+            "Output.printChar" => {
+                self.output.push(char_of(arg(0)));
+                Ok(0)
+            },
+            "Output.printString" => {
+                let string = arg(0) as usize;
+                let chars: String = (0..self.ram[string] as usize).map(|index| char_of(self.ram[string + 2 + index])).collect();
+                self.output.push_str(&chars);
+                Ok(0)
+            },
+            "Output.printInt" => {
+                self.output.push_str(&arg(0).to_string());
+                Ok(0)
+            },
+            "Output.println" => {
+                self.output.push('\n');
+                Ok(0)
+            },
+            "Sys.error" => Err(Stop::Error(arg(0))),
+            _ => Err(Stop::UnknownFunction(name.to_string()))
+        }
+    }
+
+    fn alloc(&mut self, size: usize, error: i16) -> Result<i16, Stop> {
+        if self.heap_top + size > HEAP_END {
+            return Err(Stop::Error(error));
+        }
+        let address = self.heap_top;
+        self.heap_top += size;
+        Ok(address as i16)
+    }
+}
+
+// the Hack character of a code, new line for 128
+fn char_of(code: i16) -> char {
+    match code {
+        128 => '\n',
+        code => char::from_u32(code as u32).unwrap_or('?')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<VmCommand> {
+        text.lines().map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["push", "constant", value] => VmCommand::Push(Segment::Constant, value.parse().unwrap()),
+            ["push", "argument", index] => VmCommand::Push(Segment::Argument, index.parse().unwrap()),
+            ["push", "static", index] => VmCommand::Push(Segment::Static, index.parse().unwrap()),
+            ["pop", "static", index] => VmCommand::Pop(Segment::Static, index.parse().unwrap()),
+            ["function", name, n_vars] => VmCommand::Function(name.to_string(), n_vars.parse().unwrap()),
+            ["call", name, n_args] => VmCommand::Call(name.to_string(), n_args.parse().unwrap()),
+            ["add"] => VmCommand::Arithmetic(Operator::Add),
+            ["return"] => VmCommand::Return,
+            _ => panic!("{}", line)
+        }).collect()
+    }
+
+    #[test]
+    fn calls_stubs_and_statics() {
+        let commands = parse("\
+function A.f 0
+push argument 0
+pop static 0
+push constant 5
+call B.g 1
+push static 0
+add
+return
+function B.g 0
+push argument 0
+pop static 0
+push static 0
+push constant 3
+call Math.multiply 2
+return");
+        assert_eq!(call(&commands, "A.f", &[2]).unwrap().value, 17);
+        assert_eq!(call(&commands, "B.g", &[-4]).unwrap().value, -12);
+        assert_eq!(call(&commands, "A.h", &[]), Err(Stop::UnknownFunction("A.h".to_string())));
+    }
+
+    #[test]
+    fn strings_and_output_of_the_stubs() {
+        let commands = parse("\
+function A.f 0
+push constant 2
+call String.new 1
+push constant 72
+call String.appendChar 2
+push constant 105
+call String.appendChar 2
+call Output.printString 1
+push constant 7
+call Output.printInt 1
+add
+call Output.println 0
+add
+push constant 1
+call String.new 1
+return");
+        let outcome = call(&commands, "A.f", &[]).unwrap();
+        assert_eq!(outcome.output, "Hi7\n");
+        assert_eq!(outcome.string(2048).as_deref(), Some("Hi"));
+        assert_eq!(outcome.string(outcome.value).as_deref(), Some(""));
+        assert_eq!(outcome.heap.len(), 4 + 3);
+
+        let divide = parse("function A.f 0\npush argument 0\npush constant 0\ncall Math.divide 2\nreturn");
+        assert_eq!(call(&divide, "A.f", &[1]), Err(Stop::Error(3)));
+    }
+
+    #[test]
+    fn endless_recursion_overflows() {
+        let commands = parse("function A.f 0\npush argument 0\ncall A.f 1\nreturn");
+        assert_eq!(call(&commands, "A.f", &[1]), Err(Stop::StackOverflow));
+    }
+}
@@ -0,0 +1,26 @@
+// `jack_analyzer Main.jack --dump-ast`, the tree of each class on stdout next to the usual output
+#![cfg(feature = "fs")]
+
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn the_tree_goes_to_stdout() {
+    let directory = tempfile::tempdir().unwrap();
+    fs::write(directory.path().join("Main.jack"), "class Main {\n    function int f() { return 1 + x; }\n}\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_jack_analyzer"))
+        .args(["Main.jack", "xml", "--dump-ast"])
+        .current_dir(directory.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+Class Main @1:7
+  SubroutineDec function int f() @2:18
+    ReturnStatement @2:24
+      BinaryOp + @2:33
+        IntegerConstant 1
+        VarName x @2:35
+");
+    assert!(directory.path().join("Main.xml").exists());
+}
@@ -0,0 +1,40 @@
+// `jack_analyzer eval "2 + 3"`, a snippet compiled in a class of its own and run on the VM evaluator
+#![cfg(feature = "fs")]
+
+use std::process::{Command, Output};
+
+fn jack_analyzer(args: &[&str]) -> Output {
+    // from a directory without a jack.toml above it
+    let directory = tempfile::tempdir().unwrap();
+    Command::new(env!("CARGO_BIN_EXE_jack_analyzer")).args(args).current_dir(directory.path()).output().unwrap()
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[test]
+fn expressions_and_statements_print_their_value() {
+    assert_eq!(text(&jack_analyzer(&["eval", "2 + 3 * (4 - 1)"]).stdout), "15\n");
+    assert_eq!(text(&jack_analyzer(&["--precedence", "eval", "2 + 3 * (4 - 1)"]).stdout), "11\n");
+    assert_eq!(text(&jack_analyzer(&["eval", "--statements", "let x = 5; return x * x;"]).stdout), "25\n");
+    // what the snippet prints comes first
+    let output = jack_analyzer(&["eval", "--statements", "do Output.printInt(7); return 1;"]);
+    assert!(output.status.success());
+    assert_eq!(text(&output.stdout), "7\n1\n");
+}
+
+#[test]
+fn emit_vm_prints_the_code_instead() {
+    let output = jack_analyzer(&["eval", "--emit-vm", "1 + 2"]);
+    assert_eq!(text(&output.stdout), "function Repl.eval 0\npush constant 1\npush constant 2\nadd\nreturn\n");
+}
+
+#[test]
+fn undefined_names_fail_like_in_a_class() {
+    let output = jack_analyzer(&["eval", "y + 1"]);
+    assert!(!output.status.success());
+    assert!(text(&output.stderr).starts_with("<eval>:2:8: undefined variable y in Repl.eval"));
+    assert!(!jack_analyzer(&["eval"]).status.success());
+    assert!(!jack_analyzer(&["--emit-vm", "vm", "Main.jack"]).status.success());
+}
@@ -0,0 +1,18 @@
+// The fixtures against their goldens, `UPDATE_GOLDENS=1 cargo test` takes the compiler's output as the new goldens
+#![cfg(feature = "fs")]
+
+#[test]
+fn fixtures_match_their_goldens() {
+    if let Err(error) = jack_analyzer::testing::check_goldens() {
+        panic!("{}", error);
+    }
+}
+
+#[test]
+fn selftest_checks_the_fixtures_of_the_binary() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_jack_analyzer")).arg("--selftest").output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "3 passed, 0 failed\n");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_jack_analyzer")).args(["--selftest", "vm"]).output().unwrap();
+    assert!(!output.status.success());
+}
@@ -0,0 +1,70 @@
+// `--emit-interface` for a library, then `--with-interface` for the classes that call it, without its sources
+#![cfg(feature = "fs")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn jack_analyzer(args: &[&str], directory: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jack_analyzer")).args(args).current_dir(directory).output().unwrap()
+}
+
+#[test]
+fn a_client_is_checked_against_the_interfaces_alone() {
+    let directory = tempfile::tempdir().unwrap();
+    let lib = directory.path().join("lib");
+    let app = directory.path().join("app");
+    fs::create_dir_all(&lib).unwrap();
+    fs::create_dir_all(&app).unwrap();
+    fs::write(lib.join("Ball.jack"), "\
+class Ball {
+    field int x, y;
+    constructor Ball new(int ax, int ay) { let x = ax; let y = ay; return this; }
+    method void move(int dx, int dy) { let x = x + dx; let y = y + dy; return; }
+}").unwrap();
+    fs::write(lib.join("Util.jack"), "class Util { function int twice(int n) { return n + n; } }").unwrap();
+    let output = jack_analyzer(&["lib", "--emit-interface", "-o", "interfaces"], directory.path());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        fs::read_to_string(directory.path().join("interfaces/Ball.jacki")).unwrap(),
+        "jack-interface 1\nclass Ball\nfields 2\nconstructor Ball new(int, int)\nmethod void move(int, int)\n"
+    );
+    fs::remove_dir_all(&lib).unwrap();
+
+    let main = "\
+class Main {
+    function void main() {
+        var Ball ball;
+        let ball = Ball.new(Util.twice(1), 2);
+        do ball.move(1);
+        return;
+    }
+}";
+    fs::write(app.join("Main.jack"), main).unwrap();
+    let output = jack_analyzer(&["app", "--with-interface", "interfaces"], directory.path());
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("5:12: Ball.move takes 2 arguments, found 1"), "{}", stderr);
+    assert!(stderr.contains("note: Ball.move is declared at interfaces/Ball.jacki:5:1"), "{}", stderr);
+
+    // a class compiled on its own is not checked against the others
+    assert!(jack_analyzer(&["app"], directory.path()).status.success());
+
+    fs::write(app.join("Main.jack"), main.replace("move(1)", "move(1, 2)")).unwrap();
+    let output = jack_analyzer(&["app", "--with-interface", "interfaces/Ball.jacki", "--with-interface", "interfaces/Util.jacki"], directory.path());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(app.join("Main.vm").exists());
+}
+
+#[test]
+fn interfaces_of_another_format_are_errors() {
+    let directory = tempfile::tempdir().unwrap();
+    fs::write(directory.path().join("Main.jack"), "class Main { function void main() { return; } }").unwrap();
+    fs::write(directory.path().join("Ball.jacki"), "jack-interface 7\nclass Ball\n").unwrap();
+    let output = jack_analyzer(&["Main.jack", "--with-interface", "Ball.jacki"], directory.path());
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Ball.jacki:1: interface format 7, this compiler reads format 1\n  note: write it again with --emit-interface\n"
+    );
+}
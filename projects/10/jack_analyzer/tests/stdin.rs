@@ -0,0 +1,62 @@
+// The binary reading a class from stdin, like `echo 'class T {...}' | jack_analyzer - vm`
+#![cfg(feature = "fs")]
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn jack_analyzer(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jack_analyzer"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // a binary that stops at its arguments never reads the input, and the write fails then
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[test]
+fn vm_code_goes_to_stdout() {
+    let output = jack_analyzer(&["-", "vm"], "class T { function void main() { return; } }");
+    assert!(output.status.success());
+    assert_eq!(text(&output.stdout), "function T.main 0\npush constant 0\nreturn\n");
+    assert_eq!(text(&output.stderr), "1 succeeded, 0 failed\n");
+}
+
+#[test]
+fn xml_and_fmt_go_to_stdout() {
+    let output = jack_analyzer(&["-", "xml"], "class T {}");
+    assert!(text(&output.stdout).starts_with("<class>\n  <keyword> class </keyword>\n  <identifier> T </identifier>\n"));
+    let output = jack_analyzer(&["fmt", "-"], "class T {\nfunction void main() { return; }\n}\n");
+    assert_eq!(text(&output.stdout), "class T {\n    function void main() {\n        return;\n    }\n}\n");
+}
+
+#[test]
+fn errors_go_to_stderr_with_nothing_on_stdout() {
+    let output = jack_analyzer(&["-", "vm"], "class T { function void main() { let x = 1; return; } }");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(text(&output.stderr).contains("<stdin>:1:"), "{}", text(&output.stderr));
+}
+
+#[test]
+fn class_name_is_checked() {
+    let source = "class T { function void main() { return; } }";
+    assert!(jack_analyzer(&["-", "vm", "--class-name", "T"], source).status.success());
+    let output = jack_analyzer(&["-", "vm", "--class-name", "Main"], source);
+    assert!(!output.status.success());
+    assert!(text(&output.stderr).contains("expected class Main, found class T"), "{}", text(&output.stderr));
+}
+
+#[test]
+fn stdin_needs_a_target() {
+    let output = jack_analyzer(&["-"], "class T {}");
+    assert!(!output.status.success());
+    assert!(text(&output.stderr).contains("- needs the xml, vm or fmt target."));
+}
@@ -0,0 +1,33 @@
+// `--timings` prints a row for each file and the total, with a column for each phase
+#![cfg(feature = "fs")]
+
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn the_table_has_every_phase_and_adds_up() {
+    let directory = tempfile::tempdir().unwrap();
+    fs::write(directory.path().join("Main.jack"), "class Main { function void main() { var Ball b; let b = Ball.new(); return; } }").unwrap();
+    fs::write(directory.path().join("Ball.jack"), "class Ball { field int x; constructor Ball new() { let x = 1; return this; } }").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_jack_analyzer"))
+        .args([directory.path().to_str().unwrap(), "vm", "--timings", "--quiet"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines[0].split_whitespace().collect::<Vec<_>>(), ["io", "tokenize", "parse", "analysis", "codegen", "total"]);
+    assert_eq!(lines.len(), 4, "{}", stderr);
+    assert!(lines[1].contains("Ball.jack") && lines[2].contains("Main.jack") && lines[3].starts_with("total"));
+
+    let numbers = |line: &str| -> Vec<f64> { line.split_whitespace().skip(1).map(|number| number.parse().unwrap()).collect() };
+    let rows: Vec<Vec<f64>> = lines[1..].iter().map(|line| numbers(line)).collect();
+    // the printed numbers are rounded to microseconds
+    for row in rows.iter() {
+        assert!((row[..5].iter().sum::<f64>() - row[5]).abs() < 0.01, "{:?}", row);
+    }
+    for phase in 0..6 {
+        assert!((rows[0][phase] + rows[1][phase] - rows[2][phase]).abs() < 0.01, "{:?}", rows);
+    }
+    assert!(rows[2][5] > 0.0);
+}
@@ -0,0 +1,71 @@
+// What the binary prints with --quiet, by default and with --verbose: stdout stays empty, the
+// progress goes to stderr
+#![cfg(feature = "fs")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn jack_analyzer(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jack_analyzer")).args(args).output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+// two classes, the one in A.jack is named C
+fn project(directory: &Path) -> String {
+    fs::write(directory.join("A.jack"), "class C { function void f() { return; } }").unwrap();
+    fs::write(directory.join("B.jack"), "class B { function void g() { return; } }").unwrap();
+    directory.to_str().unwrap().to_string()
+}
+
+#[test]
+fn default_prints_the_warnings_and_a_summary() {
+    let directory = tempfile::tempdir().unwrap();
+    let output = jack_analyzer(&[&project(directory.path()), "vm"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = stderr(&output);
+    assert!(stderr.starts_with("warning: "), "{}", stderr);
+    assert!(stderr.ends_with("class C should be declared in C.jack [-W class-file-name]\n2 succeeded, 0 failed\n"), "{}", stderr);
+}
+
+#[test]
+fn verbose_adds_a_line_for_each_file() {
+    let directory = tempfile::tempdir().unwrap();
+    let output = jack_analyzer(&[&project(directory.path()), "vm", "--verbose"]);
+    assert!(output.stdout.is_empty());
+    let stderr = stderr(&output);
+    assert!(stderr.contains("Compiling: C\n"), "{}", stderr);
+    assert!(stderr.ends_with("Compiling: B\n2 succeeded, 0 failed\n"), "{}", stderr);
+}
+
+#[test]
+fn quiet_prints_only_errors() {
+    let directory = tempfile::tempdir().unwrap();
+    let path = project(directory.path());
+    let output = jack_analyzer(&[&path, "vm", "--quiet"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+
+    // a denied warning is an error
+    let output = jack_analyzer(&[&path, "vm", "-q", "--deny-warnings"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(stderr(&output).contains("class C should be declared in C.jack"));
+
+    fs::write(directory.path().join("B.jack"), "class B { function void g() { return } }").unwrap();
+    let output = jack_analyzer(&[&path, "vm", "-q"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("1 succeeded, 1 failed:"), "{}", stderr(&output));
+}
+
+#[test]
+fn quiet_and_verbose_exclude_each_other() {
+    let directory = tempfile::tempdir().unwrap();
+    let output = jack_analyzer(&[&project(directory.path()), "-q", "-v"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("--quiet and --verbose cannot be combined."));
+}
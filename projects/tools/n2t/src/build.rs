@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use jack_analyzer::LanguageOptions;
+
+// How far `n2t build` goes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    // Foo.vm for each class
+    Vm,
+    // Pong.asm for Pong/
+    Asm,
+    // Pong.hack
+    Hack
+}
+
+impl Stage {
+    pub fn new(name: &str) -> Option<Self> {
+        match name {
+            "vm" => Some(Stage::Vm),
+            "asm" => Some(Stage::Asm),
+            "hack" => Some(Stage::Hack),
+            _ => None
+        }
+    }
+}
+
+// A VM file of the program, and for a compiled class the position in its Jack file that each
+// line was made for, from the source map of the compiler
+struct VmFile {
+    name: String,
+    text: String,
+    origins: HashMap<usize, String>
+}
+
+// The classes of the directory compiled with jack_analyzer, then with the other .vm files of the
+// directory like the OS translated and assembled, each stage getting the text of the one before.
+// Only the last stage is written, to the output directory. An error of the translator or the
+// assembler is reported at the Jack line its code was made for.
+pub fn build(directory: &Path, to: Stage, output: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let jack_files = files_with_extension(directory, "jack")?;
+    if jack_files.is_empty() {
+        return Err(format!("{}: no .jack files to build", directory.display()).into());
+    }
+    let sources = jack_files.iter()
+        .map(|path| Ok((path.display().to_string(), fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    let inputs: Vec<(&str, &str)> = sources.iter().map(|(name, source)| (name.as_str(), source.as_str())).collect();
+    let mut program = Vec::new();
+    let mut errors = Vec::new();
+    for ((name, _), class) in sources.iter().zip(jack_analyzer::compile_project(&inputs, LanguageOptions::default())) {
+        match class {
+            Ok(class) => {
+                for warning in class.warnings.iter() {
+                    eprintln!("{}", located(name, &warning.to_string()));
+                }
+                program.push(VmFile { name: name.replace(".jack", ".vm"), text: class.vm, origins: origins(&class.source_map) });
+            },
+            Err(error) => errors.push(located(name, &error.to_string()))
+        }
+    }
+    if !errors.is_empty() {
+        errors.push(format!("{} of {} classes failed", errors.len(), sources.len()));
+        return Err(errors.join("\n").into());
+    }
+    fs::create_dir_all(output).map_err(|e| format!("{}: {}", output.display(), e))?;
+    if to == Stage::Vm {
+        let mut written = Vec::new();
+        for file in program.iter() {
+            let path = output.join(Path::new(&file.name).file_name().unwrap_or_default());
+            write(&path, &file.text)?;
+            written.push(path);
+        }
+        return Ok(written);
+    }
+    // the .vm files of the directory without a class of the same name, like the OS
+    for path in files_with_extension(directory, "vm")? {
+        let name = path.display().to_string();
+        if !program.iter().any(|file| file.name == name) {
+            program.push(VmFile { text: fs::read_to_string(&path).map_err(|e| format!("{}: {}", name, e))?, name, origins: HashMap::new() });
+        }
+    }
+    let vm: Vec<(&str, &str)> = program.iter().map(|file| (file.name.as_str(), file.text.as_str())).collect();
+    let assembly = vmtranslator::translate(&vm).map_err(|(index, error)| translator_error(&program[index], &error))?;
+    let program_name = program_name(directory)?;
+    let asm_path = output.join(format!("{}.asm", program_name));
+    if to == Stage::Asm {
+        write(&asm_path, &assembly)?;
+        return Ok(vec![asm_path]);
+    }
+    // more than the assembler can number, which the VM code of the whole Jack OS already is
+    let instructions = assembly.lines().filter(|line| is_instruction(line)).count();
+    if instructions > ROM {
+        let error = format!("{}: the program has {} instructions, the ROM of the Hack computer holds {}", asm_path.display(), instructions, ROM);
+        return Err(error.into());
+    }
+    let binary = assembler::assemble(&assembly)
+        .map_err(|(line, error)| assembler_error(&program, &assembly, &asm_path.display().to_string(), line, &error))?;
+    let hack_path = output.join(format!("{}.hack", program_name));
+    write(&hack_path, &binary)?;
+    Ok(vec![hack_path])
+}
+
+const ROM: usize = 32768;
+
+// not a label, a comment or an empty line
+fn is_instruction(line: &str) -> bool {
+    let code = line.split_once("//").map_or(line, |(code, _)| code).trim();
+    !code.is_empty() && !code.starts_with('(')
+}
+
+// what `build` writes of a directory, for `n2t clean`
+pub fn artifacts(directory: &Path, output: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let jack_files = files_with_extension(directory, "jack")?;
+    if jack_files.is_empty() {
+        return Err(format!("{}: no .jack files, n2t builds nothing there", directory.display()).into());
+    }
+    let program_name = program_name(directory)?;
+    let mut artifacts: Vec<PathBuf> = jack_files.iter()
+        .filter_map(|path| Some(output.join(path.file_name()?).with_extension("vm")))
+        .collect();
+    artifacts.extend(["asm", "hack"].map(|extension| output.join(format!("{}.{}", program_name, extension))));
+    Ok(artifacts.into_iter().filter(|path| path.is_file()).collect())
+}
+
+// Pong for Pong/, also when it is given as `.`
+fn program_name(directory: &Path) -> Result<String, Box<dyn Error>> {
+    let directory = fs::canonicalize(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+    Ok(directory.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| "Program".to_string()))
+}
+
+// the files of a directory with the extension, sorted by path
+pub fn files_with_extension(directory: &Path, extension: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = fs::read_dir(directory).map_err(|e| format!("{}: {}", directory.display(), e))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    Ok(paths.into_iter().filter(|path| path.extension() == Some(extension.as_ref())).collect())
+}
+
+fn write(path: &Path, content: &str) -> Result<(), Box<dyn Error>> {
+    Ok(fs::write(path, content).map_err(|e| format!("{}: {}", path.display(), e))?)
+}
+
+// `3:5: ...` of the compiler as `Pong/Main.jack:3:5: ...`, the notes as they are
+fn located(file: &str, message: &str) -> String {
+    let lines: Vec<String> = message.lines()
+        .map(|line| if line.starts_with(' ') { line.to_string() } else { format!("{}:{}", file, line) })
+        .collect();
+    lines.join("\n")
+}
+
+// `12 Pong/Main.jack:5:9` of a source map, the records of the functions left out
+fn origins(source_map: &str) -> HashMap<usize, String> {
+    source_map.lines()
+        .filter(|record| !record.starts_with("function "))
+        .filter_map(|record| {
+            let (line, origin) = record.split_once(' ')?;
+            Some((line.parse().ok()?, origin.to_string()))
+        })
+        .collect()
+}
+
+// `Pong/Main.vm:12: not a VM command: ...` of the translator, at the Jack line of line 12
+fn translator_error(file: &VmFile, error: &str) -> String {
+    let located = error.strip_prefix(&format!("{}:", file.name)).and_then(|rest| rest.split_once(": "));
+    let Some((line, message)) = located else { return error.to_string() };
+    match line.parse().ok().and_then(|line: usize| file.origins.get(&line)) {
+        Some(origin) => format!("{}: {}\n  note: in line {} of the VM code, {}", origin, message, line, file.name),
+        None => error.to_string()
+    }
+}
+
+// An error of the assembler at a line of the assembly, for the VM command it is the code of. The
+// translator writes each command as a comment above its code, in the order of the VM files and
+// after the bootstrap.
+fn assembler_error(program: &[VmFile], assembly: &str, asm_file: &str, line: usize, error: &str) -> String {
+    let at_assembly = format!("{}:{}: {}", asm_file, line, error);
+    let comments: Vec<&str> = assembly.lines().take(line).filter_map(|line| line.strip_prefix("// ")).collect();
+    if comments.contains(&"Program end") {
+        return at_assembly;
+    }
+    let commands = comments.iter().filter(|comment| **comment != "Bootstrap").count();
+    let Some((file, vm_line)) = nth_command(program, commands) else { return at_assembly };
+    let command = comments.last().unwrap_or(&"");
+    match file.origins.get(&vm_line) {
+        Some(origin) => format!("{}: {}\n  note: in the code of `{}`, line {} of {}", origin, error, command, vm_line, file.name),
+        None => format!("{}:{}: {}\n  note: in the code of `{}`, line {} of {}", file.name, vm_line, error, command, line, asm_file)
+    }
+}
+
+// the file and line of the nth command of the program, counting from 1 over the lines that are
+// not empty or comments
+fn nth_command(program: &[VmFile], n: usize) -> Option<(&VmFile, usize)> {
+    let mut lines = program.iter().flat_map(|file| {
+        file.text.lines().enumerate()
+            .filter(|(_, line)| !line.split_once("//").map_or(*line, |(code, _)| code).trim().is_empty())
+            .map(move |(index, _)| (file, index + 1))
+    });
+    lines.nth(n.checked_sub(1)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compiled(name: &str, text: &str, source_map: &str) -> VmFile {
+        VmFile { name: name.to_string(), text: text.to_string(), origins: origins(source_map) }
+    }
+
+    #[test]
+    fn translator_errors_are_at_their_jack_line() {
+        let file = compiled("Pong/Main.vm", "function Main.main 0\npush constant 1\nfrobnicate\n", "1 Pong/Main.jack:2:21\n3 Pong/Main.jack:3:9\n");
+        let error = translator_error(&file, "Pong/Main.vm:3: not a VM command: frobnicate");
+        assert_eq!(error, "Pong/Main.jack:3:9: not a VM command: frobnicate\n  note: in line 3 of the VM code, Pong/Main.vm");
+        // the OS has no source map
+        assert_eq!(translator_error(&compiled("Sys.vm", "", ""), "Sys.vm:4: not a VM command: x"), "Sys.vm:4: not a VM command: x");
+    }
+
+    #[test]
+    fn assembler_errors_are_at_the_jack_line_of_their_command() {
+        let program = [
+            compiled("Main.vm", "function Main.main 0\npush constant 1\n", "function Main.main 1 Main.jack:2:21\n1 Main.jack:2:21\n2 Main.jack:3:16\n"),
+            compiled("Sys.vm", "// the OS\nfunction Sys.init 0\n", "")
+        ];
+        let assembly = "// Bootstrap\n@256\n// function Main.main 0\n(Main.main)\n// push constant 1\n@1\nD=Q\n// function Sys.init 0\n(Sys.init)\nX\n";
+        assert_eq!(
+            assembler_error(&program, assembly, "Pong.asm", 7, "Invalid comp: Q"),
+            "Main.jack:3:16: Invalid comp: Q\n  note: in the code of `push constant 1`, line 2 of Main.vm"
+        );
+        assert_eq!(
+            assembler_error(&program, assembly, "Pong.asm", 10, "Invalid instruction: X"),
+            "Sys.vm:2: Invalid instruction: X\n  note: in the code of `function Sys.init 0`, line 10 of Pong.asm"
+        );
+        assert_eq!(assembler_error(&program, assembly, "Pong.asm", 2, "Invalid"), "Pong.asm:2: Invalid");
+    }
+
+    #[test]
+    fn compile_errors_name_the_file() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("Main.jack"), "class Main { function void main() { do Ball.move(); return; } }").unwrap();
+        let error = build(directory.path(), Stage::Hack, directory.path()).unwrap_err().to_string();
+        let main = directory.path().join("Main.jack").display().to_string();
+        assert_eq!(error, format!("{}:1:40: unknown class Ball in call to Ball.move\n1 of 1 classes failed", main));
+    }
+}
@@ -0,0 +1,113 @@
+// The Hack computer of the course, running machine code the way the CPU emulator does: the program
+// in 32K words of ROM, and 32K words of RAM with the screen from 16384 and the keyboard at 24576.
+pub struct Cpu {
+    pub rom: Vec<u16>,
+    pub ram: Vec<i16>,
+    pub a: i16,
+    pub d: i16,
+    pub pc: u16,
+    // the cycles run since the program was loaded
+    pub time: u64
+}
+
+pub const SCREEN: usize = 16384;
+pub const KEYBOARD: usize = 24576;
+
+const MEMORY: usize = 32768;
+
+impl Cpu {
+    pub fn new(rom: Vec<u16>) -> Self {
+        Cpu { rom, ram: vec![0; MEMORY], a: 0, d: 0, pc: 0, time: 0 }
+    }
+
+    // The lines of 16 bits `assembler::assemble` writes. A line that is not fails with its number.
+    pub fn from_hack(text: &str) -> Result<Self, (usize, String)> {
+        let mut rom = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.len() != 16 {
+                return Err((number + 1, format!("expected an instruction of 16 bits, found {}", line)));
+            }
+            let instruction = u16::from_str_radix(line, 2).map_err(|_| (number + 1, format!("expected 0s and 1s, found {}", line)))?;
+            rom.push(instruction);
+        }
+        if rom.len() > MEMORY {
+            return Err((MEMORY + 1, format!("the program has {} instructions, the ROM holds {}", rom.len(), MEMORY)));
+        }
+        Ok(Cpu::new(rom))
+    }
+
+    // One instruction. The ROM past the program is 0, which is `@0`.
+    pub fn step(&mut self) {
+        let instruction = self.rom.get(self.pc as usize).copied().unwrap_or(0);
+        self.time += 1;
+        if instruction & 0x8000 == 0 {
+            self.a = instruction as i16;
+            self.pc = (self.pc + 1) % MEMORY as u16;
+            return;
+        }
+        // M, the jump and the destinations all use A as it was before the instruction
+        let address = self.a as u16 as usize % MEMORY;
+        let y = if instruction & 0x1000 != 0 { self.ram[address] } else { self.a };
+        let out = alu(self.d, y, instruction >> 6);
+        if instruction & 0x20 != 0 {
+            self.a = out;
+        }
+        if instruction & 0x10 != 0 {
+            self.d = out;
+        }
+        if instruction & 0x08 != 0 {
+            self.ram[address] = out;
+        }
+        let jump = (instruction & 0x4 != 0 && out < 0) || (instruction & 0x2 != 0 && out == 0) || (instruction & 0x1 != 0 && out > 0);
+        self.pc = if jump { address as u16 } else { (self.pc + 1) % MEMORY as u16 };
+    }
+
+    pub fn run(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.step();
+        }
+    }
+}
+
+// The ALU of the course, its six control bits the lowest of `bits`: zx nx zy ny f no
+fn alu(x: i16, y: i16, bits: u16) -> i16 {
+    let x = if bits & 0x20 != 0 { 0 } else { x };
+    let x = if bits & 0x10 != 0 { !x } else { x };
+    let y = if bits & 0x08 != 0 { 0 } else { y };
+    let y = if bits & 0x04 != 0 { !y } else { y };
+    let out = if bits & 0x02 != 0 { x.wrapping_add(y) } else { x & y };
+    if bits & 0x01 != 0 { !out } else { out }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_like_the_alu_of_the_course() {
+        // D+1, D-A, !D and D|A
+        assert_eq!(alu(5, 9, 0b011111), 6);
+        assert_eq!(alu(5, 9, 0b010011), -4);
+        assert_eq!(alu(5, 9, 0b001101), !5);
+        assert_eq!(alu(5, 9, 0b010101), 13);
+        assert_eq!(alu(i16::MAX, 1, 0b000010), i16::MIN);
+    }
+
+    #[test]
+    fn runs_assembled_programs() {
+        // RAM[2] = RAM[0] + RAM[1], and the loop at the end
+        let source = "@R0\nD=M\n@R1\nD=D+M\n@R2\nM=D\n(END)\n@END\n0;JMP\n";
+        let mut cpu = Cpu::from_hack(&assembler::assemble(source).unwrap()).unwrap();
+        cpu.ram[0] = 20;
+        cpu.ram[1] = 22;
+        cpu.run(100);
+        assert_eq!(cpu.ram[2], 42);
+        assert_eq!(cpu.pc, 6);
+        assert_eq!(cpu.time, 100);
+        assert_eq!(Cpu::from_hack("0000000000000001\n2\n").err().unwrap(), (2, "expected an instruction of 16 bits, found 2".to_string()));
+    }
+}
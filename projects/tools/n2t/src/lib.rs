@@ -0,0 +1,163 @@
+// One command for the tools of the course: Jack to VM code with jack_analyzer, VM code to Hack
+// assembly with the VM translator and assembly to machine code with the assembler, all in memory,
+// and the test scripts of the CPU emulator on a Hack CPU of its own.
+//
+// ```text
+// n2t build Pong/ [--to vm|asm|hack] [-o out/]
+// n2t clean Pong/ [-o out/]
+// n2t test projects/04/
+// ```
+mod build;
+pub mod cpu;
+mod tst;
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub use crate::build::Stage;
+pub use crate::cpu::Cpu;
+use crate::tst::Outcome;
+
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let output = config.output.as_deref().unwrap_or(&config.directory);
+    match config.command {
+        Command::Build(to) => {
+            for path in build::build(&config.directory, to, output)? {
+                println!("Built: {}", path.display());
+            }
+            Ok(())
+        },
+        Command::Clean => {
+            for path in build::artifacts(&config.directory, output)? {
+                fs::remove_file(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                println!("Removed: {}", path.display());
+            }
+            Ok(())
+        },
+        Command::Test => test(&config.directory)
+    }
+}
+
+// `n2t test`: the .tst files of the directory and those below it, sorted by path
+fn test(directory: &Path) -> Result<(), Box<dyn Error>> {
+    let mut scripts = Vec::new();
+    find_scripts(directory, &mut scripts)?;
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for script in scripts.iter() {
+        match tst::run(script) {
+            Ok(Outcome::Passed) => {
+                passed += 1;
+                println!("Passed: {}", script.display());
+            },
+            Ok(Outcome::Skipped(reason)) => {
+                skipped += 1;
+                println!("Skipped: {}, {}", script.display(), reason);
+            },
+            Ok(Outcome::Failed(error)) => {
+                failed += 1;
+                println!("Failed: {}: {}", script.display(), error);
+            },
+            Err(error) => {
+                failed += 1;
+                println!("Failed: {}: {}", script.display(), error);
+            }
+        }
+    }
+    let summary = format!("{} passed, {} failed, {} skipped", passed, failed, skipped);
+    if failed > 0 {
+        return Err(summary.into());
+    }
+    println!("{}", summary);
+    Ok(())
+}
+
+fn find_scripts(directory: &Path, scripts: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let mut paths = fs::read_dir(directory).map_err(|e| format!("{}: {}", directory.display(), e))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            find_scripts(&path, scripts)?;
+        } else if path.extension() == Some("tst".as_ref()) {
+            scripts.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Build(Stage),
+    Clean,
+    Test
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub command: Command,
+    pub directory: PathBuf,
+    // `-o out/`: where build writes, and clean removes from, instead of the directory
+    pub output: Option<PathBuf>
+}
+
+impl Config {
+    pub fn new(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
+        args.next();
+        let mut command = match args.next().as_deref() {
+            Some("build") => Command::Build(Stage::Hack),
+            Some("clean") => Command::Clean,
+            Some("test") => Command::Test,
+            Some(_) => return Err("the command has to be build, clean or test"),
+            None => return Err("missing command, build, clean or test")
+        };
+        let mut directory = None;
+        let mut output = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--to" => match (&mut command, args.next().as_deref().and_then(Stage::new)) {
+                    (Command::Build(to), Some(stage)) => *to = stage,
+                    (Command::Build(_), None) => return Err("--to needs vm, asm or hack"),
+                    _ => return Err("--to only applies to build")
+                },
+                "-o" => match args.next() {
+                    Some(path) => output = Some(PathBuf::from(path)),
+                    None => return Err("missing output directory")
+                },
+                flag if flag.starts_with('-') => return Err("unknown flag"),
+                _ if directory.is_some() => return Err("only one directory"),
+                path if Path::new(path).is_dir() => directory = Some(PathBuf::from(path)),
+                _ => return Err("not a directory")
+            }
+        }
+        let Some(directory) = directory else { return Err("missing directory") };
+        if output.is_some() && command == Command::Test {
+            return Err("-o does not apply to test");
+        }
+        Ok(Config { command, directory, output })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str]) -> Result<Config, &'static str> {
+        Config::new(["n2t"].iter().chain(args.iter()).map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_commands() {
+        let build = config(&["build", ".", "--to", "asm", "-o", "out"]).unwrap();
+        assert_eq!(build.command, Command::Build(Stage::Asm));
+        assert_eq!(build.output, Some(PathBuf::from("out")));
+        assert_eq!(config(&["build", "."]).unwrap().command, Command::Build(Stage::Hack));
+        assert_eq!(config(&["link", "."]).unwrap_err(), "the command has to be build, clean or test");
+        assert_eq!(config(&["build", ".", "--to", "exe"]).unwrap_err(), "--to needs vm, asm or hack");
+        assert_eq!(config(&["clean", ".", "--to", "vm"]).unwrap_err(), "--to only applies to build");
+        assert_eq!(config(&["test", ".", "-o", "out"]).unwrap_err(), "-o does not apply to test");
+        assert_eq!(config(&["test"]).unwrap_err(), "missing directory");
+        assert_eq!(config(&["test", "Cargo.toml"]).unwrap_err(), "not a directory");
+    }
+}
@@ -0,0 +1,15 @@
+use std::env;
+use std::process;
+use n2t::{run, Config};
+
+fn main() {
+    let config = Config::new(env::args()).unwrap_or_else(|err| {
+        eprintln!("Error parsing arguments: {}", err);
+        process::exit(1);
+    });
+
+    if let Err(e) = run(config) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
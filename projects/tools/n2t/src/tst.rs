@@ -0,0 +1,394 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::cpu::Cpu;
+
+// `n2t test`: the course's test scripts for the CPU emulator, run on `Cpu`. A script loads Foo.asm,
+// assembled in memory, or Foo.hack, sets the RAM and runs cycles, and each `output` is a line of a
+// table, which has to agree with the compare file. The table is compared in memory, the output file
+// of the script is not written.
+//
+// ```text
+// load Mult.asm,
+// output-file Mult.out,
+// compare-to Mult.cmp,
+// output-list RAM[0]%D2.6.2 RAM[2]%D2.6.2;
+// set RAM[0] 3,
+// repeat 120 {
+//   ticktock;
+// }
+// output;
+// ```
+//
+// Scripts of the hardware simulator and the VM emulator, and those without a compare file that are
+// run by hand, are skipped.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    // the directory for a `load` of its own
+    Load(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Set(String, String),
+    // `ticktock`, or `tock`, which ends the cycle `tick` starts
+    Cycle,
+    Tick,
+    Output,
+    // forever without a count
+    Repeat(Option<u64>, Vec<Command>),
+    // `output-file`, `echo` and `clear-echo`, which do not change what is compared
+    Ignored,
+    Unknown(String)
+}
+
+// `RAM[256]%D2.6.2`: the spaces to the left, the width of the value, and the spaces to the right
+#[derive(Debug, Clone, PartialEq)]
+struct Column {
+    name: String,
+    format: char,
+    left: usize,
+    width: usize,
+    right: usize
+}
+
+pub fn run(path: &Path) -> Result<Outcome, Box<dyn Error>> {
+    let script = fs::read_to_string(path)?;
+    let commands = parse(&script)?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let Some(Command::Load(program)) = commands.iter().find(|command| matches!(command, Command::Load(_))) else {
+        return Ok(Outcome::Skipped("it loads no program".to_string()));
+    };
+    if program.ends_with(".hdl") {
+        return Ok(Outcome::Skipped("it is a script of the hardware simulator".to_string()));
+    }
+    if !(program.ends_with(".asm") || program.ends_with(".hack")) {
+        return Ok(Outcome::Skipped("it is a script of the VM emulator".to_string()));
+    }
+    let Some(Command::CompareTo(compare)) = commands.iter().find(|command| matches!(command, Command::CompareTo(_))) else {
+        return Ok(Outcome::Skipped("it has no compare file and is run by hand".to_string()));
+    };
+    let program_path = directory.join(program);
+    if !program_path.exists() {
+        return Ok(Outcome::Skipped(format!("{} is missing", program_path.display())));
+    }
+    let text = fs::read_to_string(&program_path)?;
+    let hack = if program.ends_with(".asm") {
+        match assembler::assemble(&text) {
+            Ok(hack) => hack,
+            Err((line, error)) => return Ok(Outcome::Failed(format!("{}:{}: {}", program_path.display(), line, error)))
+        }
+    } else {
+        text
+    };
+    let cpu = Cpu::from_hack(&hack).map_err(|(line, error)| format!("{}:{}: {}", program_path.display(), line, error))?;
+    let mut runner = Runner { cpu, columns: Vec::new(), table: Vec::new() };
+    runner.run(&commands)?;
+    let compare_path = directory.join(compare);
+    let expected = fs::read_to_string(&compare_path).map_err(|e| format!("{}: {}", compare_path.display(), e))?;
+    Ok(match first_difference(&expected, &runner.table) {
+        Some(difference) => Outcome::Failed(format!("{}: {}", compare_path.display(), difference)),
+        None => Outcome::Passed
+    })
+}
+
+struct Runner {
+    cpu: Cpu,
+    columns: Vec<Column>,
+    // the header of `output-list` and a line for each `output`
+    table: Vec<String>
+}
+
+impl Runner {
+    fn run(&mut self, commands: &[Command]) -> Result<(), String> {
+        for command in commands.iter() {
+            match command {
+                Command::OutputList(columns) => {
+                    self.columns = columns.clone();
+                    // a name longer than its column is cut, `RAM[3006` of `RAM[3006]%D1.6.1`
+                    let header = columns.iter().map(|column| {
+                        let width = column.left + column.width + column.right;
+                        let name: String = column.name.chars().take(width).collect();
+                        format!("{:^width$}", name, width = width)
+                    });
+                    self.table.push(row(header));
+                },
+                Command::Set(name, value) => self.set(name, parse_value(value).ok_or(format!("set {} needs a number, found {}", name, value))?)?,
+                Command::Cycle => self.cpu.step(),
+                Command::Tick | Command::Load(_) | Command::CompareTo(_) | Command::Ignored => {},
+                Command::Output => {
+                    let values: Vec<String> = self.columns.iter().map(|column| {
+                        let value = self.get(&column.name).map_or_else(|| "?".to_string(), |value| formatted(value, column.format));
+                        format!("{}{:>width$}{}", " ".repeat(column.left), value, " ".repeat(column.right), width = column.width)
+                    }).collect();
+                    self.table.push(row(values.into_iter()));
+                },
+                Command::Repeat(Some(count), body) => for _ in 0..*count {
+                    self.run(body)?;
+                },
+                Command::Repeat(None, _) => return Err("a repeat without a count runs forever".to_string()),
+                Command::Unknown(name) => return Err(format!("unknown command {}", name))
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Option<i16> {
+        match name {
+            "A" => Some(self.cpu.a),
+            "D" => Some(self.cpu.d),
+            "PC" => Some(self.cpu.pc as i16),
+            "time" => Some(self.cpu.time as i16),
+            _ => Some(self.cpu.ram[ram_address(name)?])
+        }
+    }
+
+    fn set(&mut self, name: &str, value: i16) -> Result<(), String> {
+        match name {
+            "A" => self.cpu.a = value,
+            "D" => self.cpu.d = value,
+            "PC" => self.cpu.pc = value as u16,
+            _ => match ram_address(name) {
+                Some(address) => self.cpu.ram[address] = value,
+                None => return Err(format!("set cannot change {}", name))
+            }
+        }
+        Ok(())
+    }
+}
+
+fn row(cells: impl Iterator<Item = String>) -> String {
+    format!("|{}|", cells.collect::<Vec<_>>().join("|"))
+}
+
+// `RAM[256]`
+fn ram_address(name: &str) -> Option<usize> {
+    let address: usize = name.strip_prefix("RAM[")?.strip_suffix(']')?.parse().ok()?;
+    (address < 32768).then_some(address)
+}
+
+// `-1`, or `%B1111111111111111`, `%XFFFF` and `%D-1`
+fn parse_value(value: &str) -> Option<i16> {
+    let (radix, digits) = match value.strip_prefix('%') {
+        Some(rest) if rest.starts_with('B') => (2, &rest[1..]),
+        Some(rest) if rest.starts_with('X') => (16, &rest[1..]),
+        Some(rest) if rest.starts_with('D') => (10, &rest[1..]),
+        Some(_) => return None,
+        None => (10, value)
+    };
+    match radix {
+        10 => digits.parse().ok(),
+        _ => u16::from_str_radix(digits, radix).ok().map(|value| value as i16)
+    }
+}
+
+fn formatted(value: i16, format: char) -> String {
+    match format {
+        'B' => format!("{:016b}", value as u16),
+        'X' => format!("{:04X}", value as u16),
+        _ => value.to_string()
+    }
+}
+
+// The lines of the compare file against those of the table, the cells without their spaces, and
+// a cell of `*` matching anything
+fn first_difference(expected: &str, table: &[String]) -> Option<String> {
+    let cells = |line: &str| line.trim().split('|').map(|cell| cell.trim().to_string()).collect::<Vec<_>>();
+    let expected: Vec<&str> = expected.lines().filter(|line| !line.trim().is_empty()).collect();
+    for (index, (expected, found)) in expected.iter().zip(table.iter()).enumerate() {
+        let (expected_cells, found_cells) = (cells(expected), cells(found));
+        let agree = expected_cells.len() == found_cells.len() && expected_cells.iter().zip(found_cells.iter())
+            .all(|(expected, found)| expected == found || (!expected.is_empty() && expected.chars().all(|c| c == '*')));
+        if !agree {
+            return Some(format!("line {} differs, expected {} but the script wrote {}", index + 1, expected.trim(), found.trim()));
+        }
+    }
+    if expected.len() != table.len() {
+        return Some(format!("it has {} lines, the script wrote {}", expected.len(), table.len()));
+    }
+    None
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    // `,` and `;`
+    End,
+    Open,
+    Close
+}
+
+// Comments are `//` to the end of the line and `/* */`, strings of `echo` are in double quotes
+fn tokenize(script: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = script.chars().peekable();
+    let mut word = String::new();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+                None
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                chars.by_ref().find(|&c| std::mem::replace(&mut previous, c) == '*' && c == '/');
+                None
+            },
+            '"' => {
+                word.extend(chars.by_ref().take_while(|&c| c != '"'));
+                None
+            },
+            ',' | ';' => Some(Token::End),
+            '{' => Some(Token::Open),
+            '}' => Some(Token::Close),
+            c if c.is_whitespace() => None,
+            c => {
+                word.push(c);
+                continue;
+            }
+        };
+        if !word.is_empty() {
+            tokens.push(Token::Word(std::mem::take(&mut word)));
+        }
+        tokens.extend(token);
+    }
+    if !word.is_empty() {
+        tokens.push(Token::Word(word));
+    }
+    tokens
+}
+
+fn parse(script: &str) -> Result<Vec<Command>, String> {
+    let mut tokens = tokenize(script).into_iter();
+    let commands = parse_block(&mut tokens)?;
+    match tokens.next() {
+        Some(_) => Err("a '}' without its repeat".to_string()),
+        None => Ok(commands)
+    }
+}
+
+// the commands up to the `}` of a repeat, or to the end of the script
+fn parse_block(tokens: &mut impl Iterator<Item = Token>) -> Result<Vec<Command>, String> {
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => words.push(word),
+            Token::End => if !words.is_empty() {
+                commands.push(command(std::mem::take(&mut words))?);
+            },
+            // `while out <> 75 {` of the hardware simulator, which is skipped before it runs
+            Token::Open if words.first().is_some_and(|word| word == "while") => {
+                words.clear();
+                parse_block(tokens)?;
+                commands.push(Command::Unknown("while".to_string()));
+            },
+            Token::Open => {
+                let count = match &words[..] {
+                    [repeat] if repeat == "repeat" => None,
+                    [repeat, count] if repeat == "repeat" => Some(count.parse().map_err(|_| format!("repeat needs a number, found {}", count))?),
+                    _ => return Err(format!("expected repeat before '{{', found {}", words.join(" ")))
+                };
+                words.clear();
+                commands.push(Command::Repeat(count, parse_block(tokens)?));
+            },
+            Token::Close => break
+        }
+    }
+    if !words.is_empty() {
+        commands.push(command(words)?);
+    }
+    Ok(commands)
+}
+
+fn command(words: Vec<String>) -> Result<Command, String> {
+    let name = words[0].as_str();
+    let argument = |index: usize| words.get(index).cloned().ok_or(format!("{} needs an argument", name));
+    Ok(match name {
+        "load" => Command::Load(words.get(1).cloned().unwrap_or_default()),
+        "compare-to" => Command::CompareTo(argument(1)?),
+        "output-list" => Command::OutputList(words[1..].iter().map(|column| parse_column(column)).collect::<Result<_, _>>()?),
+        "set" => Command::Set(argument(1)?, argument(2)?),
+        "ticktock" | "tock" => Command::Cycle,
+        "tick" => Command::Tick,
+        "output" => Command::Output,
+        "output-file" | "echo" | "clear-echo" => Command::Ignored,
+        name => Command::Unknown(name.to_string())
+    })
+}
+
+// `RAM[0]%D2.6.2`, and `RAM[0]` alone for `%D1.6.1`
+fn parse_column(column: &str) -> Result<Column, String> {
+    let (name, format) = column.split_once('%').unwrap_or((column, "D1.6.1"));
+    let error = || format!("expected a column like RAM[0]%D2.6.2, found {}", column);
+    let mut chars = format.chars();
+    let format_char = chars.next().filter(|c| "BXDS".contains(*c)).ok_or_else(error)?;
+    let numbers: Vec<usize> = chars.as_str().split('.').map(|number| number.parse().map_err(|_| error())).collect::<Result<_, _>>()?;
+    let [left, width, right] = numbers[..] else { return Err(error()) };
+    Ok(Column { name: name.to_string(), format: format_char, left, width, right })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scripts() {
+        let commands = parse("\
+load Add.asm, /* the program */
+output-list RAM[0]%D2.6.2 D;
+echo \"Press a key, then wait\";
+repeat 2 { ticktock; } // two cycles
+set RAM[0] %B101, output;").unwrap();
+        assert_eq!(commands, vec![
+            Command::Load("Add.asm".to_string()),
+            Command::OutputList(vec![
+                Column { name: "RAM[0]".to_string(), format: 'D', left: 2, width: 6, right: 2 },
+                Column { name: "D".to_string(), format: 'D', left: 1, width: 6, right: 1 }
+            ]),
+            Command::Ignored,
+            Command::Repeat(Some(2), vec![Command::Cycle]),
+            Command::Set("RAM[0]".to_string(), "%B101".to_string()),
+            Command::Output
+        ]);
+        assert_eq!(parse_value("%B101"), Some(5));
+        assert_eq!(parse_value("%XFFFF"), Some(-1));
+        assert_eq!(parse("repeat x { tick; }").unwrap_err(), "repeat needs a number, found x");
+        assert_eq!(parse("while out <> 75 { tick; }").unwrap(), vec![Command::Unknown("while".to_string())]);
+        assert_eq!(parse_column("RAM[0]%Q1.2.3").unwrap_err(), "expected a column like RAM[0]%D2.6.2, found RAM[0]%Q1.2.3");
+    }
+
+    #[test]
+    fn tables_are_compared_by_their_cells() {
+        let table = vec!["|  RAM[0]  |".to_string(), "|     257  |".to_string()];
+        assert_eq!(first_difference("| RAM[0] |\n|  257 |\n", &table), None);
+        assert_eq!(first_difference("|RAM[0]|\n|***|\n", &table), None);
+        assert_eq!(
+            first_difference("|RAM[0]|\n|256|\n", &table).unwrap(),
+            "line 2 differs, expected |256| but the script wrote |     257  |"
+        );
+        assert_eq!(first_difference("|RAM[0]|\n|257|\n|258|\n", &table).unwrap(), "it has 3 lines, the script wrote 2");
+    }
+
+    #[test]
+    fn runs_a_script_against_its_compare_file() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("Add.asm"), "@R0\nD=M\n@R1\nD=D+M\n@R2\nM=D\n").unwrap();
+        let script = "load Add.asm, compare-to Add.cmp, output-list RAM[2]%D2.6.2;\nset RAM[0] 2, set RAM[1] 3, repeat 6 { ticktock; } output;";
+        fs::write(directory.path().join("Add.tst"), script).unwrap();
+        fs::write(directory.path().join("Add.cmp"), "|  RAM[2]  |\n|       5  |\n").unwrap();
+        assert_eq!(run(&directory.path().join("Add.tst")).unwrap(), Outcome::Passed);
+
+        fs::write(directory.path().join("Add.cmp"), "|  RAM[2]  |\n|       6  |\n").unwrap();
+        assert!(matches!(run(&directory.path().join("Add.tst")).unwrap(), Outcome::Failed(_)));
+        fs::write(directory.path().join("Chip.tst"), "load Chip.hdl, compare-to Chip.cmp;").unwrap();
+        assert_eq!(run(&directory.path().join("Chip.tst")).unwrap(), Outcome::Skipped("it is a script of the hardware simulator".to_string()));
+    }
+}
@@ -0,0 +1,100 @@
+// `n2t build` of a game all the way to machine code, run on the Hack CPU, and `n2t clean` and `n2t test`
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use n2t::cpu::{Cpu, SCREEN};
+
+fn n2t(args: &[&str], directory: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_n2t")).args(args).current_dir(directory).output().unwrap()
+}
+
+#[test]
+fn a_game_is_built_to_machine_code_and_runs() {
+    let directory = tempfile::tempdir().unwrap();
+    let game = directory.path().join("game");
+    fs::create_dir_all(&game).unwrap();
+    // a Sys and Memory of its own, the whole OS is more code than the ROM holds
+    fs::write(game.join("Sys.jack"), "\
+class Sys {
+    function void init() { do Memory.init(); do Main.main(); do Sys.halt(); return; }
+    function void halt() { while (true) {} return; }
+}").unwrap();
+    fs::write(game.join("Memory.jack"), "\
+class Memory {
+    static int free;
+    static Array ram;
+    function void init() { let free = 2048; let ram = 0; return; }
+    function int alloc(int size) { var int block; let block = free; let free = free + size; return block; }
+    function void poke(int address, int value) { let ram[address] = value; return; }
+}").unwrap();
+    fs::write(game.join("Main.jack"), "\
+class Main {
+    function void main() {
+        var Game game;
+        let game = Game.new(6);
+        do game.play();
+        do Memory.poke(8000, game.score());
+        return;
+    }
+}").unwrap();
+    fs::write(game.join("Game.jack"), "\
+class Game {
+    field int size, score;
+    constructor Game new(int n) { let size = n; let score = 0; return this; }
+    method void play() {
+        var int i;
+        while (i < size) { let score = score + i + i; let i = i + 1; }
+        do Memory.poke(16384, -1);
+        return;
+    }
+    method int score() { return score; }
+}").unwrap();
+    let output = n2t(&["build", "game", "-o", "out"], directory.path());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Built: out/game.hack\n");
+
+    let mut cpu = Cpu::from_hack(&fs::read_to_string(directory.path().join("out/game.hack")).unwrap()).unwrap();
+    // the score is written last, Sys.halt then loops
+    while cpu.ram[8000] == 0 && cpu.time < 1_000_000 {
+        cpu.run(1000);
+    }
+    assert_eq!(cpu.ram[8000], 30);
+    // the first 16 pixels of the screen
+    assert_eq!(cpu.ram[SCREEN], -1);
+    assert_eq!(cpu.ram[SCREEN + 1], 0);
+
+    let output = n2t(&["build", "game", "--to", "vm", "-o", "out"], directory.path());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Built: out/Game.vm\nBuilt: out/Main.vm\nBuilt: out/Memory.vm\nBuilt: out/Sys.vm\n");
+    let output = n2t(&["clean", "game", "-o", "out"], directory.path());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Removed: out/Game.vm\nRemoved: out/Main.vm\nRemoved: out/Memory.vm\nRemoved: out/Sys.vm\nRemoved: out/game.hack\n");
+    assert_eq!(fs::read_dir(directory.path().join("out")).unwrap().count(), 0);
+    assert!(game.join("Main.jack").exists());
+}
+
+#[test]
+fn a_program_larger_than_the_rom_is_an_error() {
+    let directory = tempfile::tempdir().unwrap();
+    for os in fs::read_dir("../../../tools/OS").unwrap() {
+        let os = os.unwrap().path();
+        fs::copy(&os, directory.path().join(os.file_name().unwrap())).unwrap();
+    }
+    fs::write(directory.path().join("Main.jack"), "class Main { function void main() { do Output.printInt(1); return; } }").unwrap();
+    let output = n2t(&["build", "."], directory.path());
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("instructions, the ROM of the Hack computer holds 32768"), "{}", stderr);
+}
+
+#[test]
+fn the_course_scripts_of_the_cpu_emulator_pass() {
+    let output = n2t(&["test", "../../04"], Path::new("."));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout, "\
+Skipped: ../../04/fill/Fill.tst, it has no compare file and is run by hand
+Passed: ../../04/fill/FillAutomatic.tst
+Passed: ../../04/mult/Mult.tst
+2 passed, 0 failed, 1 skipped
+");
+}